@@ -9,11 +9,46 @@
 //! new memory settings.
 //!
 //! The build script also sets the linker flags to tell it which link script to use.
+//!
+//! Finally it generates the Vial keyboard definition from the matrix size in
+//! `src/keymap.rs` and the mouse keys, compresses it with xz and writes it as constants to
+//! `$OUT_DIR/config_generated.rs`.
 
 use std::env;
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use const_gen::*;
+use xz2::read::XzEncoder;
+
+/// Must match the `DeviceConfig` in `src/central.rs`
+const VENDOR_ID: u16 = 0x4c4b;
+const PRODUCT_ID: u16 = 0x4643;
+const KEYBOARD_NAME: &str = "RMK Corne";
+
+/// Stable id Vial uses to tell keyboards apart, never change it
+const VIAL_KEYBOARD_ID: [u8; 8] = [0x4c, 0x43, 0x43, 0x6f, 0x72, 0x6e, 0x65, 0x36];
+
+/// Vial's names of the custom keycodes `Kb0` and up, in the order of
+/// `MouseKey` in `src/mouse.rs`
+const CUSTOM_KEYCODES: [(&str, &str); 12] = [
+    ("MS_UP", "Mouse up"),
+    ("MS_DOWN", "Mouse down"),
+    ("MS_LEFT", "Mouse left"),
+    ("MS_RGHT", "Mouse right"),
+    ("MS_WHLU", "Wheel up"),
+    ("MS_WHLD", "Wheel down"),
+    ("MS_WHLL", "Wheel left"),
+    ("MS_WHLR", "Wheel right"),
+    ("MS_BTN1", "Mouse button 1"),
+    ("MS_BTN2", "Mouse button 2"),
+    ("MS_BTN3", "Mouse button 3"),
+    ("MS_PREC", "Mouse precision"),
+];
+
+/// Columns of the bottom row that are wired to a thumb key
+const THUMB_COLS: [usize; 6] = [3, 4, 5, 6, 7, 8];
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
@@ -30,6 +65,15 @@ fn main() {
     // here, we ensure the build script is only re-run when
     // `memory.x` is changed.
     println!("cargo:rerun-if-changed=memory.x");
+    println!("cargo:rerun-if-changed=src/keymap.rs");
+
+    generate_vial_config(out);
+
+    // The library is also built for the host to run the tests, the linker
+    // scripts are only for the firmware
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("none") {
+        return;
+    }
 
     // Specify linker arguments.
 
@@ -44,3 +88,79 @@ fn main() {
     // Set the extra linker script from defmt
     println!("cargo:rustc-link-arg=-Tdefmt.x");
 }
+
+/// Reads `pub const <name>: usize = <n>;` from the keymap so the
+/// matrix size only lives in one place.
+fn keymap_const(keymap: &str, name: &str) -> usize {
+    let prefix = format!("const {name}: usize = ");
+    keymap
+        .lines()
+        .find_map(|line| {
+            let (_, rest) = line.split_once(&prefix)?;
+            rest.trim_end_matches(';').trim().parse().ok()
+        })
+        .unwrap_or_else(|| panic!("Cannot find `{name}` in src/keymap.rs"))
+}
+
+/// Builds the KLE layout of a 6 column Corne. The halves are split by a one
+/// unit gap and the bottom row only has the three inner thumb keys per half.
+fn physical_layout(rows: usize, cols: usize) -> json::JsonValue {
+    let half = cols / 2;
+    let mut layout = json::JsonValue::new_array();
+    for row in 0..rows {
+        let mut keys = json::JsonValue::new_array();
+        let thumb_row = row == rows - 1;
+        let mut last_x = 0.0;
+        for col in 0..cols {
+            if thumb_row && !THUMB_COLS.contains(&col) {
+                continue;
+            }
+            let x = col as f32 + if col >= half { 1.0 } else { 0.0 };
+            if x != last_x {
+                keys.push(json::object! { "x": x - last_x }).unwrap();
+            }
+            keys.push(format!("{row},{col}")).unwrap();
+            last_x = x + 1.0;
+        }
+        layout.push(keys).unwrap();
+    }
+    layout
+}
+
+fn generate_vial_config(out: &Path) {
+    let keymap = fs::read_to_string("src/keymap.rs").expect("Cannot read src/keymap.rs");
+    let rows = keymap_const(&keymap, "ROW");
+    let cols = keymap_const(&keymap, "COL");
+
+    let mut custom_keycodes = json::JsonValue::new_array();
+    for (name, title) in CUSTOM_KEYCODES {
+        custom_keycodes
+            .push(json::object! { "name": name, "title": title, "shortName": name })
+            .unwrap();
+    }
+
+    let vial_cfg = json::object! {
+        "name": KEYBOARD_NAME,
+        "vendorId": format!("{VENDOR_ID:#06X}"),
+        "productId": format!("{PRODUCT_ID:#06X}"),
+        "lighting": "none",
+        "matrix": { "rows": rows, "cols": cols },
+        "layouts": { "keymap": physical_layout(rows, cols) },
+        "customKeycodes": custom_keycodes,
+    };
+    let vial_cfg = json::stringify(vial_cfg);
+
+    let mut keyboard_def_compressed: Vec<u8> = Vec::new();
+    XzEncoder::new(vial_cfg.as_bytes(), 6)
+        .read_to_end(&mut keyboard_def_compressed)
+        .unwrap();
+
+    let keyboard_id = VIAL_KEYBOARD_ID.to_vec();
+    let const_declarations = [
+        const_declaration!(pub VIAL_KEYBOARD_DEF = keyboard_def_compressed),
+        const_declaration!(pub VIAL_KEYBOARD_ID = keyboard_id),
+    ]
+    .map(|s| "#[allow(clippy::redundant_static_lifetimes)]\n".to_owned() + s.as_str())
+    .join("\n");
+    fs::write(out.join("config_generated.rs"), const_declarations).unwrap();
+}
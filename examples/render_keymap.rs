@@ -0,0 +1,47 @@
+//! Renders every layer of the keymap the firmware uses into
+//! `<dir>/layer<n>.svg`, and all of them as ASCII tables into
+//! `<dir>/keymap.txt`. `<dir>` is the first argument, `keymap` if there is
+//! none.
+//!
+//! Runs on the host: `cargo make render`
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rmk_corne::keymap::{NUM_LAYER, get_default_keymap};
+use rmk_corne::render::{write_ascii, write_svg};
+
+fn main() -> ExitCode {
+    let dir = PathBuf::from(std::env::args().nth(1).unwrap_or_else(|| "keymap".into()));
+    if let Err(err) = fs::create_dir_all(&dir) {
+        eprintln!("can't create {}: {err}", dir.display());
+        return ExitCode::FAILURE;
+    }
+
+    let keymap = get_default_keymap();
+    let mut ascii = String::new();
+    for (layer, keys) in keymap.iter().enumerate() {
+        let mut svg = String::new();
+        // Writing into a `String` doesn't fail
+        write_svg(&mut svg, layer, keys).unwrap();
+        write_ascii(&mut ascii, layer, keys).unwrap();
+        if layer + 1 < NUM_LAYER {
+            ascii.push('\n');
+        }
+
+        let path = dir.join(format!("layer{layer}.svg"));
+        if let Err(err) = fs::write(&path, svg) {
+            eprintln!("can't write {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let path = dir.join("keymap.txt");
+    if let Err(err) = fs::write(&path, &ascii) {
+        eprintln!("can't write {}: {err}", path.display());
+        return ExitCode::FAILURE;
+    }
+    print!("{ascii}");
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,216 @@
+//! Converts keymaps between this firmware's `keymap` and QMK's
+//! `keymap.json` and ZMK's devicetree `.keymap` for a 6 column Corne
+//! (`LAYOUT_split_3x6_3`, 42 keys).
+//!
+//! Keys that the other side has no equivalent for are turned into a key that
+//! does nothing and reported as an [`Issue`].
+
+use std::fmt;
+
+use crate::{COLS, ROWS};
+
+mod json;
+pub mod qmk;
+pub mod rust;
+pub mod zmk;
+
+/// The layer `leader_layer()` builds, see `LEADER_LAYER` in `src/keymap.rs`
+const LEADER_LAYER: u8 = 8;
+
+/// Keys of the 6 column Corne, three rows of 12 and 6 thumbs
+pub const PHYSICAL_KEYS: usize = 42;
+
+/// The thumb keys are in the middle of the last matrix row, the other
+/// positions there are the firmware's `UNWIRED_KEYS`
+const THUMB_COLS: std::ops::Range<u8> = 3..9;
+
+/// Matrix position (row, col) of the `index`th key in QMK and ZMK order
+pub fn position(index: usize) -> (u8, u8) {
+    let thumbs = 3 * COLS as usize;
+    if index < thumbs {
+        ((index / COLS as usize) as u8, (index % COLS as usize) as u8)
+    } else {
+        (ROWS - 1, THUMB_COLS.start + (index - thumbs) as u8)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Modifier {
+    LCtrl,
+    LShift,
+    LAlt,
+    LGui,
+    RCtrl,
+    RShift,
+    RAlt,
+    RGui,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 8] = [
+        Modifier::LCtrl,
+        Modifier::LShift,
+        Modifier::LAlt,
+        Modifier::LGui,
+        Modifier::RCtrl,
+        Modifier::RShift,
+        Modifier::RAlt,
+        Modifier::RGui,
+    ];
+
+    /// The modifier's own key, `KC_LSFT` for `LShift`
+    pub fn keycode(self) -> u16 {
+        0xe0 + self as u16
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Key {
+    No,
+    Transparent,
+    /// A basic keycode, see `keycode.rs`
+    Key(u16),
+    /// A key sent together with a modifier, `wm!`
+    WithModifier(u16, Modifier),
+    /// Key on tap, modifier on hold, `hrm!`
+    ModTap(u16, Modifier),
+    /// Key on tap, layer on hold, `kol!`
+    LayerTap(u16, u8),
+    Momentary(u8),
+    /// Turns on the layer and all others off, `to!`
+    To(u8),
+    Toggle(u8),
+    Default(u8),
+    OneShotLayer(u8),
+    OneShotModifier(Modifier),
+    Bootloader,
+    Reboot,
+    /// Something only this firmware has (`LEADER`, `ms!(Up)`, `ash!(Kc1)`,
+    /// ...), as it is written in `src/keymap.rs`
+    Other(String),
+}
+
+/// One layer in matrix order, `ROWS` x `COLS`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Layer {
+    pub name: String,
+    pub keys: Vec<Key>,
+    /// How `keymap` builds a layer in code, `leader_layer()`.
+    /// Its keys can't be read and are all `Key::No`.
+    pub code: Option<String>,
+}
+
+impl Layer {
+    pub fn key(&self, row: u8, col: u8) -> &Key {
+        &self.keys[row as usize * COLS as usize + col as usize]
+    }
+
+    fn key_mut(&mut self, row: u8, col: u8) -> &mut Key {
+        &mut self.keys[row as usize * COLS as usize + col as usize]
+    }
+
+    /// The 42 keys with a switch, in QMK and ZMK order
+    pub fn physical_keys(&self) -> impl Iterator<Item = (usize, &Key)> {
+        (0..PHYSICAL_KEYS).map(|i| {
+            let (row, col) = position(i);
+            (i, self.key(row, col))
+        })
+    }
+}
+
+/// A key that couldn't be converted
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Issue {
+    pub layer: usize,
+    /// Matrix position of the key, `None` for the whole layer
+    pub position: Option<(u8, u8)>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some((row, col)) => {
+                write!(
+                    f,
+                    "layer {} row {row} col {col}: {}",
+                    self.layer, self.message
+                )
+            }
+            None => write!(f, "layer {}: {}", self.layer, self.message),
+        }
+    }
+}
+
+/// A keymap read from any of the formats, and what didn't convert
+#[derive(Debug, Default)]
+pub struct Converted<T> {
+    pub value: T,
+    pub issues: Vec<Issue>,
+}
+
+impl<T> Converted<T> {
+    fn issue(&mut self, layer: usize, index: usize, message: String) {
+        self.issues.push(Issue {
+            layer,
+            position: Some(position(index)),
+            message,
+        });
+    }
+
+    /// Reports a layer built in code, its keys are exported as doing nothing
+    fn code_issue(&mut self, layer: usize, code: &str, nothing: &str) {
+        self.issues.push(Issue {
+            layer,
+            position: None,
+            message: format!("{code} builds the layer in code, exported as {nothing}"),
+        });
+    }
+}
+
+/// Builds a layer from its 42 physical keys. The unwired positions get what
+/// they have on every layer of the firmware: the keys the one-shot keys hold
+/// and the key of the leader layer.
+fn layer_from_physical(name: String, keys: Vec<Key>) -> Layer {
+    let mut layer = Layer {
+        name,
+        keys: vec![Key::No; ROWS as usize * COLS as usize],
+        code: None,
+    };
+    let last = ROWS - 1;
+    *layer.key_mut(last, 0) = Key::Key(Modifier::LShift.keycode());
+    *layer.key_mut(last, 1) = Key::Key(Modifier::LCtrl.keycode());
+    *layer.key_mut(last, 2) = Key::Key(Modifier::LAlt.keycode());
+    *layer.key_mut(last, 9) = Key::Key(Modifier::LGui.keycode());
+    *layer.key_mut(last, 10) = Key::Momentary(1);
+    *layer.key_mut(last, 11) = Key::Momentary(LEADER_LAYER);
+    for (i, key) in keys.into_iter().enumerate() {
+        let (row, col) = position(i);
+        *layer.key_mut(row, col) = key;
+    }
+    layer
+}
+
+/// Error of reading a keymap file, with the line it happened on
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(text: &str, at: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: text[..at.min(text.len())].matches('\n').count() + 1,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
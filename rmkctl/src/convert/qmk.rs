@@ -0,0 +1,227 @@
+//! QMK's `keymap.json`, as written by QMK Configurator and `qmk c2json`
+
+use super::json::{self, Value};
+use super::{Converted, Key, Layer, Modifier, PHYSICAL_KEYS, ParseError, layer_from_physical};
+use crate::keycode;
+
+/// Per modifier (in `Modifier` order): its `MOD_*` name, the functions that
+/// send a key with it and the mod-tap functions. The first name is the one
+/// written out.
+const MODIFIERS: [(&str, &[&str], &[&str]); 8] = [
+    ("MOD_LCTL", &["LCTL", "C"], &["LCTL_T", "CTL_T"]),
+    ("MOD_LSFT", &["LSFT", "S"], &["LSFT_T", "SFT_T"]),
+    (
+        "MOD_LALT",
+        &["LALT", "A", "LOPT"],
+        &["LALT_T", "ALT_T", "LOPT_T", "OPT_T"],
+    ),
+    (
+        "MOD_LGUI",
+        &["LGUI", "G", "LCMD", "LWIN"],
+        &["LGUI_T", "GUI_T", "LCMD_T", "CMD_T", "LWIN_T", "WIN_T"],
+    ),
+    ("MOD_RCTL", &["RCTL"], &["RCTL_T"]),
+    ("MOD_RSFT", &["RSFT"], &["RSFT_T"]),
+    (
+        "MOD_RALT",
+        &["RALT", "ROPT", "ALGR"],
+        &["RALT_T", "ROPT_T", "ALGR_T"],
+    ),
+    (
+        "MOD_RGUI",
+        &["RGUI", "RCMD", "RWIN"],
+        &["RGUI_T", "RCMD_T", "RWIN_T"],
+    ),
+];
+
+fn find_modifier(matches: impl Fn(&(&str, &[&str], &[&str])) -> bool) -> Option<Modifier> {
+    let i = MODIFIERS.iter().position(matches)?;
+    Some(Modifier::ALL[i])
+}
+
+/// `LT(1, KC_SPC)` into `("LT", ["1", "KC_SPC"])`, `KC_A` into `("KC_A", [])`
+fn split_call(expr: &str) -> Option<(&str, Vec<&str>)> {
+    let Some((name, rest)) = expr.split_once('(') else {
+        return Some((expr.trim(), Vec::new()));
+    };
+    let args = rest.trim_end().strip_suffix(')')?;
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(args[start..].trim());
+    Some((name.trim(), parts))
+}
+
+fn basic(expr: &str) -> Option<u16> {
+    keycode::from_qmk(expr.trim()).filter(|&code| code > 1)
+}
+
+fn layer(arg: &str) -> Option<u8> {
+    arg.parse().ok()
+}
+
+/// A QMK keycode expression, `None` if there's no equivalent
+pub fn parse_key(expr: &str) -> Option<Key> {
+    let (name, args) = split_call(expr)?;
+    match (name, args.as_slice()) {
+        ("KC_NO" | "XXXXXXX", []) => Some(Key::No),
+        ("KC_TRNS" | "KC_TRANSPARENT" | "_______", []) => Some(Key::Transparent),
+        ("QK_BOOT" | "QK_BOOTLOADER" | "RESET", []) => Some(Key::Bootloader),
+        ("QK_REBOOT" | "QK_RBT", []) => Some(Key::Reboot),
+        (name, []) => basic(name).map(Key::Key),
+        ("MO", [l]) => layer(l).map(Key::Momentary),
+        ("TO", [l]) => layer(l).map(Key::To),
+        ("TG", [l]) => layer(l).map(Key::Toggle),
+        ("DF", [l]) => layer(l).map(Key::Default),
+        ("OSL", [l]) => layer(l).map(Key::OneShotLayer),
+        ("OSM", [m]) => find_modifier(|(name, _, _)| name == m).map(Key::OneShotModifier),
+        ("LT", [l, k]) => Some(Key::LayerTap(basic(k)?, layer(l)?)),
+        ("MT", [m, k]) => {
+            let modifier = find_modifier(|(name, _, _)| name == m)?;
+            Some(Key::ModTap(basic(k)?, modifier))
+        }
+        (f, [k]) => {
+            let key = basic(k)?;
+            if let Some(modifier) = find_modifier(|(_, wraps, _)| wraps.contains(&f)) {
+                Some(Key::WithModifier(key, modifier))
+            } else {
+                find_modifier(|(_, _, taps)| taps.contains(&f)).map(|m| Key::ModTap(key, m))
+            }
+        }
+        _ => None,
+    }
+}
+
+fn basic_name(code: u16) -> Option<&'static str> {
+    keycode::qmk_name(code).filter(|_| code > 1)
+}
+
+/// How a key is written in QMK, `None` if QMK has no equivalent
+pub fn key_text(key: &Key) -> Option<String> {
+    Some(match key {
+        Key::No => "KC_NO".into(),
+        Key::Transparent => "KC_TRNS".into(),
+        Key::Key(k) => basic_name(*k)?.into(),
+        Key::WithModifier(k, m) => format!("{}({})", MODIFIERS[*m as usize].1[0], basic_name(*k)?),
+        Key::ModTap(k, m) => format!("{}({})", MODIFIERS[*m as usize].2[0], basic_name(*k)?),
+        Key::LayerTap(k, l) => format!("LT({l}, {})", basic_name(*k)?),
+        Key::Momentary(l) => format!("MO({l})"),
+        Key::To(l) => format!("TO({l})"),
+        Key::Toggle(l) => format!("TG({l})"),
+        Key::Default(l) => format!("DF({l})"),
+        Key::OneShotLayer(l) => format!("OSL({l})"),
+        Key::OneShotModifier(m) => format!("OSM({})", MODIFIERS[*m as usize].0),
+        Key::Bootloader => "QK_BOOT".into(),
+        Key::Reboot => "QK_REBOOT".into(),
+        Key::Other(_) => return None,
+    })
+}
+
+/// Reads the layers of a `keymap.json` for `LAYOUT_split_3x6_3`
+pub fn import(text: &str) -> Result<Converted<Vec<Layer>>, ParseError> {
+    let root = json::parse(text)?;
+    let error = |message: String| ParseError { line: 1, message };
+    if let Some(Value::String(layout)) = root.get("layout")
+        && layout != "LAYOUT_split_3x6_3"
+    {
+        return Err(error(format!("{layout} isn't the 6 column Corne layout")));
+    }
+    let Some(Value::Array(layers)) = root.get("layers") else {
+        return Err(error("no layers".into()));
+    };
+
+    let mut converted = Converted::<Vec<Layer>>::default();
+    for (i, layer) in layers.iter().enumerate() {
+        let Value::Array(keys) = layer else {
+            return Err(error(format!("layer {i} isn't a list of keys")));
+        };
+        if keys.len() != PHYSICAL_KEYS {
+            return Err(error(format!(
+                "layer {i} has {} keys instead of {PHYSICAL_KEYS}",
+                keys.len()
+            )));
+        }
+        let mut physical = Vec::with_capacity(PHYSICAL_KEYS);
+        for (index, key) in keys.iter().enumerate() {
+            let Value::String(expr) = key else {
+                return Err(error(format!("layer {i} key {index} isn't a string")));
+            };
+            physical.push(parse_key(expr).unwrap_or_else(|| {
+                converted.issue(
+                    i,
+                    index,
+                    format!("{expr} has no equivalent, imported as k!(No)"),
+                );
+                Key::No
+            }));
+        }
+        converted
+            .value
+            .push(layer_from_physical(format!("layer {i}"), physical));
+    }
+    Ok(converted)
+}
+
+/// Writes `layers` as a `keymap.json`, the layer names go into the notes
+pub fn export(layers: &[Layer]) -> Converted<String> {
+    let mut converted = Converted::<String>::default();
+    let names: Vec<String> = layers
+        .iter()
+        .enumerate()
+        .map(|(i, layer)| format!("{i} {}", layer.name))
+        .collect();
+
+    let mut out = String::from("{\n");
+    out.push_str("  \"version\": 1,\n");
+    out.push_str(&format!(
+        "  \"notes\": {},\n",
+        json::quote(&format!(
+            "Exported from the RMK Corne keymap. Layers: {}",
+            names.join(", ")
+        ))
+    ));
+    out.push_str("  \"keyboard\": \"crkbd/rev1\",\n");
+    out.push_str("  \"keymap\": \"rmk\",\n");
+    out.push_str("  \"layout\": \"LAYOUT_split_3x6_3\",\n");
+    out.push_str("  \"layers\": [\n");
+    for (i, layer) in layers.iter().enumerate() {
+        if let Some(code) = &layer.code {
+            converted.code_issue(i, code, "KC_NO");
+        }
+        let keys: Vec<String> = layer
+            .physical_keys()
+            .map(|(index, key)| {
+                let text = key_text(key).unwrap_or_else(|| {
+                    let source = super::rust::key_text(key).unwrap_or_default();
+                    converted.issue(
+                        i,
+                        index,
+                        format!("{source} has no QMK equivalent, exported as KC_NO"),
+                    );
+                    "KC_NO".into()
+                });
+                json::quote(&text)
+            })
+            .collect();
+        out.push_str("    [\n");
+        for (row, chunk) in keys.chunks(12).enumerate() {
+            let comma = if (row + 1) * 12 < keys.len() { "," } else { "" };
+            out.push_str(&format!("      {}{comma}\n", chunk.join(", ")));
+        }
+        let comma = if i + 1 < layers.len() { "," } else { "" };
+        out.push_str(&format!("    ]{comma}\n"));
+    }
+    out.push_str("  ]\n}\n");
+    converted.value = out;
+    converted
+}
@@ -0,0 +1,263 @@
+//! `keymap` in `src/keymap.rs`, read as text like `build.rs`
+//! does, so this doesn't need the firmware to build
+
+use super::{Key, Layer, Modifier, ParseError};
+use crate::keycode;
+use crate::{COLS, ROWS};
+
+/// Names of the `ModifierCombination` constants, in `Modifier` order
+const MODIFIERS: [&str; 8] = [
+    "LCTRL", "LSHIFT", "LALT", "LGUI", "RCTRL", "RSHIFT", "RALT", "RGUI",
+];
+
+fn modifier(name: &str) -> Option<Modifier> {
+    let name = name
+        .trim()
+        .strip_prefix("ModifierCombination::")
+        .unwrap_or(name.trim());
+    let i = MODIFIERS.iter().position(|m| *m == name)?;
+    Some(Modifier::ALL[i])
+}
+
+fn modifier_name(modifier: Modifier) -> &'static str {
+    MODIFIERS[modifier as usize]
+}
+
+fn keycode(name: &str) -> Option<u16> {
+    keycode::from_rmk(name.trim()).filter(|&code| code > 1)
+}
+
+fn layer(arg: &str) -> Option<u8> {
+    arg.trim().parse().ok()
+}
+
+/// `name!(a, b)` into `("name", ["a", "b"])`
+fn split_macro(item: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, rest) = item.split_once("!(")?;
+    let args = rest.strip_suffix(')')?;
+    Some((name.trim(), args.split(',').map(str::trim).collect()))
+}
+
+/// One entry of the keymap. Anything that isn't one of the plain macros is
+/// kept as it is written.
+pub fn parse_key(item: &str) -> Key {
+    let other = || Key::Other(item.to_string());
+    let Some((name, args)) = split_macro(item) else {
+        return other();
+    };
+    let key = match (name, args.as_slice()) {
+        ("k", ["No"]) | ("a", ["No"]) => Some(Key::No),
+        ("a", ["Transparent"]) => Some(Key::Transparent),
+        ("k", ["Bootloader"]) => Some(Key::Bootloader),
+        ("k", ["Reboot"]) => Some(Key::Reboot),
+        ("k", [k]) => keycode(k).map(Key::Key),
+        ("wm", [k, m]) => keycode(k)
+            .zip(modifier(m))
+            .map(|(k, m)| Key::WithModifier(k, m)),
+        ("hrm", [k, m]) => keycode(k).zip(modifier(m)).map(|(k, m)| Key::ModTap(k, m)),
+        ("kol", [k, l]) => keycode(k).zip(layer(l)).map(|(k, l)| Key::LayerTap(k, l)),
+        ("mo", [l]) => layer(l).map(Key::Momentary),
+        ("to", [l]) => layer(l).map(Key::To),
+        ("tg", [l]) => layer(l).map(Key::Toggle),
+        ("df", [l]) => layer(l).map(Key::Default),
+        ("osl", [l]) => layer(l).map(Key::OneShotLayer),
+        ("osm", [m]) => modifier(m).map(Key::OneShotModifier),
+        _ => None,
+    };
+    key.unwrap_or_else(other)
+}
+
+/// How a key is written in the keymap, `None` for keycodes rmk has no name
+/// for here
+pub fn key_text(key: &Key) -> Option<String> {
+    let name = |code| keycode::rmk_name(code);
+    Some(match key {
+        Key::No => "k!(No)".into(),
+        Key::Transparent => "a!(Transparent)".into(),
+        Key::Key(k) => format!("k!({})", name(*k)?),
+        Key::WithModifier(k, m) => {
+            format!(
+                "wm!({}, ModifierCombination::{})",
+                name(*k)?,
+                modifier_name(*m)
+            )
+        }
+        Key::ModTap(k, m) => format!("hrm!({}, {})", name(*k)?, modifier_name(*m)),
+        Key::LayerTap(k, l) => format!("kol!({}, {l})", name(*k)?),
+        Key::Momentary(l) => format!("mo!({l})"),
+        Key::To(l) => format!("to!({l})"),
+        Key::Toggle(l) => format!("tg!({l})"),
+        Key::Default(l) => format!("df!({l})"),
+        Key::OneShotLayer(l) => format!("osl!({l})"),
+        Key::OneShotModifier(m) => format!("osm!(ModifierCombination::{})", modifier_name(*m)),
+        Key::Bootloader => "k!(Bootloader)".into(),
+        Key::Reboot => "k!(Reboot)".into(),
+        Key::Other(text) => text.clone(),
+    })
+}
+
+struct Scanner<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError::new(self.text, self.at, message)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.at..]
+    }
+
+    /// Skips whitespace and comments, returns the text of the last `//`
+    /// comment on the way
+    fn skip(&mut self) -> Option<String> {
+        let mut comment = None;
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.at += rest.len() - trimmed.len();
+            if let Some(line) = trimmed.strip_prefix("//") {
+                let end = line.find('\n').unwrap_or(line.len());
+                comment = Some(line[..end].trim().to_string());
+                self.at += 2 + end;
+            } else {
+                return comment;
+            }
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip();
+        if self.rest().starts_with(c) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{c}`")))
+        }
+    }
+
+    /// Text up to the next `,` or `]` outside of parentheses and character
+    /// literals like the one in `ch!(',')`
+    fn item(&mut self) -> Result<&'a str, ParseError> {
+        self.skip();
+        let rest = self.rest();
+        let (mut depth, mut in_char, mut escaped) = (0, false, false);
+        for (i, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_char => escaped = true,
+                '\'' => in_char = !in_char,
+                _ if in_char => (),
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' | ']' if depth == 0 => {
+                    self.at += i;
+                    return Ok(rest[..i].trim());
+                }
+                _ => (),
+            }
+        }
+        Err(self.error("unterminated row"))
+    }
+
+    fn row(&mut self) -> Result<Vec<Key>, ParseError> {
+        self.expect('[')?;
+        let mut keys = Vec::new();
+        while !self.eat(']') {
+            keys.push(parse_key(self.item()?));
+            self.eat(',');
+        }
+        if keys.len() != COLS as usize {
+            return Err(self.error(&format!("row has {} keys instead of {COLS}", keys.len())));
+        }
+        Ok(keys)
+    }
+
+    fn layer(&mut self, index: usize) -> Result<Layer, ParseError> {
+        self.skip();
+        if !self.rest().starts_with('[') {
+            // A layer built in code, named after its function
+            let code = self.item()?.to_string();
+            let name = code.trim_end_matches("()").trim_end_matches("_layer");
+            return Ok(Layer {
+                name: name.replace('_', " "),
+                keys: vec![Key::No; ROWS as usize * COLS as usize],
+                code: Some(code),
+            });
+        }
+        self.expect('[')?;
+        let name = self.skip().unwrap_or_else(|| format!("layer {index}"));
+        let mut keys = Vec::new();
+        for _ in 0..ROWS {
+            keys.extend(self.row()?);
+            self.eat(',');
+        }
+        self.expect(']')?;
+        Ok(Layer {
+            name,
+            keys,
+            code: None,
+        })
+    }
+}
+
+/// Reads the layers of `keymap`, their names are the comments
+/// after their opening brackets. A layer built in code, as `leader_layer()`,
+/// is named after its function.
+pub fn read(text: &str) -> Result<Vec<Layer>, ParseError> {
+    let start = text
+        .find("fn keymap<")
+        .ok_or_else(|| ParseError::new(text, 0, "no keymap"))?;
+    let body = start
+        + text[start..]
+            .find('{')
+            .ok_or_else(|| ParseError::new(text, start, "keymap has no body"))?;
+    let mut scanner = Scanner { text, at: body + 1 };
+    scanner.expect('[')?;
+    let mut layers = Vec::new();
+    while !scanner.eat(']') {
+        layers.push(scanner.layer(layers.len())?);
+        scanner.eat(',');
+    }
+    Ok(layers)
+}
+
+/// `keymap` with these layers, to replace the one in
+/// `src/keymap.rs`. `NUM_LAYER` has to match the number of layers.
+pub fn write(layers: &[Layer]) -> String {
+    let mut out = String::new();
+    out.push_str("#[rustfmt::skip]\n");
+    out.push_str(
+        "pub const fn keymap<const LAYOUT: u8>() -> [[[KeyAction; COL]; ROW]; NUM_LAYER] {\n",
+    );
+    out.push_str("    [\n");
+    for layer in layers {
+        if let Some(code) = &layer.code {
+            out.push_str(&format!("        {code},\n"));
+            continue;
+        }
+        out.push_str(&format!("        [ // {}\n", layer.name));
+        for row in layer.keys.chunks(COLS as usize) {
+            // The importers only make keys that have a text
+            let keys: Vec<String> = row
+                .iter()
+                .map(|key| key_text(key).unwrap_or_else(|| "k!(No)".into()))
+                .collect();
+            out.push_str(&format!("            [{}],\n", keys.join(", ")));
+        }
+        out.push_str("        ],\n");
+    }
+    out.push_str("    ]\n");
+    out.push_str("}\n");
+    out
+}
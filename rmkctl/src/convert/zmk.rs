@@ -0,0 +1,490 @@
+//! ZMK's devicetree `.keymap`. Only the keymap node and hold-tap behaviors
+//! are read, the rest of the file (combos, macros, ...) is left alone.
+
+use std::collections::HashMap;
+
+use super::{Converted, Key, Layer, Modifier, PHYSICAL_KEYS, ParseError, layer_from_physical};
+use crate::keycode;
+
+/// Functions that send a key with a modifier, in `Modifier` order
+const MODIFIER_FUNCTIONS: [&str; 8] = ["LC", "LS", "LA", "LG", "RC", "RS", "RA", "RG"];
+
+fn basic(name: &str) -> Option<u16> {
+    keycode::from_zmk(name).filter(|&code| code > 1)
+}
+
+fn basic_name(code: u16) -> Option<&'static str> {
+    keycode::zmk_name(code)
+}
+
+/// `LSHFT`, `LCTRL`, ... as modifiers
+fn modifier(name: &str) -> Option<Modifier> {
+    let code = basic(name)?;
+    let i = code.checked_sub(Modifier::LCtrl.keycode())?;
+    Modifier::ALL.get(i as usize).copied()
+}
+
+fn modifier_name(modifier: Modifier) -> &'static str {
+    keycode::zmk_name(modifier.keycode()).unwrap_or_default()
+}
+
+/// The parameter of `&kp`: `A` or `LS(N1)`
+fn kp(param: &str) -> Option<Key> {
+    if let Some((function, inner)) = param.split_once('(') {
+        let inner = inner.strip_suffix(')')?;
+        let i = MODIFIER_FUNCTIONS.iter().position(|f| *f == function)?;
+        return Some(Key::WithModifier(basic(inner)?, Modifier::ALL[i]));
+    }
+    basic(param).map(Key::Key)
+}
+
+/// What a hold-tap behavior holds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum HoldTap {
+    Modifier,
+    Layer,
+}
+
+/// A node of the devicetree, only as far as this needs it
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    label: Option<String>,
+    /// Property values as written, `<&kp>, <&kp>` is two values
+    props: Vec<(String, Vec<String>)>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn prop(&self, name: &str) -> Option<&[String]> {
+        self.props
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.prop(name)?
+            .first()?
+            .strip_prefix('"')?
+            .strip_suffix('"')
+    }
+
+    fn walk<'a>(&'a self, found: &mut Vec<&'a Node>) {
+        found.push(self);
+        self.children.iter().for_each(|child| child.walk(found));
+    }
+}
+
+/// Comments and preprocessor lines removed, with the `#define`s collected.
+/// Lines keep their place so errors point at the right one.
+fn preprocess(text: &str) -> (String, HashMap<String, String>) {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                out.push(c);
+            }
+            '/' if !in_string && chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if !in_string && chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    let mut defines = HashMap::new();
+    let lines: Vec<String> = out
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let directive = [
+                "#include", "#define", "#if", "#ifdef", "#ifndef", "#else", "#endif", "#undef",
+            ]
+            .iter()
+            .any(|d| trimmed.starts_with(d));
+            if !directive {
+                return line.to_string();
+            }
+            if let Some(define) = trimmed.strip_prefix("#define") {
+                let mut parts = define.split_whitespace();
+                if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                    defines.insert(name.to_string(), value.to_string());
+                }
+            }
+            String::new()
+        })
+        .collect();
+    (lines.join("\n"), defines)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError::new(self.text, self.at, message)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.at..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.at += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        if self.rest().starts_with(c) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{c}`")))
+        }
+    }
+
+    /// Node, label or property name
+    fn name(&mut self) -> Result<String, ParseError> {
+        self.skip_space();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| {
+                c.is_whitespace() || matches!(c, '{' | '}' | ';' | '=' | ':' | '<' | '"' | ',')
+            })
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.at += len;
+        Ok(rest[..len].to_string())
+    }
+
+    /// Up to `end`, which is consumed
+    fn until(&mut self, end: char) -> Result<String, ParseError> {
+        let start = self.at;
+        let len = self.rest()[1..]
+            .find(end)
+            .ok_or_else(|| self.error(&format!("missing `{end}`")))?;
+        self.at += len + 2;
+        Ok(self.text[start..self.at].to_string())
+    }
+
+    fn values(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_space();
+            let value = if self.rest().starts_with('<') {
+                self.until('>')?
+            } else if self.rest().starts_with('"') {
+                self.until('"')?
+            } else {
+                self.name()?
+            };
+            values.push(value);
+            if self.eat(';') {
+                return Ok(values);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    /// The contents of a node after its `{`, up to and with `};`
+    fn body(&mut self, node: &mut Node) -> Result<(), ParseError> {
+        loop {
+            if self.eat('}') {
+                self.expect(';')?;
+                return Ok(());
+            }
+            let mut name = self.name()?;
+            let mut label = None;
+            if self.eat(':') {
+                label = Some(name);
+                name = self.name()?;
+            }
+            if self.eat('{') {
+                let mut child = Node {
+                    name,
+                    label,
+                    ..Node::default()
+                };
+                self.body(&mut child)?;
+                node.children.push(child);
+            } else if self.eat('=') {
+                node.props.push((name, self.values()?));
+            } else {
+                self.expect(';')?;
+                node.props.push((name, Vec::new()));
+            }
+        }
+    }
+
+    fn file(&mut self) -> Result<Node, ParseError> {
+        let mut root = Node::default();
+        loop {
+            self.skip_space();
+            if self.rest().is_empty() {
+                return Ok(root);
+            }
+            let name = self.name()?;
+            if self.eat(';') {
+                // `/dts-v1/;` and the like
+                continue;
+            }
+            self.expect('{')?;
+            let mut node = Node {
+                name,
+                ..Node::default()
+            };
+            self.body(&mut node)?;
+            root.children.push(node);
+        }
+    }
+}
+
+/// The behaviors of a `bindings` cell list: `<&kp A &mt LSHFT F>` into
+/// `[["&kp", "A"], ["&mt", "LSHFT", "F"]]`
+fn behaviors(cells: &str, defines: &HashMap<String, String>) -> Vec<Vec<String>> {
+    let cells = cells.trim_start_matches('<').trim_end_matches('>');
+    let mut behaviors: Vec<Vec<String>> = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0;
+    for c in cells.chars().chain([' ']) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => (),
+        }
+        if c.is_whitespace() && depth == 0 {
+            if word.is_empty() {
+                continue;
+            }
+            let word = std::mem::take(&mut word);
+            let word = defines.get(&word).cloned().unwrap_or(word);
+            match behaviors.last_mut() {
+                Some(behavior) if !word.starts_with('&') => behavior.push(word),
+                _ => behaviors.push(vec![word]),
+            }
+        } else if !c.is_whitespace() {
+            word.push(c);
+        }
+    }
+    behaviors
+}
+
+fn parse_key(behavior: &[String], hold_taps: &HashMap<String, HoldTap>) -> Option<Key> {
+    let layer = |l: &String| l.parse().ok();
+    let (name, params) = behavior.split_first()?;
+    let hold_tap = name
+        .strip_prefix('&')
+        .and_then(|label| hold_taps.get(label));
+    match (name.as_str(), params) {
+        ("&none", []) => Some(Key::No),
+        ("&trans", []) => Some(Key::Transparent),
+        ("&bootloader", []) => Some(Key::Bootloader),
+        ("&sys_reset", []) => Some(Key::Reboot),
+        ("&kp", [key]) => kp(key),
+        ("&mo", [l]) => layer(l).map(Key::Momentary),
+        ("&to", [l]) => layer(l).map(Key::To),
+        ("&tog", [l]) => layer(l).map(Key::Toggle),
+        ("&sl", [l]) => layer(l).map(Key::OneShotLayer),
+        ("&sk", [m]) => modifier(m).map(Key::OneShotModifier),
+        ("&mt", [m, k]) => Some(Key::ModTap(basic(k)?, modifier(m)?)),
+        ("&lt", [l, k]) => Some(Key::LayerTap(basic(k)?, layer(l)?)),
+        (_, [hold, tap]) => match hold_tap? {
+            HoldTap::Modifier => Some(Key::ModTap(basic(tap)?, modifier(hold)?)),
+            HoldTap::Layer => Some(Key::LayerTap(basic(tap)?, layer(hold)?)),
+        },
+        _ => None,
+    }
+}
+
+/// Reads the layers of the `zmk,keymap` node. Hold-tap behaviors of the file
+/// holding `&kp` or `&mo` (home row mods and the like) are read as mod-taps
+/// and layer-taps.
+pub fn import(text: &str) -> Result<Converted<Vec<Layer>>, ParseError> {
+    let (text, defines) = preprocess(text);
+    let mut parser = Parser { text: &text, at: 0 };
+    let root = parser.file()?;
+    let mut nodes = Vec::new();
+    root.walk(&mut nodes);
+
+    let mut hold_taps = HashMap::new();
+    for node in &nodes {
+        if node.string("compatible") != Some("zmk,behavior-hold-tap") {
+            continue;
+        }
+        let (Some(label), Some(bindings)) = (&node.label, node.prop("bindings")) else {
+            continue;
+        };
+        let hold = bindings.first().map(|b| b.trim_matches(['<', '>', ' ']));
+        match hold {
+            Some("&kp") => hold_taps.insert(label.clone(), HoldTap::Modifier),
+            Some("&mo") => hold_taps.insert(label.clone(), HoldTap::Layer),
+            _ => None,
+        };
+    }
+
+    let keymap = nodes
+        .iter()
+        .find(|node| node.string("compatible") == Some("zmk,keymap"))
+        .ok_or_else(|| ParseError::new(&text, 0, "no zmk,keymap node"))?;
+    let mut converted = Converted::<Vec<Layer>>::default();
+    for (i, layer) in keymap.children.iter().enumerate() {
+        let Some(bindings) = layer.prop("bindings") else {
+            continue;
+        };
+        let behaviors = behaviors(&bindings.join(" "), &defines);
+        if behaviors.len() != PHYSICAL_KEYS {
+            return Err(ParseError::new(
+                &text,
+                0,
+                format!(
+                    "layer {} has {} bindings instead of {PHYSICAL_KEYS}",
+                    layer.name,
+                    behaviors.len()
+                ),
+            ));
+        }
+        let keys = behaviors
+            .iter()
+            .enumerate()
+            .map(|(index, behavior)| {
+                parse_key(behavior, &hold_taps).unwrap_or_else(|| {
+                    let text = behavior.join(" ");
+                    converted.issue(
+                        i,
+                        index,
+                        format!("{text} has no equivalent, imported as k!(No)"),
+                    );
+                    Key::No
+                })
+            })
+            .collect();
+        let name = layer
+            .string("display-name")
+            .or_else(|| layer.string("label"))
+            .map(str::to_string)
+            .unwrap_or_else(|| layer.name.trim_end_matches("_layer").to_string());
+        converted.value.push(layer_from_physical(name, keys));
+    }
+    Ok(converted)
+}
+
+/// How a key is written in ZMK, `None` if ZMK has no equivalent
+pub fn key_text(key: &Key) -> Option<String> {
+    Some(match key {
+        Key::No => "&none".into(),
+        Key::Transparent => "&trans".into(),
+        Key::Key(k) => format!("&kp {}", basic_name(*k)?),
+        Key::WithModifier(k, m) => {
+            format!(
+                "&kp {}({})",
+                MODIFIER_FUNCTIONS[*m as usize],
+                basic_name(*k)?
+            )
+        }
+        Key::ModTap(k, m) => format!("&mt {} {}", modifier_name(*m), basic_name(*k)?),
+        Key::LayerTap(k, l) => format!("&lt {l} {}", basic_name(*k)?),
+        Key::Momentary(l) => format!("&mo {l}"),
+        Key::To(l) => format!("&to {l}"),
+        Key::Toggle(l) => format!("&tog {l}"),
+        Key::OneShotLayer(l) => format!("&sl {l}"),
+        Key::OneShotModifier(m) => format!("&sk {}", modifier_name(*m)),
+        Key::Bootloader => "&bootloader".into(),
+        Key::Reboot => "&sys_reset".into(),
+        // ZMK has no default layer
+        Key::Default(_) | Key::Other(_) => return None,
+    })
+}
+
+/// `gaming base` as a node name, `gaming_base_layer`
+fn node_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{name}_layer")
+}
+
+/// Writes `layers` as a `.keymap` with only the keymap node
+pub fn export(layers: &[Layer]) -> Converted<String> {
+    let mut converted = Converted::<String>::default();
+    let mut out = String::new();
+    out.push_str("/* Exported from the RMK Corne keymap */\n\n");
+    out.push_str("#include <behaviors.dtsi>\n");
+    out.push_str("#include <dt-bindings/zmk/keys.h>\n\n");
+    out.push_str("/ {\n");
+    out.push_str("    keymap {\n");
+    out.push_str("        compatible = \"zmk,keymap\";\n");
+    for (i, layer) in layers.iter().enumerate() {
+        if let Some(code) = &layer.code {
+            converted.code_issue(i, code, "&none");
+        }
+        let bindings: Vec<String> = layer
+            .physical_keys()
+            .map(|(index, key)| {
+                key_text(key).unwrap_or_else(|| {
+                    let source = super::rust::key_text(key).unwrap_or_default();
+                    converted.issue(
+                        i,
+                        index,
+                        format!("{source} has no ZMK equivalent, exported as &none"),
+                    );
+                    "&none".into()
+                })
+            })
+            .collect();
+        let width = bindings.iter().map(String::len).max().unwrap_or(0);
+        out.push('\n');
+        out.push_str(&format!("        {} {{\n", node_name(&layer.name)));
+        out.push_str(&format!("            display-name = \"{}\";\n", layer.name));
+        out.push_str("            bindings = <\n");
+        for (row, chunk) in bindings.chunks(12).enumerate() {
+            // The thumbs sit below the inner three columns of each half
+            let indent = 16 + if row == 3 { 3 * (width + 2) } else { 0 };
+            let cells: Vec<String> = chunk.iter().map(|b| format!("{b:width$}")).collect();
+            out.push_str(&format!("{:indent$}{}\n", "", cells.join("  ").trim_end()));
+        }
+        out.push_str("            >;\n");
+        out.push_str("        };\n");
+    }
+    out.push_str("    };\n");
+    out.push_str("};\n");
+    converted.value = out;
+    converted
+}
@@ -0,0 +1,114 @@
+//! Raw HID access through Linux hidraw, no libraries needed.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `vid` in the `DeviceConfig` of `src/central.rs`
+pub const VENDOR_ID: u16 = 0x4c4b;
+/// `pid` in the `DeviceConfig` of `src/central.rs`
+pub const PRODUCT_ID: u16 = 0x4643;
+
+/// Size of the raw HID reports, in both directions
+pub const REPORT_LEN: usize = 32;
+
+/// Usage page of the raw HID interface, as the descriptor item `06 60 FF`
+const RAW_HID_USAGE_PAGE: [u8; 3] = [0x06, 0x60, 0xff];
+
+/// Same value on every Linux architecture this runs on (not alpha, sparc or
+/// parisc)
+const O_NONBLOCK: i32 = 0o4000;
+
+/// A device exchanging fixed size raw HID reports
+pub trait HidDevice {
+    fn write_report(&mut self, report: &[u8; REPORT_LEN]) -> io::Result<()>;
+    /// Waits up to `timeout` for the next report
+    fn read_report(&mut self, timeout: Duration) -> io::Result<[u8; REPORT_LEN]>;
+}
+
+/// A hidraw node of the dongle
+pub struct Hidraw {
+    file: File,
+}
+
+impl Hidraw {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(O_NONBLOCK)
+            .open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl HidDevice for Hidraw {
+    fn write_report(&mut self, report: &[u8; REPORT_LEN]) -> io::Result<()> {
+        // The interface has no report ids, hidraw wants a zero in front
+        let mut buf = [0; REPORT_LEN + 1];
+        buf[1..].copy_from_slice(report);
+        self.file.write_all(&buf)
+    }
+
+    fn read_report(&mut self, timeout: Duration) -> io::Result<[u8; REPORT_LEN]> {
+        let deadline = Instant::now() + timeout;
+        let mut report = [0; REPORT_LEN];
+        loop {
+            match self.file.read(&mut report) {
+                Ok(_) => return Ok(report),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no answer from the dongle",
+                ));
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+}
+
+/// Parses the `HID_ID=0003:00004C4B:00004643` line of a hidraw uevent
+fn hid_id(uevent: &str) -> Option<(u16, u16)> {
+    let id = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("HID_ID="))?;
+    let mut parts = id.split(':').skip(1);
+    let vid = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let pid = u32::from_str_radix(parts.next()?, 16).ok()?;
+    Some((vid as u16, pid as u16))
+}
+
+fn is_raw_hid(descriptor: &[u8]) -> bool {
+    descriptor
+        .windows(RAW_HID_USAGE_PAGE.len())
+        .any(|item| item == RAW_HID_USAGE_PAGE)
+}
+
+/// The `/dev/hidraw*` nodes of the raw HID interface of every connected dongle
+pub fn find_dongles() -> io::Result<Vec<PathBuf>> {
+    let mut dongles = Vec::new();
+    for entry in fs::read_dir("/sys/class/hidraw")? {
+        let entry = entry?;
+        let device = entry.path().join("device");
+        let Ok(uevent) = fs::read_to_string(device.join("uevent")) else {
+            continue;
+        };
+        if hid_id(&uevent) != Some((VENDOR_ID, PRODUCT_ID)) {
+            continue;
+        }
+        let Ok(descriptor) = fs::read(device.join("report_descriptor")) else {
+            continue;
+        };
+        if is_raw_hid(&descriptor) {
+            dongles.push(Path::new("/dev").join(entry.file_name()));
+        }
+    }
+    dongles.sort();
+    Ok(dongles)
+}
@@ -0,0 +1,420 @@
+//! Names of the basic keycodes, the ones Vial shows as plain keys, in QMK,
+//! in this firmware's keymap (rmk's `KeyCode`) and in ZMK. Everything else is
+//! shown and entered as hex.
+
+/// QMK's keycode (which rmk and Vial use too), its QMK names, the name of
+/// rmk's `KeyCode` variant and its ZMK names. The first of several names is
+/// the one written out, the others are read as well. ZMK has behaviors
+/// instead of keys for the first two.
+type Key = (
+    u16,
+    &'static [&'static str],
+    &'static str,
+    &'static [&'static str],
+);
+
+const KEYS: &[Key] = &[
+    (0x0000, &["KC_NO", "XXXXXXX"], "No", &[]),
+    (
+        0x0001,
+        &["KC_TRNS", "KC_TRANSPARENT", "_______"],
+        "Transparent",
+        &[],
+    ),
+    (0x0004, &["KC_A"], "A", &["A"]),
+    (0x0005, &["KC_B"], "B", &["B"]),
+    (0x0006, &["KC_C"], "C", &["C"]),
+    (0x0007, &["KC_D"], "D", &["D"]),
+    (0x0008, &["KC_E"], "E", &["E"]),
+    (0x0009, &["KC_F"], "F", &["F"]),
+    (0x000a, &["KC_G"], "G", &["G"]),
+    (0x000b, &["KC_H"], "H", &["H"]),
+    (0x000c, &["KC_I"], "I", &["I"]),
+    (0x000d, &["KC_J"], "J", &["J"]),
+    (0x000e, &["KC_K"], "K", &["K"]),
+    (0x000f, &["KC_L"], "L", &["L"]),
+    (0x0010, &["KC_M"], "M", &["M"]),
+    (0x0011, &["KC_N"], "N", &["N"]),
+    (0x0012, &["KC_O"], "O", &["O"]),
+    (0x0013, &["KC_P"], "P", &["P"]),
+    (0x0014, &["KC_Q"], "Q", &["Q"]),
+    (0x0015, &["KC_R"], "R", &["R"]),
+    (0x0016, &["KC_S"], "S", &["S"]),
+    (0x0017, &["KC_T"], "T", &["T"]),
+    (0x0018, &["KC_U"], "U", &["U"]),
+    (0x0019, &["KC_V"], "V", &["V"]),
+    (0x001a, &["KC_W"], "W", &["W"]),
+    (0x001b, &["KC_X"], "X", &["X"]),
+    (0x001c, &["KC_Y"], "Y", &["Y"]),
+    (0x001d, &["KC_Z"], "Z", &["Z"]),
+    (0x001e, &["KC_1"], "Kc1", &["N1", "NUMBER_1"]),
+    (0x001f, &["KC_2"], "Kc2", &["N2", "NUMBER_2"]),
+    (0x0020, &["KC_3"], "Kc3", &["N3", "NUMBER_3"]),
+    (0x0021, &["KC_4"], "Kc4", &["N4", "NUMBER_4"]),
+    (0x0022, &["KC_5"], "Kc5", &["N5", "NUMBER_5"]),
+    (0x0023, &["KC_6"], "Kc6", &["N6", "NUMBER_6"]),
+    (0x0024, &["KC_7"], "Kc7", &["N7", "NUMBER_7"]),
+    (0x0025, &["KC_8"], "Kc8", &["N8", "NUMBER_8"]),
+    (0x0026, &["KC_9"], "Kc9", &["N9", "NUMBER_9"]),
+    (0x0027, &["KC_0"], "Kc0", &["N0", "NUMBER_0"]),
+    (
+        0x0028,
+        &["KC_ENT", "KC_ENTER"],
+        "Enter",
+        &["RET", "ENTER", "RETURN"],
+    ),
+    (
+        0x0029,
+        &["KC_ESC", "KC_ESCAPE"],
+        "Escape",
+        &["ESC", "ESCAPE"],
+    ),
+    (
+        0x002a,
+        &["KC_BSPC", "KC_BACKSPACE"],
+        "Backspace",
+        &["BSPC", "BACKSPACE"],
+    ),
+    (0x002b, &["KC_TAB"], "Tab", &["TAB"]),
+    (0x002c, &["KC_SPC", "KC_SPACE"], "Space", &["SPACE", "SPC"]),
+    (0x002d, &["KC_MINS", "KC_MINUS"], "Minus", &["MINUS"]),
+    (0x002e, &["KC_EQL", "KC_EQUAL"], "Equal", &["EQUAL"]),
+    (
+        0x002f,
+        &["KC_LBRC", "KC_LEFT_BRACKET"],
+        "LeftBracket",
+        &["LBKT", "LEFT_BRACKET"],
+    ),
+    (
+        0x0030,
+        &["KC_RBRC", "KC_RIGHT_BRACKET"],
+        "RightBracket",
+        &["RBKT", "RIGHT_BRACKET"],
+    ),
+    (
+        0x0031,
+        &["KC_BSLS", "KC_BACKSLASH"],
+        "Backslash",
+        &["BSLH", "BACKSLASH"],
+    ),
+    (
+        0x0033,
+        &["KC_SCLN", "KC_SEMICOLON"],
+        "Semicolon",
+        &["SEMI", "SEMICOLON", "SCLN"],
+    ),
+    (
+        0x0034,
+        &["KC_QUOT", "KC_QUOTE"],
+        "Quote",
+        &["SQT", "APOS", "SINGLE_QUOTE", "APOSTROPHE"],
+    ),
+    (0x0035, &["KC_GRV", "KC_GRAVE"], "Grave", &["GRAVE"]),
+    (0x0036, &["KC_COMM", "KC_COMMA"], "Comma", &["COMMA"]),
+    (0x0037, &["KC_DOT"], "Dot", &["DOT", "PERIOD"]),
+    (
+        0x0038,
+        &["KC_SLSH", "KC_SLASH"],
+        "Slash",
+        &["FSLH", "SLASH"],
+    ),
+    (
+        0x0039,
+        &["KC_CAPS", "KC_CAPS_LOCK"],
+        "CapsLock",
+        &["CAPS", "CAPSLOCK", "CLCK"],
+    ),
+    (0x003a, &["KC_F1"], "F1", &["F1"]),
+    (0x003b, &["KC_F2"], "F2", &["F2"]),
+    (0x003c, &["KC_F3"], "F3", &["F3"]),
+    (0x003d, &["KC_F4"], "F4", &["F4"]),
+    (0x003e, &["KC_F5"], "F5", &["F5"]),
+    (0x003f, &["KC_F6"], "F6", &["F6"]),
+    (0x0040, &["KC_F7"], "F7", &["F7"]),
+    (0x0041, &["KC_F8"], "F8", &["F8"]),
+    (0x0042, &["KC_F9"], "F9", &["F9"]),
+    (0x0043, &["KC_F10"], "F10", &["F10"]),
+    (0x0044, &["KC_F11"], "F11", &["F11"]),
+    (0x0045, &["KC_F12"], "F12", &["F12"]),
+    (
+        0x0049,
+        &["KC_INS", "KC_INSERT"],
+        "Insert",
+        &["INS", "INSERT"],
+    ),
+    (0x004a, &["KC_HOME"], "Home", &["HOME"]),
+    (
+        0x004b,
+        &["KC_PGUP", "KC_PAGE_UP"],
+        "PageUp",
+        &["PG_UP", "PAGE_UP"],
+    ),
+    (
+        0x004c,
+        &["KC_DEL", "KC_DELETE"],
+        "Delete",
+        &["DEL", "DELETE"],
+    ),
+    (0x004d, &["KC_END"], "End", &["END"]),
+    (
+        0x004e,
+        &["KC_PGDN", "KC_PAGE_DOWN"],
+        "PageDown",
+        &["PG_DN", "PAGE_DOWN"],
+    ),
+    (
+        0x004f,
+        &["KC_RGHT", "KC_RIGHT"],
+        "Right",
+        &["RIGHT", "RARW"],
+    ),
+    (0x0050, &["KC_LEFT"], "Left", &["LEFT", "LARW"]),
+    (0x0051, &["KC_DOWN"], "Down", &["DOWN", "DARW"]),
+    (0x0052, &["KC_UP"], "Up", &["UP", "UARW"]),
+    (0x0057, &["KC_PPLS", "KC_KP_PLUS"], "KpPlus", &["KP_PLUS"]),
+    (
+        0x0059,
+        &["KC_P1", "KC_KP_1"],
+        "Kp1",
+        &["KP_N1", "KP_NUMBER_1"],
+    ),
+    (
+        0x005a,
+        &["KC_P2", "KC_KP_2"],
+        "Kp2",
+        &["KP_N2", "KP_NUMBER_2"],
+    ),
+    (
+        0x005b,
+        &["KC_P3", "KC_KP_3"],
+        "Kp3",
+        &["KP_N3", "KP_NUMBER_3"],
+    ),
+    (
+        0x005c,
+        &["KC_P4", "KC_KP_4"],
+        "Kp4",
+        &["KP_N4", "KP_NUMBER_4"],
+    ),
+    (
+        0x005d,
+        &["KC_P5", "KC_KP_5"],
+        "Kp5",
+        &["KP_N5", "KP_NUMBER_5"],
+    ),
+    (
+        0x005e,
+        &["KC_P6", "KC_KP_6"],
+        "Kp6",
+        &["KP_N6", "KP_NUMBER_6"],
+    ),
+    (
+        0x005f,
+        &["KC_P7", "KC_KP_7"],
+        "Kp7",
+        &["KP_N7", "KP_NUMBER_7"],
+    ),
+    (
+        0x0060,
+        &["KC_P8", "KC_KP_8"],
+        "Kp8",
+        &["KP_N8", "KP_NUMBER_8"],
+    ),
+    (
+        0x0061,
+        &["KC_P9", "KC_KP_9"],
+        "Kp9",
+        &["KP_N9", "KP_NUMBER_9"],
+    ),
+    (
+        0x0062,
+        &["KC_P0", "KC_KP_0"],
+        "Kp0",
+        &["KP_N0", "KP_NUMBER_0"],
+    ),
+    (
+        0x0067,
+        &["KC_PEQL", "KC_KP_EQUAL"],
+        "KpEqual",
+        &["KP_EQUAL"],
+    ),
+    (
+        0x00a5,
+        &["KC_PWR", "KC_SYSTEM_POWER"],
+        "SystemPower",
+        &["SYS_PWR", "SYSTEM_POWER"],
+    ),
+    (
+        0x00a6,
+        &["KC_SLEP", "KC_SYSTEM_SLEEP"],
+        "SystemSleep",
+        &["SYS_SLEEP", "SYSTEM_SLEEP"],
+    ),
+    (
+        0x00a7,
+        &["KC_WAKE", "KC_SYSTEM_WAKE"],
+        "SystemWake",
+        &["SYS_WAKE", "SYSTEM_WAKE_UP"],
+    ),
+    (
+        0x00a8,
+        &["KC_MUTE", "KC_AUDIO_MUTE"],
+        "AudioMute",
+        &["C_MUTE"],
+    ),
+    (
+        0x00a9,
+        &["KC_VOLU", "KC_AUDIO_VOL_UP"],
+        "AudioVolUp",
+        &["C_VOL_UP", "C_VOLUME_UP"],
+    ),
+    (
+        0x00aa,
+        &["KC_VOLD", "KC_AUDIO_VOL_DOWN"],
+        "AudioVolDown",
+        &["C_VOL_DN", "C_VOLUME_DOWN"],
+    ),
+    (
+        0x00ab,
+        &["KC_MNXT", "KC_MEDIA_NEXT_TRACK"],
+        "MediaNextTrack",
+        &["C_NEXT"],
+    ),
+    (
+        0x00ac,
+        &["KC_MPRV", "KC_MEDIA_PREV_TRACK"],
+        "MediaPrevTrack",
+        &["C_PREV"],
+    ),
+    (
+        0x00ad,
+        &["KC_MSTP", "KC_MEDIA_STOP"],
+        "MediaStop",
+        &["C_STOP"],
+    ),
+    (
+        0x00ae,
+        &["KC_MPLY", "KC_MEDIA_PLAY_PAUSE"],
+        "MediaPlayPause",
+        &["C_PP", "C_PLAY_PAUSE"],
+    ),
+    (
+        0x00bd,
+        &["KC_BRIU", "KC_BRIGHTNESS_UP"],
+        "BrightnessUp",
+        &["C_BRI_UP", "C_BRIGHTNESS_INC"],
+    ),
+    (
+        0x00be,
+        &["KC_BRID", "KC_BRIGHTNESS_DOWN"],
+        "BrightnessDown",
+        &["C_BRI_DN", "C_BRIGHTNESS_DEC"],
+    ),
+    (
+        0x00e0,
+        &["KC_LCTL", "KC_LEFT_CTRL"],
+        "LCtrl",
+        &["LCTRL", "LEFT_CONTROL"],
+    ),
+    (
+        0x00e1,
+        &["KC_LSFT", "KC_LEFT_SHIFT"],
+        "LShift",
+        &["LSHFT", "LSHIFT", "LEFT_SHIFT"],
+    ),
+    (
+        0x00e2,
+        &["KC_LALT", "KC_LEFT_ALT"],
+        "LAlt",
+        &["LALT", "LEFT_ALT"],
+    ),
+    (
+        0x00e3,
+        &["KC_LGUI", "KC_LEFT_GUI"],
+        "LGui",
+        &["LGUI", "LEFT_GUI", "LCMD", "LWIN"],
+    ),
+    (
+        0x00e4,
+        &["KC_RCTL", "KC_RIGHT_CTRL"],
+        "RCtrl",
+        &["RCTRL", "RIGHT_CONTROL"],
+    ),
+    (
+        0x00e5,
+        &["KC_RSFT", "KC_RIGHT_SHIFT"],
+        "RShift",
+        &["RSHFT", "RSHIFT", "RIGHT_SHIFT"],
+    ),
+    (
+        0x00e6,
+        &["KC_RALT", "KC_RIGHT_ALT"],
+        "RAlt",
+        &["RALT", "RIGHT_ALT"],
+    ),
+    (
+        0x00e7,
+        &["KC_RGUI", "KC_RIGHT_GUI"],
+        "RGui",
+        &["RGUI", "RIGHT_GUI", "RCMD", "RWIN"],
+    ),
+];
+
+fn find(keycode: u16) -> Option<&'static Key> {
+    KEYS.iter().find(|key| key.0 == keycode)
+}
+
+/// `KC_A` for a basic keycode, `0x5220` for the rest
+pub fn name(keycode: u16) -> String {
+    match qmk_name(keycode) {
+        Some(name) => name.to_string(),
+        None => format!("{keycode:#06x}"),
+    }
+}
+
+/// Reads a keycode name (case insensitive, `KC_` optional) or a hex value
+pub fn parse(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    let text = text.to_ascii_uppercase();
+    let text = text.strip_prefix("KC_").unwrap_or(&text);
+    KEYS.iter()
+        .find(|key| {
+            key.1
+                .iter()
+                .any(|name| name.strip_prefix("KC_").unwrap_or(name) == text)
+        })
+        .map(|key| key.0)
+}
+
+pub fn qmk_name(keycode: u16) -> Option<&'static str> {
+    find(keycode).map(|key| key.1[0])
+}
+
+/// The keycode of a QMK name as written in a keymap, e.g. `KC_ENTER`
+pub fn from_qmk(name: &str) -> Option<u16> {
+    KEYS.iter()
+        .find(|key| key.1.contains(&name))
+        .map(|key| key.0)
+}
+
+/// Name of the `KeyCode` variant, as in `k!(LeftBracket)`
+pub fn rmk_name(keycode: u16) -> Option<&'static str> {
+    find(keycode).map(|key| key.2)
+}
+
+pub fn from_rmk(name: &str) -> Option<u16> {
+    KEYS.iter().find(|key| key.2 == name).map(|key| key.0)
+}
+
+pub fn zmk_name(keycode: u16) -> Option<&'static str> {
+    find(keycode).and_then(|key| key.3.first().copied())
+}
+
+/// The keycode of a ZMK name as written after `&kp`, e.g. `LBKT`
+pub fn from_zmk(name: &str) -> Option<u16> {
+    KEYS.iter()
+        .find(|key| key.3.contains(&name))
+        .map(|key| key.0)
+}
@@ -0,0 +1,13 @@
+//! Host side of the RMK Corne dongle: finds it over raw HID and speaks the
+//! VIA/Vial protocol its firmware answers. Also converts the keymap from and
+//! to QMK and ZMK.
+
+pub mod convert;
+pub mod hid;
+pub mod keycode;
+pub mod via;
+
+/// `ROW` in `src/keymap.rs`
+pub const ROWS: u8 = 4;
+/// `COL` in `src/keymap.rs`
+pub const COLS: u8 = 12;
@@ -0,0 +1,227 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use rmkctl::convert::{self, Converted, qmk, rust, zmk};
+use rmkctl::hid::{self, Hidraw};
+use rmkctl::via::Client;
+use rmkctl::{COLS, ROWS, keycode};
+
+const USAGE: &str = "\
+usage: rmkctl [--device /dev/hidrawN] <command>
+
+commands:
+  list                          connected dongles
+  info                          protocol, uptime, layers, Vial lock
+  keymap [layer]                print the keymap
+  get <layer> <row> <col>       print one key
+  set <layer> <row> <col> <key> change one key, e.g. KC_A or 0x5220
+  status                        halves, battery levels, layers, BLE profile
+  default-layer [layer]         print or set the default layer
+  toggle-layer <layer>          turn num (1), nav (2), mouse (6) or media (7)
+                                on or off
+  profile [profile]             print or switch the BLE profile of the host
+  stats                         key presses since the dongle started, as CSV
+  bootloader                    reboot the dongle to its bootloader
+  import <keymap.json|*.keymap> QMK or ZMK keymap as keymap
+  export <qmk|zmk> <keymap.rs>  src/keymap.rs as QMK keymap.json or ZMK .keymap";
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn number(arg: Option<&String>, what: &str) -> Result<u8> {
+    let arg = arg.ok_or_else(|| format!("missing {what}"))?;
+    Ok(arg.parse().map_err(|_| format!("bad {what}: {arg}"))?)
+}
+
+fn open(device: Option<PathBuf>) -> Result<Client<Hidraw>> {
+    let path = match device {
+        Some(path) => path,
+        None => {
+            let mut dongles = hid::find_dongles()?;
+            match dongles.len() {
+                0 => return Err("no dongle found".into()),
+                1 => dongles.remove(0),
+                _ => return Err("more than one dongle, pick one with --device".into()),
+            }
+        }
+    };
+    let device = Hidraw::open(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(Client::new(device))
+}
+
+fn print_layer(keymap: &[u16], layer: u8) {
+    println!("layer {layer}");
+    let start = layer as usize * ROWS as usize * COLS as usize;
+    for row in keymap[start..].chunks(COLS as usize).take(ROWS as usize) {
+        let keys: Vec<String> = row
+            .iter()
+            .map(|&k| format!("{:>8}", keycode::name(k)))
+            .collect();
+        println!("{}", keys.join(" "));
+    }
+}
+
+fn read(path: &str) -> Result<String> {
+    Ok(fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?)
+}
+
+/// Prints what converted to stdout and what didn't to stderr
+fn print_converted(converted: Converted<String>) {
+    print!("{}", converted.value);
+    for issue in &converted.issues {
+        eprintln!("{issue}");
+    }
+}
+
+fn import(path: &str) -> Result<()> {
+    let text = read(path)?;
+    let imported = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("json") => qmk::import(&text),
+        Some("keymap") => zmk::import(&text),
+        _ => return Err(format!("{path}: expected a QMK .json or a ZMK .keymap").into()),
+    }
+    .map_err(|e| format!("{path}: {e}"))?;
+    print_converted(Converted {
+        value: rust::write(&imported.value),
+        issues: imported.issues,
+    });
+    Ok(())
+}
+
+fn export(format: &str, path: &str) -> Result<()> {
+    let layers = rust::read(&read(path)?).map_err(|e| format!("{path}: {e}"))?;
+    let exported: fn(&[convert::Layer]) -> Converted<String> = match format {
+        "qmk" => qmk::export,
+        "zmk" => zmk::export,
+        _ => return Err(format!("unknown format: {format}, expected qmk or zmk").into()),
+    };
+    print_converted(exported(&layers));
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let (device, args) = match args {
+        [flag, path, rest @ ..] if flag == "--device" => (Some(PathBuf::from(path)), rest),
+        _ => (None, args),
+    };
+    let Some(command) = args.first() else {
+        return Err(USAGE.into());
+    };
+    match command.as_str() {
+        "list" => {
+            for path in hid::find_dongles()? {
+                println!("{}", path.display());
+            }
+        }
+        "info" => {
+            let mut client = open(device)?;
+            let id = client.keyboard_id()?;
+            println!("VIA protocol: {}", client.protocol_version()?);
+            println!("Vial protocol: {}", id.protocol);
+            println!("keyboard id: {:02x?}", id.uid);
+            println!("uptime: {}s", client.uptime()?.as_secs());
+            println!("layers: {}", client.layer_count()?);
+            let lock = if client.unlocked()? {
+                "unlocked"
+            } else {
+                "locked"
+            };
+            println!("Vial: {lock}");
+        }
+        "keymap" => {
+            let mut client = open(device)?;
+            let layers = client.layer_count()?;
+            let keymap = client.keymap(layers, ROWS, COLS)?;
+            match args.get(1) {
+                Some(_) => {
+                    let layer = number(args.get(1), "layer")?;
+                    if layer >= layers {
+                        return Err(format!("the keymap has {layers} layers").into());
+                    }
+                    print_layer(&keymap, layer);
+                }
+                None => (0..layers).for_each(|layer| print_layer(&keymap, layer)),
+            }
+        }
+        "get" => {
+            let (layer, row, col) = (
+                number(args.get(1), "layer")?,
+                number(args.get(2), "row")?,
+                number(args.get(3), "col")?,
+            );
+            let keycode = open(device)?.keycode(layer, row, col)?;
+            println!("{}", keycode::name(keycode));
+        }
+        "set" => {
+            let (layer, row, col) = (
+                number(args.get(1), "layer")?,
+                number(args.get(2), "row")?,
+                number(args.get(3), "col")?,
+            );
+            let key = args.get(4).ok_or("missing key")?;
+            let keycode = keycode::parse(key).ok_or_else(|| format!("unknown key: {key}"))?;
+            open(device)?.set_keycode(layer, row, col, keycode)?;
+        }
+        "status" => {
+            let mut client = open(device)?;
+            for (name, half) in ["left", "right"].iter().zip(client.halves()?) {
+                let link = if half.connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                };
+                let battery = match half.battery_percent {
+                    Some(percent) => format!("{percent}%"),
+                    None => "unknown".to_string(),
+                };
+                println!("{name}: {link}, battery {battery}");
+            }
+            println!("layer: {}", client.layer()?);
+            println!("default layer: {}", client.default_layer()?);
+            println!("host profile: {}", client.host_profile()?);
+        }
+        "default-layer" => {
+            let mut client = open(device)?;
+            match args.get(1) {
+                Some(_) => client.set_default_layer(number(args.get(1), "layer")?)?,
+                None => println!("{}", client.default_layer()?),
+            }
+        }
+        "toggle-layer" => open(device)?.toggle_layer(number(args.get(1), "layer")?)?,
+        "profile" => {
+            let mut client = open(device)?;
+            match args.get(1) {
+                Some(_) => client.switch_profile(number(args.get(1), "profile")?)?,
+                None => println!("{}", client.host_profile()?),
+            }
+        }
+        "stats" => {
+            let presses = open(device)?.key_presses(ROWS, COLS)?;
+            println!("row,col,presses");
+            for (i, count) in presses.iter().enumerate() {
+                let (row, col) = (i / COLS as usize, i % COLS as usize);
+                println!("{row},{col},{count}");
+            }
+        }
+        "bootloader" => open(device)?.bootloader()?,
+        "import" => import(args.get(1).ok_or("missing keymap file")?)?,
+        "export" => export(
+            args.get(1).ok_or("missing format")?,
+            args.get(2).ok_or("missing keymap.rs")?,
+        )?,
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
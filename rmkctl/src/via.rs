@@ -0,0 +1,253 @@
+//! The VIA/Vial raw HID protocol as the dongle's firmware (rmk) answers it,
+//! and the dongle's own custom values (`src/via.rs`).
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::hid::{HidDevice, REPORT_LEN};
+
+const GET_PROTOCOL_VERSION: u8 = 0x01;
+const GET_KEYBOARD_VALUE: u8 = 0x02;
+const DYNAMIC_KEYMAP_GET_KEYCODE: u8 = 0x04;
+const DYNAMIC_KEYMAP_SET_KEYCODE: u8 = 0x05;
+const CUSTOM_SET_VALUE: u8 = 0x07;
+const CUSTOM_GET_VALUE: u8 = 0x08;
+const BOOTLOADER_JUMP: u8 = 0x0b;
+const DYNAMIC_KEYMAP_GET_LAYER_COUNT: u8 = 0x11;
+const DYNAMIC_KEYMAP_GET_BUFFER: u8 = 0x12;
+const VIAL_PREFIX: u8 = 0xfe;
+/// The firmware's answer to a command it doesn't know
+const UNHANDLED: u8 = 0xff;
+
+const VALUE_UPTIME: u8 = 0x01;
+
+/// VIA's channel for the values of the keyboard itself, `CUSTOM_CHANNEL`
+const CUSTOM_CHANNEL: u8 = 0;
+// `CustomValue` in the firmware
+const CUSTOM_DEFAULT_LAYER: u8 = 0x01;
+const CUSTOM_LAYER: u8 = 0x02;
+const CUSTOM_HOST_PROFILE: u8 = 0x03;
+const CUSTOM_HALVES: u8 = 0x04;
+const CUSTOM_KEY_PRESSES: u8 = 0x05;
+
+/// Keys a get of the key presses answers for, `KEY_PRESSES_PER_GET`
+const KEY_PRESSES_PER_GET: u8 = 6;
+
+const VIAL_GET_KEYBOARD_ID: u8 = 0x00;
+const VIAL_GET_UNLOCK_STATUS: u8 = 0x05;
+
+/// Keymap bytes per `DYNAMIC_KEYMAP_GET_BUFFER` request
+const BUFFER_CHUNK: usize = REPORT_LEN - 4;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The firmware doesn't support the command
+    Unhandled(u8),
+    /// The answer doesn't belong to the command
+    BadResponse(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Unhandled(command) => {
+                write!(f, "the firmware doesn't support command {command:#04x}")
+            }
+            Error::BadResponse(command) => {
+                write!(f, "unexpected answer to command {command:#04x}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Identifies the keyboard to Vial, `VIAL_KEYBOARD_ID` in the firmware
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyboardId {
+    pub protocol: u32,
+    pub uid: [u8; 8],
+}
+
+/// What the dongle knows about a half
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HalfStatus {
+    pub connected: bool,
+    /// `None` until the half sent it since it connected
+    pub battery_percent: Option<u8>,
+}
+
+/// Talks to the dongle over a raw HID device
+pub struct Client<D> {
+    device: D,
+}
+
+impl<D: HidDevice> Client<D> {
+    pub fn new(device: D) -> Self {
+        Self { device }
+    }
+
+    pub fn into_device(self) -> D {
+        self.device
+    }
+
+    fn request(&mut self, command: &[u8]) -> Result<[u8; REPORT_LEN]> {
+        let mut report = [0; REPORT_LEN];
+        report[..command.len()].copy_from_slice(command);
+        self.device.write_report(&report)?;
+        let response = self.device.read_report(TIMEOUT)?;
+        if response[0] == UNHANDLED {
+            return Err(Error::Unhandled(command[0]));
+        }
+        // Vial answers without echoing the command
+        if command[0] != VIAL_PREFIX && response[0] != command[0] {
+            return Err(Error::BadResponse(command[0]));
+        }
+        Ok(response)
+    }
+
+    pub fn protocol_version(&mut self) -> Result<u16> {
+        let response = self.request(&[GET_PROTOCOL_VERSION])?;
+        Ok(u16::from_be_bytes([response[1], response[2]]))
+    }
+
+    pub fn uptime(&mut self) -> Result<Duration> {
+        let response = self.request(&[GET_KEYBOARD_VALUE, VALUE_UPTIME])?;
+        let ms = u32::from_be_bytes([response[2], response[3], response[4], response[5]]);
+        Ok(Duration::from_millis(ms.into()))
+    }
+
+    pub fn layer_count(&mut self) -> Result<u8> {
+        Ok(self.request(&[DYNAMIC_KEYMAP_GET_LAYER_COUNT])?[1])
+    }
+
+    pub fn keycode(&mut self, layer: u8, row: u8, col: u8) -> Result<u16> {
+        let response = self.request(&[DYNAMIC_KEYMAP_GET_KEYCODE, layer, row, col])?;
+        Ok(u16::from_be_bytes([response[4], response[5]]))
+    }
+
+    pub fn set_keycode(&mut self, layer: u8, row: u8, col: u8, keycode: u16) -> Result<()> {
+        let [high, low] = keycode.to_be_bytes();
+        self.request(&[DYNAMIC_KEYMAP_SET_KEYCODE, layer, row, col, high, low])?;
+        Ok(())
+    }
+
+    /// All keycodes of the keymap, layer by layer and row by row
+    pub fn keymap(&mut self, layers: u8, rows: u8, cols: u8) -> Result<Vec<u16>> {
+        let len = layers as usize * rows as usize * cols as usize * 2;
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let [high, low] = (bytes.len() as u16).to_be_bytes();
+            let size = BUFFER_CHUNK.min(len - bytes.len());
+            let response = self.request(&[DYNAMIC_KEYMAP_GET_BUFFER, high, low, size as u8])?;
+            bytes.extend_from_slice(&response[4..4 + size]);
+        }
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|keycode| u16::from_be_bytes([keycode[0], keycode[1]]))
+            .collect())
+    }
+
+    pub fn keyboard_id(&mut self) -> Result<KeyboardId> {
+        let response = self.request(&[VIAL_PREFIX, VIAL_GET_KEYBOARD_ID])?;
+        Ok(KeyboardId {
+            protocol: u32::from_le_bytes([response[0], response[1], response[2], response[3]]),
+            uid: response[4..12].try_into().unwrap(),
+        })
+    }
+
+    /// Whether the unlock combo (`VIAL_UNLOCK_KEYS`) was held
+    pub fn unlocked(&mut self) -> Result<bool> {
+        Ok(self.request(&[VIAL_PREFIX, VIAL_GET_UNLOCK_STATUS])?[0] == 1)
+    }
+
+    /// Data of a custom value, after the arguments `args`
+    fn custom_get(&mut self, value: u8, args: &[u8]) -> Result<[u8; REPORT_LEN - 3]> {
+        let mut request = vec![CUSTOM_GET_VALUE, CUSTOM_CHANNEL, value];
+        request.extend_from_slice(args);
+        let response = self.request(&request)?;
+        Ok(response[3..].try_into().unwrap())
+    }
+
+    /// The firmware answers a value it can't set to like an unknown command
+    fn custom_set(&mut self, value: u8, data: u8) -> Result<()> {
+        self.request(&[CUSTOM_SET_VALUE, CUSTOM_CHANNEL, value, data])?;
+        Ok(())
+    }
+
+    pub fn default_layer(&mut self) -> Result<u8> {
+        Ok(self.custom_get(CUSTOM_DEFAULT_LAYER, &[])?[0])
+    }
+
+    /// Like a `df!` key, the dongle keeps it across restarts
+    pub fn set_default_layer(&mut self, layer: u8) -> Result<()> {
+        self.custom_set(CUSTOM_DEFAULT_LAYER, layer)
+    }
+
+    /// The highest active layer
+    pub fn layer(&mut self) -> Result<u8> {
+        Ok(self.custom_get(CUSTOM_LAYER, &[])?[0])
+    }
+
+    /// Turns `layer` on or off, only for the layers of `REMOTE_LAYERS`
+    pub fn toggle_layer(&mut self, layer: u8) -> Result<()> {
+        self.custom_set(CUSTOM_LAYER, layer)
+    }
+
+    /// The BLE profile of the host link
+    pub fn host_profile(&mut self) -> Result<u8> {
+        Ok(self.custom_get(CUSTOM_HOST_PROFILE, &[])?[0])
+    }
+
+    pub fn switch_profile(&mut self, profile: u8) -> Result<()> {
+        self.custom_set(CUSTOM_HOST_PROFILE, profile)
+    }
+
+    /// The left half first
+    pub fn halves(&mut self) -> Result<[HalfStatus; 2]> {
+        let data = self.custom_get(CUSTOM_HALVES, &[])?;
+        Ok([0, 1].map(|half| HalfStatus {
+            connected: data[2 * half] != 0,
+            battery_percent: Some(data[2 * half + 1]).filter(|&percent| percent <= 100),
+        }))
+    }
+
+    /// How often each key was pressed since the dongle started, row by row
+    pub fn key_presses(&mut self, rows: u8, cols: u8) -> Result<Vec<u32>> {
+        let mut presses = Vec::with_capacity(rows as usize * cols as usize);
+        for row in 0..rows {
+            for first in (0..cols).step_by(KEY_PRESSES_PER_GET.into()) {
+                let data = self.custom_get(CUSTOM_KEY_PRESSES, &[row, first])?;
+                let count = KEY_PRESSES_PER_GET.min(cols - first) as usize;
+                presses.extend(
+                    data[2..]
+                        .chunks_exact(4)
+                        .take(count)
+                        .map(|count| u32::from_be_bytes(count.try_into().unwrap())),
+                );
+            }
+        }
+        Ok(presses)
+    }
+
+    /// The dongle resets right away and doesn't answer
+    pub fn bootloader(&mut self) -> Result<()> {
+        let mut report = [0; REPORT_LEN];
+        report[0] = BOOTLOADER_JUMP;
+        self.device.write_report(&report)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use rmkctl::convert::{Issue, Key, Modifier, position, qmk, rust, zmk};
+use rmkctl::keycode;
+
+const KEYMAP: &str = include_str!("../../src/keymap.rs");
+
+fn code(name: &str) -> u16 {
+    keycode::from_rmk(name).unwrap()
+}
+
+const QMK: &str = r#"{
+  "keyboard": "crkbd/rev1",
+  "layout": "LAYOUT_split_3x6_3",
+  "layers": [
+    [
+      "KC_TAB", "KC_Q", "KC_W", "KC_E", "KC_R", "KC_T", "KC_Y", "KC_U", "KC_I", "KC_O", "KC_P", "KC_BSPC",
+      "KC_LCTL", "LGUI_T(KC_A)", "KC_S", "KC_D", "KC_F", "KC_G", "KC_H", "KC_J", "KC_K", "KC_L", "KC_SCLN", "KC_QUOT",
+      "KC_LSFT", "KC_Z", "KC_X", "KC_C", "KC_V", "KC_B", "KC_N", "KC_M", "KC_COMM", "KC_DOT", "KC_SLSH", "KC_ESC",
+      "KC_LGUI", "MO(1)", "KC_SPC", "LT(1, KC_ENT)", "OSM(MOD_RALT)", "RGB_TOG"
+    ],
+    [
+      "_______", "KC_1", "KC_2", "KC_3", "KC_4", "KC_5", "KC_6", "KC_7", "KC_8", "KC_9", "KC_0", "QK_BOOT",
+      "XXXXXXX", "XXXXXXX", "XXXXXXX", "XXXXXXX", "XXXXXXX", "XXXXXXX", "KC_LEFT", "KC_DOWN", "KC_UP", "KC_RGHT", "XXXXXXX", "XXXXXXX",
+      "KC_TRNS", "XXXXXXX", "XXXXXXX", "XXXXXXX", "XXXXXXX", "XXXXXXX", "S(KC_1)", "TG(1)", "DF(0)", "OSL(1)", "TO(0)", "XXXXXXX",
+      "KC_TRNS", "KC_TRNS", "KC_TRNS", "KC_TRNS", "KC_TRNS", "KC_TRNS"
+    ]
+  ]
+}"#;
+
+#[test]
+fn qmk_import() {
+    let imported = qmk::import(QMK).unwrap();
+    let [base, lower] = imported.value.as_slice() else {
+        panic!("expected two layers");
+    };
+    assert_eq!(base.key(0, 1), &Key::Key(code("Q")));
+    assert_eq!(base.key(1, 1), &Key::ModTap(code("A"), Modifier::LGui));
+    assert_eq!(base.key(3, 4), &Key::Momentary(1));
+    assert_eq!(base.key(3, 6), &Key::LayerTap(code("Enter"), 1));
+    assert_eq!(base.key(3, 7), &Key::OneShotModifier(Modifier::RAlt));
+    assert_eq!(lower.key(0, 0), &Key::Transparent);
+    assert_eq!(lower.key(0, 11), &Key::Bootloader);
+    assert_eq!(
+        lower.key(2, 6),
+        &Key::WithModifier(code("Kc1"), Modifier::LShift)
+    );
+    assert_eq!(lower.key(2, 7), &Key::Toggle(1));
+    assert_eq!(lower.key(2, 8), &Key::Default(0));
+    assert_eq!(lower.key(2, 10), &Key::To(0));
+
+    // The unwired positions are filled in like on every layer of the firmware
+    assert_eq!(base.key(3, 0), &Key::Key(code("LShift")));
+    assert_eq!(base.key(3, 10), &Key::Momentary(1));
+    assert_eq!(base.key(3, 11), &Key::Momentary(8));
+
+    let [issue] = imported.issues.as_slice() else {
+        panic!("expected one issue, got {:?}", imported.issues);
+    };
+    assert_eq!((issue.layer, issue.position), (0, Some((3, 8))));
+    assert!(issue.message.contains("RGB_TOG"));
+    assert_eq!(base.key(3, 8), &Key::No);
+}
+
+#[test]
+fn qmk_import_rejects_other_layouts() {
+    let other = QMK.replace("LAYOUT_split_3x6_3", "LAYOUT_split_3x5_3");
+    assert!(qmk::import(&other).is_err());
+    let short = QMK.replace("\"KC_LGUI\", ", "");
+    assert!(qmk::import(&short).unwrap_err().message.contains("41 keys"));
+}
+
+const ZMK: &str = r#"
+#include <behaviors.dtsi>
+#include <dt-bindings/zmk/keys.h>
+#include <dt-bindings/zmk/bt.h>
+
+#define LOWER 1
+
+/ {
+    behaviors {
+        hm: homerow_mods {
+            compatible = "zmk,behavior-hold-tap";
+            #binding-cells = <2>;
+            flavor = "balanced";
+            tapping-term-ms = <200>;
+            bindings = <&kp>, <&kp>;
+        };
+    };
+
+    keymap {
+        compatible = "zmk,keymap";
+
+        default_layer {
+            // -----------------------------------------------------------
+            // | TAB |  Q  |  W  |  E  |  R  |  T  |   |  Y  | ...
+            bindings = <
+   &kp TAB   &kp Q        &kp W &kp E &kp R &kp T   &kp Y &kp U  &kp I     &kp O   &kp P    &kp BSPC
+   &kp LCTRL &hm LGUI A   &kp S &kp D &kp F &kp G   &kp H &kp J  &kp K     &kp L   &kp SEMI &kp SQT
+   &kp LSHFT &kp Z        &kp X &kp C &kp V &kp B   &kp N &kp M  &kp COMMA &kp DOT &kp FSLH &kp ESC
+                     &kp LGUI &mo LOWER &kp SPACE   &lt 1 RET &sk RALT &bt BT_CLR
+            >;
+        };
+
+        lower_layer {
+            display-name = "Lower";
+            bindings = <
+   &trans &kp N1 &kp N2 &kp N3 &kp N4 &kp N5   &kp N6 &kp N7 &kp N8 &kp N9 &kp N0 &bootloader
+   &none  &none  &none  &none  &none  &none    &kp LEFT &kp DOWN &kp UP &kp RIGHT &none &none
+   &trans &none  &none  &none  &none  &none    &kp LS(N1) &tog 1 &sl 1 &to 0 &mt LSHFT Z &sys_reset
+                        &trans &trans &trans   &trans &trans &trans
+            >;
+        };
+    };
+};
+"#;
+
+#[test]
+fn zmk_import() {
+    let imported = zmk::import(ZMK).unwrap();
+    let [base, lower] = imported.value.as_slice() else {
+        panic!("expected two layers");
+    };
+    assert_eq!(base.name, "default");
+    assert_eq!(lower.name, "Lower");
+    assert_eq!(base.key(0, 0), &Key::Key(code("Tab")));
+    assert_eq!(base.key(1, 1), &Key::ModTap(code("A"), Modifier::LGui));
+    assert_eq!(base.key(3, 4), &Key::Momentary(1));
+    assert_eq!(base.key(3, 6), &Key::LayerTap(code("Enter"), 1));
+    assert_eq!(base.key(3, 7), &Key::OneShotModifier(Modifier::RAlt));
+    assert_eq!(lower.key(0, 0), &Key::Transparent);
+    assert_eq!(lower.key(0, 11), &Key::Bootloader);
+    assert_eq!(
+        lower.key(2, 6),
+        &Key::WithModifier(code("Kc1"), Modifier::LShift)
+    );
+    assert_eq!(lower.key(2, 7), &Key::Toggle(1));
+    assert_eq!(lower.key(2, 8), &Key::OneShotLayer(1));
+    assert_eq!(lower.key(2, 9), &Key::To(0));
+    assert_eq!(lower.key(2, 10), &Key::ModTap(code("Z"), Modifier::LShift));
+    assert_eq!(lower.key(2, 11), &Key::Reboot);
+
+    let [issue] = imported.issues.as_slice() else {
+        panic!("expected one issue, got {:?}", imported.issues);
+    };
+    assert_eq!((issue.layer, issue.position), (0, Some((3, 8))));
+    assert!(issue.message.contains("&bt BT_CLR"));
+}
+
+#[test]
+fn zmk_import_needs_a_keymap() {
+    assert!(
+        zmk::import("/ { behaviors { }; };")
+            .unwrap_err()
+            .message
+            .contains("zmk,keymap")
+    );
+}
+
+#[test]
+fn reads_the_firmware_keymap() {
+    let layers = rust::read(KEYMAP).unwrap();
+    assert_eq!(layers.len(), 9);
+    assert_eq!(layers[0].name, "base");
+    assert_eq!(layers[0].key(0, 1), &Key::Key(code("Q")));
+    assert_eq!(layers[0].key(1, 1), &Key::ModTap(code("A"), Modifier::LAlt));
+    assert_eq!(layers[0].key(3, 5), &Key::LayerTap(code("Space"), 1));
+    assert!(matches!(layers[0].key(1, 0), Key::Other(text) if text == "LEADER"));
+    // Characters depend on the host layout, they are kept as written
+    assert!(matches!(layers[0].key(2, 8), Key::Other(text) if text == "ch!(',')"));
+    assert!(matches!(layers[0].key(2, 11), Key::Other(text) if text == "ch!('\\\\')"));
+    assert!(matches!(layers[1].key(0, 7), Key::Other(text) if text == "ch!('{')"));
+    assert!(matches!(layers[0].key(1, 11), Key::Other(text) if text == "ch!('\\'')"));
+    // The leader layer is built in code
+    assert_eq!(layers[8].name, "leader");
+    assert_eq!(layers[8].code.as_deref(), Some("leader_layer()"));
+    assert!(layers[8].keys.iter().all(|key| *key == Key::No));
+}
+
+#[test]
+fn export_reports_what_has_no_equivalent() {
+    let layers = rust::read(KEYMAP).unwrap();
+    let qmk = qmk::export(&layers);
+    let zmk = zmk::export(&layers);
+    let reported =
+        |issues: &[Issue], what: &str| issues.iter().any(|i| i.message.starts_with(what));
+
+    for what in ["LEADER", "td!(", "ash!(", "ms!("] {
+        assert!(reported(&qmk.issues, what), "{what} not reported for QMK");
+        assert!(reported(&zmk.issues, what), "{what} not reported for ZMK");
+    }
+    for issues in [&qmk.issues, &zmk.issues] {
+        assert!(issues.iter().any(|i| i.layer == 8 && i.position.is_none()));
+    }
+    // ZMK has no default layer, QMK does
+    assert!(reported(&zmk.issues, "df!("));
+    assert!(!reported(&qmk.issues, "df!("));
+    assert!(qmk.value.contains("\"DF(3)\""));
+}
+
+#[test]
+fn round_trips_through_qmk_and_zmk() {
+    let layers = rust::read(KEYMAP).unwrap();
+    let from_qmk = qmk::import(&qmk::export(&layers).value).unwrap();
+    let from_zmk = zmk::import(&zmk::export(&layers).value).unwrap();
+    assert!(from_qmk.issues.is_empty(), "{:?}", from_qmk.issues);
+    assert!(from_zmk.issues.is_empty(), "{:?}", from_zmk.issues);
+
+    for (i, layer) in layers.iter().enumerate() {
+        assert_eq!(from_zmk.value[i].name, layer.name);
+        for (index, key) in layer.physical_keys() {
+            let (row, col) = position(index);
+            let (qmk, zmk) = (
+                from_qmk.value[i].key(row, col),
+                from_zmk.value[i].key(row, col),
+            );
+            if qmk::key_text(key).is_some() {
+                assert_eq!(qmk, key, "layer {i} row {row} col {col}");
+            }
+            if zmk::key_text(key).is_some() {
+                assert_eq!(zmk, key, "layer {i} row {row} col {col}");
+            }
+        }
+    }
+}
+
+#[test]
+fn writes_keymap() {
+    let layers = rust::read(KEYMAP).unwrap();
+    let written = rust::write(&layers);
+    assert!(written.starts_with("#[rustfmt::skip]\npub const fn keymap<const LAYOUT: u8>()"));
+    assert_eq!(rust::read(&written).unwrap(), layers);
+}
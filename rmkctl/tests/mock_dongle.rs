@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+
+use rmkctl::hid::{HidDevice, REPORT_LEN};
+use rmkctl::via::{Client, Error, HalfStatus};
+use rmkctl::{COLS, ROWS, keycode};
+
+const LAYERS: u8 = 9;
+
+/// Layers the host can toggle, `REMOTE_LAYERS`
+const REMOTE_LAYERS: [u8; 4] = [1, 2, 6, 7];
+const PROFILES: u8 = 3;
+
+/// Answers raw HID reports like the dongle's firmware
+struct MockDongle {
+    keymap: Vec<u16>,
+    unlocked: bool,
+    bootloader: bool,
+    default_layer: u8,
+    /// Layers turned on by the host
+    layers: Vec<u8>,
+    profile: u8,
+    /// Connected and battery level of each half, `0xff` while unknown
+    halves: [u8; 4],
+    presses: Vec<u32>,
+    answers: VecDeque<[u8; REPORT_LEN]>,
+}
+
+impl MockDongle {
+    fn new() -> Self {
+        let keys = LAYERS as usize * ROWS as usize * COLS as usize;
+        Self {
+            keymap: (0..keys as u16).collect(),
+            unlocked: false,
+            bootloader: false,
+            default_layer: 0,
+            layers: vec![],
+            profile: 0,
+            halves: [1, 0xff, 0, 0xff],
+            presses: vec![0; ROWS as usize * COLS as usize],
+            answers: VecDeque::new(),
+        }
+    }
+
+    fn index(layer: u8, row: u8, col: u8) -> usize {
+        (layer as usize * ROWS as usize + row as usize) * COLS as usize + col as usize
+    }
+
+    fn answer(&mut self, request: &[u8; REPORT_LEN]) -> Option<[u8; REPORT_LEN]> {
+        let mut answer = *request;
+        match request[0] {
+            0x01 => answer[1..3].copy_from_slice(&9u16.to_be_bytes()),
+            0x02 => answer[2..6].copy_from_slice(&61_000u32.to_be_bytes()),
+            0x04 => {
+                let key = self.keymap[Self::index(request[1], request[2], request[3])];
+                answer[4..6].copy_from_slice(&key.to_be_bytes());
+            }
+            0x05 => {
+                let key = u16::from_be_bytes([request[4], request[5]]);
+                self.keymap[Self::index(request[1], request[2], request[3])] = key;
+            }
+            0x07 if request[1] == 0 => {
+                let data = request[3];
+                match request[2] {
+                    0x01 if [0, 3].contains(&data) => self.default_layer = data,
+                    0x02 if REMOTE_LAYERS.contains(&data) => {
+                        match self.layers.iter().position(|&l| l == data) {
+                            Some(i) => {
+                                self.layers.remove(i);
+                            }
+                            None => self.layers.push(data),
+                        }
+                    }
+                    0x03 if data < PROFILES => self.profile = data,
+                    _ => answer[0] = 0xff,
+                }
+            }
+            0x08 if request[1] == 0 => match request[2] {
+                0x01 => answer[3] = self.default_layer,
+                0x02 => answer[3] = self.layers.iter().copied().max().unwrap_or(0),
+                0x03 => answer[3] = self.profile,
+                0x04 => answer[3..7].copy_from_slice(&self.halves),
+                0x05 if request[3] < ROWS && request[4] < COLS => {
+                    for i in 0..6 {
+                        let col = request[4] as usize + i;
+                        let count = if col < COLS as usize {
+                            self.presses[request[3] as usize * COLS as usize + col]
+                        } else {
+                            0
+                        };
+                        answer[5 + 4 * i..9 + 4 * i].copy_from_slice(&count.to_be_bytes());
+                    }
+                }
+                _ => answer[0] = 0xff,
+            },
+            0x0b => {
+                self.bootloader = true;
+                return None;
+            }
+            0x11 => answer[1] = LAYERS,
+            0x12 => {
+                let offset = u16::from_be_bytes([request[1], request[2]]) as usize;
+                let size = request[3] as usize;
+                let bytes: Vec<u8> = self.keymap.iter().flat_map(|k| k.to_be_bytes()).collect();
+                answer[4..4 + size].copy_from_slice(&bytes[offset..offset + size]);
+            }
+            0xfe => {
+                answer = [0; REPORT_LEN];
+                match request[1] {
+                    0x00 => {
+                        answer[0..4].copy_from_slice(&6u32.to_le_bytes());
+                        answer[4..12].copy_from_slice(b"LCCorne6");
+                    }
+                    0x05 => answer[0] = self.unlocked as u8,
+                    _ => answer[0] = 0xff,
+                }
+            }
+            _ => answer[0] = 0xff,
+        }
+        Some(answer)
+    }
+}
+
+impl HidDevice for MockDongle {
+    fn write_report(&mut self, report: &[u8; REPORT_LEN]) -> io::Result<()> {
+        if let Some(answer) = self.answer(report) {
+            self.answers.push_back(answer);
+        }
+        Ok(())
+    }
+
+    fn read_report(&mut self, _timeout: Duration) -> io::Result<[u8; REPORT_LEN]> {
+        self.answers
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "no answer"))
+    }
+}
+
+#[test]
+fn reads_the_keyboard_info() {
+    let mut client = Client::new(MockDongle::new());
+    assert_eq!(client.protocol_version().unwrap(), 9);
+    assert_eq!(client.uptime().unwrap(), Duration::from_secs(61));
+    assert_eq!(client.layer_count().unwrap(), LAYERS);
+    let id = client.keyboard_id().unwrap();
+    assert_eq!(id.protocol, 6);
+    assert_eq!(&id.uid, b"LCCorne6");
+    assert!(!client.unlocked().unwrap());
+}
+
+#[test]
+fn reads_the_whole_keymap_in_chunks() {
+    let mut client = Client::new(MockDongle::new());
+    let keymap = client.keymap(LAYERS, ROWS, COLS).unwrap();
+    assert_eq!(keymap, client.into_device().keymap);
+}
+
+#[test]
+fn sets_a_key() {
+    let mut client = Client::new(MockDongle::new());
+    let key = keycode::parse("kc_esc").unwrap();
+    client.set_keycode(2, 3, 4, key).unwrap();
+    assert_eq!(client.keycode(2, 3, 4).unwrap(), key);
+    assert_eq!(keycode::name(client.keycode(2, 3, 4).unwrap()), "KC_ESC");
+    assert_eq!(
+        client.into_device().keymap[MockDongle::index(2, 3, 4)],
+        0x29
+    );
+}
+
+#[test]
+fn jumps_to_the_bootloader_without_an_answer() {
+    let mut client = Client::new(MockDongle::new());
+    client.bootloader().unwrap();
+    assert!(client.into_device().bootloader);
+}
+
+#[test]
+fn reports_unsupported_commands() {
+    let mut dongle = MockDongle::new();
+    // The firmware answers an unknown command with 0xff
+    dongle.answers.push_back([0xff; REPORT_LEN]);
+    let mut client = Client::new(dongle);
+    assert!(matches!(client.layer_count(), Err(Error::Unhandled(0x11))));
+}
+
+#[test]
+fn reads_and_sets_the_default_layer() {
+    let mut client = Client::new(MockDongle::new());
+    assert_eq!(client.default_layer().unwrap(), 0);
+    client.set_default_layer(3).unwrap();
+    assert_eq!(client.default_layer().unwrap(), 3);
+    // Only the base and gaming layers can be the default layer
+    assert!(matches!(
+        client.set_default_layer(2),
+        Err(Error::Unhandled(0x07))
+    ));
+    assert_eq!(client.default_layer().unwrap(), 3);
+}
+
+#[test]
+fn toggles_layers_and_switches_profiles() {
+    let mut client = Client::new(MockDongle::new());
+    client.toggle_layer(6).unwrap();
+    assert_eq!(client.layer().unwrap(), 6);
+    client.toggle_layer(6).unwrap();
+    assert_eq!(client.layer().unwrap(), 0);
+    assert!(matches!(
+        client.toggle_layer(5),
+        Err(Error::Unhandled(0x07))
+    ));
+
+    client.switch_profile(2).unwrap();
+    assert_eq!(client.host_profile().unwrap(), 2);
+    assert!(matches!(
+        client.switch_profile(PROFILES),
+        Err(Error::Unhandled(0x07))
+    ));
+}
+
+#[test]
+fn reads_the_status_of_the_halves() {
+    let mut dongle = MockDongle::new();
+    dongle.halves = [1, 80, 0, 0xff];
+    let mut client = Client::new(dongle);
+    assert_eq!(
+        client.halves().unwrap(),
+        [
+            HalfStatus {
+                connected: true,
+                battery_percent: Some(80)
+            },
+            HalfStatus {
+                connected: false,
+                battery_percent: None
+            },
+        ]
+    );
+}
+
+#[test]
+fn reads_the_key_presses_of_every_key() {
+    let mut dongle = MockDongle::new();
+    dongle.presses = (0..ROWS as u32 * COLS as u32).map(|i| i * 1000).collect();
+    let expected = dongle.presses.clone();
+    let mut client = Client::new(dongle);
+    assert_eq!(client.key_presses(ROWS, COLS).unwrap(), expected);
+}
+
+#[test]
+fn parses_key_names_and_hex() {
+    assert_eq!(keycode::parse("A"), Some(0x04));
+    assert_eq!(keycode::parse("KC_LSFT"), Some(0xe1));
+    assert_eq!(keycode::parse("0x5220"), Some(0x5220));
+    assert_eq!(keycode::parse("nope"), None);
+    assert_eq!(keycode::name(0x5220), "0x5220");
+}
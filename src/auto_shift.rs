@@ -0,0 +1,113 @@
+//! Auto-shift: digits and symbols that type their shifted form when held.
+//!
+//! An `ash!` key is a tap-hold key of rmk whose hold is the shifted
+//! character, held for [`AUTO_SHIFT_MS`] it types `!` instead of `1`. The
+//! shifted characters are those of a US layout, typed on the host layout.
+//! Only the time it is held counts: another key pressed meanwhile taps it
+//! before rmk sees that key, so fast typing keeps its order and isn't
+//! shifted.
+//!
+//! [`AUTO_SHIFT_CLASSES`] picks the classes of keys that auto-shift, and
+//! [`AUTO_SHIFT_TOGGLE`] or the console turn it off and on. rmk's keymap can't be changed
+//! from outside, so a key that doesn't auto-shift is tapped as soon as it is
+//! pressed: it types at once, like a plain key, but doesn't repeat.
+//!
+//! [`AUTO_SHIFT_MS`]: crate::keymap::AUTO_SHIFT_MS
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::info;
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+
+use crate::host_layout::{HostLayout, char_action, us_shifted};
+use crate::keymap::{AUTO_SHIFT_CLASSES, AUTO_SHIFT_PROFILE};
+
+/// Turns auto-shift off and on
+pub const AUTO_SHIFT_TOGGLE: KeyAction = KeyAction::Single(Action::Key(KeyCode::User28));
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Whether auto-shift is on
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+    info!("Auto-shift {}", enabled);
+}
+
+/// Types `c` on `layout` when tapped and its shifted character when held
+/// for the hold timeout of [`AUTO_SHIFT_PROFILE`]
+pub const fn auto_shift(layout: HostLayout, c: char) -> KeyAction {
+    KeyAction::TapHold(
+        char_action(layout, c),
+        char_action(layout, us_shifted(c)),
+        AUTO_SHIFT_PROFILE,
+    )
+}
+
+/// The class of an `ash!` key
+pub fn class(action: &KeyAction) -> Option<AutoShiftClass> {
+    match *action {
+        KeyAction::TapHold(tap, _, profile) if profile == AUTO_SHIFT_PROFILE => {
+            AutoShiftClass::of(tap)
+        }
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoShiftClass {
+    Digit,
+    Symbol,
+}
+
+impl AutoShiftClass {
+    /// The class of the key typing `tap`, digits are at the same keys on
+    /// every host layout
+    pub fn of(tap: Action) -> Option<Self> {
+        match tap {
+            Action::Key(
+                KeyCode::Kc1
+                | KeyCode::Kc2
+                | KeyCode::Kc3
+                | KeyCode::Kc4
+                | KeyCode::Kc5
+                | KeyCode::Kc6
+                | KeyCode::Kc7
+                | KeyCode::Kc8
+                | KeyCode::Kc9
+                | KeyCode::Kc0,
+            ) => Some(Self::Digit),
+            Action::Key(keycode) if (KeyCode::A..=KeyCode::Z).contains(&keycode) => None,
+            Action::Key(_) | Action::KeyWithModifier(..) | Action::TriggerMacro(_) => {
+                Some(Self::Symbol)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Which classes of `ash!` keys auto-shift
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoShiftClasses {
+    pub digits: bool,
+    pub symbols: bool,
+}
+
+impl AutoShiftClasses {
+    pub fn contains(&self, class: AutoShiftClass) -> bool {
+        match class {
+            AutoShiftClass::Digit => self.digits,
+            AutoShiftClass::Symbol => self.symbols,
+        }
+    }
+}
+
+/// Whether a key with `action` shifts when held, with auto-shift turned on
+/// or off by `enabled`
+pub fn shifts(action: &KeyAction, enabled: bool) -> bool {
+    enabled && class(action).is_some_and(|class| AUTO_SHIFT_CLASSES.contains(class))
+}
@@ -0,0 +1,60 @@
+//! Battery level of the halves.
+//!
+//! Each half samples its battery with the ADC, [`BatteryReporter`] turns the
+//! samples into a percentage and sends it to the dongle, see `split.rs`.
+
+use rmk::event::Event;
+use rmk::input_device::InputDevice;
+
+use crate::split::HalfMessage;
+
+/// ADC reading of a full battery at 4.2 V, with the default SAADC config:
+/// gain 1/6, 0.6 V reference and 12 bits
+const FULL: u32 = 4755;
+
+/// ADC reading of an empty battery, a bit below 3.6 V so that the range is
+/// 7 per percent
+const EMPTY: u32 = FULL - 700;
+
+/// Battery level in percent for an ADC reading of `adc`, behind a voltage
+/// divider of `divider` (measured and total resistance)
+pub const fn percent(adc: u16, divider: (u32, u32)) -> u8 {
+    let (measured, total) = divider;
+    let battery = adc as u32 * total / measured;
+    if battery >= FULL {
+        100
+    } else if battery <= EMPTY {
+        0
+    } else {
+        ((battery - EMPTY) / 7) as u8
+    }
+}
+
+/// Input device of a half that reads the battery from the ADC device `adc`
+/// and turns its readings into [`HalfMessage::Battery`] events
+pub struct BatteryReporter<D> {
+    adc: D,
+    half: u8,
+    divider: (u32, u32),
+}
+
+impl<D: InputDevice> BatteryReporter<D> {
+    pub fn new(adc: D, half: u8, divider: (u32, u32)) -> Self {
+        Self { adc, half, divider }
+    }
+}
+
+impl<D: InputDevice> InputDevice for BatteryReporter<D> {
+    async fn read_event(&mut self) -> Event {
+        loop {
+            if let Event::Battery(adc) = self.adc.read_event().await {
+                let percent = percent(adc, self.divider);
+                return HalfMessage::Battery {
+                    half: self.half,
+                    percent,
+                }
+                .to_event();
+            }
+        }
+    }
+}
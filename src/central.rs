@@ -1,13 +1,9 @@
 #![no_std]
 #![no_main]
 
-#[macro_use]
-mod macros;
-
-mod keymap;
-use keymap::{COL, NUM_LAYER, ROW};
-
+use bt_hci::param::ConnHandle;
 use defmt::{info, unwrap};
+use embassy_embedded_hal::flash::partition::Partition;
 use embassy_executor::Spawner;
 use embassy_nrf::gpio::Output;
 use embassy_nrf::mode::Async;
@@ -16,26 +12,44 @@ use embassy_nrf::saadc::{self};
 use embassy_nrf::usb::Driver;
 use embassy_nrf::usb::vbus_detect::HardwareVbusDetect;
 use embassy_nrf::{bind_interrupts, rng, usb};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
 use nrf_mpsl::Flash;
 use nrf_sdc::mpsl::MultiprotocolServiceLayer;
+use nrf_sdc::vendor::ZephyrWriteTxPower;
 use nrf_sdc::{self as sdc, mpsl};
 use rand_chacha::ChaCha12Rng;
 use rand_core::SeedableRng;
 use rmk::ble::build_ble_stack;
-use rmk::config::{BehaviorConfig, DeviceConfig, PositionalConfig, RmkConfig, StorageConfig};
-use rmk::controller::EventController as _;
+use rmk::channel::CONTROLLER_CHANNEL;
+use rmk::config::{DeviceConfig, RmkConfig, StorageConfig, VialConfig};
 use rmk::controller::led_indicator::KeyboardIndicatorController;
-use rmk::futures::future::{join, join4};
+use rmk::controller::{Controller, EventController as _};
+use rmk::event::ControllerEvent;
+use rmk::futures::future::{join3, join4, join5};
 use rmk::input_device::Runnable;
-use rmk::keyboard::Keyboard;
 use rmk::split::ble::central::{read_peripheral_addresses, scan_peripherals};
 use rmk::split::central::run_peripheral_manager;
 use rmk::types::action::EncoderAction;
 use rmk::{HostResources, initialize_encoder_keymap_and_storage, run_rmk};
+use rmk_corne::console::{ConsoleBackend, UsbSerial, run_console};
+use rmk_corne::default_layer::DefaultLayerController;
+use rmk_corne::device::{PRODUCT_NAME, SerialNumber, ble_addr};
+use rmk_corne::keyboard::Keyboard;
+use rmk_corne::keymap::{self, COL, HOST_LINK, NUM_LAYER, ROW, SPLIT_LINK, VIAL_UNLOCK_KEYS};
+use rmk_corne::mouse::MouseKeyController;
+use rmk_corne::radio::{Board, LinkController, TxPower, apply_links};
+use rmk_corne::settings::{SETTINGS_SIZE, SETTINGS_START, Settings};
+use rmk_corne::split;
+use rmk_corne::status::{self, StatusController};
+use rmk_corne::tapping_term::{self, TappingTermController};
+use rmk_corne::via::ViaDriver;
 use static_cell::StaticCell;
 
 use {defmt_rtt as _, panic_probe as _};
 
+include!(concat!(env!("OUT_DIR"), "/config_generated.rs"));
+
 bind_interrupts!(struct Irqs {
     USBD => usb::InterruptHandler<USBD>;
     SAADC => saadc::InterruptHandler;
@@ -52,6 +66,11 @@ async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) -> ! {
     mpsl.run().await
 }
 
+/// rmk's storage, it ends where the settings start
+const STORAGE_START: usize = 0xA0000;
+const STORAGE_SECTORS: u8 = 6;
+const _: () = assert!(STORAGE_START + STORAGE_SECTORS as usize * 4096 == SETTINGS_START as usize);
+
 /// How many outgoing L2CAP buffers per link
 const L2CAP_TXQ: u8 = 3;
 
@@ -61,6 +80,14 @@ const L2CAP_RXQ: u8 = 3;
 /// Size of L2CAP packets
 const L2CAP_MTU: usize = 251;
 
+/// `handle_type` of a connection in the Zephyr TX power command
+const TX_POWER_HANDLE_CONN: u8 = 2;
+
+/// The controller's command for the TX power of a connection
+fn conn_tx_power(handle: ConnHandle, power: TxPower) -> ZephyrWriteTxPower {
+    ZephyrWriteTxPower::new(TX_POWER_HANDLE_CONN, handle.raw(), power.dbm())
+}
+
 fn build_sdc<'d, const N: usize>(
     p: nrf_sdc::Peripherals<'d>,
     rng: &'d mut rng::Rng<Async>,
@@ -83,12 +110,50 @@ fn build_sdc<'d, const N: usize>(
         .build(p, rng, mpsl, mem)
 }
 
-fn ble_addr() -> [u8; 6] {
+/// Unique per chip, set in the factory
+fn device_id() -> u64 {
     let ficr = embassy_nrf::pac::FICR;
     let high = u64::from(ficr.deviceid(1).read());
-    let addr = high << 32 | u64::from(ficr.deviceid(0).read());
-    let addr = addr | 0x0000_c000_0000_0000;
-    unwrap!(addr.to_le_bytes()[..6].try_into())
+    high << 32 | u64::from(ficr.deviceid(0).read())
+}
+
+/// The status, with the BLE profile of the host link rmk only reports with
+/// BLE
+struct DongleStatus(StatusController);
+
+impl Controller for DongleStatus {
+    type Event = ControllerEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        if let ControllerEvent::BleProfile(profile) = event {
+            status::set_host_profile(profile);
+        }
+        self.0.process_event(event).await
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        self.0.next_message().await
+    }
+}
+
+/// What the console does on the dongle
+struct DongleConsole;
+
+impl ConsoleBackend for DongleConsole {
+    async fn forget_peripherals(&mut self) {
+        // The split drivers clear the stored addresses and tell the halves
+        CONTROLLER_CHANNEL
+            .immediate_publisher()
+            .publish_immediate(ControllerEvent::ClearPeer);
+    }
+
+    async fn bootloader(&mut self) {
+        // Like rmk's `jump_to_bootloader` with the Adafruit bootloader
+        embassy_nrf::pac::POWER
+            .gpregret()
+            .write_value(embassy_nrf::pac::power::regs::Gpregret(0x57));
+        cortex_m::peripheral::SCB::sys_reset();
+    }
 }
 
 #[embassy_executor::main]
@@ -126,44 +191,60 @@ async fn main(spawner: Spawner) {
     let mut rng_gen = ChaCha12Rng::from_rng(&mut rng).unwrap();
     let mut sdc_mem = sdc::Mem::<15472>::new();
     let sdc = unwrap!(build_sdc(sdc_p, &mut rng, mpsl, &mut sdc_mem));
+    info!("Split link: {}", SPLIT_LINK);
+    info!("Host link: {}", HOST_LINK);
     let mut host_resources = HostResources::new();
-    let stack = build_ble_stack(sdc, ble_addr(), &mut rng_gen, &mut host_resources).await;
+    let stack = build_ble_stack(
+        LinkController::new(&sdc, Board::Dongle),
+        ble_addr(device_id()),
+        &mut rng_gen,
+        &mut host_resources,
+    )
+    .await;
 
-    // Initialize usb driver
-    let driver = Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs));
+    // Initialize usb driver, Vial can't change the keymap until it's unlocked
+    let driver = ViaDriver::new(Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs)));
 
-    // Initialize flash
-    let flash = Flash::take(mpsl, p.NVMC);
+    // Initialize flash, shared by rmk's storage and the settings
+    static FLASH: StaticCell<Mutex<NoopRawMutex, Flash<'static>>> = StaticCell::new();
+    let flash = FLASH.init(Mutex::new(Flash::take(mpsl, p.NVMC)));
+    let settings = || Settings::new(Partition::new(flash, SETTINGS_START, SETTINGS_SIZE));
+    let flash = Partition::new(flash, 0, SETTINGS_START);
 
     // Keyboard config
+    static SERIAL_NUMBER: StaticCell<SerialNumber> = StaticCell::new();
     let keyboard_device_config = DeviceConfig {
         vid: 0x4c4b,
         pid: 0x4643,
         manufacturer: "LegitCamper",
-        product_name: "RMK Keyboard",
-        serial_number: "na",
+        product_name: PRODUCT_NAME,
+        serial_number: SERIAL_NUMBER.init(SerialNumber::new(device_id())).as_str(),
     };
     let storage_config = StorageConfig {
-        start_addr: 0xA0000,
-        num_sectors: 6,
+        start_addr: STORAGE_START,
+        num_sectors: STORAGE_SECTORS,
         #[cfg(feature = "reset")]
         clear_storage: true,
         #[cfg(feature = "reset")]
         clear_layout: true,
         ..Default::default()
     };
+    let vial_config = VialConfig::new(VIAL_KEYBOARD_ID, VIAL_KEYBOARD_DEF, &VIAL_UNLOCK_KEYS);
     let rmk_config = RmkConfig {
         device_config: keyboard_device_config,
+        vial_config,
         storage_config,
         ..Default::default()
     };
 
     // Initialize keyboard stuffs
+    // Restore the tapping terms, the global one goes to rmk with the behavior
+    let mut tapping_terms = TappingTermController::new(settings()).await;
     // Initialize the storage and keymap
     let mut default_keymap = keymap::get_default_keymap();
-    let mut behavior_config = BehaviorConfig::default();
-    behavior_config.morse.enable_flow_tap = true;
-    let mut key_config = PositionalConfig::default();
+    let mut behavior_config = keymap::behavior_config();
+    tapping_term::current().apply(&mut behavior_config);
+    let mut key_config = keymap::positional_config();
     let mut encoder_config = [{
         EncoderAction::default();
         [] as [EncoderAction; 0]
@@ -195,10 +276,41 @@ async fn main(spawner: Spawner) {
         false,
         rmk::types::led_indicator::LedIndicatorType::CapsLock,
     );
+    // The blue LED of the XIAO, lit off the base layer
+    let mut default_layer_led = DefaultLayerController::new(
+        Output::new(
+            p.P0_06,
+            embassy_nrf::gpio::Level::High,
+            embassy_nrf::gpio::OutputDrive::Standard,
+        ),
+        true,
+        settings(),
+    )
+    .await;
+    let mut mouse_keys = MouseKeyController::new();
+    let mut status = DongleStatus(StatusController::new());
+    let mut console_settings = settings();
 
     // Start
-    join(
-        join(keyboard.run(), capslock_led.event_loop()),
+    join3(
+        join5(
+            keyboard.run(),
+            capslock_led.event_loop(),
+            default_layer_led.event_loop(),
+            mouse_keys.event_loop(),
+            tapping_terms.event_loop(),
+        ),
+        // The console only gets input in the `usb_logging` build
+        join4(
+            status.event_loop(),
+            split::receive_half_messages(),
+            apply_links(&sdc, conn_tx_power),
+            run_console(
+                &mut UsbSerial::new(),
+                &mut console_settings,
+                &mut DongleConsole,
+            ),
+        ),
         join4(
             scan_peripherals(&stack, &peripheral_addrs),
             run_peripheral_manager::<ROW, COL, 0, 0, _>(0, &peripheral_addrs, &stack),
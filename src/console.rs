@@ -0,0 +1,465 @@
+//! Line based management console of the dongle.
+//!
+//! The console runs on the USB serial port of the `usb_logging` build, a
+//! CDC-ACM class rmk adds next to its HID classes for its log. rmk builds the
+//! USB device itself and takes no classes of its own, so the console shares
+//! that port: [`ViaDriver`] hands what the host types there to [`receive`],
+//! and [`UsbSerial`] writes the answers as log lines. The port doesn't echo,
+//! and rmk's own log shows up in between.
+//!
+//! The parser and the command loop only need a byte stream, so they run
+//! against an in-memory serial port in the tests.
+//!
+//! [`ViaDriver`]: crate::via::ViaDriver
+
+use core::convert::Infallible;
+use core::fmt::{self, Write as _};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_time::Timer;
+use embedded_io_async::{ErrorType, Read, Write};
+use embedded_storage_async::nor_flash::NorFlash;
+use rmk::heapless::String;
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::modifier::ModifierCombination;
+
+use crate::auto_shift;
+use crate::default_layer;
+use crate::keymap::{COL, NUM_LAYER, ROW, get_default_keymap};
+use crate::radio::{self, LinkKind};
+use crate::settings::Settings;
+use crate::split::half_name;
+use crate::status::{self, Status};
+use crate::tapping_term::{self, TermTarget};
+
+const KEYMAP: [[[KeyAction; COL]; ROW]; NUM_LAYER] = get_default_keymap();
+
+/// Longest command line, longer lines are dropped
+pub const MAX_LINE_LEN: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    TappingTerm(TermTarget),
+    AutoShift,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    /// Halves, battery, layers, host profile and links
+    Status,
+    /// One layer or all of them
+    Keymap(Option<u8>),
+    Set(Param, u16),
+    ForgetPeripherals,
+    Bootloader,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    UnknownCommand,
+    UnknownParam,
+    MissingArgument,
+    TooManyArguments,
+    BadNumber,
+    LineTooLong,
+}
+
+impl ParseError {
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::UnknownCommand => "unknown command, try `help`",
+            Self::UnknownParam => "unknown parameter, try `help`",
+            Self::MissingArgument => "missing argument",
+            Self::TooManyArguments => "too many arguments",
+            Self::BadNumber => "not a number",
+            Self::LineTooLong => "line too long",
+        }
+    }
+}
+
+const HELP: &str = "\
+status                  halves, layers, host profile, links\r\n\
+keymap [layer]          the keymap the firmware was built with\r\n\
+set term <ms>           global tapping term\r\n\
+set term.hrm <ms>       tapping term of the home row mods\r\n\
+set term.thumb <ms>     tapping term of the thumb keys\r\n\
+set autoshift <0|1>     turn auto-shift off or on\r\n\
+forget                  forget the paired halves\r\n\
+bootloader              reboot to the bootloader\r\n";
+
+fn parse_number(word: &str) -> Result<u16, ParseError> {
+    word.parse().map_err(|_| ParseError::BadNumber)
+}
+
+fn parse_param(word: &str) -> Result<Param, ParseError> {
+    match word {
+        "term" => Ok(Param::TappingTerm(TermTarget::Global)),
+        "term.hrm" => Ok(Param::TappingTerm(TermTarget::HomeRowMods)),
+        "term.thumb" => Ok(Param::TappingTerm(TermTarget::Thumbs)),
+        "autoshift" => Ok(Param::AutoShift),
+        _ => Err(ParseError::UnknownParam),
+    }
+}
+
+/// Parses a line that isn't blank, words are separated by any amount of
+/// whitespace
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let mut words = line.split_ascii_whitespace();
+    let command = match words.next().unwrap_or_default() {
+        "help" | "?" => Command::Help,
+        "status" => Command::Status,
+        "keymap" => Command::Keymap(
+            words
+                .next()
+                .map(|word| word.parse().map_err(|_| ParseError::BadNumber))
+                .transpose()?,
+        ),
+        "set" => {
+            let param = parse_param(words.next().ok_or(ParseError::MissingArgument)?)?;
+            let value = parse_number(words.next().ok_or(ParseError::MissingArgument)?)?;
+            Command::Set(param, value)
+        }
+        "forget" => Command::ForgetPeripherals,
+        "bootloader" => Command::Bootloader,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+    match words.next() {
+        Some(_) => Err(ParseError::TooManyArguments),
+        None => Ok(command),
+    }
+}
+
+/// Collects received bytes into lines, with backspace
+pub struct LineBuffer {
+    line: String<MAX_LINE_LEN>,
+    /// The line got too long and is dropped up to the next newline
+    overflow: bool,
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self {
+            line: String::new(),
+            overflow: false,
+        }
+    }
+
+    /// Adds `byte`, returns the line once it is complete. Blank lines, like
+    /// the `\n` of a `\r\n`, are skipped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<String<MAX_LINE_LEN>, ParseError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let line = core::mem::take(&mut self.line);
+                if core::mem::take(&mut self.overflow) {
+                    Some(Err(ParseError::LineTooLong))
+                } else if line.trim().is_empty() {
+                    None
+                } else {
+                    Some(Ok(line))
+                }
+            }
+            0x08 | 0x7f => {
+                self.line.pop();
+                None
+            }
+            _ if byte.is_ascii() && !byte.is_ascii_control() => {
+                if self.line.push(byte as char).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What the console needs of the chip and of rmk's BLE stack
+// The console runs on the single-threaded executor, no `Send` bounds needed
+#[allow(async_fn_in_trait)]
+pub trait ConsoleBackend {
+    async fn forget_peripherals(&mut self);
+    async fn bootloader(&mut self);
+}
+
+/// The modifiers by their bit
+const MODIFIERS: [&str; 8] = [
+    "LCtrl", "LShift", "LAlt", "LGui", "RCtrl", "RShift", "RAlt", "RGui",
+];
+
+fn write_modifiers(out: &mut impl fmt::Write, modifiers: ModifierCombination) -> fmt::Result {
+    let bits = modifiers.into_bits();
+    let mut names = (0..MODIFIERS.len())
+        .filter(|bit| bits & 1 << bit != 0)
+        .map(|bit| MODIFIERS[bit]);
+    if let Some(first) = names.next() {
+        out.write_str(first)?;
+    }
+    names.try_for_each(|name| write!(out, "+{name}"))
+}
+
+fn write_action(out: &mut impl fmt::Write, action: Action) -> fmt::Result {
+    match action {
+        Action::No => out.write_str("No"),
+        Action::Transparent => out.write_str("___"),
+        Action::Key(key) => write!(out, "{key:?}"),
+        Action::Modifier(modifiers) => write_modifiers(out, modifiers),
+        Action::KeyWithModifier(key, modifiers) => {
+            write_modifiers(out, modifiers)?;
+            write!(out, "+{key:?}")
+        }
+        Action::LayerOn(layer) => write!(out, "MO({layer})"),
+        Action::LayerOnWithModifier(layer, modifiers) => {
+            write!(out, "LM({layer},")?;
+            write_modifiers(out, modifiers)?;
+            out.write_str(")")
+        }
+        Action::LayerOff(layer) => write!(out, "OFF({layer})"),
+        Action::LayerToggle(layer) => write!(out, "TG({layer})"),
+        Action::DefaultLayer(layer) => write!(out, "DF({layer})"),
+        Action::LayerToggleOnly(layer) => write!(out, "TO({layer})"),
+        Action::TriggerMacro(index) => write!(out, "MACRO({index})"),
+        Action::OneShotLayer(layer) => write!(out, "OSL({layer})"),
+        Action::OneShotModifier(modifiers) => {
+            out.write_str("OSM(")?;
+            write_modifiers(out, modifiers)?;
+            out.write_str(")")
+        }
+        Action::OneShotKey(key) => write!(out, "OSK({key:?})"),
+    }
+}
+
+/// Short legend of a key, tap and hold of a tap-hold key apart by a `/`
+fn write_key_action(out: &mut impl fmt::Write, action: KeyAction) -> fmt::Result {
+    match action {
+        KeyAction::No => out.write_str("No"),
+        KeyAction::Transparent => out.write_str("___"),
+        KeyAction::Single(action) | KeyAction::Tap(action) => write_action(out, action),
+        KeyAction::TapHold(tap, hold, _) => {
+            write_action(out, tap)?;
+            out.write_str("/")?;
+            write_action(out, hold)
+        }
+        KeyAction::Morse(index) => write!(out, "TD({index})"),
+    }
+}
+
+/// Longest status report, with every link up
+const STATUS_LEN: usize = 640;
+
+fn format_status(status: &Status) -> String<STATUS_LEN> {
+    let mut out = String::new();
+    for (half, state) in status.halves.iter().enumerate() {
+        let link = if state.connected {
+            "connected"
+        } else {
+            "disconnected"
+        };
+        let _ = write!(out, "{}: {link}, battery ", half_name(half));
+        let _ = match state.battery_percent {
+            Some(percent) => write!(out, "{percent}%\r\n"),
+            None => write!(out, "unknown\r\n"),
+        };
+    }
+    let _ = write!(
+        out,
+        "layer: {}\r\ndefault layer: {}\r\nhost profile: {}\r\n",
+        status.layer,
+        default_layer::current(),
+        status.host_profile
+    );
+    // What keymap.rs asks for, then what each link that is up got
+    for kind in [LinkKind::Split, LinkKind::Host] {
+        let _ = write!(out, "{kind} link: {}\r\n", kind.link());
+    }
+    for connection in radio::connections().into_iter().flatten() {
+        let _ = write!(
+            out,
+            "  {} link {}: {}\r\n",
+            connection.kind,
+            connection.handle.raw(),
+            connection.params
+        );
+    }
+    out
+}
+
+/// Runs the console on `serial` until it fails. Changed tapping terms are
+/// stored in `settings`.
+pub async fn run_console<S, F, B>(
+    serial: &mut S,
+    settings: &mut Settings<F>,
+    backend: &mut B,
+) -> Result<(), S::Error>
+where
+    S: Read + Write,
+    F: NorFlash,
+    B: ConsoleBackend,
+{
+    let mut lines = LineBuffer::new();
+    let mut buf = [0; 32];
+    loop {
+        let len = serial.read(&mut buf).await?;
+        for &byte in &buf[..len] {
+            match lines.push(byte) {
+                None => (),
+                Some(Ok(line)) => match parse(&line) {
+                    Ok(command) => execute(serial, settings, backend, command).await?,
+                    Err(error) => write_line(serial, error.message()).await?,
+                },
+                Some(Err(error)) => write_line(serial, error.message()).await?,
+            }
+        }
+    }
+}
+
+async fn write_line<S: Write>(serial: &mut S, line: &str) -> Result<(), S::Error> {
+    serial.write_all(line.as_bytes()).await?;
+    serial.write_all(b"\r\n").await
+}
+
+async fn execute<S, F, B>(
+    serial: &mut S,
+    settings: &mut Settings<F>,
+    backend: &mut B,
+    command: Command,
+) -> Result<(), S::Error>
+where
+    S: Write,
+    F: NorFlash,
+    B: ConsoleBackend,
+{
+    match command {
+        Command::Help => serial.write_all(HELP.as_bytes()).await,
+        Command::Status => {
+            let out = format_status(&status::current());
+            serial.write_all(out.as_bytes()).await
+        }
+        Command::Keymap(layer) => {
+            let layers = match layer {
+                Some(layer) if (layer as usize) < NUM_LAYER => layer..layer + 1,
+                Some(_) => return write_line(serial, "no such layer").await,
+                None => 0..NUM_LAYER as u8,
+            };
+            for layer in layers {
+                let mut out: String<64> = String::new();
+                let _ = write!(out, "layer {layer}\r\n");
+                serial.write_all(out.as_bytes()).await?;
+                for (row, keys) in KEYMAP[layer as usize].iter().enumerate() {
+                    for (col, &action) in keys.iter().enumerate() {
+                        out.clear();
+                        let _ = write!(out, "  {row},{col}: ");
+                        let _ = write_key_action(&mut out, action);
+                        let _ = out.write_str("\r\n");
+                        serial.write_all(out.as_bytes()).await?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::Set(Param::TappingTerm(target), term) => {
+            let reply = if tapping_term::set(settings, target, term).await {
+                "ok"
+            } else {
+                "out of range"
+            };
+            write_line(serial, reply).await
+        }
+        Command::Set(Param::AutoShift, value @ (0 | 1)) => {
+            auto_shift::set_enabled(value == 1);
+            write_line(serial, "ok").await
+        }
+        Command::Set(Param::AutoShift, _) => write_line(serial, "out of range").await,
+        Command::ForgetPeripherals => {
+            backend.forget_peripherals().await;
+            write_line(serial, "halves forgotten, they pair again after a reboot").await
+        }
+        Command::Bootloader => {
+            write_line(serial, "rebooting to the bootloader").await?;
+            serial.flush().await?;
+            backend.bootloader().await;
+            Ok(())
+        }
+    }
+}
+
+/// What the host typed on the USB serial port, not read by the console yet
+static INPUT: Pipe<CriticalSectionRawMutex, 64> = Pipe::new();
+
+/// Hands bytes the host sent on the USB serial port to the console, they are
+/// dropped while the console is behind
+pub fn receive(bytes: &[u8]) {
+    let _ = INPUT.try_write(bytes);
+}
+
+/// Pause after each line, so the log's buffer doesn't overflow on the keymap
+const LINE_GAP_MS: u64 = 2;
+
+/// Longest log line, longer lines are split. The status lines of the links
+/// are the longest.
+const LOG_LINE_LEN: usize = 96;
+
+/// The console's end of the USB serial port: reads what [`receive`] got,
+/// writes every line as a log line
+pub struct UsbSerial {
+    line: String<LOG_LINE_LEN>,
+}
+
+impl Default for UsbSerial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsbSerial {
+    pub const fn new() -> Self {
+        Self {
+            line: String::new(),
+        }
+    }
+}
+
+impl ErrorType for UsbSerial {
+    type Error = Infallible;
+}
+
+impl Read for UsbSerial {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(INPUT.read(buf).await)
+    }
+}
+
+impl Write for UsbSerial {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        for &byte in buf {
+            match byte {
+                // The log ends its lines itself
+                b'\r' => (),
+                b'\n' => self.flush().await?,
+                _ => {
+                    if self.line.push(byte as char).is_err() {
+                        self.flush().await?;
+                        let _ = self.line.push(byte as char);
+                    }
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if !self.line.is_empty() {
+            log::info!("{}", self.line);
+            self.line.clear();
+            Timer::after_millis(LINE_GAP_MS).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! The default layer of the keymap, kept in the settings across power cycles
+//! and shown on the dongle's LED.
+//!
+//! rmk switches the default layer with `df!` keys but forgets it on a reset,
+//! and can't be told to switch it by anything but a key. So the dongle
+//! switches it the same way a user does: it presses the `df!` key of the
+//! built in keymap that leads from the current default layer to the wanted
+//! one, at boot for the stored layer, whenever the host asks over USB and when
+//! a tap dance switches the default layer (see `tap_dance.rs`).
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use defmt::info;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embedded_hal::digital::OutputPin;
+use embedded_storage_async::nor_flash::NorFlash;
+use rmk::channel::{CONTROLLER_CHANNEL, ControllerSub, KEY_EVENT_CHANNEL};
+use rmk::controller::Controller;
+use rmk::embassy_futures::select::{Either, select};
+use rmk::event::{ControllerEvent, KeyboardEvent};
+use rmk::types::action::{Action, KeyAction};
+
+use crate::keymap::{COL, NUM_LAYER, ROW, TAP_DANCES, TRI_LAYER, get_default_keymap};
+use crate::settings::{Setting, Settings};
+
+const KEYMAP: [[[KeyAction; COL]; ROW]; NUM_LAYER] = get_default_keymap();
+
+/// Position of the key on layer `from` that makes `to` the default layer
+const fn switch_key(from: u8, to: u8) -> Option<(u8, u8)> {
+    let mut row = 0;
+    while row < ROW {
+        let mut col = 0;
+        while col < COL {
+            if let KeyAction::Single(Action::DefaultLayer(layer)) = KEYMAP[from as usize][row][col]
+                && layer == to
+            {
+                return Some((row as u8, col as u8));
+            }
+            col += 1;
+        }
+        row += 1;
+    }
+    None
+}
+
+/// Whether `layer` can be the default layer, the base layer or the target
+/// of a `df!` key
+pub const fn is_default_layer(layer: u8) -> bool {
+    if layer == 0 {
+        return true;
+    }
+    if layer as usize >= NUM_LAYER {
+        return false;
+    }
+    let mut from = 0;
+    while from < NUM_LAYER as u8 {
+        if switch_key(from, layer).is_some() {
+            return true;
+        }
+        from += 1;
+    }
+    false
+}
+
+// The dongle can only switch between default layers that have a `df!` key to
+// each other
+const _: () = {
+    let mut from = 0;
+    while from < NUM_LAYER as u8 {
+        let mut to = 0;
+        while to < NUM_LAYER as u8 {
+            if from != to && is_default_layer(from) && is_default_layer(to) {
+                assert!(
+                    switch_key(from, to).is_some(),
+                    "A default layer has no df! key to another default layer"
+                );
+            }
+            to += 1;
+        }
+        from += 1;
+    }
+};
+
+/// The layer `action` turns on as a layer, unlike a `df!` key
+const fn layer_on(action: Action) -> Option<u8> {
+    match action {
+        Action::LayerOn(layer)
+        | Action::LayerOnWithModifier(layer, _)
+        | Action::LayerToggle(layer)
+        | Action::LayerToggleOnly(layer)
+        | Action::OneShotLayer(layer) => Some(layer),
+        _ => None,
+    }
+}
+
+const fn turns_on(action: Action, layer: u8) -> bool {
+    matches!(layer_on(action), Some(l) if l == layer)
+}
+
+/// Whether a key of the keymap, the tri-layer or the hold of a tap dance
+/// turns `layer` on as a layer
+const fn is_turned_on(layer: u8) -> bool {
+    if TRI_LAYER[2] == layer {
+        return true;
+    }
+    let mut i = 0;
+    while i < TAP_DANCES.len() {
+        let mut n = 0;
+        while n < TAP_DANCES[i].holds.len() {
+            if turns_on(TAP_DANCES[i].holds[n], layer) || turns_on(TAP_DANCES[i].taps[n], layer) {
+                return true;
+            }
+            n += 1;
+        }
+        i += 1;
+    }
+    let mut from = 0;
+    while from < NUM_LAYER {
+        let mut row = 0;
+        while row < ROW {
+            let mut col = 0;
+            while col < COL {
+                let on = match KEYMAP[from][row][col] {
+                    KeyAction::Single(action) | KeyAction::Tap(action) => turns_on(action, layer),
+                    KeyAction::TapHold(tap, hold, _) => {
+                        turns_on(tap, layer) || turns_on(hold, layer)
+                    }
+                    _ => false,
+                };
+                if on {
+                    return true;
+                }
+                col += 1;
+            }
+            row += 1;
+        }
+        from += 1;
+    }
+    false
+}
+
+// A tap dance that switches the default layer turns that layer on for a
+// moment instead, which nothing else does to a default layer. So a default
+// layer that turns on as a layer is to become the default layer.
+const _: () = {
+    let mut layer = 0;
+    while layer < NUM_LAYER as u8 {
+        if is_default_layer(layer) {
+            assert!(
+                !is_turned_on(layer),
+                "A key turns on a default layer as a layer"
+            );
+        }
+        layer += 1;
+    }
+};
+
+static LAYER: AtomicU8 = AtomicU8::new(0);
+
+/// Layer the host asked for, applied by [`DefaultLayerController`]
+static REQUEST: Signal<CriticalSectionRawMutex, u8> = Signal::new();
+
+/// The current default layer
+pub fn current() -> u8 {
+    LAYER.load(Ordering::Acquire)
+}
+
+/// Asks to make `layer` the default layer, false if it can't be one
+pub fn request(layer: u8) -> bool {
+    if !is_default_layer(layer) {
+        return false;
+    }
+    REQUEST.signal(layer);
+    true
+}
+
+pub enum DefaultLayerEvent {
+    Controller(ControllerEvent),
+    Request(u8),
+}
+
+/// Follows the `df!` keys and tap dances, stores the default layer and lights the LED while
+/// it isn't the base layer
+pub struct DefaultLayerController<P, F> {
+    led: P,
+    low_active: bool,
+    settings: Settings<F>,
+    sub: ControllerSub,
+    /// Highest active layer, the `df!` keys are only on the default layers
+    active: u8,
+    /// Requested layer waiting for the momentary layers to be released
+    pending: Option<u8>,
+}
+
+impl<P: OutputPin, F: NorFlash> DefaultLayerController<P, F> {
+    /// Switches to the stored default layer, before the keyboard runs
+    pub async fn new(led: P, low_active: bool, settings: Settings<F>) -> Self {
+        LAYER.store(0, Ordering::Release);
+        REQUEST.reset();
+        let mut controller = Self {
+            led,
+            low_active,
+            settings,
+            sub: CONTROLLER_CHANNEL
+                .subscriber()
+                .expect("No subscriber left for the default layer"),
+            active: 0,
+            pending: None,
+        };
+        controller.show(0);
+        match controller.settings.get(Setting::DefaultLayer).await {
+            Some(layer) if layer != 0 && is_default_layer(layer) => {
+                info!("Restoring default layer {}", layer);
+                controller.switch(layer).await;
+            }
+            _ => (),
+        }
+        controller
+    }
+
+    fn show(&mut self, layer: u8) {
+        if (layer != 0) != self.low_active {
+            self.led.set_high().ok();
+        } else {
+            self.led.set_low().ok();
+        }
+    }
+
+    /// Taps the `df!` key to `layer`, once no layer is held on top of the
+    /// default one
+    async fn switch(&mut self, layer: u8) {
+        let current = current();
+        if layer == current {
+            return;
+        }
+        if self.active != current {
+            self.pending = Some(layer);
+            return;
+        }
+        self.pending = None;
+        if let Some((row, col)) = switch_key(current, layer) {
+            KEY_EVENT_CHANNEL
+                .send(KeyboardEvent::key(row, col, true))
+                .await;
+            KEY_EVENT_CHANNEL
+                .send(KeyboardEvent::key(row, col, false))
+                .await;
+        }
+    }
+}
+
+impl<P: OutputPin, F: NorFlash> Controller for DefaultLayerController<P, F> {
+    type Event = DefaultLayerEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        match event {
+            DefaultLayerEvent::Controller(ControllerEvent::Key(
+                _,
+                KeyAction::Single(Action::DefaultLayer(layer)),
+            )) => {
+                // rmk sends the key on press and on release
+                self.active = layer;
+                if layer != LAYER.swap(layer, Ordering::AcqRel) {
+                    info!("Default layer {}", layer);
+                    self.show(layer);
+                    self.settings.set(Setting::DefaultLayer, layer).await;
+                }
+            }
+            DefaultLayerEvent::Controller(ControllerEvent::Layer(layer)) => {
+                self.active = layer;
+                if layer != current() && is_default_layer(layer) {
+                    // A tap dance, switch once it turned the layer off again
+                    self.pending = Some(layer);
+                } else if let Some(layer) = self.pending {
+                    self.switch(layer).await;
+                }
+            }
+            DefaultLayerEvent::Controller(_) => (),
+            DefaultLayerEvent::Request(layer) => self.switch(layer).await,
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        match select(self.sub.next_message_pure(), REQUEST.wait()).await {
+            Either::First(event) => DefaultLayerEvent::Controller(event),
+            Either::Second(layer) => DefaultLayerEvent::Request(layer),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! What the dongle tells the host about itself over USB.
+//!
+//! Two dongles on one machine differ by their serial number, the device id
+//! of the chip, which is also where the BLE address comes from. The product
+//! name says which build runs, so a reset or logging build stands out.
+
+/// The USB product name of this build
+pub const PRODUCT_NAME: &str = product_name(cfg!(feature = "reset"), cfg!(feature = "usb_logging"));
+
+/// The USB product name of a build with or without the `reset` and
+/// `usb_logging` features
+pub const fn product_name(reset: bool, log: bool) -> &'static str {
+    match (reset, log) {
+        (false, false) => "RMK Keyboard",
+        (true, false) => "RMK Keyboard-reset",
+        (false, true) => "RMK Keyboard-log",
+        (true, true) => "RMK Keyboard-reset-log",
+    }
+}
+
+/// A device id as 16 hex digits, the USB serial number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SerialNumber([u8; 16]);
+
+impl SerialNumber {
+    pub fn new(device_id: u64) -> Self {
+        let mut digits = [0; 16];
+        for (i, digit) in digits.iter_mut().enumerate() {
+            let nibble = (device_id >> (60 - 4 * i)) as u8 & 0xf;
+            *digit = b"0123456789ABCDEF"[nibble as usize];
+        }
+        Self(digits)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only hex digits
+        core::str::from_utf8(&self.0).unwrap_or_default()
+    }
+}
+
+/// The BLE address of a chip, a static random address made of the low bytes
+/// of its device id
+pub fn ble_addr(device_id: u64) -> [u8; 6] {
+    // The two top bits mark a static random address
+    let addr = device_id | 0x0000_c000_0000_0000;
+    let bytes = addr.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]]
+}
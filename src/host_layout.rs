@@ -0,0 +1,442 @@
+//! Which key and modifier type a character on the layout the host is set to,
+//! so the keymap can be written in characters (`ch!('{')`) instead of US key
+//! positions.
+//!
+//! A character behind a dead key, like `~` on Nordic, is typed by a macro
+//! that taps the dead key and then space, see [`dead_key_macros`]. Dead keys
+//! are those of the Windows layouts. A character the layout doesn't have
+//! fails the build, see [`char_action`].
+
+use rmk::heapless;
+use rmk::keyboard_macros::MacroOperation;
+use rmk::types::action::Action;
+use rmk::types::keycode::{KeyCode, KeyCode as K};
+use rmk::types::modifier::ModifierCombination;
+
+use crate::keymap::MACROS;
+use crate::validate::Message;
+use Level::{AltGr, Plain, Shift};
+
+/// Keyboard layout the host is set to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HostLayout {
+    Us,
+    /// German QWERTZ
+    German,
+    /// Swedish and Finnish
+    Nordic,
+}
+
+impl HostLayout {
+    /// Every layout, `keymap::<LAYOUT>()` takes a layout as its index here
+    pub const ALL: [HostLayout; 3] = [HostLayout::Us, HostLayout::German, HostLayout::Nordic];
+
+    /// The most [`dead_chars`](Self::dead_chars) of a layout
+    pub const MAX_DEAD_CHARS: usize = NORDIC_DEAD_CHARS.len();
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            HostLayout::Us => "US",
+            HostLayout::German => "German",
+            HostLayout::Nordic => "Nordic",
+        }
+    }
+
+    /// The characters typed by a dead key and space, in the order of their
+    /// macros
+    pub const fn dead_chars(self) -> &'static [char] {
+        match self {
+            HostLayout::Us => &[],
+            HostLayout::German => &GERMAN_DEAD_CHARS,
+            HostLayout::Nordic => &NORDIC_DEAD_CHARS,
+        }
+    }
+}
+
+/// Modifier held with the key
+#[derive(Clone, Copy)]
+enum Level {
+    Plain,
+    Shift,
+    AltGr,
+}
+
+/// Letters and digits are where a US layout has them, except for the
+/// exceptions handled by each layout first
+const fn letter_or_digit(c: char) -> Option<(KeyCode, Level)> {
+    const LETTERS: [KeyCode; 26] = [
+        K::A,
+        K::B,
+        K::C,
+        K::D,
+        K::E,
+        K::F,
+        K::G,
+        K::H,
+        K::I,
+        K::J,
+        K::K,
+        K::L,
+        K::M,
+        K::N,
+        K::O,
+        K::P,
+        K::Q,
+        K::R,
+        K::S,
+        K::T,
+        K::U,
+        K::V,
+        K::W,
+        K::X,
+        K::Y,
+        K::Z,
+    ];
+    const DIGITS: [KeyCode; 10] = [
+        K::Kc0,
+        K::Kc1,
+        K::Kc2,
+        K::Kc3,
+        K::Kc4,
+        K::Kc5,
+        K::Kc6,
+        K::Kc7,
+        K::Kc8,
+        K::Kc9,
+    ];
+    let key = match c {
+        'a'..='z' => (LETTERS[(c as u8 - b'a') as usize], Plain),
+        'A'..='Z' => (LETTERS[(c as u8 - b'A') as usize], Shift),
+        '0'..='9' => (DIGITS[(c as u8 - b'0') as usize], Plain),
+        ' ' => (K::Space, Plain),
+        '\n' => (K::Enter, Plain),
+        '\t' => (K::Tab, Plain),
+        _ => return None,
+    };
+    Some(key)
+}
+
+const fn us(c: char) -> Option<(KeyCode, Level)> {
+    let key = match c {
+        '!' => (K::Kc1, Shift),
+        '@' => (K::Kc2, Shift),
+        '#' => (K::Kc3, Shift),
+        '$' => (K::Kc4, Shift),
+        '%' => (K::Kc5, Shift),
+        '^' => (K::Kc6, Shift),
+        '&' => (K::Kc7, Shift),
+        '*' => (K::Kc8, Shift),
+        '(' => (K::Kc9, Shift),
+        ')' => (K::Kc0, Shift),
+        '-' => (K::Minus, Plain),
+        '_' => (K::Minus, Shift),
+        '=' => (K::Equal, Plain),
+        '+' => (K::Equal, Shift),
+        '[' => (K::LeftBracket, Plain),
+        '{' => (K::LeftBracket, Shift),
+        ']' => (K::RightBracket, Plain),
+        '}' => (K::RightBracket, Shift),
+        '\\' => (K::Backslash, Plain),
+        '|' => (K::Backslash, Shift),
+        ';' => (K::Semicolon, Plain),
+        ':' => (K::Semicolon, Shift),
+        '\'' => (K::Quote, Plain),
+        '"' => (K::Quote, Shift),
+        '`' => (K::Grave, Plain),
+        '~' => (K::Grave, Shift),
+        ',' => (K::Comma, Plain),
+        '<' => (K::Comma, Shift),
+        '.' => (K::Dot, Plain),
+        '>' => (K::Dot, Shift),
+        '/' => (K::Slash, Plain),
+        '?' => (K::Slash, Shift),
+        _ => return letter_or_digit(c),
+    };
+    Some(key)
+}
+
+const GERMAN_DEAD_CHARS: [char; 3] = ['^', '´', '`'];
+
+const fn german(c: char) -> Option<(KeyCode, Level)> {
+    let key = match c {
+        'z' => (K::Y, Plain),
+        'Z' => (K::Y, Shift),
+        'y' => (K::Z, Plain),
+        'Y' => (K::Z, Shift),
+        '^' => (K::Grave, Plain),
+        '°' => (K::Grave, Shift),
+        '!' => (K::Kc1, Shift),
+        '"' => (K::Kc2, Shift),
+        '²' => (K::Kc2, AltGr),
+        '§' => (K::Kc3, Shift),
+        '³' => (K::Kc3, AltGr),
+        '$' => (K::Kc4, Shift),
+        '%' => (K::Kc5, Shift),
+        '&' => (K::Kc6, Shift),
+        '/' => (K::Kc7, Shift),
+        '{' => (K::Kc7, AltGr),
+        '(' => (K::Kc8, Shift),
+        '[' => (K::Kc8, AltGr),
+        ')' => (K::Kc9, Shift),
+        ']' => (K::Kc9, AltGr),
+        '=' => (K::Kc0, Shift),
+        '}' => (K::Kc0, AltGr),
+        'ß' => (K::Minus, Plain),
+        '?' => (K::Minus, Shift),
+        '\\' => (K::Minus, AltGr),
+        '´' => (K::Equal, Plain),
+        '`' => (K::Equal, Shift),
+        'ü' => (K::LeftBracket, Plain),
+        'Ü' => (K::LeftBracket, Shift),
+        '+' => (K::RightBracket, Plain),
+        '*' => (K::RightBracket, Shift),
+        '~' => (K::RightBracket, AltGr),
+        '#' => (K::NonusHash, Plain),
+        '\'' => (K::NonusHash, Shift),
+        'ö' => (K::Semicolon, Plain),
+        'Ö' => (K::Semicolon, Shift),
+        'ä' => (K::Quote, Plain),
+        'Ä' => (K::Quote, Shift),
+        ',' => (K::Comma, Plain),
+        ';' => (K::Comma, Shift),
+        '.' => (K::Dot, Plain),
+        ':' => (K::Dot, Shift),
+        '-' => (K::Slash, Plain),
+        '_' => (K::Slash, Shift),
+        '<' => (K::NonusBackslash, Plain),
+        '>' => (K::NonusBackslash, Shift),
+        '|' => (K::NonusBackslash, AltGr),
+        '@' => (K::Q, AltGr),
+        '€' => (K::E, AltGr),
+        'µ' => (K::M, AltGr),
+        _ => return letter_or_digit(c),
+    };
+    Some(key)
+}
+
+const NORDIC_DEAD_CHARS: [char; 5] = ['´', '`', '¨', '^', '~'];
+
+const fn nordic(c: char) -> Option<(KeyCode, Level)> {
+    let key = match c {
+        '§' => (K::Grave, Plain),
+        '½' => (K::Grave, Shift),
+        '!' => (K::Kc1, Shift),
+        '"' => (K::Kc2, Shift),
+        '@' => (K::Kc2, AltGr),
+        '#' => (K::Kc3, Shift),
+        '£' => (K::Kc3, AltGr),
+        '¤' => (K::Kc4, Shift),
+        '$' => (K::Kc4, AltGr),
+        '%' => (K::Kc5, Shift),
+        '€' => (K::Kc5, AltGr),
+        '&' => (K::Kc6, Shift),
+        '/' => (K::Kc7, Shift),
+        '{' => (K::Kc7, AltGr),
+        '(' => (K::Kc8, Shift),
+        '[' => (K::Kc8, AltGr),
+        ')' => (K::Kc9, Shift),
+        ']' => (K::Kc9, AltGr),
+        '=' => (K::Kc0, Shift),
+        '}' => (K::Kc0, AltGr),
+        '+' => (K::Minus, Plain),
+        '?' => (K::Minus, Shift),
+        '\\' => (K::Minus, AltGr),
+        '´' => (K::Equal, Plain),
+        '`' => (K::Equal, Shift),
+        'å' => (K::LeftBracket, Plain),
+        'Å' => (K::LeftBracket, Shift),
+        '¨' => (K::RightBracket, Plain),
+        '^' => (K::RightBracket, Shift),
+        '~' => (K::RightBracket, AltGr),
+        '\'' => (K::NonusHash, Plain),
+        '*' => (K::NonusHash, Shift),
+        'ö' => (K::Semicolon, Plain),
+        'Ö' => (K::Semicolon, Shift),
+        'ä' => (K::Quote, Plain),
+        'Ä' => (K::Quote, Shift),
+        ',' => (K::Comma, Plain),
+        ';' => (K::Comma, Shift),
+        '.' => (K::Dot, Plain),
+        ':' => (K::Dot, Shift),
+        '-' => (K::Slash, Plain),
+        '_' => (K::Slash, Shift),
+        '<' => (K::NonusBackslash, Plain),
+        '>' => (K::NonusBackslash, Shift),
+        '|' => (K::NonusBackslash, AltGr),
+        'µ' => (K::M, AltGr),
+        _ => return letter_or_digit(c),
+    };
+    Some(key)
+}
+
+/// How a character is typed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CharKey {
+    pub keycode: KeyCode,
+    /// Empty for unshifted characters
+    pub modifiers: ModifierCombination,
+    /// The key is a dead key, space has to follow it
+    pub dead: bool,
+}
+
+/// Index of `c` in `chars`
+const fn position(chars: &[char], c: char) -> Option<usize> {
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == c {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+const fn lookup(layout: HostLayout, c: char) -> Option<(KeyCode, Level)> {
+    match layout {
+        HostLayout::Us => us(c),
+        HostLayout::German => german(c),
+        HostLayout::Nordic => nordic(c),
+    }
+}
+
+/// How `c` is typed on `layout`, `None` if the layout doesn't have it
+pub const fn char_key(layout: HostLayout, c: char) -> Option<CharKey> {
+    let Some((keycode, level)) = lookup(layout, c) else {
+        return None;
+    };
+    let modifiers = match level {
+        Plain => ModifierCombination::new(),
+        Shift => ModifierCombination::LSHIFT,
+        AltGr => ModifierCombination::RALT,
+    };
+    Some(CharKey {
+        keycode,
+        modifiers,
+        dead: position(layout.dead_chars(), c).is_some(),
+    })
+}
+
+/// The action typing `c` on `layout`, what `ch!` puts in the keymap. A dead
+/// key character triggers its macro, they come after [`MACROS`].
+///
+/// Panics if the layout doesn't have `c`, which fails the build when the
+/// keymap is evaluated for that layout.
+pub const fn char_action(layout: HostLayout, c: char) -> Action {
+    let Some((keycode, level)) = lookup(layout, c) else {
+        let message = Message::new()
+            .push("`")
+            .push_char(c)
+            .push("` can't be typed on the ")
+            .push(layout.name())
+            .push(" host layout");
+        panic!("{}", message.as_str());
+    };
+    if let Some(i) = position(layout.dead_chars(), c) {
+        return Action::TriggerMacro((MACROS.len() + i) as u8);
+    }
+    match level {
+        Plain => Action::Key(keycode),
+        Shift => Action::KeyWithModifier(keycode, ModifierCombination::LSHIFT),
+        AltGr => Action::KeyWithModifier(keycode, ModifierCombination::RALT),
+    }
+}
+
+/// The character shift types with `c` on a US layout, `!` for `1`. An
+/// `ash!` key types it when held, on any host layout.
+pub const fn us_shifted(c: char) -> char {
+    if let Some((keycode, Plain)) = us(c) {
+        let mut shifted = b' ';
+        while shifted <= b'~' {
+            if let Some((other, Shift)) = us(shifted as char)
+                && other as u16 == keycode as u16
+            {
+                return shifted as char;
+            }
+            shifted += 1;
+        }
+    }
+    let message = Message::new()
+        .push("`")
+        .push_char(c)
+        .push("` has no shifted character on a US layout");
+    panic!("{}", message.as_str());
+}
+
+/// Decodes the character of UTF-8 `bytes` at `at`, with its length
+const fn decode(bytes: &[u8], at: usize) -> (char, usize) {
+    let (len, first) = match bytes[at] {
+        b if b < 0x80 => (1, b as u32),
+        b if b >= 0xf0 => (4, b as u32 & 0x07),
+        b if b >= 0xe0 => (3, b as u32 & 0x0f),
+        b => (2, b as u32 & 0x1f),
+    };
+    let mut code = first;
+    let mut i = 1;
+    while i < len {
+        code = (code << 6) | (bytes[at + i] as u32 & 0x3f);
+        i += 1;
+    }
+    match char::from_u32(code) {
+        Some(c) => (c, len),
+        None => (char::REPLACEMENT_CHARACTER, len),
+    }
+}
+
+/// Panics like [`char_action`] on the first character of `text` that
+/// `layout` can't type, so a macro text fails the build too
+pub const fn check_text(layout: HostLayout, text: &str) {
+    let bytes = text.as_bytes();
+    let mut at = 0;
+    while at < bytes.len() {
+        let (c, len) = decode(bytes, at);
+        char_action(layout, c);
+        at += len;
+    }
+}
+
+/// Adds the operations typing `key` to `operations`. The modifier is pressed
+/// around the key, rmk's `Text` operation only knows US characters.
+fn push_key<const N: usize>(operations: &mut heapless::Vec<MacroOperation, N>, key: CharKey) {
+    let modifier = if key.modifiers == ModifierCombination::LSHIFT {
+        Some(KeyCode::LShift)
+    } else if key.modifiers == ModifierCombination::RALT {
+        Some(KeyCode::RAlt)
+    } else {
+        None
+    };
+    let steps = [
+        modifier.map(MacroOperation::Press),
+        Some(MacroOperation::Tap(key.keycode)),
+        modifier.map(MacroOperation::Release),
+        key.dead.then_some(MacroOperation::Tap(KeyCode::Space)),
+    ];
+    for step in steps.into_iter().flatten() {
+        operations
+            .push(step)
+            .expect("The macros don't fit rmk's macro space");
+    }
+}
+
+/// rmk's macro typing `text` on `layout`, see [`check_text`] for the
+/// characters it doesn't have
+pub fn text_macro<const N: usize>(
+    layout: HostLayout,
+    text: &str,
+) -> heapless::Vec<MacroOperation, N> {
+    let mut operations = heapless::Vec::new();
+    for key in text.chars().filter_map(|c| char_key(layout, c)) {
+        push_key(&mut operations, key);
+    }
+    operations
+}
+
+/// rmk's macros typing the dead key characters of `layout`, in the order of
+/// [`HostLayout::dead_chars`]
+pub fn dead_key_macros<const N: usize>(
+    layout: HostLayout,
+) -> impl Iterator<Item = heapless::Vec<MacroOperation, N>> {
+    layout
+        .dead_chars()
+        .iter()
+        .map(move |&c| text_macro(layout, c.encode_utf8(&mut [0; 4])))
+}
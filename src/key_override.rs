@@ -0,0 +1,49 @@
+//! Key overrides: a key sends another key while some modifiers are held,
+//! without those modifiers.
+//!
+//! rmk calls them forks and checks the modifiers that are held at the time,
+//! so the shift of the `hrm!` keys counts as well as a shift key. The keys
+//! are actions, so a character of the host layout can be one, see
+//! `char_action`.
+
+use rmk::fork::{Fork, StateBits};
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::led_indicator::LedIndicator;
+use rmk::types::modifier::ModifierCombination;
+use rmk::types::mouse_button::MouseButtons;
+
+/// Sends `output` instead of `trigger` while any of `modifiers` is held
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyOverride {
+    pub trigger: Action,
+    pub modifiers: ModifierCombination,
+    pub output: Action,
+}
+
+impl KeyOverride {
+    pub const fn new(trigger: Action, modifiers: ModifierCombination, output: Action) -> Self {
+        Self {
+            trigger,
+            modifiers,
+            output,
+        }
+    }
+
+    /// rmk's fork of the override, the matched modifiers are not sent along
+    /// with `output`
+    pub fn fork(&self) -> Fork {
+        Fork::new(
+            KeyAction::Single(self.trigger),
+            KeyAction::Single(self.trigger),
+            KeyAction::Single(self.output),
+            StateBits::new_from(
+                self.modifiers,
+                LedIndicator::default(),
+                MouseButtons::default(),
+            ),
+            StateBits::default(),
+            ModifierCombination::default(),
+            false,
+        )
+    }
+}
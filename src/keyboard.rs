@@ -0,0 +1,453 @@
+//! rmk's keyboard with the behaviors of the keymap that rmk doesn't have.
+//!
+//! [`Keyboard`] runs rmk's keyboard loop itself, so it sees every key event
+//! before rmk handles it. What rmk made of a key comes back on the controller
+//! channel while rmk handles it: the action of the key on the active layers,
+//! and the modifiers and layer it changed.
+//!
+//! The keys of a leader sequence never reach rmk, the keyboard runs the
+//! sequence itself once it is complete. It also turns a locked layer back on
+//! after rmk turned it off, and resolves the tap-hold keys with rules before
+//! rmk sees the next key, see `tap_hold.rs`. Each tap-hold key waits for the
+//! tapping term in use for it, longer while typing fast, see
+//! `tapping_term.rs` and `typing_speed.rs`. An auto-shift key is tapped when
+//! the next key is pressed, see `auto_shift.rs`.
+//!
+//! The layers and profiles the host asks for over raw HID are switched by
+//! keys of the leader layer as well, see `remote.rs`. The keyboard also
+//! counts the presses of each key, see `stats.rs`.
+
+use core::cell::RefCell;
+
+use embassy_time::{Duration, Instant, with_deadline};
+use rmk::channel::{CONTROLLER_CHANNEL, ControllerSub, KEY_EVENT_CHANNEL};
+use rmk::embassy_futures::select::{Either, select};
+use rmk::event::{ControllerEvent, KeyboardEvent};
+use rmk::input_device::Runnable;
+use rmk::keymap::KeyMap;
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+
+use crate::auto_shift::{self, AUTO_SHIFT_TOGGLE};
+use crate::keymap::{
+    ADAPTIVE_TERM, COL, FLOW_TAP_MS, LEADER_LAYER_KEY, LEADER_TIMEOUT_MS, LOCKABLE_LAYERS,
+    NUM_LAYER, ONE_SHOT, ONE_SHOT_KEYS, ROW, TAP_HOLD_KEYS,
+};
+use crate::layer_lock::{LAYER_LOCK, LayerLock};
+use crate::leader::{LEADER, Leader, Take, layer_toggle_position, sequence_position};
+use crate::one_shot::{self, OneShot, OneShots};
+use crate::remote::{self, Remote};
+use crate::stats;
+use crate::tap_hold::{self, Resolve};
+use crate::tapping_term;
+use crate::typing_speed::TypingSpeed;
+
+/// Position and whether it is a press, rmk keeps them to itself
+pub(crate) fn decode(event: KeyboardEvent) -> Option<((u8, u8), bool)> {
+    for row in 0..ROW as u8 {
+        for col in 0..COL as u8 {
+            for pressed in [true, false] {
+                if event == KeyboardEvent::key(row, col, pressed) {
+                    return Some(((row, col), pressed));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Whether a key with `action` types something, so it uses up the one-shot
+/// modifiers and layers
+fn types(action: &KeyAction) -> bool {
+    match action {
+        KeyAction::Single(action) | KeyAction::Tap(action) => match action {
+            Action::Key(code) => !code.is_modifier() && !code.is_user() && *code != KeyCode::No,
+            Action::KeyWithModifier(..) | Action::TriggerMacro(_) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+pub struct Keyboard<'a> {
+    inner: rmk::keyboard::Keyboard<'a, ROW, COL, NUM_LAYER>,
+    sub: ControllerSub,
+    one_shots: OneShots<{ ONE_SHOT_KEYS.len() }>,
+    /// Whether the hold position of each one-shot key is pressed
+    one_shots_held: [bool; ONE_SHOT_KEYS.len()],
+    leader: Leader,
+    layer_lock: LayerLock,
+    /// Tap-hold keys released to rmk to tap them, their real release is kept
+    /// from rmk
+    tapped: [[bool; COL]; ROW],
+    last_press_ms: Option<u64>,
+    typing_speed: Option<TypingSpeed>,
+    /// Held modifiers and highest layer, as rmk last reported them
+    modifiers: u8,
+    layer: u8,
+}
+
+impl<'a> Keyboard<'a> {
+    pub fn new(keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER>>) -> Self {
+        Self {
+            inner: rmk::keyboard::Keyboard::new(keymap),
+            sub: CONTROLLER_CHANNEL
+                .subscriber()
+                .expect("No subscriber left for the keyboard"),
+            one_shots: OneShots::new(ONE_SHOT),
+            one_shots_held: [false; ONE_SHOT_KEYS.len()],
+            leader: Leader::new(LEADER_TIMEOUT_MS),
+            layer_lock: LayerLock::new(),
+            tapped: [[false; COL]; ROW],
+            last_press_ms: None,
+            typing_speed: ADAPTIVE_TERM.map(TypingSpeed::new),
+            modifiers: 0,
+            layer: 0,
+        }
+    }
+
+    fn now_ms() -> u64 {
+        Instant::now().as_millis()
+    }
+
+    async fn process(&mut self, event: KeyboardEvent) {
+        let key = decode(event);
+        if let Some((pos, pressed)) = key {
+            if pressed {
+                stats::count(pos);
+            }
+            match self.leader.take(pos, pressed, Self::now_ms()) {
+                Take::Passed => (),
+                Take::Taken => return,
+                Take::Done(sequence) => {
+                    self.run_sequence(sequence).await;
+                    return;
+                }
+            }
+            let (row, col) = pos;
+            if !pressed && core::mem::take(&mut self.tapped[row as usize][col as usize]) {
+                return;
+            }
+            if pressed {
+                self.resolve_tap_holds(pos).await;
+            }
+        }
+        self.inner.process_inner(event).await;
+        self.follow_rmk();
+        if let Some((pos, true)) = key {
+            self.auto_shift_key(pos).await;
+            self.flow_tap(pos).await;
+            self.time_tap_hold(pos);
+            if let Some(speed) = &mut self.typing_speed {
+                speed.key_pressed(Self::now_ms());
+            }
+        }
+        self.hold_one_shots().await;
+        self.toggle_locked_layer().await;
+    }
+
+    /// Resolves the tap-hold keys rmk waits on by their rules for the key
+    /// pressed at `next`
+    async fn resolve_tap_holds(&mut self, next: (u8, u8)) {
+        for key in TAP_HOLD_KEYS.iter().filter(|key| key.pos != next) {
+            if !self.is_unresolved(key.pos) {
+                continue;
+            }
+            match key.resolve(next) {
+                Some(Resolve::Tap) => self.tap_now(key.pos).await,
+                Some(Resolve::Hold) => self.hold_now(key.pos).await,
+                None => (),
+            }
+        }
+        // Only the time held makes an auto-shift key shift
+        let mut pending = self.inner.held_buffer.clone();
+        while let Some(key) = pending.remove_if(|key| auto_shift::class(&key.action).is_some()) {
+            if let Some((pos, true)) = decode(key.event)
+                && pos != next
+                && self.is_unresolved(pos)
+            {
+                self.tap_now(pos).await;
+            }
+        }
+    }
+
+    /// Taps the auto-shift key just pressed at `pos` if it doesn't shift, so
+    /// it types at once
+    async fn auto_shift_key(&mut self, pos: (u8, u8)) {
+        if let Some(action) = self.buffered(pos)
+            && auto_shift::class(&action).is_some()
+            && !auto_shift::shifts(&action, auto_shift::is_enabled())
+            && self.is_unresolved(pos)
+        {
+            self.tap_now(pos).await;
+        }
+    }
+
+    /// Taps the tap-hold key just pressed at `pos` if it has flow tap and
+    /// follows the key before closely, or ends a burst of fast typing
+    async fn flow_tap(&mut self, pos: (u8, u8)) {
+        let now_ms = Self::now_ms();
+        let typing = self
+            .last_press_ms
+            .is_some_and(|last| now_ms.saturating_sub(last) < FLOW_TAP_MS)
+            || self
+                .typing_speed
+                .as_ref()
+                .is_some_and(|speed| speed.taps(now_ms));
+        self.last_press_ms = Some(now_ms);
+        if typing && tap_hold::find(pos).is_some_and(|key| key.flow_tap) && self.is_unresolved(pos)
+        {
+            self.tap_now(pos).await;
+        }
+    }
+
+    /// Gives the tap-hold key just pressed at `pos` its tapping term, rmk
+    /// only knows the terms of the first boot after a flash and not the
+    /// typing speed
+    fn time_tap_hold(&mut self, (row, col): (u8, u8)) {
+        // Auto-shift keys have a time of their own
+        let auto_shift = self
+            .buffered((row, col))
+            .is_some_and(|action| auto_shift::class(&action).is_some());
+        if auto_shift || !self.is_unresolved((row, col)) {
+            return;
+        }
+        let press = KeyboardEvent::key(row, col, true);
+        if let Some(mut key) = self.inner.held_buffer.remove_if(|key| key.event == press) {
+            let extra = self
+                .typing_speed
+                .as_ref()
+                .map_or(0, |speed| speed.extra_term_ms(Self::now_ms()));
+            let term = tapping_term::current().of((row, col)) + extra;
+            key.timeout_time = key.press_time + Duration::from_millis(term.into());
+            self.inner.held_buffer.push(key);
+        }
+    }
+
+    /// The action rmk buffered for the key pressed at `pos`
+    fn buffered(&self, (row, col): (u8, u8)) -> Option<KeyAction> {
+        let press = KeyboardEvent::key(row, col, true);
+        self.inner
+            .held_buffer
+            .next_timeout(|key| key.event == press)
+            .map(|key| key.action)
+    }
+
+    /// Whether rmk still waits to resolve the tap-hold key pressed at `pos`
+    fn is_unresolved(&mut self, (row, col): (u8, u8)) -> bool {
+        let press = KeyboardEvent::key(row, col, true);
+        // rmk only tells whether the first of its buffered keys waits, so it
+        // is asked with the key alone in its buffer
+        let mut alone = self.inner.held_buffer.clone();
+        while alone.remove_if(|key| key.event != press).is_some() {}
+        let buffer = core::mem::replace(&mut self.inner.held_buffer, alone);
+        let unresolved = self
+            .inner
+            .next_buffered_key()
+            .is_some_and(|key| key.action.is_morse());
+        self.inner.held_buffer = buffer;
+        unresolved
+    }
+
+    /// Releases the tap-hold key at `pos` to rmk, which taps it
+    async fn tap_now(&mut self, (row, col): (u8, u8)) {
+        self.tapped[row as usize][col as usize] = true;
+        self.inner
+            .process_inner(KeyboardEvent::key(row, col, false))
+            .await;
+        self.follow_rmk();
+    }
+
+    /// Times the tap-hold key at `pos` out in rmk, which holds it
+    async fn hold_now(&mut self, (row, col): (u8, u8)) {
+        let press = KeyboardEvent::key(row, col, true);
+        let Some(mut key) = self
+            .inner
+            .held_buffer
+            .next_timeout(|key| key.event == press)
+        else {
+            return;
+        };
+        // rmk's timeout takes a key event that is already waiting, the
+        // keyboard keeps them for itself. Without room for them rmk decides.
+        while !KEY_EVENT_CHANNEL.is_empty() {
+            if self.inner.unprocessed_events.is_full() {
+                return;
+            }
+            if let Ok(event) = KEY_EVENT_CHANNEL.try_receive() {
+                let _ = self.inner.unprocessed_events.push(event);
+            }
+        }
+        key.timeout_time = Instant::now();
+        self.inner.process_buffered_key(key).await;
+        self.follow_rmk();
+    }
+
+    /// Goes through what rmk did since the last call
+    fn follow_rmk(&mut self) {
+        let now_ms = Self::now_ms();
+        // A tap-hold key was released, it was a hold if its modifier or layer
+        // goes off right after
+        let mut tap_hold_released = false;
+        while let Some(event) = self.sub.try_next_message_pure() {
+            match event {
+                ControllerEvent::Key(event, action) => {
+                    if tap_hold_released {
+                        self.one_shots.use_up();
+                        tap_hold_released = false;
+                    }
+                    let Some((pos, pressed)) = decode(event) else {
+                        continue;
+                    };
+                    if one_shot::is_hold_position(pos) {
+                        continue;
+                    }
+                    if pressed && action == LEADER {
+                        self.leader.start(now_ms);
+                    }
+                    if pressed && action == LAYER_LOCK {
+                        self.layer_lock.lock_key(self.layer);
+                    }
+                    if pressed && action == AUTO_SHIFT_TOGGLE {
+                        auto_shift::set_enabled(!auto_shift::is_enabled());
+                    }
+                    match (one_shot::find(&action), pressed) {
+                        (Some(i), true) => self.one_shots.press(i),
+                        (Some(i), false) => self.one_shots.release(i, now_ms),
+                        (None, false) if action.is_morse() => tap_hold_released = true,
+                        (None, false) if types(&action) => self.one_shots.use_up(),
+                        (None, _) => (),
+                    }
+                }
+                ControllerEvent::Modifier(modifiers) => {
+                    tap_hold_released = false;
+                    self.modifiers = modifiers.into_bits();
+                }
+                ControllerEvent::Layer(layer) => {
+                    tap_hold_released = false;
+                    self.layer = layer;
+                    self.layer_lock.layer(layer);
+                }
+                _ => (),
+            }
+        }
+        if tap_hold_released {
+            self.one_shots.use_up();
+        }
+    }
+
+    /// Taps the key of leader sequence `i` on the leader layer
+    async fn run_sequence(&mut self, i: usize) {
+        self.tap_on_leader_layer(sequence_position(i)).await;
+        self.hold_one_shots().await;
+        self.toggle_locked_layer().await;
+    }
+
+    /// Taps the key on the leader layer that does what the host asked for
+    async fn run_remote(&mut self, remote: Remote) {
+        if let Some(pos) = remote.position() {
+            self.tap_on_leader_layer(pos).await;
+            self.hold_one_shots().await;
+        }
+    }
+
+    /// Processes the next key event, or runs the next request of the host
+    async fn receive(&mut self, input: Either<KeyboardEvent, Remote>) {
+        match input {
+            Either::First(event) => self.process(event).await,
+            Either::Second(remote) => self.run_remote(remote).await,
+        }
+    }
+
+    /// Taps the key at `key` while the leader layer is held
+    async fn tap_on_leader_layer(&mut self, key: (u8, u8)) {
+        let hold = LEADER_LAYER_KEY;
+        for ((row, col), pressed) in [(hold, true), (key, true), (key, false), (hold, false)] {
+            self.inner
+                .process_inner(KeyboardEvent::key(row, col, pressed))
+                .await;
+            self.follow_rmk();
+        }
+    }
+
+    /// Turns the locked layer back on once its key is released, or off when
+    /// it is unlocked
+    async fn toggle_locked_layer(&mut self) {
+        if let Some(layer) = self.layer_lock.take_toggle()
+            && let Some(i) = LOCKABLE_LAYERS.iter().position(|&l| l == layer)
+        {
+            self.tap_on_leader_layer(layer_toggle_position(i)).await;
+        }
+    }
+
+    /// Presses or releases the hold positions of the one-shot keys
+    async fn hold_one_shots(&mut self) {
+        for (i, key) in ONE_SHOT_KEYS.iter().enumerate() {
+            let on = self.one_shots.is_on(i);
+            // Another key of the same modifier or layer turned it off in rmk,
+            // press it again
+            let lost = on
+                && self.one_shots_held[i]
+                && match key.one_shot {
+                    OneShot::Modifier(code) => {
+                        self.modifiers & code.to_hid_modifiers().into_bits() == 0
+                    }
+                    OneShot::Layer(layer) => self.layer < layer,
+                };
+            if on != self.one_shots_held[i] || lost {
+                self.one_shots_held[i] = on;
+                let (row, col) = key.hold;
+                self.inner
+                    .process_inner(KeyboardEvent::key(row, col, on))
+                    .await;
+                self.follow_rmk();
+            }
+        }
+    }
+}
+
+impl Runnable for Keyboard<'_> {
+    /// rmk's keyboard loop, with the one-shot keys and leader sequences timing
+    /// out and the requests of the host as well
+    async fn run(&mut self) {
+        loop {
+            if !self.inner.unprocessed_events.is_empty() {
+                let event = self.inner.unprocessed_events.remove(0);
+                self.process(event).await;
+                continue;
+            }
+            let key = self.inner.next_buffered_key();
+            let deadline = [
+                key.map(|key| key.timeout_time),
+                self.one_shots.deadline_ms().map(Instant::from_millis),
+                self.leader.deadline_ms().map(Instant::from_millis),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let next = select(KEY_EVENT_CHANNEL.receive(), remote::next());
+            let Some(deadline) = deadline else {
+                let input = next.await;
+                self.receive(input).await;
+                continue;
+            };
+            match with_deadline(deadline, next).await {
+                Ok(input) => self.receive(input).await,
+                Err(_) => {
+                    if let Some(key) = key
+                        && key.timeout_time <= Instant::now()
+                    {
+                        // Nothing can be sent to the channel before rmk
+                        // looks at it, so rmk times the key out as well
+                        self.inner.process_buffered_key(key).await;
+                        self.follow_rmk();
+                    }
+                    self.one_shots.time_out(Self::now_ms());
+                    self.leader.time_out(Self::now_ms());
+                    self.hold_one_shots().await;
+                    self.toggle_locked_layer().await;
+                }
+            }
+        }
+    }
+}
@@ -1,50 +1,408 @@
-#[cfg(not(any(feature = "peripheral_left", feature = "peripheral_right")))]
+use rmk::config::{BehaviorConfig, Hand, PositionalConfig};
+use rmk::heapless;
+use rmk::keyboard_macros::define_macro_sequences;
 use rmk::types::{
     action::{Action, KeyAction, MorseMode, MorseProfile},
     keycode::KeyCode,
     modifier::ModifierCombination,
 };
-#[cfg(not(any(feature = "peripheral_left", feature = "peripheral_right")))]
-use rmk::{a, k, mo, to, wm};
+use rmk::{a, df, k, mo, td, tg};
 
-pub(crate) const COL: usize = 12;
-pub(crate) const ROW: usize = 4;
-#[cfg(not(any(feature = "peripheral_left", feature = "peripheral_right")))]
-pub(crate) const NUM_LAYER: usize = 5;
+use crate::auto_shift::{AUTO_SHIFT_TOGGLE, AutoShiftClasses, auto_shift};
+use crate::host_layout::{HostLayout, char_action, check_text, dead_key_macros, text_macro};
+use crate::key_override::KeyOverride;
+use crate::layer_lock::LAYER_LOCK;
+use crate::leader::{LEADER, LeaderSequence, leader_layer};
+use crate::mouse::{MouseCurve, MouseKey};
+use crate::one_shot::{DoubleTap, OneShot, OneShotConfig, OneShotKey, one_shot_key};
+use crate::radio::{ConnParams, Link, TxPower, assert_valid};
+use crate::tap_dance::TapDance;
+use crate::tap_hold::{Hands, Resolve, Rows, TapHoldKey, TapHoldRule};
+use crate::tapping_term::{TERM_DOWN, TERM_PRINT, TERM_RESET, TERM_SELECT, TERM_UP, TappingTerms};
+use crate::typing_speed::AdaptiveTermConfig;
+use crate::validate::validate_keymap;
 
-#[cfg(not(any(feature = "peripheral_left", feature = "peripheral_right")))]
-#[rustfmt::skip]
+pub const COL: usize = 12;
+pub const ROW: usize = 4;
+pub const NUM_LAYER: usize = 9;
+
+/// Names of the layers, as in the comments of `keymap`
+pub const LAYER_NAMES: [&str; NUM_LAYER] = [
+    "base",
+    "num",
+    "nav",
+    "gaming base",
+    "gaming upper",
+    "adjust",
+    "mouse",
+    "media",
+    "leader",
+];
+
+/// Layout the host is set to. `ch!` and `ash!` keys, the key overrides and
+/// the macros type their characters with its keys, keys written as `k!` stay
+/// at their US position.
+pub const HOST_LAYOUT: HostLayout = HostLayout::Us;
+
+/// How long a tap-hold key or tap dance has to be held to do its hold, and
+/// how long a tap dance waits for another tap. The global tapping term until
+/// it is changed from the adjust layer, see `tapping_term.rs`.
+pub const TAPPING_TERM_MS: u16 = 175;
+/// How much the adjust layer raises or lowers a tapping term, and how far
+pub const TAPPING_TERM_STEP_MS: u16 = 25;
+pub const TAPPING_TERM_MIN_MS: u16 = 50;
+pub const TAPPING_TERM_MAX_MS: u16 = 1000;
+
+/// Which hand types each key, the left half has the first six columns
+pub const HANDS: [[Hand; COL]; ROW] = {
+    let mut hands = [[Hand::Left; COL]; ROW];
+    let mut row = 0;
+    while row < ROW {
+        let mut col = COL / 2;
+        while col < COL {
+            hands[row][col] = Hand::Right;
+            col += 1;
+        }
+        row += 1;
+    }
+    hands
+};
+
+/// A tap-hold key with flow tap pressed this soon after the key before is a
+/// tap
+pub const FLOW_TAP_MS: u64 = 120;
+
+/// While typing fast the tapping terms get longer, so rolls over the home row
+/// mods don't turn into holds, see `typing_speed.rs`. `None` keeps the terms
+/// as they are.
+pub const ADAPTIVE_TERM: Option<AdaptiveTermConfig> = Some(AdaptiveTermConfig {
+    fast_interval_ms: 150,
+    min_burst: 3,
+    idle_ms: 500,
+    extra_term_ms: 75,
+    tap_in_burst: false,
+});
+
+/// The thumb layer keys are held for any key of the other hand and tapped
+/// for a thumb key of their own. Other keys of their hand are left to
+/// permissive hold, so the nav arrows need no wait.
+const THUMB_RULES: [TapHoldRule; 2] = [
+    TapHoldRule::new(Hands::Opposite, Rows::Any, Resolve::Hold),
+    TapHoldRule::new(Hands::Same, Rows::Same, Resolve::Tap),
+];
+
+/// The home row mods are tapped for a key of their own hand, a roll, unless it
+/// is on another row. There it is a shortcut, as Ctrl+W, left to permissive
+/// hold like a key of the other hand.
+const HOME_ROW_RULES: [TapHoldRule; 1] = [TapHoldRule::new(Hands::Same, Rows::Same, Resolve::Tap)];
+
+/// The tap-hold keys resolved by the next key pressed, see `tap_hold.rs`.
+/// Flow tap is for the home row mods only, a thumb key right after typing
+/// is still held for a layer.
+pub const TAP_HOLD_KEYS: [TapHoldKey; 10] = [
+    TapHoldKey::new((1, 1), &HOME_ROW_RULES, true),
+    TapHoldKey::new((1, 2), &HOME_ROW_RULES, true),
+    TapHoldKey::new((1, 3), &HOME_ROW_RULES, true),
+    TapHoldKey::new((1, 4), &HOME_ROW_RULES, true),
+    TapHoldKey::new((1, 7), &HOME_ROW_RULES, true),
+    TapHoldKey::new((1, 8), &HOME_ROW_RULES, true),
+    TapHoldKey::new((1, 9), &HOME_ROW_RULES, true),
+    TapHoldKey::new((1, 10), &HOME_ROW_RULES, true),
+    TapHoldKey::new((3, 5), &THUMB_RULES, false),
+    TapHoldKey::new((3, 6), &THUMB_RULES, false),
+];
+
+/// How long an `ash!` key is held to type its shifted form, see
+/// `auto_shift.rs`
+pub const AUTO_SHIFT_MS: u16 = 200;
+/// Only the time held counts, the keyboard taps the key when another one is
+/// pressed
+pub const AUTO_SHIFT_PROFILE: MorseProfile =
+    MorseProfile::new(None, Some(MorseMode::Normal), Some(AUTO_SHIFT_MS), None);
+/// Which `ash!` keys auto-shift, the others type like plain keys
+pub const AUTO_SHIFT_CLASSES: AutoShiftClasses = AutoShiftClasses {
+    digits: true,
+    symbols: true,
+};
+
+/// Holding both the num (1) and the nav (2) layer turns on the adjust layer (5)
+pub const TRI_LAYER: [u8; 3] = [1, 2, 5];
+
+/// Matrix positions (row, col) that have to be held together to unlock Vial
+/// editing, Q and P on the base layer
+pub const VIAL_UNLOCK_KEYS: [(u8, u8); 2] = [(0, 1), (0, 10)];
+
+const _: () = {
+    let mut i = 0;
+    while i < VIAL_UNLOCK_KEYS.len() {
+        let (row, col) = VIAL_UNLOCK_KEYS[i];
+        assert!(
+            (row as usize) < ROW,
+            "Vial unlock key row is out of the matrix"
+        );
+        assert!(
+            (col as usize) < COL,
+            "Vial unlock key col is out of the matrix"
+        );
+        i += 1;
+    }
+};
+
+/// Positions of the matrix without a switch, the thumb row only has three
+/// keys per half
+pub const UNWIRED_KEYS: [(u8, u8); 6] = [(3, 0), (3, 1), (3, 2), (3, 9), (3, 10), (3, 11)];
+
+/// How long a tapped one-shot key waits for the key it applies to, and what
+/// tapping it twice does
+pub const ONE_SHOT: OneShotConfig = OneShotConfig {
+    timeout_ms: 1000,
+    double_tap: DoubleTap::Lock,
+};
+
+/// The one-shot keys of the keymap, written as `one_shot!(LShift)` or
+/// `one_shot!(1)`. Each holds its modifier or layer down with the key at an
+/// unwired position, which has that key on every layer.
+pub const ONE_SHOT_KEYS: [OneShotKey; 5] = [
+    OneShotKey::new(KeyCode::User16, OneShot::Modifier(KeyCode::LShift), (3, 0)),
+    OneShotKey::new(KeyCode::User17, OneShot::Modifier(KeyCode::LCtrl), (3, 1)),
+    OneShotKey::new(KeyCode::User18, OneShot::Modifier(KeyCode::LAlt), (3, 2)),
+    OneShotKey::new(KeyCode::User19, OneShot::Modifier(KeyCode::LGui), (3, 9)),
+    OneShotKey::new(KeyCode::User20, OneShot::Layer(1), (3, 10)),
+];
+
+/// The tap dances of the keymap, `td!(i)` is `TAP_DANCES[i]`
+pub const TAP_DANCES: [TapDance; 1] = [
+    // Escape, twice for the gaming default layer, held for the nav layer
+    TapDance::new(
+        [
+            Action::Key(KeyCode::Escape),
+            Action::DefaultLayer(3),
+            Action::No,
+        ],
+        [Action::LayerOn(2), Action::No, Action::No],
+    ),
+];
+
+/// A key pressed while a tap dance is held makes it a hold
+pub const TAP_DANCE_PROFILE: MorseProfile =
+    MorseProfile::new(None, Some(MorseMode::HoldOnOtherPress), None, None);
+
+/// Layers the layer lock key keeps on, num and nav
+pub const LOCKABLE_LAYERS: [u8; 2] = [1, 2];
+
+/// Layers the host can turn on and off over raw HID, num, nav, mouse and
+/// media, see `remote.rs`
+pub const REMOTE_LAYERS: [u8; 4] = [1, 2, 6, 7];
+
+/// rmk's keys that switch to each BLE profile, `User0` to `User2` with its
+/// default of three profiles
+pub const PROFILE_KEYS: [KeyCode; 3] = [KeyCode::User0, KeyCode::User1, KeyCode::User2];
+
+/// The links between the dongle and the halves, applied by both ends, see
+/// `radio.rs`. `ConnParams::BATTERY` lets the halves run longer at the cost
+/// of delay.
+pub const SPLIT_LINK: Link = Link {
+    tx_power: TxPower::Zero,
+    params: ConnParams::LOW_LATENCY,
+};
+
+/// The dongle's link to the host. The host has the last word on its
+/// parameters.
+pub const HOST_LINK: Link = Link {
+    tx_power: TxPower::Zero,
+    params: ConnParams::LOW_LATENCY,
+};
+
+const _: () = {
+    assert_valid("SPLIT_LINK", &SPLIT_LINK.params);
+    assert_valid("HOST_LINK", &HOST_LINK.params);
+};
+
+/// Layer with the keys of the leader sequences, see `leader.rs`
+pub const LEADER_LAYER: u8 = 8;
+/// Unwired position that holds the leader layer on every layer
+pub const LEADER_LAYER_KEY: (u8, u8) = (3, 11);
+/// How long the leader key waits for the next key of a sequence
+pub const LEADER_TIMEOUT_MS: u64 = 1000;
+
+/// rmk's key that clears the pairing of the active BLE profile, `User5` with
+/// its default of three profiles
+const CLEAR_PAIRING: KeyAction = k!(User5);
+
+/// The key sequences after the leader key and the keys they run. No sequence
+/// may be the start of another one.
+pub const LEADER_SEQUENCES: [LeaderSequence; 5] = [
+    LeaderSequence::new(
+        &[KeyCode::B, KeyCode::O, KeyCode::O, KeyCode::T],
+        k!(Bootloader),
+    ),
+    LeaderSequence::new(&[KeyCode::C, KeyCode::L, KeyCode::R], CLEAR_PAIRING),
+    LeaderSequence::new(&[KeyCode::M], tg!(6)),
+    LeaderSequence::new(
+        &[KeyCode::G, KeyCode::S],
+        KeyAction::Single(Action::TriggerMacro(0)),
+    ),
+    LeaderSequence::new(
+        &[KeyCode::G, KeyCode::D],
+        KeyAction::Single(Action::TriggerMacro(1)),
+    ),
+];
+
+/// Text rmk types for `Action::TriggerMacro(i)`, on the host layout. The
+/// macros typing the dead keys of the host layout come after these.
+pub const MACROS: [&str; 2] = ["git status\n", "git diff\n"];
+
+/// How often a held mouse key moves the pointer
+pub const MOUSE_TICK_MS: u64 = 16;
+/// How often a held mouse wheel key scrolls
+pub const MOUSE_WHEEL_INTERVAL_MS: u64 = 80;
+/// Pointer speed per tick, from slow and precise to fast in one second
+pub const MOUSE_MOVE_CURVE: MouseCurve = MouseCurve::Accelerated {
+    start: 2,
+    max: 24,
+    ramp_ms: 1000,
+    exponent: 2,
+};
+/// Pointer speed per tick once `ms!(Precision)` is tapped
+pub const MOUSE_PRECISION_CURVE: MouseCurve = MouseCurve::Constant { speed: 2 };
+/// Wheel units per scroll
+pub const MOUSE_WHEEL_CURVE: MouseCurve = MouseCurve::Accelerated {
+    start: 1,
+    max: 4,
+    ramp_ms: 2000,
+    exponent: 1,
+};
+
+/// Shift+Backspace is Delete and Shift+Comma is Semicolon, with either shift
+pub const KEY_OVERRIDES: [KeyOverride; 2] = [
+    KeyOverride::new(
+        Action::Key(KeyCode::Backspace),
+        SHIFT,
+        Action::Key(KeyCode::Delete),
+    ),
+    KeyOverride::new(
+        char_action(HOST_LAYOUT, ','),
+        SHIFT,
+        char_action(HOST_LAYOUT, ';'),
+    ),
+];
+
+const SHIFT: ModifierCombination = ModifierCombination::new()
+    .with_left_shift(true)
+    .with_right_shift(true);
+
+/// The keymap for [`HOST_LAYOUT`]
 pub const fn get_default_keymap() -> [[[KeyAction; COL]; ROW]; NUM_LAYER] {
+    keymap::<{ HOST_LAYOUT as u8 }>()
+}
+
+/// The keymap for the host layout `HostLayout::ALL[LAYOUT]`, which `ch!` and
+/// `ash!` pick their keys from. A character the layout can't type fails the
+/// build when the keymap is evaluated for it.
+#[rustfmt::skip]
+pub const fn keymap<const LAYOUT: u8>() -> [[[KeyAction; COL]; ROW]; NUM_LAYER] {
     [
         [ // base
-            [k!(No), k!(Q), k!(W), k!(E), k!(R), k!(T), k!(Y), k!(U), k!(I), k!(O), k!(P), to!(3)],
-            [k!(No), hrm!(A, LALT), hrm!(S, LGUI), hrm!(D, LCTRL), hrm!(F, LSHIFT), k!(G), k!(H), hrm!(J, LSHIFT), hrm!(K, LCTRL), hrm!(L, LGUI), hrm!(Semicolon, LALT), k!(Quote)],
-            [k!(No), k!(Z), k!(X), k!(C), k!(V), k!(B), k!(N), k!(M), k!(Comma), k!(Dot), k!(Slash),k!(Backslash)],
-            [k!(No), k!(No), k!(No), k!(Backspace), k!(Escape), kol!(Space, 1), kol!(Enter, 2), k!(Tab), k!(Delete), k!(No), a!(No), k!(No)],
+            [k!(No), k!(Q), k!(W), k!(E), k!(R), k!(T), k!(Y), k!(U), k!(I), k!(O), k!(P), df!(3)],
+            [LEADER, hrm!(A, LALT), hrm!(S, LGUI), hrm!(D, LCTRL), hrm!(F, LSHIFT), k!(G), k!(H), hrm!(J, LSHIFT), hrm!(K, LCTRL), hrm!(L, LGUI), hrm!(';', LALT), ch!('\'')],
+            [one_shot!(1), k!(Z), k!(X), k!(C), k!(V), k!(B), k!(N), k!(M), ch!(','), ch!('.'), ch!('/'), ch!('\\')],
+            [k!(LShift), k!(LCtrl), k!(LAlt), k!(Backspace), td!(0), kol!(Space, 1), kol!(Enter, 2), k!(Tab), one_shot!(LShift), k!(LGui), mo!(1), mo!(8)],
         ],
         [ // num
-            [a!(Transparent), a!(Transparent),a!(Transparent), k!(LeftBracket), k!(RightBracket), k!(Grave), wm!(Grave, ModifierCombination::LSHIFT), wm!(LeftBracket, ModifierCombination::LSHIFT), wm!(RightBracket, ModifierCombination::LSHIFT), a!(Transparent), a!(Transparent), a!(Transparent)],  
-            [k!(CapsLock),  k!(Kc1), k!(Kc2), k!(Kc3), k!(Kc4), k!(Kc5), k!(Kc6), k!(Kc7), k!(Kc8), k!(Kc9), k!(Kc0), a!(Transparent)], 
-            [a!(Transparent), a!(Transparent), a!(Transparent), k!(Enter), k!(Minus), wm!(Minus, ModifierCombination::LSHIFT), k!(KpEqual), k!(KpPlus), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent)], 
-            [k!(No),  k!(No), k!(No), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), k!(No), k!(No), k!(No)], 
+            [LAYER_LOCK, a!(Transparent),a!(Transparent), ash!('['), ash!(']'), ash!('`'), ch!('~'), ch!('{'), ch!('}'), a!(Transparent), a!(Transparent), a!(Transparent)],  
+            [k!(CapsLock),  ash!('1'), ash!('2'), ash!('3'), ash!('4'), ash!('5'), ash!('6'), ash!('7'), ash!('8'), ash!('9'), ash!('0'), a!(Transparent)], 
+            [a!(Transparent), a!(Transparent), a!(Transparent), k!(Enter), ash!('-'), ch!('_'), k!(KpEqual), k!(KpPlus), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent)], 
+            [k!(LShift), k!(LCtrl), k!(LAlt), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), k!(LGui), mo!(1), mo!(8)], 
         ], 
         [ // nav
-            [k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(Home), k!(PageDown), k!(PageUp), k!(End), k!(No), k!(No)], 
-            [k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(Left), k!(Down), k!(Up), k!(Right), k!(No), k!(No)], 
-            [k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No)], 
-            [k!(No), k!(No), k!(No), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), k!(No), k!(No), k!(No)], 
+            [LAYER_LOCK, k!(No), k!(No), k!(No), k!(No), mo!(7), k!(Home), k!(PageDown), k!(PageUp), k!(End), k!(No), k!(No)], 
+            [k!(No), one_shot!(LAlt), one_shot!(LGui), one_shot!(LCtrl), one_shot!(LShift), k!(No), k!(Left), k!(Down), k!(Up), k!(Right), k!(No), k!(No)], 
+            [k!(No), k!(No), k!(No), k!(No), k!(No), tg!(6), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No)], 
+            [k!(LShift), k!(LCtrl), k!(LAlt), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), k!(LGui), mo!(1), mo!(8)], 
         ],
         [ // gaming base
-            [k!(Tab), k!(Q), k!(W), k!(E), k!(R), k!(T), k!(Y), k!(U), k!(I), k!(O), k!(P),to!(0)],
+            [k!(Tab), k!(Q), k!(W), k!(E), k!(R), k!(T), k!(Y), k!(U), k!(I), k!(O), k!(P),df!(0)],
             [k!(LCtrl), k!(A), k!(S), k!(D), k!(F), k!(G), k!(H), k!(J), k!(K), k!(L), k!(No), k!(No)],
-            [k!(LShift), k!(Z), k!(X), k!(C), k!(V), k!(B), k!(N), k!(M), k!(Comma), k!(Dot), k!(No),k!(No)],
-            [k!(No), k!(No), k!(No), k!(LAlt), mo!(4), k!(Space), k!(Enter), k!(Tab), k!(Delete), k!(No), k!(No), k!(No)],
+            [k!(LShift), k!(Z), k!(X), k!(C), k!(V), k!(B), k!(N), k!(M), ch!(','), ch!('.'), k!(No),k!(No)],
+            [k!(LShift), k!(LCtrl), k!(LAlt), k!(LAlt), mo!(4), k!(Space), k!(Enter), k!(Tab), k!(Delete), k!(LGui), mo!(1), mo!(8)],
         ],
         [ // gaming upper
             [k!(Escape), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent)],
             [k!(CapsLock), k!(Kc1), k!(Kc2), k!(Kc3), k!(Kc4), k!(Kc5), k!(Kc6), k!(Kc7), k!(Kc8), k!(Kc9), k!(Kc0), a!(Transparent)],
             [a!(Transparent), k!(Kp6), k!(Kp7), k!(Kp8), k!(Kp9), k!(Kp0), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent)],
-            [a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent)],
+            [k!(LShift), k!(LCtrl), k!(LAlt), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), k!(LGui), mo!(1), mo!(8)],
+        ],
+        [ // adjust
+            [k!(Bootloader), k!(F1), k!(F2), k!(F3), k!(F4), k!(F5), k!(F6), k!(F7), k!(F8), k!(F9), k!(F10), k!(Bootloader)],
+            [k!(Reboot), AUTO_SHIFT_TOGGLE, k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(F11), k!(F12), k!(Reboot)],
+            [k!(No), TERM_SELECT, TERM_DOWN, TERM_UP, TERM_PRINT, TERM_RESET, k!(No), k!(No), k!(No), k!(No), k!(No), k!(No)],
+            [k!(LShift), k!(LCtrl), k!(LAlt), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), k!(LGui), mo!(1), mo!(8)],
         ],
+        [ // mouse
+            [k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), ms!(WheelLeft), ms!(WheelDown), ms!(WheelUp), ms!(WheelRight), k!(No), k!(No)],
+            [k!(No), one_shot!(LAlt), one_shot!(LGui), one_shot!(LCtrl), one_shot!(LShift), k!(No), ms!(Left), ms!(Down), ms!(Up), ms!(Right), k!(No), k!(No)],
+            [k!(No), k!(No), k!(No), k!(No), ms!(Precision), tg!(6), k!(No), k!(No), k!(No), k!(No), k!(No), k!(No)],
+            [k!(LShift), k!(LCtrl), k!(LAlt), ms!(Button3), ms!(Button2), ms!(Button1), k!(No), k!(No), k!(No), k!(LGui), mo!(1), mo!(8)],
+        ],
+        [ // media
+            [k!(No), k!(No), k!(No), k!(No), k!(No), a!(Transparent), k!(MediaPrevTrack), k!(AudioVolDown), k!(AudioVolUp), k!(MediaNextTrack), k!(No), k!(No)],
+            [k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(MediaPlayPause), k!(BrightnessDown), k!(BrightnessUp), k!(AudioMute), k!(No), k!(No)],
+            [k!(No), k!(No), k!(No), k!(No), k!(No), k!(No), k!(SystemSleep), k!(SystemWake), k!(No), k!(No), k!(No), k!(SystemPower)],
+            [k!(LShift), k!(LCtrl), k!(LAlt), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), a!(Transparent), k!(LGui), mo!(1), mo!(8)],
+        ],
+        leader_layer(),
     ]
 }
+
+/// The behavior of the keymap's keys, given to rmk with the keymap
+pub fn behavior_config() -> BehaviorConfig {
+    let mut behavior_config = BehaviorConfig {
+        tri_layer: Some(TRI_LAYER),
+        ..Default::default()
+    };
+    TappingTerms::DEFAULT.apply(&mut behavior_config);
+    let macros: heapless::Vec<_, { MACROS.len() + HostLayout::MAX_DEAD_CHARS }> = MACROS
+        .iter()
+        .map(|text| text_macro(HOST_LAYOUT, text))
+        .chain(dead_key_macros(HOST_LAYOUT))
+        .collect();
+    behavior_config.keyboard_macros.macro_sequences = define_macro_sequences(&macros);
+    for tap_dance in TAP_DANCES {
+        behavior_config
+            .morse
+            .morses
+            .push(tap_dance.morse(TAP_DANCE_PROFILE))
+            .expect("More tap dances than rmk has morse keys");
+    }
+    for key_override in KEY_OVERRIDES {
+        behavior_config
+            .fork
+            .forks
+            .push(key_override.fork())
+            .expect("More key overrides than rmk has forks");
+    }
+    behavior_config
+}
+
+/// The hands of the keys, given to rmk with the keymap
+pub fn positional_config() -> PositionalConfig<ROW, COL> {
+    PositionalConfig::new(HANDS)
+}
+
+// Fails the build on macros the host layout can't type, see host_layout.rs
+const _: () = {
+    let mut i = 0;
+    while i < MACROS.len() {
+        check_text(HOST_LAYOUT, MACROS[i]);
+        i += 1;
+    }
+};
+
+// Fails the build on mistakes in the keymap and the tap dances, see
+// validate.rs
+const _: () = {
+    if let Err(error) = validate_keymap(&get_default_keymap(), &TAP_DANCES) {
+        let message = error.message();
+        panic!("{}", message.as_str());
+    }
+};
@@ -0,0 +1,97 @@
+//! Layer lock: a key that keeps the held num or nav layer on once its thumb
+//! key is let go, until the lock key is pressed again or the layer's key is
+//! held and released once more.
+//!
+//! What is held comes from rmk's layer events only. A `kol!` key that rmk
+//! decides was tapped never turns its layer on, so it neither locks nor
+//! unlocks it. rmk turns a layer off when its key is released, so the
+//! keyboard turns a locked layer back on, and off again to unlock it, with the
+//! `tg!` key of that layer on the leader layer, see `leader.rs`.
+
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+
+use crate::default_layer::is_default_layer;
+use crate::keymap::{LOCKABLE_LAYERS, NUM_LAYER};
+
+/// The layer lock key of the keymap, a user keycode rmk ignores
+pub const LAYER_LOCK: KeyAction = KeyAction::Single(Action::Key(KeyCode::User22));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayerLock {
+    locked: Option<u8>,
+    /// The key that turned the locked layer on is still down
+    key_held: bool,
+    /// Layer whose `tg!` key the keyboard has to tap
+    toggle: Option<u8>,
+}
+
+impl LayerLock {
+    pub const fn new() -> Self {
+        Self {
+            locked: None,
+            key_held: false,
+            toggle: None,
+        }
+    }
+
+    /// The locked layer
+    pub fn locked(&self) -> Option<u8> {
+        self.locked
+    }
+
+    /// The layer to toggle in rmk, once
+    pub fn take_toggle(&mut self) -> Option<u8> {
+        self.toggle.take()
+    }
+
+    /// The lock key was pressed while `highest` was rmk's highest layer
+    pub fn lock_key(&mut self, highest: u8) {
+        match self.locked.take() {
+            // Still held, it goes off with the release of its key
+            Some(_) if self.key_held => (),
+            Some(layer) => self.toggle = Some(layer),
+            None if LOCKABLE_LAYERS.contains(&highest) => {
+                self.locked = Some(highest);
+                self.key_held = true;
+            }
+            None => (),
+        }
+    }
+
+    /// rmk's highest layer is `highest` now
+    pub fn layer(&mut self, highest: u8) {
+        let Some(layer) = self.locked else {
+            return;
+        };
+        if highest >= layer {
+            return;
+        }
+        if self.key_held {
+            // Its key was released, the keyboard keeps it on
+            self.key_held = false;
+            self.toggle = Some(layer);
+        } else {
+            // Held and released again
+            self.locked = None;
+        }
+    }
+}
+
+// A lockable layer exists and isn't a default layer, which rmk reports as on
+// whenever it is the default
+const _: () = {
+    let mut i = 0;
+    while i < LOCKABLE_LAYERS.len() {
+        let layer = LOCKABLE_LAYERS[i];
+        assert!(
+            (layer as usize) < NUM_LAYER,
+            "A lockable layer doesn't exist"
+        );
+        assert!(
+            !is_default_layer(layer) && layer > 0,
+            "A default layer can't be locked"
+        );
+        i += 1;
+    }
+};
@@ -0,0 +1,399 @@
+//! Leader key sequences.
+//!
+//! The keys typed after the leader key, each within
+//! [`LEADER_TIMEOUT_MS`](crate::keymap::LEADER_TIMEOUT_MS) of the one before,
+//! are matched against [`LEADER_SEQUENCES`] instead of being sent. A key
+//! counts as its tap key on the base layer, so `hrm!(S, LGUI)` is S.
+//!
+//! The sequences are a trie built in a const context, so a sequence that is
+//! the start of another one, or has a key the base layer doesn't have, fails
+//! the build. A complete sequence runs its key through rmk, a macro, a layer
+//! toggle or `Bootloader` for example: the leader layer has the key of every
+//! sequence, and the keyboard taps it there while it holds the leader layer
+//! on with the key at [`LEADER_LAYER_KEY`], an unwired position. After the
+//! sequences the layer has a `tg!` key for every layer the layer lock key can
+//! lock, which the keyboard taps the same way. The keys the host asks for
+//! come last, see `remote.rs`.
+
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+
+use crate::keymap::{
+    COL, LEADER_LAYER, LEADER_LAYER_KEY, LEADER_SEQUENCES, LOCKABLE_LAYERS, NUM_LAYER,
+    ONE_SHOT_KEYS, PROFILE_KEYS, REMOTE_LAYERS, ROW, UNWIRED_KEYS, get_default_keymap,
+};
+
+const KEYMAP: [[[KeyAction; COL]; ROW]; NUM_LAYER] = get_default_keymap();
+
+/// The leader key of the keymap, a user keycode rmk ignores
+pub const LEADER_KEY: KeyCode = KeyCode::User21;
+
+/// The leader key in the keymap
+pub const LEADER: KeyAction = KeyAction::Single(Action::Key(LEADER_KEY));
+
+/// A sequence of keys after the leader key, and the key it runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LeaderSequence {
+    pub keys: &'static [KeyCode],
+    pub action: KeyAction,
+}
+
+impl LeaderSequence {
+    pub const fn new(keys: &'static [KeyCode], action: KeyAction) -> Self {
+        Self { keys, action }
+    }
+}
+
+/// What the sequence typed so far leads to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeaderStep {
+    /// More keys are needed, continue from this node of the trie
+    Pending(usize),
+    /// The sequence of [`LEADER_SEQUENCES`] with this index is complete
+    Done(usize),
+    NoMatch,
+}
+
+const NONE: usize = usize::MAX;
+
+#[derive(Clone, Copy)]
+struct Node {
+    key: u16,
+    first_child: usize,
+    next_sibling: usize,
+    sequence: usize,
+}
+
+impl Node {
+    const EMPTY: Node = Node {
+        key: 0,
+        first_child: NONE,
+        next_sibling: NONE,
+        sequence: NONE,
+    };
+}
+
+/// Number of trie nodes needed for `sequences`, at most
+pub const fn leader_trie_size(sequences: &[LeaderSequence]) -> usize {
+    let mut size = 1;
+    let mut i = 0;
+    while i < sequences.len() {
+        size += sequences[i].keys.len();
+        i += 1;
+    }
+    size
+}
+
+/// Leader sequences as a trie, node 0 is the root
+pub struct LeaderTrie<const N: usize> {
+    nodes: [Node; N],
+}
+
+impl<const N: usize> LeaderTrie<N> {
+    /// Fails for an empty or duplicate sequence, or a sequence that is the
+    /// start of another one
+    pub const fn new(sequences: &[LeaderSequence]) -> Self {
+        let mut nodes = [Node::EMPTY; N];
+        let mut len = 1;
+        let mut s = 0;
+        while s < sequences.len() {
+            let keys = sequences[s].keys;
+            assert!(!keys.is_empty(), "Empty leader sequence");
+            let mut node = 0;
+            let mut i = 0;
+            while i < keys.len() {
+                assert!(
+                    nodes[node].sequence == NONE,
+                    "A leader sequence starts with another sequence"
+                );
+                let key = keys[i] as u16;
+                let mut child = nodes[node].first_child;
+                while child != NONE && nodes[child].key != key {
+                    child = nodes[child].next_sibling;
+                }
+                if child == NONE {
+                    child = len;
+                    len += 1;
+                    nodes[child] = Node {
+                        key,
+                        first_child: NONE,
+                        next_sibling: nodes[node].first_child,
+                        sequence: NONE,
+                    };
+                    nodes[node].first_child = child;
+                }
+                node = child;
+                i += 1;
+            }
+            assert!(nodes[node].sequence == NONE, "Duplicate leader sequence");
+            assert!(
+                nodes[node].first_child == NONE,
+                "A leader sequence is the start of another sequence"
+            );
+            nodes[node].sequence = s;
+            s += 1;
+        }
+        Self { nodes }
+    }
+
+    /// Follows `key` from `node`
+    pub fn step(&self, node: usize, key: KeyCode) -> LeaderStep {
+        let mut child = self.nodes[node].first_child;
+        while child != NONE {
+            let next = &self.nodes[child];
+            if next.key == key as u16 {
+                return match next.sequence {
+                    NONE => LeaderStep::Pending(child),
+                    sequence => LeaderStep::Done(sequence),
+                };
+            }
+            child = next.next_sibling;
+        }
+        LeaderStep::NoMatch
+    }
+}
+
+pub static LEADER_TRIE: LeaderTrie<{ leader_trie_size(&LEADER_SEQUENCES) }> =
+    LeaderTrie::new(&LEADER_SEQUENCES);
+
+/// The key a sequence key stands for, its tap key
+const fn tap_keycode(action: KeyAction) -> Option<KeyCode> {
+    match action {
+        KeyAction::Single(Action::Key(k)) | KeyAction::TapHold(Action::Key(k), _, _) => Some(k),
+        _ => None,
+    }
+}
+
+/// The key at `pos` in a leader sequence
+pub fn sequence_key((row, col): (u8, u8)) -> Option<KeyCode> {
+    tap_keycode(KEYMAP[0][row as usize][col as usize])
+}
+
+const fn is_unwired(row: usize, col: usize) -> bool {
+    let mut i = 0;
+    while i < UNWIRED_KEYS.len() {
+        if UNWIRED_KEYS[i].0 as usize == row && UNWIRED_KEYS[i].1 as usize == col {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Position of the key of sequence `i` on the leader layer, the wired
+/// positions in order
+pub const fn sequence_position(i: usize) -> (u8, u8) {
+    let mut n = 0;
+    let mut row = 0;
+    while row < ROW {
+        let mut col = 0;
+        while col < COL {
+            if !is_unwired(row, col) {
+                if n == i {
+                    return (row as u8, col as u8);
+                }
+                n += 1;
+            }
+            col += 1;
+        }
+        row += 1;
+    }
+    panic!("More leader sequences, layers and profiles than the leader layer has keys")
+}
+
+/// Position of the `tg!` key of lockable layer `i` on the leader layer, after
+/// the keys of the sequences
+pub const fn layer_toggle_position(i: usize) -> (u8, u8) {
+    sequence_position(LEADER_SEQUENCES.len() + i)
+}
+
+/// Position of the `tg!` key of remote layer `i` on the leader layer, after
+/// the ones of the lockable layers
+pub const fn remote_layer_position(i: usize) -> (u8, u8) {
+    layer_toggle_position(LOCKABLE_LAYERS.len() + i)
+}
+
+/// Position of the key of BLE profile `profile` on the leader layer, after
+/// the remote layers
+pub const fn profile_position(profile: usize) -> (u8, u8) {
+    remote_layer_position(REMOTE_LAYERS.len() + profile)
+}
+
+/// The leader layer: the key of every sequence, a `tg!` key for every lockable
+/// and remote layer, the profile keys, the one-shot keys and the key that
+/// holds the layer at their unwired positions, nothing elsewhere
+pub const fn leader_layer() -> [[KeyAction; COL]; ROW] {
+    let mut layer = [[KeyAction::Single(Action::Key(KeyCode::No)); COL]; ROW];
+    let mut i = 0;
+    while i < LEADER_SEQUENCES.len() {
+        let (row, col) = sequence_position(i);
+        layer[row as usize][col as usize] = LEADER_SEQUENCES[i].action;
+        i += 1;
+    }
+    let mut i = 0;
+    while i < LOCKABLE_LAYERS.len() {
+        let (row, col) = layer_toggle_position(i);
+        layer[row as usize][col as usize] =
+            KeyAction::Single(Action::LayerToggle(LOCKABLE_LAYERS[i]));
+        i += 1;
+    }
+    let mut i = 0;
+    while i < REMOTE_LAYERS.len() {
+        let (row, col) = remote_layer_position(i);
+        layer[row as usize][col as usize] =
+            KeyAction::Single(Action::LayerToggle(REMOTE_LAYERS[i]));
+        i += 1;
+    }
+    let mut i = 0;
+    while i < PROFILE_KEYS.len() {
+        let (row, col) = profile_position(i);
+        layer[row as usize][col as usize] = KeyAction::Single(Action::Key(PROFILE_KEYS[i]));
+        i += 1;
+    }
+    let mut i = 0;
+    while i < ONE_SHOT_KEYS.len() {
+        let (row, col) = ONE_SHOT_KEYS[i].hold;
+        layer[row as usize][col as usize] = ONE_SHOT_KEYS[i].hold_action();
+        i += 1;
+    }
+    let (row, col) = LEADER_LAYER_KEY;
+    layer[row as usize][col as usize] = KeyAction::Single(Action::LayerOn(LEADER_LAYER));
+    layer
+}
+
+// Every sequence key is on the base layer, and the leader layer key is
+// unwired and holds the leader layer on every layer
+const _: () = {
+    let mut s = 0;
+    while s < LEADER_SEQUENCES.len() {
+        let keys = LEADER_SEQUENCES[s].keys;
+        let mut i = 0;
+        while i < keys.len() {
+            let mut found = false;
+            let mut row = 0;
+            while row < ROW {
+                let mut col = 0;
+                while col < COL {
+                    if let Some(k) = tap_keycode(KEYMAP[0][row][col])
+                        && k as u16 == keys[i] as u16
+                    {
+                        found = true;
+                    }
+                    col += 1;
+                }
+                row += 1;
+            }
+            assert!(found, "A leader sequence has a key the base layer doesn't");
+            i += 1;
+        }
+        s += 1;
+    }
+
+    let (row, col) = LEADER_LAYER_KEY;
+    assert!(
+        is_unwired(row as usize, col as usize),
+        "The leader layer key has a switch"
+    );
+    let mut layer = 0;
+    while layer < NUM_LAYER {
+        assert!(
+            matches!(
+                KEYMAP[layer][row as usize][col as usize],
+                KeyAction::Single(Action::LayerOn(l)) if l == LEADER_LAYER
+            ),
+            "The leader layer key doesn't hold the leader layer on every layer"
+        );
+        layer += 1;
+    }
+};
+
+/// The state of a leader sequence being typed
+pub struct Leader {
+    /// Trie node of the keys typed so far
+    node: Option<usize>,
+    deadline_ms: u64,
+    timeout_ms: u64,
+    /// Keys pressed during a sequence, their release is kept from rmk as well
+    held: [[bool; COL]; ROW],
+}
+
+impl Leader {
+    pub const fn new(timeout_ms: u64) -> Self {
+        Self {
+            node: None,
+            deadline_ms: 0,
+            timeout_ms,
+            held: [[false; COL]; ROW],
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.node.is_some()
+    }
+
+    /// The leader key was pressed
+    pub fn start(&mut self, now_ms: u64) {
+        self.node = Some(0);
+        self.deadline_ms = now_ms + self.timeout_ms;
+    }
+
+    /// When the sequence typed so far times out
+    pub fn deadline_ms(&self) -> Option<u64> {
+        self.node.map(|_| self.deadline_ms)
+    }
+
+    pub fn time_out(&mut self, now_ms: u64) {
+        if self
+            .deadline_ms()
+            .is_some_and(|deadline| deadline <= now_ms)
+        {
+            self.node = None;
+        }
+    }
+
+    /// Takes the key event at `pos` if it belongs to a sequence. Returns the
+    /// index of the sequence it completes.
+    pub fn take(&mut self, (row, col): (u8, u8), pressed: bool, now_ms: u64) -> Take {
+        let held = &mut self.held[row as usize][col as usize];
+        if !pressed {
+            return if core::mem::take(held) {
+                Take::Taken
+            } else {
+                Take::Passed
+            };
+        }
+        let Some(node) = self.node else {
+            return Take::Passed;
+        };
+        *held = true;
+        let step = match sequence_key((row, col)) {
+            Some(key) => LEADER_TRIE.step(node, key),
+            None => LeaderStep::NoMatch,
+        };
+        match step {
+            LeaderStep::Pending(next) => {
+                self.node = Some(next);
+                self.deadline_ms = now_ms + self.timeout_ms;
+                Take::Taken
+            }
+            LeaderStep::Done(sequence) => {
+                self.node = None;
+                Take::Done(sequence)
+            }
+            LeaderStep::NoMatch => {
+                self.node = None;
+                Take::Taken
+            }
+        }
+    }
+}
+
+/// What [`Leader::take`] did with a key event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Take {
+    /// It isn't part of a sequence, rmk handles it
+    Passed,
+    Taken,
+    /// It completed the sequence with this index
+    Done(usize),
+}
@@ -0,0 +1,35 @@
+//! The keymap and everything of the firmware that doesn't need the nRF52840,
+//! so it also builds for the host and can be tested there.
+
+#![no_std]
+
+#[macro_use]
+mod macros;
+
+pub mod auto_shift;
+pub mod battery;
+pub mod console;
+pub mod default_layer;
+pub mod device;
+pub mod host_layout;
+pub mod key_override;
+pub mod keyboard;
+pub mod keymap;
+pub mod layer_lock;
+pub mod leader;
+pub mod log_forward;
+pub mod mouse;
+pub mod one_shot;
+pub mod radio;
+pub mod remote;
+pub mod render;
+pub mod settings;
+pub mod split;
+pub mod stats;
+pub mod status;
+pub mod tap_dance;
+pub mod tap_hold;
+pub mod tapping_term;
+pub mod typing_speed;
+pub mod validate;
+pub mod via;
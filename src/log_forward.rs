@@ -0,0 +1,195 @@
+//! Forwards the defmt log of the halves to the dongle's USB log.
+//!
+//! With the `log_forward` feature a half logs into a buffer instead of RTT.
+//! [`LogReporter`] sends the buffer to the dongle in [`HalfMessage::Log`]
+//! messages, and [`LogJoiner`] puts the frames back together there. The frames
+//! stay defmt encoded: the dongle writes each one as a hex line tagged with the
+//! half, and `defmt-print` decodes them on the host with the ELF of the half.
+//!
+//! A full buffer or a lost message drops log data, the joiner then skips to
+//! the start of the next frame.
+
+use core::fmt;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_time::Timer;
+use rmk::event::Event;
+use rmk::heapless::Vec;
+use rmk::input_device::InputDevice;
+
+use crate::split::{HalfMessage, LOG_CHUNK_LEN, half_name};
+
+/// The log a half wrote and didn't send yet
+static FRAMES: Pipe<CriticalSectionRawMutex, 1024> = Pipe::new();
+
+/// Time between two log messages of a half, so that the log doesn't crowd
+/// out the keys on the split link
+const LOG_GAP_MS: u64 = 5;
+
+/// Longest frame the dongle puts back together, longer ones are dropped
+pub const MAX_FRAME_LEN: usize = 256;
+
+/// Adds encoded log bytes to the buffer of the half, what doesn't fit is
+/// dropped
+pub fn capture(bytes: &[u8]) {
+    let _ = FRAMES.try_write(bytes);
+}
+
+/// Input device of a half that sends its log to the dongle
+pub struct LogReporter {
+    half: u8,
+    seq: u8,
+}
+
+impl LogReporter {
+    pub fn new(half: u8) -> Self {
+        Self { half, seq: 0 }
+    }
+}
+
+impl InputDevice for LogReporter {
+    async fn read_event(&mut self) -> Event {
+        let mut data = [0; LOG_CHUNK_LEN];
+        let mut len = FRAMES.read(&mut data).await;
+        // Let more log come in, rather than sending it byte by byte
+        Timer::after_millis(LOG_GAP_MS).await;
+        len += FRAMES.try_read(&mut data[len..]).unwrap_or(0);
+        let message = HalfMessage::Log {
+            half: self.half,
+            seq: self.seq,
+            len: len as u8,
+            data,
+        };
+        self.seq = self.seq.wrapping_add(1);
+        message.to_event()
+    }
+}
+
+/// What [`LogJoiner`] got out of a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Joined<'a> {
+    /// A whole frame, with the zero that ends it
+    Frame(&'a [u8]),
+    /// Messages of the half went missing
+    Lost(u8),
+    /// A frame longer than [`MAX_FRAME_LEN`] was dropped
+    TooLong,
+}
+
+/// Puts the frames of one half back together on the dongle
+pub struct LogJoiner {
+    next_seq: Option<u8>,
+    frame: Vec<u8, MAX_FRAME_LEN>,
+    /// Bytes are dropped up to the end of the frame
+    skipping: bool,
+}
+
+impl Default for LogJoiner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogJoiner {
+    pub const fn new() -> Self {
+        Self {
+            next_seq: None,
+            frame: Vec::new(),
+            skipping: false,
+        }
+    }
+
+    /// Adds the log bytes of the message `seq`, calling `emit` for every frame
+    /// they complete
+    pub fn receive(&mut self, seq: u8, data: &[u8], mut emit: impl FnMut(Joined)) {
+        if let Some(next) = self.next_seq {
+            let lost = seq.wrapping_sub(next);
+            if lost != 0 {
+                emit(Joined::Lost(lost));
+                self.frame.clear();
+                self.skipping = true;
+            }
+        }
+        self.next_seq = Some(seq.wrapping_add(1));
+        for &byte in data {
+            if self.skipping {
+                self.skipping = byte != 0;
+            } else if self.frame.push(byte).is_err() {
+                emit(Joined::TooLong);
+                self.frame.clear();
+                self.skipping = byte != 0;
+            } else if byte == 0 {
+                emit(Joined::Frame(&self.frame));
+                self.frame.clear();
+            }
+        }
+    }
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// Writes what came of the log of the half `half` to the USB log
+pub(crate) fn emit(half: usize, joined: Joined) {
+    let half = half_name(half);
+    match joined {
+        Joined::Frame(frame) => log::info!("{} defmt: {}", half, Hex(frame)),
+        Joined::Lost(count) => log::info!("{} log: lost {} messages", half, count),
+        Joined::TooLong => log::info!("{} log: dropped a frame too long", half),
+    }
+}
+
+/// The defmt logger of a half that forwards its log
+#[cfg(all(target_os = "none", feature = "log_forward"))]
+mod logger {
+    use core::cell::UnsafeCell;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use critical_section::RestoreState;
+
+    #[defmt::global_logger]
+    struct Logger;
+
+    static TAKEN: AtomicBool = AtomicBool::new(false);
+    static mut RESTORE: RestoreState = RestoreState::invalid();
+    static ENCODER: Encoder = Encoder(UnsafeCell::new(defmt::Encoder::new()));
+
+    struct Encoder(UnsafeCell<defmt::Encoder>);
+
+    // Only used by the holder of the logger, in a critical section
+    unsafe impl Sync for Encoder {}
+
+    fn encoder() -> &'static mut defmt::Encoder {
+        unsafe { &mut *ENCODER.0.get() }
+    }
+
+    // Like defmt-rtt's logger, with the buffer instead of RTT
+    unsafe impl defmt::Logger for Logger {
+        fn acquire() {
+            let restore = unsafe { critical_section::acquire() };
+            if TAKEN.swap(true, Ordering::Relaxed) {
+                panic!("defmt logger taken reentrantly")
+            }
+            unsafe { RESTORE = restore };
+            encoder().start_frame(super::capture);
+        }
+
+        unsafe fn flush() {}
+
+        unsafe fn release() {
+            encoder().end_frame(super::capture);
+            TAKEN.store(false, Ordering::Relaxed);
+            unsafe { critical_section::release(RESTORE) };
+        }
+
+        unsafe fn write(bytes: &[u8]) {
+            encoder().write(bytes, super::capture)
+        }
+    }
+}
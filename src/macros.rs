@@ -1,24 +1,17 @@
-#[cfg(any(feature = "peripheral_left", feature = "peripheral_right"))]
-macro_rules! config_matrix_pins_nrf {
-    (peripherals: $p:ident, input: [$($in_pin:ident), *], output: [$($out_pin:ident), +]) => {
-        {
-            let mut output_pins = [$(Output::new($p.$out_pin, embassy_nrf::gpio::Level::Low, embassy_nrf::gpio::OutputDrive::Standard)), +];
-            let input_pins = [$(Input::new($p.$in_pin, embassy_nrf::gpio::Pull::Down)), +];
-            output_pins.iter_mut().for_each(|p| {
-                p.set_low();
-            });
-            (input_pins, output_pins)
-        }
-    };
-}
-
 #[macro_export]
 macro_rules! hrm {
     ($k: ident, $m: ident) => {
         KeyAction::TapHold(
             Action::Key(KeyCode::$k),
             Action::Modifier(ModifierCombination::$m),
-            MorseProfile::new(Some(true), Some(MorseMode::PermissiveHold), Some(175), None),
+            MorseProfile::new(None, Some(MorseMode::PermissiveHold), None, None),
+        )
+    };
+    ($c: literal, $m: ident) => {
+        KeyAction::TapHold(
+            const { char_action(HostLayout::ALL[LAYOUT as usize], $c) },
+            Action::Modifier(ModifierCombination::$m),
+            MorseProfile::new(None, Some(MorseMode::PermissiveHold), None, None),
         )
     };
 }
@@ -30,7 +23,43 @@ macro_rules! kol {
         KeyAction::TapHold(
             Action::Key(KeyCode::$k),
             Action::LayerOn($x),
-            MorseProfile::new(None, None, Some(175), None),
+            MorseProfile::new(None, Some(MorseMode::PermissiveHold), None, None),
         )
     };
 }
+
+// one-shot modifier, by its keycode, or one-shot layer
+#[macro_export]
+macro_rules! one_shot {
+    ($k: ident) => {
+        one_shot_key(OneShot::Modifier(KeyCode::$k))
+    };
+    ($x: literal) => {
+        one_shot_key(OneShot::Layer($x))
+    };
+}
+
+// auto-shift character on the host layout of `keymap`, see auto_shift.rs
+#[macro_export]
+macro_rules! ash {
+    ($c: literal) => {
+        const { auto_shift(HostLayout::ALL[LAYOUT as usize], $c) }
+    };
+}
+
+// mouse key, see `MouseKey` in mouse.rs
+#[macro_export]
+macro_rules! ms {
+    ($k: ident) => {
+        KeyAction::Single(Action::Key(MouseKey::$k.keycode()))
+    };
+}
+
+// character on the host layout of `keymap`, see `char_action` in
+// host_layout.rs
+#[macro_export]
+macro_rules! ch {
+    ($c: literal) => {
+        KeyAction::Single(const { char_action(HostLayout::ALL[LAYOUT as usize], $c) })
+    };
+}
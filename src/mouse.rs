@@ -0,0 +1,332 @@
+//! Mouse keys: moving the pointer, scrolling and clicking from the keymap.
+//!
+//! The mouse keys are keyboard keycodes (`Kb0` and up) rmk leaves alone, so
+//! [`MouseKeyController`] alone decides how far the pointer moves. It follows
+//! the keys on the controller channel and sends mouse reports through rmk's
+//! report channel, which reach the host over USB or BLE, whichever is active.
+
+use embassy_time::{Duration, Instant, Timer};
+use rmk::channel::{CONTROLLER_CHANNEL, ControllerSub, KEYBOARD_REPORT_CHANNEL};
+use rmk::controller::Controller;
+use rmk::embassy_futures::select::{Either, select};
+use rmk::event::{ControllerEvent, KeyboardEvent};
+use rmk::hid::Report;
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+use usbd_hid::descriptor::MouseReport;
+
+use crate::keyboard::decode;
+use crate::keymap::{
+    MOUSE_MOVE_CURVE, MOUSE_PRECISION_CURVE, MOUSE_TICK_MS, MOUSE_WHEEL_CURVE,
+    MOUSE_WHEEL_INTERVAL_MS,
+};
+
+/// Fixed point scale of the ramp, `1 << 16` is the end of the ramp
+const ONE: u64 = 1 << 16;
+
+/// How far the pointer or wheel moves per report while a mouse key is held
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseCurve {
+    /// Always moves `speed` units
+    Constant { speed: u8 },
+    /// Starts at `start` and reaches `max` after `ramp_ms`. An `exponent` of
+    /// 1 ramps up linearly, higher values stay slow for longer.
+    Accelerated {
+        start: u8,
+        max: u8,
+        ramp_ms: u32,
+        exponent: u8,
+    },
+}
+
+impl MouseCurve {
+    /// Distance of one report after the key was held for `held_ms`
+    pub const fn step(self, held_ms: u64) -> u8 {
+        match self {
+            MouseCurve::Constant { speed } => speed,
+            MouseCurve::Accelerated {
+                start,
+                max,
+                ramp_ms,
+                exponent,
+            } => {
+                if max <= start || held_ms >= ramp_ms as u64 {
+                    return if max > start { max } else { start };
+                }
+                // Way through the ramp, raised to `exponent`. It never gets
+                // above `ONE`, so the products fit whatever the exponent.
+                let ramp = held_ms * ONE / ramp_ms as u64;
+                let mut progress = ONE;
+                let mut i = 0;
+                while i < exponent {
+                    progress = progress * ramp / ONE;
+                    i += 1;
+                }
+                start + ((max - start) as u64 * progress / ONE) as u8
+            }
+        }
+    }
+
+    /// Whether every step moves, and fits in a report
+    pub const fn is_valid(self) -> bool {
+        match self {
+            MouseCurve::Constant { speed } => speed > 0 && speed <= i8::MAX as u8,
+            MouseCurve::Accelerated { start, max, .. } => {
+                start > 0 && start <= max && max <= i8::MAX as u8
+            }
+        }
+    }
+}
+
+const _: () = {
+    assert!(
+        MOUSE_MOVE_CURVE.is_valid(),
+        "MOUSE_MOVE_CURVE stalls or is too fast for a report"
+    );
+    assert!(
+        MOUSE_PRECISION_CURVE.is_valid(),
+        "MOUSE_PRECISION_CURVE stalls or is too fast for a report"
+    );
+    assert!(
+        MOUSE_WHEEL_CURVE.is_valid(),
+        "MOUSE_WHEEL_CURVE stalls or is too fast for a report"
+    );
+};
+
+/// The mouse keys of the keymap, written as `ms!(Up)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+    Button1,
+    Button2,
+    Button3,
+    /// Toggles between the accelerated and the constant speed curve
+    Precision,
+}
+
+impl MouseKey {
+    pub const ALL: [MouseKey; 12] = [
+        MouseKey::Up,
+        MouseKey::Down,
+        MouseKey::Left,
+        MouseKey::Right,
+        MouseKey::WheelUp,
+        MouseKey::WheelDown,
+        MouseKey::WheelLeft,
+        MouseKey::WheelRight,
+        MouseKey::Button1,
+        MouseKey::Button2,
+        MouseKey::Button3,
+        MouseKey::Precision,
+    ];
+
+    /// Keycode of the key, Vial shows them as its custom keycodes
+    pub const fn keycode(self) -> KeyCode {
+        match self {
+            MouseKey::Up => KeyCode::Kb0,
+            MouseKey::Down => KeyCode::Kb1,
+            MouseKey::Left => KeyCode::Kb2,
+            MouseKey::Right => KeyCode::Kb3,
+            MouseKey::WheelUp => KeyCode::Kb4,
+            MouseKey::WheelDown => KeyCode::Kb5,
+            MouseKey::WheelLeft => KeyCode::Kb6,
+            MouseKey::WheelRight => KeyCode::Kb7,
+            MouseKey::Button1 => KeyCode::Kb8,
+            MouseKey::Button2 => KeyCode::Kb9,
+            MouseKey::Button3 => KeyCode::Kb10,
+            MouseKey::Precision => KeyCode::Kb11,
+        }
+    }
+
+    pub fn from_keycode(keycode: KeyCode) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.keycode() == keycode)
+    }
+
+    const fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+const MOVE_KEYS: u16 =
+    MouseKey::Up.bit() | MouseKey::Down.bit() | MouseKey::Left.bit() | MouseKey::Right.bit();
+const WHEEL_KEYS: u16 = MouseKey::WheelUp.bit()
+    | MouseKey::WheelDown.bit()
+    | MouseKey::WheelLeft.bit()
+    | MouseKey::WheelRight.bit();
+
+pub enum MouseEvent {
+    Controller(ControllerEvent),
+    /// Time for the next report of a held move or wheel key
+    Tick,
+}
+
+/// Sends mouse reports for the mouse keys
+pub struct MouseKeyController {
+    sub: ControllerSub,
+    /// Bits of the held [`MouseKey`]s
+    held: u16,
+    buttons: u8,
+    precision: bool,
+    move_since: Instant,
+    wheel_since: Instant,
+    /// When the wheel moved last, `None` until it did since it was pressed
+    last_wheel: Option<Instant>,
+    next_tick: Instant,
+}
+
+impl Default for MouseKeyController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MouseKeyController {
+    pub fn new() -> Self {
+        Self {
+            sub: CONTROLLER_CHANNEL
+                .subscriber()
+                .expect("No subscriber left for the mouse keys"),
+            held: 0,
+            buttons: 0,
+            precision: false,
+            move_since: Instant::now(),
+            wheel_since: Instant::now(),
+            last_wheel: None,
+            next_tick: Instant::now(),
+        }
+    }
+
+    fn is_held(&self, key: MouseKey) -> bool {
+        self.held & key.bit() != 0
+    }
+
+    /// Signed distance along one axis, `0` when both or no keys are held
+    fn axis(&self, negative: MouseKey, positive: MouseKey, step: u8) -> i8 {
+        match (self.is_held(negative), self.is_held(positive)) {
+            (true, false) => -(step as i8),
+            (false, true) => step as i8,
+            _ => 0,
+        }
+    }
+
+    async fn process_key(&mut self, event: KeyboardEvent, key: MouseKey) {
+        let Some((_, pressed)) = decode(event) else {
+            return;
+        };
+        if !pressed {
+            self.held &= !key.bit();
+        } else {
+            let now = Instant::now();
+            if self.held & (MOVE_KEYS | WHEEL_KEYS) == 0 {
+                self.next_tick = now;
+            }
+            if self.held & MOVE_KEYS == 0 && key.bit() & MOVE_KEYS != 0 {
+                self.move_since = now;
+            }
+            if self.held & WHEEL_KEYS == 0 && key.bit() & WHEEL_KEYS != 0 {
+                self.wheel_since = now;
+                self.last_wheel = None;
+            }
+            self.held |= key.bit();
+        }
+
+        let button = match key {
+            MouseKey::Button1 => 1 << 0,
+            MouseKey::Button2 => 1 << 1,
+            MouseKey::Button3 => 1 << 2,
+            MouseKey::Precision => {
+                if pressed {
+                    self.precision = !self.precision;
+                }
+                return;
+            }
+            _ => return,
+        };
+        if pressed {
+            self.buttons |= button;
+        } else {
+            self.buttons &= !button;
+        }
+        self.send(0, 0, 0, 0).await;
+    }
+
+    async fn tick(&mut self) {
+        let now = Instant::now();
+        self.next_tick = now + Duration::from_millis(MOUSE_TICK_MS);
+        let (mut x, mut y, mut wheel, mut pan) = (0, 0, 0, 0);
+
+        if self.held & MOVE_KEYS != 0 {
+            let curve = if self.precision {
+                MOUSE_PRECISION_CURVE
+            } else {
+                MOUSE_MOVE_CURVE
+            };
+            let step = curve.step((now - self.move_since).as_millis());
+            x = self.axis(MouseKey::Left, MouseKey::Right, step);
+            y = self.axis(MouseKey::Up, MouseKey::Down, step);
+        }
+
+        let wheel_due = self
+            .last_wheel
+            .is_none_or(|last| (now - last).as_millis() >= MOUSE_WHEEL_INTERVAL_MS);
+        if self.held & WHEEL_KEYS != 0 && wheel_due {
+            let step = MOUSE_WHEEL_CURVE.step((now - self.wheel_since).as_millis());
+            wheel = self.axis(MouseKey::WheelDown, MouseKey::WheelUp, step);
+            pan = self.axis(MouseKey::WheelLeft, MouseKey::WheelRight, step);
+            self.last_wheel = Some(now);
+        }
+
+        if x != 0 || y != 0 || wheel != 0 || pan != 0 {
+            self.send(x, y, wheel, pan).await;
+        }
+    }
+
+    async fn send(&self, x: i8, y: i8, wheel: i8, pan: i8) {
+        let report = MouseReport {
+            buttons: self.buttons,
+            x,
+            y,
+            wheel,
+            pan,
+        };
+        KEYBOARD_REPORT_CHANNEL
+            .send(Report::MouseReport(report))
+            .await;
+    }
+}
+
+impl Controller for MouseKeyController {
+    type Event = MouseEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        match event {
+            MouseEvent::Controller(ControllerEvent::Key(
+                event,
+                KeyAction::Single(Action::Key(keycode)),
+            )) => {
+                if let Some(key) = MouseKey::from_keycode(keycode) {
+                    self.process_key(event, key).await;
+                }
+            }
+            MouseEvent::Controller(_) => (),
+            MouseEvent::Tick => self.tick().await,
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        if self.held & (MOVE_KEYS | WHEEL_KEYS) == 0 {
+            return MouseEvent::Controller(self.sub.next_message_pure().await);
+        }
+        match select(self.sub.next_message_pure(), Timer::at(self.next_tick)).await {
+            Either::First(event) => MouseEvent::Controller(event),
+            Either::Second(()) => MouseEvent::Tick,
+        }
+    }
+}
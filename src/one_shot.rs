@@ -0,0 +1,299 @@
+//! One-shot modifiers and layers.
+//!
+//! A tapped one-shot key keeps its modifier or layer on for the next key,
+//! several of them stack, and held down it works like a plain modifier or
+//! layer key. Tapping it twice can lock it until it is tapped again, see
+//! [`DoubleTap`].
+//!
+//! rmk's own `osm!` and `osl!` can't be locked: their release waits for the
+//! next key itself and a second tap always arms them again. So the one-shot
+//! keys of the keymap are user keycodes rmk ignores, and [`OneShots`] decides
+//! when their modifier or layer is on. The keyboard holds it down by pressing
+//! the key at the [`OneShotKey::hold`] position, an unwired position of the
+//! matrix with the modifier or layer key on every layer.
+
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+
+use crate::keymap::{COL, NUM_LAYER, ONE_SHOT_KEYS, ROW, UNWIRED_KEYS, get_default_keymap};
+
+const KEYMAP: [[[KeyAction; COL]; ROW]; NUM_LAYER] = get_default_keymap();
+
+/// What a one-shot key turns on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OneShot {
+    /// A modifier, by its keycode
+    Modifier(KeyCode),
+    Layer(u8),
+}
+
+/// What tapping a one-shot key again while it waits for the next key does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoubleTap {
+    /// It stays on until it is tapped once more
+    Lock,
+    /// It is turned off
+    Cancel,
+    /// Nothing, it waits for the next key again
+    Ignore,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OneShotConfig {
+    /// How long a tapped one-shot key waits for the next key
+    pub timeout_ms: u64,
+    pub double_tap: DoubleTap,
+}
+
+/// A one-shot key of the keymap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OneShotKey {
+    /// User keycode of the key in the keymap
+    pub code: KeyCode,
+    pub one_shot: OneShot,
+    /// Unwired position whose key holds the modifier or layer down
+    pub hold: (u8, u8),
+}
+
+impl OneShotKey {
+    pub const fn new(code: KeyCode, one_shot: OneShot, hold: (u8, u8)) -> Self {
+        Self {
+            code,
+            one_shot,
+            hold,
+        }
+    }
+
+    /// The key at the [`hold`](Self::hold) position on every layer
+    pub const fn hold_action(&self) -> KeyAction {
+        match self.one_shot {
+            OneShot::Modifier(code) => KeyAction::Single(Action::Key(code)),
+            OneShot::Layer(layer) => KeyAction::Single(Action::LayerOn(layer)),
+        }
+    }
+}
+
+/// `a == b` for the keys at the hold positions, in a const context
+const fn same_key(a: KeyAction, b: KeyAction) -> bool {
+    match (a, b) {
+        (KeyAction::Transparent, KeyAction::Transparent) => true,
+        (KeyAction::Single(Action::Key(a)), KeyAction::Single(Action::Key(b))) => {
+            a as u16 == b as u16
+        }
+        (KeyAction::Single(Action::LayerOn(a)), KeyAction::Single(Action::LayerOn(b))) => a == b,
+        _ => false,
+    }
+}
+
+const fn same_one_shot(a: OneShot, b: OneShot) -> bool {
+    match (a, b) {
+        (OneShot::Modifier(a), OneShot::Modifier(b)) => a as u16 == b as u16,
+        (OneShot::Layer(a), OneShot::Layer(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The keymap key of `one_shot`, used by the `one_shot!` macro
+pub const fn one_shot_key(one_shot: OneShot) -> KeyAction {
+    let mut i = 0;
+    while i < ONE_SHOT_KEYS.len() {
+        if same_one_shot(ONE_SHOT_KEYS[i].one_shot, one_shot) {
+            return KeyAction::Single(Action::Key(ONE_SHOT_KEYS[i].code));
+        }
+        i += 1;
+    }
+    panic!("No one-shot key in ONE_SHOT_KEYS for this modifier or layer")
+}
+
+/// Index in [`ONE_SHOT_KEYS`] of the one-shot key `action` is
+pub fn find(action: &KeyAction) -> Option<usize> {
+    match action {
+        KeyAction::Single(Action::Key(code)) => ONE_SHOT_KEYS.iter().position(|k| k.code == *code),
+        _ => None,
+    }
+}
+
+/// Whether `pos` is the hold position of a one-shot key
+pub fn is_hold_position(pos: (u8, u8)) -> bool {
+    ONE_SHOT_KEYS.iter().any(|k| k.hold == pos)
+}
+
+// The hold positions have no switch, are used once and hold the same key on
+// every layer, so no layer or default layer can change what they press
+const _: () = {
+    let mut i = 0;
+    while i < ONE_SHOT_KEYS.len() {
+        let key = ONE_SHOT_KEYS[i];
+        assert!(
+            key.code as u16 >= KeyCode::User16 as u16 && key.code as u16 <= KeyCode::User31 as u16,
+            "One-shot keys have to be User16 to User31, rmk uses the lower user keycodes"
+        );
+        let (row, col) = key.hold;
+        let mut unwired = false;
+        let mut j = 0;
+        while j < UNWIRED_KEYS.len() {
+            if UNWIRED_KEYS[j].0 == row && UNWIRED_KEYS[j].1 == col {
+                unwired = true;
+            }
+            j += 1;
+        }
+        assert!(unwired, "A one-shot hold position has a switch");
+        let mut j = 0;
+        while j < i {
+            assert!(
+                ONE_SHOT_KEYS[j].hold.0 != row || ONE_SHOT_KEYS[j].hold.1 != col,
+                "Two one-shot keys share a hold position"
+            );
+            assert!(
+                ONE_SHOT_KEYS[j].code as u16 != key.code as u16,
+                "Two one-shot keys share a keycode"
+            );
+            j += 1;
+        }
+        let hold = key.hold_action();
+        let mut layer = 0;
+        while layer < NUM_LAYER {
+            assert!(
+                same_key(KEYMAP[layer][row as usize][col as usize], hold),
+                "A one-shot hold position doesn't hold its modifier or layer on every layer"
+            );
+            layer += 1;
+        }
+        i += 1;
+    }
+};
+
+// A locked one-shot layer is unlocked with its key, so the layer has to let
+// it through
+const _: () = {
+    let mut i = 0;
+    while i < ONE_SHOT_KEYS.len() {
+        if let OneShot::Layer(target) = ONE_SHOT_KEYS[i].one_shot {
+            let key = KeyAction::Single(Action::Key(ONE_SHOT_KEYS[i].code));
+            let mut layer = 0;
+            while layer < NUM_LAYER {
+                let mut row = 0;
+                while row < ROW {
+                    let mut col = 0;
+                    while col < COL {
+                        if same_key(KEYMAP[layer][row][col], key) {
+                            let on_target = KEYMAP[target as usize][row][col];
+                            assert!(
+                                same_key(on_target, KeyAction::Transparent)
+                                    || same_key(on_target, key),
+                                "A one-shot layer key is covered on the layer it turns on"
+                            );
+                        }
+                        col += 1;
+                    }
+                    row += 1;
+                }
+                layer += 1;
+            }
+        }
+        i += 1;
+    }
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Off,
+    /// The key is down, `used` once a key was typed meanwhile
+    Held {
+        used: bool,
+    },
+    /// Tapped, waiting for the next key since `since_ms`
+    Waiting {
+        since_ms: u64,
+    },
+    /// Tapped twice and down, locked once released
+    Locking,
+    Locked,
+    /// Locked and down, off once released
+    Unlocking,
+    /// Tapped again to cancel it and down
+    Cancelling,
+}
+
+/// The state of every one-shot key of the keymap, fed with the keys and the
+/// time in milliseconds.
+pub struct OneShots<const N: usize> {
+    config: OneShotConfig,
+    states: [State; N],
+}
+
+impl<const N: usize> OneShots<N> {
+    pub const fn new(config: OneShotConfig) -> Self {
+        Self {
+            config,
+            states: [State::Off; N],
+        }
+    }
+
+    /// Whether the modifier or layer of the `i`th key is on
+    pub fn is_on(&self, i: usize) -> bool {
+        !matches!(self.states[i], State::Off | State::Cancelling)
+    }
+
+    pub fn is_locked(&self, i: usize) -> bool {
+        matches!(self.states[i], State::Locked | State::Unlocking)
+    }
+
+    /// The `i`th one-shot key was pressed
+    pub fn press(&mut self, i: usize) {
+        self.states[i] = match self.states[i] {
+            State::Off => State::Held { used: false },
+            State::Waiting { .. } => match self.config.double_tap {
+                DoubleTap::Lock => State::Locking,
+                DoubleTap::Cancel => State::Cancelling,
+                DoubleTap::Ignore => State::Held { used: false },
+            },
+            State::Locked => State::Unlocking,
+            state => state,
+        }
+    }
+
+    /// The `i`th one-shot key was released
+    pub fn release(&mut self, i: usize, now_ms: u64) {
+        self.states[i] = match self.states[i] {
+            // A press held back by a tap-hold key shows up as release only
+            State::Off | State::Held { used: false } => State::Waiting { since_ms: now_ms },
+            State::Held { used: true } | State::Unlocking | State::Cancelling => State::Off,
+            State::Locking => State::Locked,
+            state => state,
+        }
+    }
+
+    /// A key was typed with the modifiers and layers that are on
+    pub fn use_up(&mut self) {
+        for state in &mut self.states {
+            *state = match *state {
+                State::Held { .. } | State::Locking => State::Held { used: true },
+                State::Waiting { .. } => State::Off,
+                state => state,
+            }
+        }
+    }
+
+    /// Turns off the keys that waited too long at `now_ms`
+    pub fn time_out(&mut self, now_ms: u64) {
+        for state in &mut self.states {
+            if let State::Waiting { since_ms } = *state
+                && now_ms >= since_ms + self.config.timeout_ms
+            {
+                *state = State::Off;
+            }
+        }
+    }
+
+    /// When the first waiting key times out
+    pub fn deadline_ms(&self) -> Option<u64> {
+        self.states
+            .iter()
+            .filter_map(|state| match state {
+                State::Waiting { since_ms } => Some(since_ms + self.config.timeout_ms),
+                _ => None,
+            })
+            .min()
+    }
+}
@@ -1,9 +1,7 @@
 #![no_std]
 #![no_main]
 
-#[macro_use]
-mod macros;
-
+use bt_hci::param::ConnHandle;
 use defmt::{info, unwrap};
 use embassy_executor::Spawner;
 use embassy_nrf::gpio::{Input, Output};
@@ -12,25 +10,47 @@ use embassy_nrf::mode::Async;
 use embassy_nrf::peripherals::{RNG, SAADC, USBD};
 use embassy_nrf::saadc::{self, AnyInput, Input as _, Saadc};
 use embassy_nrf::{Peri, bind_interrupts, rng, usb};
+use embassy_time::Duration;
 use nrf_mpsl::Flash;
 use nrf_sdc::mpsl::MultiprotocolServiceLayer;
+use nrf_sdc::vendor::ZephyrWriteTxPower;
 use nrf_sdc::{self as sdc, mpsl};
+use panic_probe as _;
 use rand_chacha::ChaCha12Rng;
 use rand_core::SeedableRng;
 use rmk::ble::build_ble_stack;
 use rmk::channel::EVENT_CHANNEL;
 use rmk::config::StorageConfig;
 use rmk::debounce::default_debouncer::DefaultDebouncer;
-use rmk::futures::future::join;
+use rmk::futures::future::join3;
+use rmk::input_device::adc::{AnalogEventType, NrfAdc};
 use rmk::matrix::Matrix;
 use rmk::split::peripheral::run_rmk_split_peripheral;
 use rmk::storage::new_storage_for_split_peripheral;
 use rmk::{HostResources, run_devices};
 use static_cell::StaticCell;
-use {defmt_rtt as _, panic_probe as _};
 
-mod keymap;
-use keymap::{COL, ROW};
+#[cfg(not(feature = "log_forward"))]
+use defmt_rtt as _;
+
+use rmk_corne::battery::BatteryReporter;
+use rmk_corne::device::ble_addr;
+use rmk_corne::keymap::{COL, ROW, SPLIT_LINK};
+use rmk_corne::log_forward::LogReporter;
+use rmk_corne::radio::{Board, LinkController, TxPower, apply_links};
+
+macro_rules! config_matrix_pins_nrf {
+    (peripherals: $p:ident, input: [$($in_pin:ident), *], output: [$($out_pin:ident), +]) => {
+        {
+            let mut output_pins = [$(Output::new($p.$out_pin, embassy_nrf::gpio::Level::Low, embassy_nrf::gpio::OutputDrive::Standard)), +];
+            let input_pins = [$(Input::new($p.$in_pin, embassy_nrf::gpio::Pull::Down)), +];
+            output_pins.iter_mut().for_each(|p| {
+                p.set_low();
+            });
+            (input_pins, output_pins)
+        }
+    };
+}
 
 bind_interrupts!(struct Irqs {
     USBD => usb::InterruptHandler<USBD>;
@@ -57,6 +77,14 @@ const L2CAP_RXQ: u8 = 3;
 /// Size of L2CAP packets
 const L2CAP_MTU: usize = 251;
 
+/// `handle_type` of a connection in the Zephyr TX power command
+const TX_POWER_HANDLE_CONN: u8 = 2;
+
+/// The controller's command for the TX power of a connection
+fn conn_tx_power(handle: ConnHandle, power: TxPower) -> ZephyrWriteTxPower {
+    ZephyrWriteTxPower::new(TX_POWER_HANDLE_CONN, handle.raw(), power.dbm())
+}
+
 fn build_sdc<'d, const N: usize>(
     p: nrf_sdc::Peripherals<'d>,
     rng: &'d mut rng::Rng<Async>,
@@ -74,6 +102,16 @@ fn build_sdc<'d, const N: usize>(
         .build(p, rng, mpsl, mem)
 }
 
+/// Voltage divider in front of the battery pin, measured and total
+/// resistance, the one of rmk's nRF52840 examples. Set it to the board's.
+const BATTERY_DIVIDER: (u32, u32) = (2000, 2806);
+
+/// Which half this is, by the id of its peripheral manager on the dongle
+#[cfg(feature = "peripheral_left")]
+const HALF: u8 = 0;
+#[cfg(not(feature = "peripheral_left"))]
+const HALF: u8 = 1;
+
 /// Initializes the SAADC peripheral in single-ended mode on the given pin.
 fn init_adc(adc_pin: AnyInput, adc: Peri<'static, SAADC>) -> Saadc<'static, 1> {
     // Then we initialize the ADC. We are only using one channel in this example.
@@ -84,12 +122,11 @@ fn init_adc(adc_pin: AnyInput, adc: Peri<'static, SAADC>) -> Saadc<'static, 1> {
     saadc::Saadc::new(adc, Irqs, config, [channel_cfg])
 }
 
-fn ble_addr() -> [u8; 6] {
+/// Unique per chip, set in the factory
+fn device_id() -> u64 {
     let ficr = embassy_nrf::pac::FICR;
     let high = u64::from(ficr.deviceid(1).read());
-    let addr = high << 32 | u64::from(ficr.deviceid(0).read());
-    let addr = addr | 0x0000_c000_0000_0000;
-    unwrap!(addr.to_le_bytes()[..6].try_into())
+    high << 32 | u64::from(ficr.deviceid(0).read())
 }
 
 #[embassy_executor::main]
@@ -127,9 +164,16 @@ async fn main(spawner: Spawner) {
     let mut rng_generator = ChaCha12Rng::from_rng(&mut rng).unwrap();
     let mut sdc_mem = sdc::Mem::<4624>::new();
     let sdc = unwrap!(build_sdc(sdc_p, &mut rng, mpsl, &mut sdc_mem));
+    info!("Split link: {}", SPLIT_LINK);
 
     let mut resources = HostResources::new();
-    let stack = build_ble_stack(sdc, ble_addr(), &mut rng_generator, &mut resources).await;
+    let stack = build_ble_stack(
+        LinkController::new(&sdc, Board::Half),
+        ble_addr(device_id()),
+        &mut rng_generator,
+        &mut resources,
+    )
+    .await;
 
     // Initialize the ADC. We are only using one channel for detecting battery level
     let adc_pin = p.P0_05.degrade_saadc();
@@ -164,16 +208,27 @@ async fn main(spawner: Spawner) {
     // Initialize the peripheral matrix
     let debouncer = DefaultDebouncer::new();
     let mut matrix = Matrix::<_, _, _, ROW, { COL / 2 }, true>::new(row_pins, col_pins, debouncer);
+    // The battery level goes to the dongle with the key events
+    let mut battery = BatteryReporter::new(
+        NrfAdc::new(
+            saadc,
+            [AnalogEventType::Battery],
+            Duration::from_secs(12),
+            None,
+        ),
+        HALF,
+        BATTERY_DIVIDER,
+    );
+    // Only has something to send with the `log_forward` feature
+    let mut log = LogReporter::new(HALF);
 
     // Start
-    join(
+    join3(
         run_devices! (
-            (matrix) => EVENT_CHANNEL, // Peripheral uses EVENT_CHANNEL to send events to central
+            (matrix, battery, log) => EVENT_CHANNEL, // Peripheral uses EVENT_CHANNEL to send events to central
         ),
-        #[cfg(feature = "peripheral_left")] // left
-        run_rmk_split_peripheral(0, &stack, &mut storage),
-        #[cfg(not(feature = "peripheral_left"))] // right
-        run_rmk_split_peripheral(1, &stack, &mut storage),
+        run_rmk_split_peripheral(HALF as usize, &stack, &mut storage),
+        apply_links(&sdc, conn_tx_power),
     )
     .await;
 }
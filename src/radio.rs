@@ -0,0 +1,492 @@
+//! TX power and connection parameters of the BLE links. The dongle has a
+//! split link to each half and a host link to the computer, the halves only
+//! have their split link. The values are picked in keymap.rs and checked
+//! while it compiles, see [`assert_valid`].
+//!
+//! rmk opens the links and answers the host itself, so the binaries hand it
+//! the controller wrapped in a [`LinkController`]. That one reports every new
+//! connection to [`apply_links`], which sets the link's TX power and asks for
+//! its parameters over HCI, and keeps the parameters both ends agreed on for
+//! the status report, see [`connections`].
+
+use core::cell::Cell;
+use core::fmt;
+
+use bt_hci::ControllerToHostPacket;
+use bt_hci::cmd::le::LeConnUpdate;
+use bt_hci::cmd::{AsyncCmd, Error as CmdError, SyncCmd};
+use bt_hci::controller::{Controller, ControllerCmdAsync, ControllerCmdSync};
+use bt_hci::data::{AclPacket, IsoPacket, SyncPacket};
+use bt_hci::event::le::LeEvent;
+use bt_hci::event::{Event, EventPacket};
+use bt_hci::param::{ConnHandle, Duration, LeConnRole, Status};
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embedded_io_async::ErrorType;
+
+use crate::keymap::{HOST_LINK, SPLIT_LINK};
+use crate::validate::Message;
+
+/// TX power levels of the nRF52840 radio. Advertising and scanning keep the
+/// controller's default.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub enum TxPower {
+    Minus40,
+    Minus20,
+    Minus16,
+    Minus12,
+    Minus8,
+    Minus4,
+    /// What the controller uses unless told otherwise
+    #[default]
+    Zero,
+    Plus2,
+    Plus3,
+    Plus4,
+    Plus5,
+    Plus6,
+    Plus7,
+    Plus8,
+}
+
+impl TxPower {
+    pub const fn dbm(self) -> i8 {
+        match self {
+            TxPower::Minus40 => -40,
+            TxPower::Minus20 => -20,
+            TxPower::Minus16 => -16,
+            TxPower::Minus12 => -12,
+            TxPower::Minus8 => -8,
+            TxPower::Minus4 => -4,
+            TxPower::Zero => 0,
+            TxPower::Plus2 => 2,
+            TxPower::Plus3 => 3,
+            TxPower::Plus4 => 4,
+            TxPower::Plus5 => 5,
+            TxPower::Plus6 => 6,
+            TxPower::Plus7 => 7,
+            TxPower::Plus8 => 8,
+        }
+    }
+}
+
+impl fmt::Display for TxPower {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} dBm", self.dbm())
+    }
+}
+
+/// Connection parameters of a link, in the steps BLE allows
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ConnParams {
+    /// Time between connection events in µs, a multiple of 1250 from 7500
+    /// to 4 s. Every key press on the other end waits for the next event.
+    pub interval_us: u32,
+    /// Events the peripheral may sleep through while it has nothing to send
+    pub latency: u16,
+    /// The link drops after this long without a packet, in ms, a multiple of
+    /// 10 from 100 to 32000
+    pub timeout_ms: u32,
+}
+
+impl ConnParams {
+    /// Shortest interval and no sleeping, for the least delay per key
+    pub const LOW_LATENCY: Self = Self {
+        interval_us: 7_500,
+        latency: 0,
+        timeout_ms: 2_000,
+    };
+
+    /// Twice the interval, and the peripheral sleeps while idle
+    pub const BATTERY: Self = Self {
+        interval_us: 15_000,
+        latency: 30,
+        timeout_ms: 6_000,
+    };
+
+    pub const fn preset_name(&self) -> Option<&'static str> {
+        let Self {
+            interval_us,
+            latency,
+            timeout_ms,
+        } = *self;
+        match (interval_us, latency, timeout_ms) {
+            (7_500, 0, 2_000) => Some("low latency"),
+            (15_000, 30, 6_000) => Some("battery"),
+            _ => None,
+        }
+    }
+
+    /// The interval in the 1.25 ms steps of HCI
+    pub const fn hci_interval(&self) -> Duration<1_250> {
+        Duration::from_u16((self.interval_us / 1_250) as u16)
+    }
+
+    /// The timeout in the 10 ms steps of HCI
+    pub const fn hci_timeout(&self) -> Duration<10_000> {
+        Duration::from_u16((self.timeout_ms / 10) as u16)
+    }
+
+    fn from_hci(interval: Duration<1_250>, latency: u16, timeout: Duration<10_000>) -> Self {
+        Self {
+            interval_us: interval.as_micros() as u32,
+            latency,
+            timeout_ms: timeout.as_millis(),
+        }
+    }
+
+    /// The first rule of the BLE spec these parameters break
+    pub const fn check(&self) -> Result<(), &'static str> {
+        if self.interval_us < 7_500 || self.interval_us > 4_000_000 {
+            return Err("the interval has to be from 7.5 ms to 4 s");
+        }
+        if !self.interval_us.is_multiple_of(1_250) {
+            return Err("the interval has to be a multiple of 1.25 ms");
+        }
+        if self.latency > 499 {
+            return Err("the latency can be at most 499 events");
+        }
+        if self.timeout_ms < 100 || self.timeout_ms > 32_000 {
+            return Err("the timeout has to be from 100 ms to 32 s");
+        }
+        if !self.timeout_ms.is_multiple_of(10) {
+            return Err("the timeout has to be a multiple of 10 ms");
+        }
+        // The link must survive a peripheral sleeping for `latency` events
+        let slept_us = (1 + self.latency as u64) * self.interval_us as u64 * 2;
+        if self.timeout_ms as u64 * 1_000 <= slept_us {
+            return Err("the timeout has to be longer than (1 + latency) * interval * 2");
+        }
+        Ok(())
+    }
+}
+
+impl Default for ConnParams {
+    fn default() -> Self {
+        Self::LOW_LATENCY
+    }
+}
+
+/// `interval 7.5ms, latency 0, timeout 2000ms (low latency)`
+impl fmt::Display for ConnParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (ms, us) = (self.interval_us / 1_000, self.interval_us % 1_000);
+        match us {
+            0 => write!(f, "interval {ms}ms")?,
+            us if us % 100 == 0 => write!(f, "interval {ms}.{}ms", us / 100)?,
+            us => write!(f, "interval {ms}.{:02}ms", us / 10)?,
+        }
+        write!(
+            f,
+            ", latency {}, timeout {}ms",
+            self.latency, self.timeout_ms
+        )?;
+        match self.preset_name() {
+            Some(name) => write!(f, " ({name})"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl defmt::Format for ConnParams {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "interval {}us, latency {}, timeout {}ms",
+            self.interval_us,
+            self.latency,
+            self.timeout_ms
+        );
+    }
+}
+
+/// TX power and connection parameters of one kind of link
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Link {
+    pub tx_power: TxPower,
+    pub params: ConnParams,
+}
+
+/// `0 dBm, interval 7.5ms, latency 0, timeout 2000ms (low latency)`
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.tx_power, self.params)
+    }
+}
+
+impl defmt::Format for Link {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{} dBm, {}", self.tx_power.dbm(), self.params);
+    }
+}
+
+/// Fails the build if `params` break the BLE spec, `name` says which link
+pub const fn assert_valid(name: &str, params: &ConnParams) {
+    if let Err(error) = params.check() {
+        let message = Message::new().push(name).push(": ").push(error);
+        panic!("{}", message.as_str());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum LinkKind {
+    /// Between the dongle and a half
+    Split,
+    /// Between the dongle and the computer
+    Host,
+}
+
+impl LinkKind {
+    /// The values keymap.rs picked for the link
+    pub const fn link(self) -> Link {
+        match self {
+            LinkKind::Split => SPLIT_LINK,
+            LinkKind::Host => HOST_LINK,
+        }
+    }
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LinkKind::Split => "split",
+            LinkKind::Host => "host",
+        })
+    }
+}
+
+/// Which firmware the controller runs in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Board {
+    Dongle,
+    Half,
+}
+
+impl Board {
+    /// The dongle is the central of the split links and the peripheral of
+    /// the host link, a half only has its split link
+    pub const fn link_kind(self, role: LeConnRole) -> LinkKind {
+        match (self, role) {
+            (Board::Dongle, LeConnRole::Peripheral) => LinkKind::Host,
+            _ => LinkKind::Split,
+        }
+    }
+}
+
+/// What an HCI event tells about the links
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LinkEvent {
+    /// A new connection, with the role of this end and the parameters it
+    /// started with
+    Connected(ConnHandle, LeConnRole, ConnParams),
+    /// Both ends agreed on other parameters
+    Updated(ConnHandle, ConnParams),
+    Disconnected(ConnHandle),
+}
+
+/// The link event `event` reports, if it is one and succeeded
+pub fn link_event(event: &EventPacket) -> Option<LinkEvent> {
+    let link_event = match Event::try_from(event.clone()).ok()? {
+        Event::Le(LeEvent::LeConnectionComplete(e)) if e.status == Status::SUCCESS => {
+            LinkEvent::Connected(
+                e.handle,
+                e.role,
+                ConnParams::from_hci(e.conn_interval, e.peripheral_latency, e.supervision_timeout),
+            )
+        }
+        Event::Le(LeEvent::LeEnhancedConnectionComplete(e)) if e.status == Status::SUCCESS => {
+            LinkEvent::Connected(
+                e.handle,
+                e.role,
+                ConnParams::from_hci(e.conn_interval, e.peripheral_latency, e.supervision_timeout),
+            )
+        }
+        Event::Le(LeEvent::LeConnectionUpdateComplete(e)) if e.status == Status::SUCCESS => {
+            LinkEvent::Updated(
+                e.handle,
+                ConnParams::from_hci(e.conn_interval, e.peripheral_latency, e.supervision_timeout),
+            )
+        }
+        Event::DisconnectionComplete(e) if e.status == Status::SUCCESS => {
+            LinkEvent::Disconnected(e.handle)
+        }
+        _ => return None,
+    };
+    Some(link_event)
+}
+
+/// A link that is up, with the parameters both ends agreed on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Connection {
+    pub handle: ConnHandle,
+    pub kind: LinkKind,
+    pub params: ConnParams,
+}
+
+/// Two split links and the host link
+pub const MAX_CONNECTIONS: usize = 3;
+
+static CONNECTIONS: Mutex<CriticalSectionRawMutex, Cell<[Option<Connection>; MAX_CONNECTIONS]>> =
+    Mutex::new(Cell::new([None; MAX_CONNECTIONS]));
+
+/// New connections, for [`apply_links`]
+static NEW_CONNECTIONS: Channel<CriticalSectionRawMutex, (ConnHandle, LinkKind), MAX_CONNECTIONS> =
+    Channel::new();
+
+/// The links that are up, by when they came up
+pub fn connections() -> [Option<Connection>; MAX_CONNECTIONS] {
+    CONNECTIONS.lock(|connections| connections.get())
+}
+
+fn update(change: impl FnOnce(&mut [Option<Connection>; MAX_CONNECTIONS])) {
+    CONNECTIONS.lock(|connections| {
+        let mut new = connections.get();
+        change(&mut new);
+        connections.set(new);
+    });
+}
+
+/// Passes everything through to the controller and follows the links in
+/// its events. `Board` tells which link a new connection is.
+pub struct LinkController<'d, C> {
+    inner: &'d C,
+    board: Board,
+}
+
+impl<'d, C> LinkController<'d, C> {
+    /// [`apply_links`] runs its commands on `inner` as well
+    pub fn new(inner: &'d C, board: Board) -> Self {
+        Self { inner, board }
+    }
+
+    fn follow(&self, event: LinkEvent) {
+        match event {
+            LinkEvent::Connected(handle, role, params) => {
+                let kind = self.board.link_kind(role);
+                info!("{} link {} up: {}", kind, handle.raw(), params);
+                update(|connections| {
+                    if let Some(free) = connections.iter_mut().find(|c| c.is_none()) {
+                        *free = Some(Connection {
+                            handle,
+                            kind,
+                            params,
+                        });
+                    }
+                });
+                if NEW_CONNECTIONS.try_send((handle, kind)).is_err() {
+                    warn!(
+                        "Link {} keeps the default TX power and parameters",
+                        handle.raw()
+                    );
+                }
+            }
+            LinkEvent::Updated(handle, params) => {
+                info!("Link {} updated: {}", handle.raw(), params);
+                update(|connections| {
+                    for connection in connections.iter_mut().flatten() {
+                        if connection.handle == handle {
+                            connection.params = params;
+                        }
+                    }
+                });
+            }
+            LinkEvent::Disconnected(handle) => update(|connections| {
+                for connection in connections.iter_mut() {
+                    if connection.is_some_and(|c| c.handle == handle) {
+                        *connection = None;
+                    }
+                }
+            }),
+        }
+    }
+}
+
+impl<C: ErrorType> ErrorType for LinkController<'_, C> {
+    type Error = C::Error;
+}
+
+impl<C: Controller> Controller for LinkController<'_, C> {
+    async fn write_acl_data(&self, packet: &AclPacket<'_>) -> Result<(), Self::Error> {
+        self.inner.write_acl_data(packet).await
+    }
+
+    async fn write_sync_data(&self, packet: &SyncPacket<'_>) -> Result<(), Self::Error> {
+        self.inner.write_sync_data(packet).await
+    }
+
+    async fn write_iso_data(&self, packet: &IsoPacket<'_>) -> Result<(), Self::Error> {
+        self.inner.write_iso_data(packet).await
+    }
+
+    async fn read<'a>(&self, buf: &'a mut [u8]) -> Result<ControllerToHostPacket<'a>, Self::Error> {
+        let packet = self.inner.read(buf).await?;
+        if let ControllerToHostPacket::Event(event) = &packet
+            && let Some(event) = link_event(event)
+        {
+            self.follow(event);
+        }
+        Ok(packet)
+    }
+}
+
+impl<C, Cmd> ControllerCmdSync<Cmd> for LinkController<'_, C>
+where
+    C: ControllerCmdSync<Cmd>,
+    Cmd: SyncCmd + ?Sized,
+{
+    async fn exec(&self, cmd: &Cmd) -> Result<Cmd::Return, CmdError<Self::Error>> {
+        self.inner.exec(cmd).await
+    }
+}
+
+impl<C, Cmd> ControllerCmdAsync<Cmd> for LinkController<'_, C>
+where
+    C: ControllerCmdAsync<Cmd>,
+    Cmd: AsyncCmd + ?Sized,
+{
+    async fn exec(&self, cmd: &Cmd) -> Result<(), CmdError<Self::Error>> {
+        self.inner.exec(cmd).await
+    }
+}
+
+/// Sets the TX power and asks for the parameters of its link on every new
+/// connection the [`LinkController`] sees. HCI has no standard command for
+/// the TX power of a connection, `tx_power` builds the controller's own.
+///
+/// The central of a link applies the parameters, a peripheral only asks the
+/// central for them, the host may keep its own.
+pub async fn apply_links<C, P>(controller: &C, tx_power: fn(ConnHandle, TxPower) -> P) -> !
+where
+    C: ControllerCmdSync<P> + ControllerCmdAsync<LeConnUpdate>,
+    C::Error: defmt::Format,
+    P: SyncCmd,
+{
+    loop {
+        let (handle, kind) = NEW_CONNECTIONS.receive().await;
+        let link = kind.link();
+        let set_power = tx_power(handle, link.tx_power);
+        if let Err(error) = ControllerCmdSync::exec(controller, &set_power).await {
+            warn!(
+                "Setting the TX power of link {} failed: {}",
+                handle.raw(),
+                error
+            );
+        }
+        let params = link.params;
+        let update = LeConnUpdate::new(
+            handle,
+            params.hci_interval(),
+            params.hci_interval(),
+            params.latency,
+            params.hci_timeout(),
+            Duration::from_u16(0),
+            Duration::from_u16(0),
+        );
+        match ControllerCmdAsync::exec(controller, &update).await {
+            Ok(()) => info!("{} link {} asked for {}", kind, handle.raw(), link),
+            Err(error) => warn!("Updating link {} failed: {}", handle.raw(), error),
+        }
+    }
+}
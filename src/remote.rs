@@ -0,0 +1,48 @@
+//! What the host asks of the keyboard over raw HID, see `via.rs`: turning a
+//! layer on or off and switching the BLE profile.
+//!
+//! rmk does both only for keys, so the keyboard taps a key of the leader
+//! layer for a request, like it does for a leader sequence: the `tg!` key of
+//! one of [`REMOTE_LAYERS`] or one of rmk's [`PROFILE_KEYS`].
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+
+use crate::keymap::{PROFILE_KEYS, REMOTE_LAYERS};
+use crate::leader::{profile_position, remote_layer_position};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Remote {
+    /// Turns one of [`REMOTE_LAYERS`] on or off
+    ToggleLayer(u8),
+    SwitchProfile(u8),
+}
+
+impl Remote {
+    /// Position of the key for it on the leader layer, `None` for a layer
+    /// or profile it can't switch
+    pub(crate) fn position(self) -> Option<(u8, u8)> {
+        match self {
+            Self::ToggleLayer(layer) => REMOTE_LAYERS
+                .iter()
+                .position(|&l| l == layer)
+                .map(remote_layer_position),
+            Self::SwitchProfile(profile) => ((profile as usize) < PROFILE_KEYS.len())
+                .then(|| profile_position(profile as usize)),
+        }
+    }
+}
+
+/// Requests waiting for the keyboard
+static REQUESTS: Channel<CriticalSectionRawMutex, Remote, 4> = Channel::new();
+
+/// Hands `remote` to the keyboard, false if it can't do it or too many
+/// requests are waiting
+pub fn request(remote: Remote) -> bool {
+    remote.position().is_some() && REQUESTS.try_send(remote).is_ok()
+}
+
+/// The next request, for the keyboard
+pub(crate) async fn next() -> Remote {
+    REQUESTS.receive().await
+}
@@ -0,0 +1,499 @@
+//! Draws the layers of the keymap as ASCII tables and SVG pictures, see
+//! examples/render_keymap.rs. Only keys with a switch are drawn, the
+//! `UNWIRED_KEYS` of the thumb row are left out.
+
+use core::fmt::{self, Write};
+
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+use rmk::types::modifier::ModifierCombination;
+
+use crate::auto_shift::AUTO_SHIFT_TOGGLE;
+use crate::keymap::{
+    COL, HOST_LAYOUT, LAYER_NAMES, MACROS, ONE_SHOT_KEYS, PROFILE_KEYS, ROW, TAP_DANCES,
+    UNWIRED_KEYS,
+};
+use crate::layer_lock::LAYER_LOCK;
+use crate::leader::LEADER;
+use crate::mouse::MouseKey;
+use crate::one_shot::{self, OneShot};
+use crate::tap_dance::TapDance;
+use crate::tapping_term::{TERM_DOWN, TERM_PRINT, TERM_RESET, TERM_SELECT, TERM_UP};
+
+/// Characters that fit on a key
+pub const LABEL_LEN: usize = 8;
+
+/// Our own keys on user keycodes
+const NAMED_KEYS: [(KeyAction, &str); 8] = [
+    (LEADER, "Leader"),
+    (LAYER_LOCK, "LyrLock"),
+    (AUTO_SHIFT_TOGGLE, "AutoSft"),
+    (TERM_SELECT, "TT Key"),
+    (TERM_DOWN, "TT-"),
+    (TERM_UP, "TT+"),
+    (TERM_PRINT, "TT?"),
+    (TERM_RESET, "TT Rst"),
+];
+
+/// Keys that read better as a symbol or a shorter name than rmk's
+const SHORT_NAMES: [(KeyCode, &str); 24] = [
+    (KeyCode::Minus, "-"),
+    (KeyCode::Equal, "="),
+    (KeyCode::LeftBracket, "["),
+    (KeyCode::RightBracket, "]"),
+    (KeyCode::Backslash, "\\"),
+    (KeyCode::Semicolon, ";"),
+    (KeyCode::Quote, "'"),
+    (KeyCode::Grave, "`"),
+    (KeyCode::Comma, ","),
+    (KeyCode::Dot, "."),
+    (KeyCode::Slash, "/"),
+    (KeyCode::KpEqual, "Kp="),
+    (KeyCode::KpPlus, "Kp+"),
+    (KeyCode::Backspace, "Bksp"),
+    (KeyCode::Delete, "Del"),
+    (KeyCode::Escape, "Esc"),
+    (KeyCode::CapsLock, "Caps"),
+    (KeyCode::PageUp, "PgUp"),
+    (KeyCode::PageDown, "PgDn"),
+    (KeyCode::AudioVolUp, "Vol+"),
+    (KeyCode::AudioVolDown, "Vol-"),
+    (KeyCode::AudioMute, "Mute"),
+    (KeyCode::Bootloader, "Boot"),
+    (KeyCode::User5, "BtClear"),
+];
+
+/// Text on a key, cut off after `LABEL_LEN` characters
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Label {
+    buf: [char; LABEL_LEN],
+    len: usize,
+}
+
+impl Label {
+    fn of(args: fmt::Arguments) -> Self {
+        let mut label = Self::default();
+        // Only fails once the label is full, the rest is cut off
+        let _ = label.write_fmt(args);
+        label
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.buf[..self.len].iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Write for Label {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.len == LABEL_LEN {
+                return Err(fmt::Error);
+            }
+            self.buf[self.len] = c;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.chars().try_for_each(|c| f.write_char(c))
+    }
+}
+
+/// What is drawn on a key
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Legend {
+    /// No switch at this position
+    Unwired,
+    /// Falls through to the layer below
+    Transparent,
+    /// `hold` is only there for tap-holds like `hrm!` and `kol!`
+    Key { tap: Label, hold: Option<Label> },
+}
+
+fn keycode_name(keycode: KeyCode) -> Label {
+    if let Some(profile) = PROFILE_KEYS.iter().position(|&k| k == keycode) {
+        return Label::of(format_args!("BT{profile}"));
+    }
+    if let Some((_, name)) = SHORT_NAMES.iter().find(|(k, _)| *k == keycode) {
+        return Label::of(format_args!("{name}"));
+    }
+    if let Some(mouse_key) = MouseKey::from_keycode(keycode) {
+        return Label::of(format_args!("Ms{mouse_key:?}"));
+    }
+    match keycode {
+        KeyCode::No => Label::default(),
+        KeyCode::Kc1 => Label::of(format_args!("1")),
+        KeyCode::Kc2 => Label::of(format_args!("2")),
+        KeyCode::Kc3 => Label::of(format_args!("3")),
+        KeyCode::Kc4 => Label::of(format_args!("4")),
+        KeyCode::Kc5 => Label::of(format_args!("5")),
+        KeyCode::Kc6 => Label::of(format_args!("6")),
+        KeyCode::Kc7 => Label::of(format_args!("7")),
+        KeyCode::Kc8 => Label::of(format_args!("8")),
+        KeyCode::Kc9 => Label::of(format_args!("9")),
+        KeyCode::Kc0 => Label::of(format_args!("0")),
+        _ => Label::of(format_args!("{keycode:?}")),
+    }
+}
+
+/// `Shift`, `Ctrl+Alt`, ... for modifiers held on their own
+struct Modifiers(ModifierCombination);
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Bits as in the HID report, the right hand ones are the upper four
+        const NAMES: [&str; 4] = ["Ctrl", "Shift", "Alt", "Gui"];
+        let bits = self.0.into_bits();
+        let mut first = true;
+        for (i, name) in NAMES.iter().enumerate() {
+            if bits & (0x11 << i) != 0 {
+                if !first {
+                    f.write_char('+')?;
+                }
+                let side = if bits & (0x01 << i) != 0 { "" } else { "R" };
+                write!(f, "{side}{name}")?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `C-`, `S-`, ... in front of a key sent with modifiers
+struct ModifierPrefix(ModifierCombination);
+
+impl fmt::Display for ModifierPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const PREFIXES: [char; 4] = ['C', 'S', 'A', 'G'];
+        let bits = self.0.into_bits();
+        for (i, prefix) in PREFIXES.iter().enumerate() {
+            if bits & (0x11 << i) != 0 {
+                write!(f, "{prefix}-")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `Shift`, `Ctrl`, ... for the keycode of a modifier
+fn modifier_name(keycode: KeyCode) -> Label {
+    match keycode {
+        KeyCode::LCtrl => Label::of(format_args!("Ctrl")),
+        KeyCode::LShift => Label::of(format_args!("Shift")),
+        KeyCode::LAlt => Label::of(format_args!("Alt")),
+        KeyCode::LGui => Label::of(format_args!("Gui")),
+        _ => keycode_name(keycode),
+    }
+}
+
+/// The actions of a tap dance that do something, between `/`
+fn tap_dance_label(actions: &[Action], held: bool) -> Option<Label> {
+    let mut label = Label::default();
+    for &action in actions.iter().filter(|&&action| action != Action::No) {
+        if !label.is_empty() {
+            // Only fails once the label is full, the rest is cut off
+            let _ = label.write_char('/');
+        }
+        let _ = write!(label, "{}", action_label(action, held));
+    }
+    (!label.is_empty()).then_some(label)
+}
+
+/// Label of an action, `held` for the hold of a tap-hold
+fn action_label(action: Action, held: bool) -> Label {
+    match action {
+        Action::No => Label::default(),
+        Action::Key(keycode) => keycode_name(keycode),
+        Action::KeyWithModifier(keycode, modifiers) => Label::of(format_args!(
+            "{}{}",
+            ModifierPrefix(modifiers),
+            keycode_name(keycode)
+        )),
+        Action::Modifier(modifiers) => Label::of(format_args!("{}", Modifiers(modifiers))),
+        Action::LayerOn(layer) if held => Label::of(format_args!("L{layer}")),
+        Action::LayerOn(layer) => Label::of(format_args!("MO{layer}")),
+        Action::LayerOnWithModifier(layer, _) => Label::of(format_args!("LM{layer}")),
+        Action::LayerToggle(layer) => Label::of(format_args!("TG{layer}")),
+        Action::LayerToggleOnly(layer) => Label::of(format_args!("TO{layer}")),
+        Action::DefaultLayer(layer) => Label::of(format_args!("DF{layer}")),
+        Action::OneShotLayer(layer) => Label::of(format_args!("OSL{layer}")),
+        // The macros after `MACROS` type the dead keys of the host layout
+        Action::TriggerMacro(i) => match (i as usize)
+            .checked_sub(MACROS.len())
+            .and_then(|dead| HOST_LAYOUT.dead_chars().get(dead))
+        {
+            Some(c) => Label::of(format_args!("{c}")),
+            None => Label::of(format_args!("Macro{i}")),
+        },
+        Action::OneShotModifier(modifiers) => {
+            Label::of(format_args!("OS {}", Modifiers(modifiers)))
+        }
+        _ => Label::of(format_args!("{action:?}")),
+    }
+}
+
+/// What is drawn on `key`
+pub fn legend(key: KeyAction) -> Legend {
+    if let Some((_, name)) = NAMED_KEYS.iter().find(|(k, _)| *k == key) {
+        return Legend::Key {
+            tap: Label::of(format_args!("{name}")),
+            hold: None,
+        };
+    }
+    if let Some(i) = one_shot::find(&key) {
+        let tap = match ONE_SHOT_KEYS[i].one_shot {
+            OneShot::Modifier(keycode) => Label::of(format_args!("OS {}", modifier_name(keycode))),
+            OneShot::Layer(layer) => Label::of(format_args!("OSL{layer}")),
+        };
+        return Legend::Key { tap, hold: None };
+    }
+    match key {
+        KeyAction::Transparent => Legend::Transparent,
+        KeyAction::No => Legend::Key {
+            tap: Label::default(),
+            hold: None,
+        },
+        KeyAction::Single(action) => Legend::Key {
+            tap: action_label(action, false),
+            hold: None,
+        },
+        KeyAction::TapHold(tap, hold, _) => Legend::Key {
+            tap: action_label(tap, false),
+            hold: Some(action_label(hold, true)),
+        },
+        // The taps of a tap dance on top, its holds below
+        KeyAction::Morse(i) if (i as usize) < TAP_DANCES.len() => {
+            let TapDance { taps, holds } = TAP_DANCES[i as usize];
+            Legend::Key {
+                tap: tap_dance_label(&taps, false).unwrap_or_default(),
+                hold: tap_dance_label(&holds, true),
+            }
+        }
+        // rmk's name for anything else
+        _ => Legend::Key {
+            tap: Label::of(format_args!("{key:?}")),
+            hold: None,
+        },
+    }
+}
+
+/// Legends of a layer, `Legend::Unwired` where there's no switch
+pub fn legends(keys: &[[KeyAction; COL]; ROW]) -> [[Legend; COL]; ROW] {
+    let mut legends = [[Legend::Unwired; COL]; ROW];
+    for (row, keys) in keys.iter().enumerate() {
+        for (col, &key) in keys.iter().enumerate() {
+            if !UNWIRED_KEYS.contains(&(row as u8, col as u8)) {
+                legends[row][col] = legend(key);
+            }
+        }
+    }
+    legends
+}
+
+/// Transparent keys are drawn like QMK's `_______`
+const ASCII_TRANSPARENT: &str = "________";
+
+/// Space between the halves
+const ASCII_GAP: &str = "    ";
+
+/// One line of the table: a border of `fill`, or the labels of the keys
+/// between `|`. `present` says where there is a key.
+fn write_ascii_line<W: Write>(
+    w: &mut W,
+    present: &[bool; COL],
+    fill: Option<char>,
+    label: &dyn Fn(usize) -> Option<Label>,
+    transparent: &dyn Fn(usize) -> bool,
+) -> fmt::Result {
+    const CELL: usize = LABEL_LEN + 2;
+    let mut line = [' '; COL * (CELL + 1) + 2 + ASCII_GAP.len()];
+    let edge = if fill.is_some() { '+' } else { '|' };
+    let mut at = 0;
+    for half in [0..COL / 2, COL / 2..COL] {
+        for col in half.clone() {
+            if present[col] || (col > half.start && present[col - 1]) {
+                line[at] = edge;
+            }
+            let cell = &mut line[at + 1..at + 1 + CELL];
+            if present[col] {
+                match fill {
+                    Some(fill) => cell.fill(fill),
+                    None if transparent(col) => cell[1..]
+                        .iter_mut()
+                        .zip(ASCII_TRANSPARENT.chars())
+                        .for_each(|(c, t)| *c = t),
+                    None => {
+                        if let Some(label) = label(col) {
+                            cell[1..]
+                                .iter_mut()
+                                .zip(label.chars())
+                                .for_each(|(c, l)| *c = l);
+                        }
+                    }
+                }
+            }
+            at += 1 + CELL;
+        }
+        if present[half.end - 1] {
+            line[at] = edge;
+        }
+        at += 1;
+        if half.start == 0 {
+            at += ASCII_GAP.len();
+        }
+    }
+    let end = line.iter().rposition(|&c| c != ' ').map_or(0, |i| i + 1);
+    line[..end].iter().try_for_each(|&c| w.write_char(c))?;
+    w.write_char('\n')
+}
+
+/// Draws a layer as a table, with the tap of a key in the first line of its
+/// box and the hold in the second
+pub fn write_ascii<W: Write>(
+    w: &mut W,
+    layer: usize,
+    keys: &[[KeyAction; COL]; ROW],
+) -> fmt::Result {
+    let legends = legends(keys);
+    let present = legends.map(|row| row.map(|legend| legend != Legend::Unwired));
+    let no_label = |_: usize| None::<Label>;
+    let not_transparent = |_: usize| false;
+
+    writeln!(w, "Layer {layer}: {}", LAYER_NAMES[layer])?;
+    for row in 0..=ROW {
+        let mut border = [false; COL];
+        for (col, border) in border.iter_mut().enumerate() {
+            *border = (row > 0 && present[row - 1][col]) || (row < ROW && present[row][col]);
+        }
+        write_ascii_line(w, &border, Some('-'), &no_label, &not_transparent)?;
+        if row == ROW {
+            break;
+        }
+        let keys = &legends[row];
+        let transparent = |col: usize| keys[col] == Legend::Transparent;
+        let tap = |col: usize| match keys[col] {
+            Legend::Key { tap, .. } => Some(tap),
+            _ => None,
+        };
+        let hold = |col: usize| match keys[col] {
+            Legend::Key { hold, .. } => hold,
+            _ => None,
+        };
+        write_ascii_line(w, &present[row], None, &tap, &transparent)?;
+        write_ascii_line(w, &present[row], None, &hold, &not_transparent)?;
+    }
+    Ok(())
+}
+
+const SVG_KEY: u32 = 54;
+const SVG_PITCH: u32 = 58;
+const SVG_GAP: u32 = 40;
+const SVG_MARGIN: u32 = 10;
+const SVG_TITLE: u32 = 30;
+
+/// `&`, `<` and `>` as XML entities
+struct Escaped(Label);
+
+impl fmt::Display for Escaped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.chars().try_for_each(|c| match c {
+            '&' => f.write_str("&amp;"),
+            '<' => f.write_str("&lt;"),
+            '>' => f.write_str("&gt;"),
+            c => f.write_char(c),
+        })
+    }
+}
+
+/// Draws a layer as an SVG picture. Holds are written smaller below the tap,
+/// transparent keys have a dashed outline and an arrow pointing down.
+pub fn write_svg<W: Write>(w: &mut W, layer: usize, keys: &[[KeyAction; COL]; ROW]) -> fmt::Result {
+    let width = 2 * SVG_MARGIN + COL as u32 * SVG_PITCH + SVG_GAP;
+    let height = 2 * SVG_MARGIN + SVG_TITLE + ROW as u32 * SVG_PITCH;
+    let center = (SVG_KEY / 2) as f32;
+
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    )?;
+    writeln!(w, "<style>")?;
+    writeln!(
+        w,
+        "  rect {{ fill: #f6f6f6; stroke: #444; stroke-width: 1.5; }}"
+    )?;
+    writeln!(
+        w,
+        "  rect.transparent {{ fill: none; stroke: #aaa; stroke-dasharray: 4 3; }}"
+    )?;
+    writeln!(
+        w,
+        "  text {{ font: 12px sans-serif; text-anchor: middle; dominant-baseline: middle; }}"
+    )?;
+    writeln!(
+        w,
+        "  text.title {{ font-size: 16px; font-weight: bold; text-anchor: start; }}"
+    )?;
+    writeln!(w, "  text.hold {{ font-size: 10px; fill: #777; }}")?;
+    writeln!(w, "  text.transparent {{ fill: #aaa; }}")?;
+    writeln!(w, "</style>")?;
+    writeln!(
+        w,
+        r#"<text class="title" x="{SVG_MARGIN}" y="{}">Layer {layer}: {}</text>"#,
+        SVG_MARGIN + SVG_TITLE / 2,
+        LAYER_NAMES[layer]
+    )?;
+
+    for (row, legends) in legends(keys).iter().enumerate() {
+        for (col, legend) in legends.iter().enumerate() {
+            let gap = if col < COL / 2 { 0 } else { SVG_GAP };
+            let x = SVG_MARGIN + col as u32 * SVG_PITCH + gap;
+            let y = SVG_MARGIN + SVG_TITLE + row as u32 * SVG_PITCH;
+            let (cx, cy) = (x as f32 + center, y as f32 + center);
+            match *legend {
+                Legend::Unwired => (),
+                Legend::Transparent => {
+                    writeln!(
+                        w,
+                        r#"<rect class="transparent" x="{x}" y="{y}" width="{SVG_KEY}" height="{SVG_KEY}" rx="6"/>"#
+                    )?;
+                    writeln!(w, r#"<text class="transparent" x="{cx}" y="{cy}">▽</text>"#)?;
+                }
+                Legend::Key { tap, hold } => {
+                    writeln!(
+                        w,
+                        r#"<rect x="{x}" y="{y}" width="{SVG_KEY}" height="{SVG_KEY}" rx="6"/>"#
+                    )?;
+                    match hold {
+                        Some(hold) => {
+                            writeln!(
+                                w,
+                                r#"<text x="{cx}" y="{}">{}</text>"#,
+                                cy - 8.0,
+                                Escaped(tap)
+                            )?;
+                            writeln!(
+                                w,
+                                r#"<text class="hold" x="{cx}" y="{}">{}</text>"#,
+                                cy + 12.0,
+                                Escaped(hold)
+                            )?;
+                        }
+                        None => writeln!(w, r#"<text x="{cx}" y="{cy}">{}</text>"#, Escaped(tap))?,
+                    }
+                }
+            }
+        }
+    }
+    writeln!(w, "</svg>")
+}
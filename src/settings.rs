@@ -0,0 +1,99 @@
+//! Settings of the dongle that rmk doesn't keep itself.
+//!
+//! They live in their own flash pages right after rmk's storage, so clearing
+//! rmk's storage doesn't lose them. Each setting is one byte in a
+//! `sequential-storage` map keyed by [`Setting`]. Several [`Settings`] can
+//! share the flash, one reads or writes at a time.
+
+use core::ops::Range;
+
+use defmt::{Debug2Format, warn};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embedded_storage_async::nor_flash::NorFlash;
+use sequential_storage::cache::NoCache;
+use sequential_storage::map::{fetch_item, store_item};
+
+/// Start of the settings in the flash, the end of rmk's storage
+pub const SETTINGS_START: u32 = 0xA6000;
+
+/// Size of the settings, two pages so one can be erased while the other
+/// holds the data
+pub const SETTINGS_SIZE: u32 = 0x2000;
+
+const RANGE: Range<u32> = 0..SETTINGS_SIZE;
+
+/// Held while the settings are read or written
+static LOCK: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Setting {
+    /// Layer the keyboard starts on
+    DefaultLayer = 0,
+    /// Global tapping term, in steps of 5 ms
+    TappingTerm = 1,
+    /// Tapping term of the home row mods, in steps of 5 ms, `0` follows the
+    /// global term
+    HomeRowModTerm = 2,
+    /// Tapping term of the thumb layer keys, as the home row mods
+    ThumbTerm = 3,
+}
+
+/// The settings in a flash partition of [`SETTINGS_SIZE`] bytes
+pub struct Settings<F> {
+    flash: F,
+}
+
+impl<F: NorFlash> Settings<F> {
+    pub fn new(flash: F) -> Self {
+        Self { flash }
+    }
+
+    /// Reads `setting`, `None` if it was never stored or can't be read
+    pub async fn get(&mut self, setting: Setting) -> Option<u8> {
+        let _lock = LOCK.lock().await;
+        let mut buffer = [0; 16];
+        match fetch_item::<u8, u8, _>(
+            &mut self.flash,
+            RANGE,
+            &mut NoCache::new(),
+            &mut buffer,
+            &(setting as u8),
+        )
+        .await
+        {
+            Ok(value) => value,
+            Err(e) => {
+                warn!(
+                    "Failed to read setting {}: {:?}",
+                    setting as u8,
+                    Debug2Format(&e)
+                );
+                None
+            }
+        }
+    }
+
+    /// Stores `value` for `setting`
+    pub async fn set(&mut self, setting: Setting, value: u8) {
+        let _lock = LOCK.lock().await;
+        let mut buffer = [0; 16];
+        if let Err(e) = store_item(
+            &mut self.flash,
+            RANGE,
+            &mut NoCache::new(),
+            &mut buffer,
+            &(setting as u8),
+            &value,
+        )
+        .await
+        {
+            warn!(
+                "Failed to store setting {}: {:?}",
+                setting as u8,
+                Debug2Format(&e)
+            );
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Messages from the halves to the dongle that rmk has no split message for.
+//!
+//! They travel as rmk's `Event::Custom`: a half puts them on its
+//! `EVENT_CHANNEL` like any input device does, rmk sends them over the split
+//! link and the peripheral manager of the dongle puts them on the dongle's
+//! `EVENT_CHANNEL`. rmk only forwards them while the dongle is connected to a
+//! host.
+
+use defmt::info;
+use rmk::channel::EVENT_CHANNEL;
+use rmk::event::Event;
+
+use crate::log_forward::{self, LogJoiner};
+use crate::status;
+
+/// The halves, left and right, by the id rmk gives their peripheral manager
+pub const HALVES: usize = 2;
+
+/// Name of the half with the id `half`
+pub fn half_name(half: usize) -> &'static str {
+    match half {
+        0 => "left",
+        _ => "right",
+    }
+}
+
+/// First byte of a custom event
+const BATTERY: u8 = 0x01;
+const LOG: u8 = 0x02;
+
+/// Log bytes in a [`HalfMessage::Log`], what is left of a custom event
+pub const LOG_CHUNK_LEN: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HalfMessage {
+    /// Battery level of a half, in percent
+    Battery { half: u8, percent: u8 },
+    /// Next bytes of the defmt log of a half, `seq` counts up per message
+    Log {
+        half: u8,
+        seq: u8,
+        len: u8,
+        data: [u8; LOG_CHUNK_LEN],
+    },
+}
+
+impl HalfMessage {
+    pub fn to_event(self) -> Event {
+        let mut data = [0; 16];
+        match self {
+            Self::Battery { half, percent } => {
+                data[..3].copy_from_slice(&[BATTERY, half, percent]);
+            }
+            Self::Log {
+                half,
+                seq,
+                len,
+                data: log,
+            } => {
+                data[..4].copy_from_slice(&[LOG, half, seq, len]);
+                data[4..].copy_from_slice(&log);
+            }
+        }
+        Event::Custom(data)
+    }
+
+    /// The message in `event`, `None` for other events and invalid messages
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let Event::Custom(data) = event else {
+            return None;
+        };
+        match *data {
+            [BATTERY, half, percent, ..] if (half as usize) < HALVES && percent <= 100 => {
+                Some(Self::Battery { half, percent })
+            }
+            [LOG, half, seq, len, ref log @ ..]
+                if (half as usize) < HALVES && len as usize <= LOG_CHUNK_LEN =>
+            {
+                Some(Self::Log {
+                    half,
+                    seq,
+                    len,
+                    data: *log,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Takes the messages of the halves off the dongle's `EVENT_CHANNEL`,
+/// nothing else on the dongle reads it
+pub async fn receive_half_messages() -> ! {
+    let mut logs = [LogJoiner::new(), LogJoiner::new()];
+    loop {
+        match HalfMessage::from_event(&EVENT_CHANNEL.receive().await) {
+            Some(HalfMessage::Battery { half, percent }) => {
+                info!(
+                    "Battery of the {} half: {}%",
+                    half_name(half as usize),
+                    percent
+                );
+                status::set_battery(half as usize, percent);
+            }
+            Some(HalfMessage::Log {
+                half,
+                seq,
+                len,
+                data,
+            }) => logs[half as usize].receive(seq, &data[..len as usize], |joined| {
+                log_forward::emit(half as usize, joined)
+            }),
+            None => (),
+        }
+    }
+}
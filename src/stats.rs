@@ -0,0 +1,29 @@
+//! How often each key was pressed since the dongle started, for the host to
+//! read over raw HID, see `via.rs`.
+//!
+//! The keyboard counts the presses of the switches, not the keys it taps
+//! itself. The counts aren't stored, they start over on a reset.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+use crate::keymap::{COL, ROW};
+
+static PRESSES: Mutex<CriticalSectionRawMutex, RefCell<[[u32; COL]; ROW]>> =
+    Mutex::new(RefCell::new([[0; COL]; ROW]));
+
+/// Counts a press of the key at `(row, col)`
+pub fn count((row, col): (u8, u8)) {
+    PRESSES.lock(|presses| {
+        let mut presses = presses.borrow_mut();
+        let count = &mut presses[row as usize][col as usize];
+        *count = count.saturating_add(1);
+    });
+}
+
+/// How often the key at `(row, col)` was pressed
+pub fn presses((row, col): (u8, u8)) -> u32 {
+    PRESSES.lock(|presses| presses.borrow()[row as usize][col as usize])
+}
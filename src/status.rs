@@ -0,0 +1,110 @@
+//! What the dongle knows about the halves, the layers and the host link, for
+//! the console and the host over raw HID.
+//!
+//! [`StatusController`] follows rmk's events for the links to the halves and
+//! the active layer, [`receive_half_messages`] picks up the battery levels
+//! the halves send. rmk's event for the BLE profile only exists with BLE, the
+//! dongle hands it to [`set_host_profile`].
+//!
+//! [`receive_half_messages`]: crate::split::receive_half_messages
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use rmk::channel::{CONTROLLER_CHANNEL, ControllerSub};
+use rmk::controller::Controller;
+use rmk::event::ControllerEvent;
+
+use crate::split::HALVES;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HalfStatus {
+    pub connected: bool,
+    /// `None` until the half sent it since it connected
+    pub battery_percent: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    pub halves: [HalfStatus; HALVES],
+    /// Highest active layer
+    pub layer: u8,
+    /// BLE profile of the host link
+    pub host_profile: u8,
+}
+
+static STATUS: Mutex<CriticalSectionRawMutex, Cell<Status>> = Mutex::new(Cell::new(Status {
+    halves: [HalfStatus {
+        connected: false,
+        battery_percent: None,
+    }; HALVES],
+    layer: 0,
+    host_profile: 0,
+}));
+
+pub fn current() -> Status {
+    STATUS.lock(|status| status.get())
+}
+
+fn update(change: impl FnOnce(&mut Status)) {
+    STATUS.lock(|status| {
+        let mut new = status.get();
+        change(&mut new);
+        status.set(new);
+    });
+}
+
+/// Follows the links to the halves and the active layer
+pub struct StatusController {
+    sub: ControllerSub,
+}
+
+impl Default for StatusController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusController {
+    pub fn new() -> Self {
+        Self {
+            sub: CONTROLLER_CHANNEL
+                .subscriber()
+                .expect("No subscriber left for the status"),
+        }
+    }
+}
+
+impl Controller for StatusController {
+    type Event = ControllerEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        match event {
+            ControllerEvent::SplitPeripheral(half, connected) if half < HALVES => {
+                update(|status| {
+                    status.halves[half] = HalfStatus {
+                        connected,
+                        battery_percent: None,
+                    }
+                });
+            }
+            ControllerEvent::Layer(layer) => update(|status| status.layer = layer),
+            _ => (),
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        self.sub.next_message_pure().await
+    }
+}
+
+/// Battery level the half `half` sent, until it disconnects
+pub(crate) fn set_battery(half: usize, percent: u8) {
+    update(|status| status.halves[half].battery_percent = Some(percent));
+}
+
+/// BLE profile rmk switched the host link to
+pub fn set_host_profile(profile: u8) {
+    update(|status| status.host_profile = profile);
+}
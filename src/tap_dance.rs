@@ -0,0 +1,48 @@
+//! Tap dances: keys that do something else for one, two or three taps, and
+//! when held after them.
+//!
+//! rmk runs them as morse keys, the keymap has `td!(i)` for the tap dance
+//! `i` of [`TAP_DANCES`]. rmk doesn't tell the controllers what a morse key
+//! did though, so a tap dance that makes a layer the default layer taps that
+//! layer on instead, and [`DefaultLayerController`] switches to it with the
+//! `df!` key of the keymap, as for a request of the host.
+//!
+//! validate.rs checks the tap dances with the keymap.
+//!
+//! [`DefaultLayerController`]: crate::default_layer::DefaultLayerController
+//! [`TAP_DANCES`]: crate::keymap::TAP_DANCES
+
+use rmk::morse::Morse;
+use rmk::types::action::{Action, MorseProfile};
+
+/// Most taps a tap dance tells apart
+pub const MAX_TAPS: usize = 3;
+
+/// A tap dance of the keymap, `Action::No` where it does nothing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapDance {
+    /// What one, two and three taps do
+    pub taps: [Action; MAX_TAPS],
+    /// What holding the key does, right away, after one tap and after two
+    pub holds: [Action; MAX_TAPS],
+}
+
+impl TapDance {
+    pub const fn new(taps: [Action; MAX_TAPS], holds: [Action; MAX_TAPS]) -> Self {
+        Self { taps, holds }
+    }
+
+    /// rmk's morse key of the tap dance
+    pub fn morse(&self, profile: MorseProfile) -> Morse {
+        let actions = |actions: &[Action; MAX_TAPS]| {
+            actions
+                .iter()
+                .map(|&action| match action {
+                    Action::DefaultLayer(layer) => Action::LayerOn(layer),
+                    action => action,
+                })
+                .collect()
+        };
+        Morse::new_with_actions(actions(&self.taps), actions(&self.holds), profile)
+    }
+}
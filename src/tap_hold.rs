@@ -0,0 +1,163 @@
+//! Per-key rules that resolve a tap-hold key by the next key pressed.
+//!
+//! rmk resolves a tap-hold key by its mode alone, the same for every key of
+//! that mode, and its flow tap is a single switch for all of them. So the
+//! keyboard decides first for the keys of [`TAP_HOLD_KEYS`]: when another key
+//! is pressed while rmk still waits on one of them, the first of its rules
+//! that matches the hand and row of the other key, from [`HANDS`], decides.
+//! For a hold the keyboard times the key out in rmk right away, for a tap it
+//! releases it to rmk, and keeps its real release from rmk later. Without a
+//! matching rule rmk decides as always.
+//!
+//! A key with flow tap is tapped when it is pressed within [`FLOW_TAP_MS`] of
+//! the key before, while typing, so it can't turn into a hold by accident.
+//!
+//! [`TAP_HOLD_KEYS`]: crate::keymap::TAP_HOLD_KEYS
+//! [`HANDS`]: crate::keymap::HANDS
+//! [`FLOW_TAP_MS`]: crate::keymap::FLOW_TAP_MS
+
+use rmk::config::Hand;
+use rmk::types::action::KeyAction;
+
+use crate::keymap::{COL, HANDS, NUM_LAYER, ROW, TAP_HOLD_KEYS, UNWIRED_KEYS, get_default_keymap};
+
+const KEYMAP: [[[KeyAction; COL]; ROW]; NUM_LAYER] = get_default_keymap();
+
+/// Where the next key is from the tap-hold key's hand
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hands {
+    Same,
+    Opposite,
+    Any,
+}
+
+/// Where the next key is from the tap-hold key's row
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rows {
+    Same,
+    Other,
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolve {
+    Tap,
+    Hold,
+}
+
+/// Resolves the tap-hold key if the next key is on `hands` and `rows`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapHoldRule {
+    pub hands: Hands,
+    pub rows: Rows,
+    pub resolve: Resolve,
+}
+
+impl TapHoldRule {
+    pub const fn new(hands: Hands, rows: Rows, resolve: Resolve) -> Self {
+        Self {
+            hands,
+            rows,
+            resolve,
+        }
+    }
+
+    fn matches(&self, (row, col): (u8, u8), (next_row, next_col): (u8, u8)) -> bool {
+        let hand = HANDS[row as usize][col as usize];
+        let next_hand = HANDS[next_row as usize][next_col as usize];
+        let hands = match self.hands {
+            Hands::Same => hand == next_hand,
+            Hands::Opposite => hand != next_hand,
+            Hands::Any => true,
+        };
+        let rows = match self.rows {
+            Rows::Same => row == next_row,
+            Rows::Other => row != next_row,
+            Rows::Any => true,
+        };
+        hands && rows
+    }
+}
+
+/// The rules of the tap-hold key at `pos`, on whatever layer it is a
+/// tap-hold key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapHoldKey {
+    pub pos: (u8, u8),
+    /// Tried in order, the first that matches decides
+    pub rules: &'static [TapHoldRule],
+    pub flow_tap: bool,
+}
+
+impl TapHoldKey {
+    pub const fn new(pos: (u8, u8), rules: &'static [TapHoldRule], flow_tap: bool) -> Self {
+        Self {
+            pos,
+            rules,
+            flow_tap,
+        }
+    }
+
+    /// What the key at `next` makes of this key, `None` leaves it to rmk
+    pub fn resolve(&self, next: (u8, u8)) -> Option<Resolve> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(self.pos, next))
+            .map(|rule| rule.resolve)
+    }
+}
+
+/// The rules of the key at `pos`
+pub fn find(pos: (u8, u8)) -> Option<&'static TapHoldKey> {
+    TAP_HOLD_KEYS.iter().find(|key| key.pos == pos)
+}
+
+// Every key with rules is a tap-hold key of the base layer and has a switch,
+// no key has two sets of rules, and every key with a switch has a hand
+const _: () = {
+    let mut i = 0;
+    while i < TAP_HOLD_KEYS.len() {
+        let (row, col) = TAP_HOLD_KEYS[i].pos;
+        assert!(
+            (row as usize) < ROW && (col as usize) < COL,
+            "A tap-hold key is out of the matrix"
+        );
+        assert!(
+            matches!(
+                KEYMAP[0][row as usize][col as usize],
+                KeyAction::TapHold(..)
+            ),
+            "A key of TAP_HOLD_KEYS isn't a tap-hold key on the base layer"
+        );
+        let mut j = 0;
+        while j < i {
+            assert!(
+                TAP_HOLD_KEYS[j].pos.0 != row || TAP_HOLD_KEYS[j].pos.1 != col,
+                "A tap-hold key has two sets of rules"
+            );
+            j += 1;
+        }
+        i += 1;
+    }
+
+    let mut row = 0;
+    while row < ROW {
+        let mut col = 0;
+        while col < COL {
+            let mut unwired = false;
+            let mut i = 0;
+            while i < UNWIRED_KEYS.len() {
+                if UNWIRED_KEYS[i].0 as usize == row && UNWIRED_KEYS[i].1 as usize == col {
+                    unwired = true;
+                }
+                i += 1;
+            }
+            assert!(
+                unwired || !matches!(HANDS[row][col], Hand::Unknown),
+                "A key with a switch has no hand"
+            );
+            col += 1;
+        }
+        row += 1;
+    }
+};
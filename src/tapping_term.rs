@@ -0,0 +1,344 @@
+//! Tapping terms changed from the adjust layer, without a reflash.
+//!
+//! There is a global term, and one for the home row mods and one for the
+//! thumb layer keys that follow the global term until they are changed. The
+//! keys of the adjust layer select one of them, raise, lower, type out and
+//! reset it. [`TappingTermController`] keeps them in the settings and reads
+//! them back on boot.
+//!
+//! rmk stores the behavior config it gets on the first boot after a flash
+//! and uses its stored copy from then on, so a term changed since would be
+//! lost on the next boot. The global term still goes into the
+//! `BehaviorConfig`, for rmk's tap dance timeouts, but the keyboard gives
+//! every tap-hold key its term itself once rmk buffered its press.
+
+use core::cell::Cell;
+
+use defmt::info;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embedded_storage_async::nor_flash::NorFlash;
+use rmk::channel::{CONTROLLER_CHANNEL, ControllerSub, KEYBOARD_REPORT_CHANNEL};
+use rmk::config::BehaviorConfig;
+use rmk::controller::Controller;
+use rmk::descriptor::KeyboardReport;
+use rmk::event::ControllerEvent;
+use rmk::hid::Report;
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+
+use crate::keyboard::decode;
+use crate::keymap::{
+    COL, NUM_LAYER, ROW, TAPPING_TERM_MAX_MS, TAPPING_TERM_MIN_MS, TAPPING_TERM_MS,
+    TAPPING_TERM_STEP_MS, get_default_keymap,
+};
+use crate::settings::{Setting, Settings};
+
+const KEYMAP: [[[KeyAction; COL]; ROW]; NUM_LAYER] = get_default_keymap();
+
+/// Selects the next term to change
+pub const TERM_SELECT: KeyAction = KeyAction::Single(Action::Key(KeyCode::User23));
+/// Lowers the selected term by [`TAPPING_TERM_STEP_MS`]
+pub const TERM_DOWN: KeyAction = KeyAction::Single(Action::Key(KeyCode::User24));
+/// Raises the selected term by [`TAPPING_TERM_STEP_MS`]
+pub const TERM_UP: KeyAction = KeyAction::Single(Action::Key(KeyCode::User25));
+/// Types out the selected term, as "global 175ms"
+pub const TERM_PRINT: KeyAction = KeyAction::Single(Action::Key(KeyCode::User26));
+/// Sets the global term back to [`TAPPING_TERM_MS`], or makes the selected
+/// one follow the global term again
+pub const TERM_RESET: KeyAction = KeyAction::Single(Action::Key(KeyCode::User27));
+
+/// The terms are stored in steps of 5 ms, to fit a byte of the settings
+const STORED_UNIT_MS: u16 = 5;
+
+const _: () = {
+    assert!(TAPPING_TERM_MIN_MS > 0 && TAPPING_TERM_MIN_MS <= TAPPING_TERM_MS);
+    assert!(TAPPING_TERM_MS <= TAPPING_TERM_MAX_MS);
+    assert!(TAPPING_TERM_MAX_MS / STORED_UNIT_MS <= u8::MAX as u16);
+    assert!(
+        TAPPING_TERM_MS.is_multiple_of(STORED_UNIT_MS)
+            && TAPPING_TERM_STEP_MS.is_multiple_of(STORED_UNIT_MS)
+    );
+};
+
+/// A term of [`TappingTerms`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermTarget {
+    Global,
+    HomeRowMods,
+    Thumbs,
+}
+
+impl TermTarget {
+    /// The term of the key at `pos`, by what it is on the base layer
+    pub const fn of((row, col): (u8, u8)) -> Self {
+        match KEYMAP[0][row as usize][col as usize] {
+            KeyAction::TapHold(_, Action::Modifier(_), _) => Self::HomeRowMods,
+            KeyAction::TapHold(_, Action::LayerOn(_), _) => Self::Thumbs,
+            _ => Self::Global,
+        }
+    }
+
+    /// The target [`TERM_SELECT`] selects after this one
+    pub fn next(self) -> Self {
+        match self {
+            Self::Global => Self::HomeRowMods,
+            Self::HomeRowMods => Self::Thumbs,
+            Self::Thumbs => Self::Global,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::HomeRowMods => "home row mods",
+            Self::Thumbs => "thumbs",
+        }
+    }
+
+    fn setting(self) -> Setting {
+        match self {
+            Self::Global => Setting::TappingTerm,
+            Self::HomeRowMods => Setting::HomeRowModTerm,
+            Self::Thumbs => Setting::ThumbTerm,
+        }
+    }
+}
+
+/// The tapping terms in ms, `None` follows the global term
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TappingTerms {
+    pub global: u16,
+    pub home_row_mods: Option<u16>,
+    pub thumbs: Option<u16>,
+}
+
+impl Default for TappingTerms {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TappingTerms {
+    pub const DEFAULT: Self = Self {
+        global: TAPPING_TERM_MS,
+        home_row_mods: None,
+        thumbs: None,
+    };
+
+    /// The term of `target`, the global one if it follows it
+    pub fn get(&self, target: TermTarget) -> u16 {
+        self.own(target).unwrap_or(self.global)
+    }
+
+    /// The term of the key at `pos`
+    pub fn of(&self, pos: (u8, u8)) -> u16 {
+        self.get(TermTarget::of(pos))
+    }
+
+    fn own(&self, target: TermTarget) -> Option<u16> {
+        match target {
+            TermTarget::Global => Some(self.global),
+            TermTarget::HomeRowMods => self.home_row_mods,
+            TermTarget::Thumbs => self.thumbs,
+        }
+    }
+
+    fn set(&mut self, target: TermTarget, term: Option<u16>) {
+        match target {
+            TermTarget::Global => self.global = term.unwrap_or(TAPPING_TERM_MS),
+            TermTarget::HomeRowMods => self.home_row_mods = term,
+            TermTarget::Thumbs => self.thumbs = term,
+        }
+    }
+
+    /// Raises the term of `target` by `steps` of [`TAPPING_TERM_STEP_MS`], or
+    /// lowers it for negative steps, within the limits of the keymap
+    pub fn adjust(&mut self, target: TermTarget, steps: i16) {
+        let term = self.get(target) as i32 + steps as i32 * TAPPING_TERM_STEP_MS as i32;
+        let term = term.clamp(TAPPING_TERM_MIN_MS as i32, TAPPING_TERM_MAX_MS as i32);
+        self.set(target, Some(term as u16));
+    }
+
+    /// Sets the global term back to [`TAPPING_TERM_MS`], or lets `target`
+    /// follow the global term
+    pub fn reset(&mut self, target: TermTarget) {
+        self.set(target, None);
+    }
+
+    /// Puts the global term into the default profile rmk uses for the keys
+    /// without their own timeouts
+    pub fn apply(&self, behavior: &mut BehaviorConfig) {
+        behavior.morse.default_profile = behavior
+            .morse
+            .default_profile
+            .with_hold_timeout_ms(Some(self.global))
+            .with_gap_timeout_ms(Some(self.global));
+    }
+
+    /// Reads the stored terms, the defaults for those never stored
+    pub async fn load<F: NorFlash>(settings: &mut Settings<F>) -> Self {
+        let mut terms = Self::DEFAULT;
+        for target in [
+            TermTarget::Global,
+            TermTarget::HomeRowMods,
+            TermTarget::Thumbs,
+        ] {
+            let term = match settings.get(target.setting()).await {
+                Some(0) | None => None,
+                Some(value) => Some(
+                    (value as u16 * STORED_UNIT_MS).clamp(TAPPING_TERM_MIN_MS, TAPPING_TERM_MAX_MS),
+                ),
+            };
+            terms.set(target, term);
+        }
+        terms
+    }
+
+    async fn store<F: NorFlash>(&self, settings: &mut Settings<F>, target: TermTarget) {
+        let value = self
+            .own(target)
+            .map_or(0, |term| (term / STORED_UNIT_MS) as u8);
+        settings.set(target.setting(), value).await;
+    }
+}
+
+static TERMS: Mutex<CriticalSectionRawMutex, Cell<TappingTerms>> =
+    Mutex::new(Cell::new(TappingTerms::DEFAULT));
+
+/// The tapping terms in use
+pub fn current() -> TappingTerms {
+    TERMS.lock(|terms| terms.get())
+}
+
+fn update(terms: TappingTerms) {
+    TERMS.lock(|current| current.set(terms));
+}
+
+/// Makes `terms` the ones in use and stores the term of `target`, if it
+/// changed
+async fn commit<F: NorFlash>(settings: &mut Settings<F>, target: TermTarget, terms: TappingTerms) {
+    if terms != current() {
+        update(terms);
+        info!(
+            "Tapping term of {} is {}ms",
+            target.name(),
+            terms.get(target)
+        );
+        terms.store(settings, target).await;
+    }
+}
+
+/// Sets the term of `target` to `term` ms, rounded down to the 5 ms it is
+/// stored in, and stores it. False if it is out of the keymap's limits.
+pub async fn set<F: NorFlash>(settings: &mut Settings<F>, target: TermTarget, term: u16) -> bool {
+    if !(TAPPING_TERM_MIN_MS..=TAPPING_TERM_MAX_MS).contains(&term) {
+        return false;
+    }
+    let mut terms = current();
+    terms.set(target, Some(term - term % STORED_UNIT_MS));
+    commit(settings, target, terms).await;
+    true
+}
+
+/// HID keycode that types `c`, for the letters, digits and space
+fn keycode(c: u8) -> Option<u8> {
+    match c {
+        b'a'..=b'z' => Some(KeyCode::A as u8 + (c - b'a')),
+        b'1'..=b'9' => Some(KeyCode::Kc1 as u8 + (c - b'1')),
+        b'0' => Some(KeyCode::Kc0 as u8),
+        b' ' => Some(KeyCode::Space as u8),
+        _ => None,
+    }
+}
+
+/// Follows the tapping term keys and keeps the terms in the settings
+pub struct TappingTermController<F> {
+    settings: Settings<F>,
+    sub: ControllerSub,
+    target: TermTarget,
+}
+
+impl<F: NorFlash> TappingTermController<F> {
+    /// Restores the stored terms, before the keyboard runs
+    pub async fn new(mut settings: Settings<F>) -> Self {
+        let terms = TappingTerms::load(&mut settings).await;
+        info!(
+            "Tapping terms: global {}ms, home row mods {}ms, thumbs {}ms",
+            terms.global,
+            terms.get(TermTarget::HomeRowMods),
+            terms.get(TermTarget::Thumbs)
+        );
+        update(terms);
+        Self {
+            settings,
+            sub: CONTROLLER_CHANNEL
+                .subscriber()
+                .expect("No subscriber left for the tapping terms"),
+            target: TermTarget::Global,
+        }
+    }
+
+    async fn change(&mut self, change: impl FnOnce(&mut TappingTerms, TermTarget)) {
+        let mut terms = current();
+        change(&mut terms, self.target);
+        commit(&mut self.settings, self.target, terms).await;
+    }
+
+    /// Types the selected term, as "global 175ms"
+    async fn print(&self) {
+        let mut digits = [0; 5];
+        let mut term = current().get(self.target);
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (term % 10) as u8;
+            term /= 10;
+            if term == 0 {
+                break;
+            }
+        }
+        let text = [self.target.name().as_bytes(), b" ", &digits[start..], b"ms"];
+        for keycode in text.into_iter().flatten().filter_map(|&c| keycode(c)) {
+            for keycodes in [[keycode, 0, 0, 0, 0, 0], [0; 6]] {
+                KEYBOARD_REPORT_CHANNEL
+                    .send(Report::KeyboardReport(KeyboardReport {
+                        modifier: 0,
+                        reserved: 0,
+                        leds: 0,
+                        keycodes,
+                    }))
+                    .await;
+            }
+        }
+    }
+}
+
+impl<F: NorFlash> Controller for TappingTermController<F> {
+    type Event = ControllerEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        let ControllerEvent::Key(event, action) = event else {
+            return;
+        };
+        // rmk sends the key on press and on release
+        if !matches!(decode(event), Some((_, true))) {
+            return;
+        }
+        if action == TERM_SELECT {
+            self.target = self.target.next();
+        } else if action == TERM_DOWN {
+            self.change(|terms, target| terms.adjust(target, -1)).await;
+        } else if action == TERM_UP {
+            self.change(|terms, target| terms.adjust(target, 1)).await;
+        } else if action == TERM_RESET {
+            self.change(TappingTerms::reset).await;
+        } else if action == TERM_PRINT {
+            self.print().await;
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        self.sub.next_message_pure().await
+    }
+}
@@ -0,0 +1,91 @@
+//! Tapping terms that adapt to the typing speed.
+//!
+//! Fast typing rolls over the home row mods and holds one a little too long
+//! now and then. [`TypingSpeed`] follows the time between key presses, and
+//! while they come fast it makes the tapping terms longer, or has a tap-hold
+//! key with flow tap tapped right away, as [`AdaptiveTermConfig`] says. See
+//! [`ADAPTIVE_TERM`].
+//!
+//! [`ADAPTIVE_TERM`]: crate::keymap::ADAPTIVE_TERM
+
+/// When typing counts as fast and what that does to the tap-hold keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptiveTermConfig {
+    /// Typing is fast while the average time between key presses is below
+    /// this
+    pub fast_interval_ms: u16,
+    /// Key presses in a row before the average counts
+    pub min_burst: u8,
+    /// A pause this long ends a burst
+    pub idle_ms: u16,
+    /// Added to the tapping terms while typing fast
+    pub extra_term_ms: u16,
+    /// Whether a tap-hold key with flow tap pressed while typing fast is a
+    /// tap, however long it is held
+    pub tap_in_burst: bool,
+}
+
+/// Follows the time between key presses with a moving average
+#[derive(Clone, Debug)]
+pub struct TypingSpeed {
+    config: AdaptiveTermConfig,
+    last_press_ms: Option<u64>,
+    /// Moving average of the times between the presses of the burst
+    average_ms: u64,
+    /// Presses of the burst after its first one
+    burst: u8,
+}
+
+impl TypingSpeed {
+    pub const fn new(config: AdaptiveTermConfig) -> Self {
+        Self {
+            config,
+            last_press_ms: None,
+            average_ms: 0,
+            burst: 0,
+        }
+    }
+
+    /// A key was pressed at `now_ms`
+    pub fn key_pressed(&mut self, now_ms: u64) {
+        let interval = self.last_press_ms.map(|last| now_ms.saturating_sub(last));
+        self.last_press_ms = Some(now_ms);
+        match interval {
+            Some(interval) if interval < self.config.idle_ms as u64 => {
+                // The last presses count the most
+                self.average_ms = if self.burst == 0 {
+                    interval
+                } else {
+                    (self.average_ms * 3 + interval) / 4
+                };
+                self.burst = self.burst.saturating_add(1);
+            }
+            _ => {
+                self.average_ms = 0;
+                self.burst = 0;
+            }
+        }
+    }
+
+    /// Whether a burst of fast typing goes on at `now_ms`
+    pub fn is_fast(&self, now_ms: u64) -> bool {
+        self.last_press_ms
+            .is_some_and(|last| now_ms.saturating_sub(last) < self.config.idle_ms as u64)
+            && self.burst >= self.config.min_burst
+            && self.average_ms < self.config.fast_interval_ms as u64
+    }
+
+    /// What to add to the tapping term of a key pressed at `now_ms`
+    pub fn extra_term_ms(&self, now_ms: u64) -> u16 {
+        if self.is_fast(now_ms) {
+            self.config.extra_term_ms
+        } else {
+            0
+        }
+    }
+
+    /// Whether a tap-hold key with flow tap pressed at `now_ms` is a tap
+    pub fn taps(&self, now_ms: u64) -> bool {
+        self.config.tap_in_burst && self.is_fast(now_ms)
+    }
+}
@@ -0,0 +1,462 @@
+//! Checks of the keymap that run while it is compiled, see the `const _`
+//! block at the end of keymap.rs. A mistake fails the build with the layer,
+//! row and col of the key.
+//!
+//! The tap dances are checked with the keymap, a `td!` key does what its
+//! taps and holds do.
+
+use rmk::types::action::{Action, KeyAction};
+
+use crate::tap_dance::{MAX_TAPS, TapDance};
+
+/// Where a key is, (layer, row, col)
+pub type KeyPos = (u8, u8, u8);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeymapError {
+    /// The key refers to a layer past the end of the keymap
+    LayerOutOfRange { key: KeyPos, layer: u8 },
+    /// The `td!` key has no tap dance
+    NoTapDance { key: KeyPos, index: u8 },
+    /// The key toggles a layer that has no key to toggle it off again
+    NoToggleBack { key: KeyPos, layer: u8 },
+    /// The key makes a layer the default one, but nothing gets from there
+    /// back to the base layer
+    NoWayToBase { key: KeyPos, layer: u8 },
+    /// The tap dance of the key makes a layer the default one that no `df!`
+    /// key switches to, the dongle needs one to switch
+    NoDefaultLayerKey { key: KeyPos, layer: u8 },
+    /// Holding the tap dance of the key switches the default layer, only
+    /// taps can
+    DefaultLayerOnHold { key: KeyPos },
+    /// The key is transparent on a layer that can be the default one, so
+    /// there is nothing below it
+    TransparentOnBase { key: KeyPos },
+    /// The key has no action at all (`a!(No)`) on a layer that can be the
+    /// default one. A key that does nothing on purpose is `k!(No)`.
+    NoActionOnBase { key: KeyPos },
+    /// Two tap-holds on one layer tap the same key
+    DuplicateTapHold { first: KeyPos, second: KeyPos },
+}
+
+const MESSAGE_LEN: usize = 128;
+
+/// Text of a [`KeymapError`] or of another mistake that fails the build,
+/// built without allocating so it works in const
+pub struct Message {
+    buf: [u8; MESSAGE_LEN],
+    len: usize,
+}
+
+impl Message {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buf: [0; MESSAGE_LEN],
+            len: 0,
+        }
+    }
+
+    pub(crate) const fn push(mut self, s: &str) -> Self {
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() && self.len < MESSAGE_LEN {
+            self.buf[self.len] = bytes[i];
+            self.len += 1;
+            i += 1;
+        }
+        self
+    }
+
+    pub(crate) const fn push_char(self, c: char) -> Self {
+        let mut buf = [0; 4];
+        self.push(c.encode_utf8(&mut buf))
+    }
+
+    const fn push_num(mut self, n: u8) -> Self {
+        let digits = [n / 100, n / 10 % 10, n % 10];
+        let mut i = if n >= 100 {
+            0
+        } else if n >= 10 {
+            1
+        } else {
+            2
+        };
+        while i < digits.len() && self.len < MESSAGE_LEN {
+            self.buf[self.len] = b'0' + digits[i];
+            self.len += 1;
+            i += 1;
+        }
+        self
+    }
+
+    const fn push_pos(self, (layer, row, col): KeyPos) -> Self {
+        self.push("layer ")
+            .push_num(layer)
+            .push(" row ")
+            .push_num(row)
+            .push(" col ")
+            .push_num(col)
+    }
+
+    pub const fn as_str(&self) -> &str {
+        let (bytes, _) = self.buf.split_at(self.len);
+        match core::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(_) => "invalid keymap",
+        }
+    }
+}
+
+impl KeymapError {
+    pub const fn message(&self) -> Message {
+        let message = Message::new().push("keymap: ");
+        match *self {
+            KeymapError::LayerOutOfRange { key, layer } => message
+                .push_pos(key)
+                .push(" refers to layer ")
+                .push_num(layer)
+                .push(", which doesn't exist"),
+            KeymapError::NoTapDance { key, index } => message
+                .push_pos(key)
+                .push(" is tap dance ")
+                .push_num(index)
+                .push(", which TAP_DANCES doesn't have"),
+            KeymapError::NoToggleBack { key, layer } => message
+                .push_pos(key)
+                .push(" toggles layer ")
+                .push_num(layer)
+                .push(", which has no key to toggle it off"),
+            KeymapError::NoWayToBase { key, layer } => message
+                .push_pos(key)
+                .push(" makes layer ")
+                .push_num(layer)
+                .push(" the default, which has no way back to layer 0"),
+            KeymapError::NoDefaultLayerKey { key, layer } => message
+                .push_pos(key)
+                .push(" makes layer ")
+                .push_num(layer)
+                .push(" the default, which no df! key switches to"),
+            KeymapError::DefaultLayerOnHold { key } => message
+                .push_pos(key)
+                .push(" switches the default layer when held, only taps can"),
+            KeymapError::TransparentOnBase { key } => message
+                .push_pos(key)
+                .push(" is transparent on a default layer"),
+            KeymapError::NoActionOnBase { key } => message
+                .push_pos(key)
+                .push(" has no action on a default layer, use k!(No)"),
+            KeymapError::DuplicateTapHold { first, second } => message
+                .push_pos(first)
+                .push(" and ")
+                .push_pos(second)
+                .push(" are tap-holds of the same key"),
+        }
+    }
+}
+
+/// Most actions of a key, those of a tap dance
+const MAX_ACTIONS: usize = 2 * MAX_TAPS;
+
+/// The actions of a key: tap and hold of a tap-hold, the taps and holds of
+/// a tap dance
+const fn actions(key: KeyAction, tap_dances: &[TapDance]) -> [Option<Action>; MAX_ACTIONS] {
+    let mut actions = [None; MAX_ACTIONS];
+    match key {
+        KeyAction::Single(action) | KeyAction::Tap(action) => actions[0] = Some(action),
+        KeyAction::TapHold(tap, hold, _) => {
+            actions[0] = Some(tap);
+            actions[1] = Some(hold);
+        }
+        KeyAction::Morse(i) if (i as usize) < tap_dances.len() => {
+            let dance = &tap_dances[i as usize];
+            let mut n = 0;
+            while n < MAX_TAPS {
+                actions[n] = Some(dance.taps[n]);
+                actions[MAX_TAPS + n] = Some(dance.holds[n]);
+                n += 1;
+            }
+        }
+        _ => (),
+    }
+    actions
+}
+
+/// The layer an action turns on, toggles or switches to
+const fn layer_target(action: Action) -> Option<u8> {
+    match action {
+        Action::LayerOn(layer)
+        | Action::LayerOnWithModifier(layer, _)
+        | Action::LayerOff(layer)
+        | Action::LayerToggle(layer)
+        | Action::LayerToggleOnly(layer)
+        | Action::DefaultLayer(layer)
+        | Action::OneShotLayer(layer) => Some(layer),
+        _ => None,
+    }
+}
+
+const fn toggle_target(action: Action) -> Option<u8> {
+    match action {
+        Action::LayerToggle(layer) | Action::LayerToggleOnly(layer) => Some(layer),
+        _ => None,
+    }
+}
+
+const fn default_target(action: Action) -> Option<u8> {
+    match action {
+        Action::DefaultLayer(layer) => Some(layer),
+        _ => None,
+    }
+}
+
+/// What to look for in [`targets`]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// Layers turned on in any way but as the default layer
+    On,
+    /// Layers made the default one
+    Default,
+    /// Layers toggled
+    Toggle,
+}
+
+/// Bits of the layers the keys on the layers in `layers` switch to as
+/// `target` says
+const fn targets<const ROW: usize, const COL: usize, const NUM_LAYER: usize>(
+    keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
+    tap_dances: &[TapDance],
+    layers: u32,
+    target: Target,
+) -> u32 {
+    let mut found = 0;
+    let mut layer = 0;
+    while layer < NUM_LAYER {
+        if layers & (1 << layer) != 0 {
+            let mut row = 0;
+            while row < ROW {
+                let mut col = 0;
+                while col < COL {
+                    let actions = actions(keymap[layer][row][col], tap_dances);
+                    let mut i = 0;
+                    while i < actions.len() {
+                        if let Some(action) = actions[i] {
+                            let layer = match target {
+                                Target::On if default_target(action).is_some() => None,
+                                Target::On => layer_target(action),
+                                Target::Default => default_target(action),
+                                Target::Toggle => toggle_target(action),
+                            };
+                            if let Some(layer) = layer {
+                                found |= 1 << layer;
+                            }
+                        }
+                        i += 1;
+                    }
+                    col += 1;
+                }
+                row += 1;
+            }
+        }
+        layer += 1;
+    }
+    found
+}
+
+/// Bits of `layers` and every layer their keys turn on, and so on
+const fn turned_on<const ROW: usize, const COL: usize, const NUM_LAYER: usize>(
+    keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
+    tap_dances: &[TapDance],
+    layers: u32,
+) -> u32 {
+    let mut active = layers;
+    loop {
+        let more = active | targets(keymap, tap_dances, active, Target::On);
+        if more == active {
+            return active;
+        }
+        active = more;
+    }
+}
+
+/// Whether the base layer becomes the default again after `layer` did: the
+/// default layer or any layer it turns on has a key that leads back.
+const fn leads_to_base<const ROW: usize, const COL: usize, const NUM_LAYER: usize>(
+    keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
+    tap_dances: &[TapDance],
+    layer: u8,
+) -> bool {
+    let mut defaults = 1 << layer;
+    loop {
+        let active = turned_on(keymap, tap_dances, defaults);
+        let more = defaults | targets(keymap, tap_dances, active, Target::Default);
+        if more == defaults {
+            return defaults & 1 != 0;
+        }
+        defaults = more;
+    }
+}
+
+/// Whether `layer`, or a layer it turns on, has a key that toggles it
+const fn toggles_back<const ROW: usize, const COL: usize, const NUM_LAYER: usize>(
+    keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
+    tap_dances: &[TapDance],
+    layer: u8,
+) -> bool {
+    let active = turned_on(keymap, tap_dances, 1 << layer);
+    targets(keymap, tap_dances, active, Target::Toggle) & (1 << layer) != 0
+}
+
+/// Whether a `df!` key makes `layer` the default one
+const fn has_default_layer_key<const ROW: usize, const COL: usize, const NUM_LAYER: usize>(
+    keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
+    layer: u8,
+) -> bool {
+    let all = (1 << NUM_LAYER) - 1;
+    layer == 0 || targets(keymap, &[], all, Target::Default) & (1 << layer) != 0
+}
+
+/// The key a tap-hold taps, `None` for other keys
+const fn tap_keycode(key: KeyAction) -> Option<u16> {
+    match key {
+        KeyAction::TapHold(Action::Key(keycode), _, _) => Some(keycode as u16),
+        _ => None,
+    }
+}
+
+/// Checks that
+/// - every `td!` key has a tap dance, and every layer a key refers to exists
+/// - a toggled layer can be toggled off
+/// - a layer made the default leads back to layer 0
+/// - a tap dance makes a layer the default only when tapped, and only one a
+///   `df!` key switches to
+/// - layer 0 and the layers made the default have no transparent keys and
+///   no keys without an action
+/// - no two tap-holds on a layer tap the same key
+///
+/// The keymap is at most 32 layers of 256 x 256 keys.
+pub const fn validate_keymap<const ROW: usize, const COL: usize, const NUM_LAYER: usize>(
+    keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
+    tap_dances: &[TapDance],
+) -> Result<(), KeymapError> {
+    assert!(NUM_LAYER <= 32, "the keymap has more than 32 layers");
+    assert!(
+        ROW <= 256 && COL <= 256,
+        "the keymap has more than 256 rows or cols"
+    );
+
+    // The other checks follow the layer references, so they have to be in
+    // range first
+    let mut layer = 0;
+    while layer < NUM_LAYER {
+        let mut row = 0;
+        while row < ROW {
+            let mut col = 0;
+            while col < COL {
+                let key = (layer as u8, row as u8, col as u8);
+                if let KeyAction::Morse(index) = keymap[layer][row][col]
+                    && index as usize >= tap_dances.len()
+                {
+                    return Err(KeymapError::NoTapDance { key, index });
+                }
+                let actions = actions(keymap[layer][row][col], tap_dances);
+                let mut i = 0;
+                while i < actions.len() {
+                    if let Some(action) = actions[i]
+                        && let Some(target) = layer_target(action)
+                        && target as usize >= NUM_LAYER
+                    {
+                        return Err(KeymapError::LayerOutOfRange { key, layer: target });
+                    }
+                    i += 1;
+                }
+                col += 1;
+            }
+            row += 1;
+        }
+        layer += 1;
+    }
+
+    let mut base_layers = 1;
+    let mut layer = 0;
+    while layer < NUM_LAYER {
+        let mut row = 0;
+        while row < ROW {
+            let mut col = 0;
+            while col < COL {
+                let key = (layer as u8, row as u8, col as u8);
+                let tap_dance = matches!(keymap[layer][row][col], KeyAction::Morse(_));
+                let actions = actions(keymap[layer][row][col], tap_dances);
+                let mut i = 0;
+                while i < actions.len() {
+                    if let Some(action) = actions[i]
+                        && let Some(target) = layer_target(action)
+                    {
+                        if toggle_target(action).is_some()
+                            && !toggles_back(keymap, tap_dances, target)
+                        {
+                            return Err(KeymapError::NoToggleBack { key, layer: target });
+                        }
+                        if default_target(action).is_some() {
+                            if !leads_to_base(keymap, tap_dances, target) {
+                                return Err(KeymapError::NoWayToBase { key, layer: target });
+                            }
+                            if tap_dance && i >= MAX_TAPS {
+                                return Err(KeymapError::DefaultLayerOnHold { key });
+                            }
+                            if tap_dance && !has_default_layer_key(keymap, target) {
+                                return Err(KeymapError::NoDefaultLayerKey { key, layer: target });
+                            }
+                            base_layers |= 1 << target;
+                        }
+                    }
+                    i += 1;
+                }
+                col += 1;
+            }
+            row += 1;
+        }
+        layer += 1;
+    }
+
+    let mut layer = 0;
+    while layer < NUM_LAYER {
+        let mut row = 0;
+        while row < ROW {
+            let mut col = 0;
+            while col < COL {
+                let key = (layer as u8, row as u8, col as u8);
+                if base_layers & (1 << layer) != 0 {
+                    match keymap[layer][row][col] {
+                        KeyAction::Transparent => {
+                            return Err(KeymapError::TransparentOnBase { key });
+                        }
+                        KeyAction::No => return Err(KeymapError::NoActionOnBase { key }),
+                        _ => (),
+                    }
+                }
+                if let Some(tap) = tap_keycode(keymap[layer][row][col]) {
+                    // Only the keys before this one, so the first of the two
+                    // is reported first
+                    let mut other = 0;
+                    while other < row * COL + col {
+                        let (other_row, other_col) = (other / COL, other % COL);
+                        if let Some(other_tap) = tap_keycode(keymap[layer][other_row][other_col])
+                            && other_tap == tap
+                        {
+                            return Err(KeymapError::DuplicateTapHold {
+                                first: (layer as u8, other_row as u8, other_col as u8),
+                                second: key,
+                            });
+                        }
+                        other += 1;
+                    }
+                }
+                col += 1;
+            }
+            row += 1;
+        }
+        layer += 1;
+    }
+
+    Ok(())
+}
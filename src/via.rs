@@ -0,0 +1,321 @@
+//! The VIA reports between the host and rmk, and what the dongle does with
+//! them on the way.
+//!
+//! [`ViaDriver`] wraps the USB driver given to `run_rmk` and looks at the VIA
+//! reports, they are the only 32 byte reports on an interrupt endpoint. Only
+//! USB goes through it, VIA over BLE doesn't.
+//!
+//! rmk's `vial_lock` only hides the matrix tester while Vial is locked, keymap
+//! edits are still applied. While locked, commands that change the keyboard
+//! are turned into `Unhandled` here, which rmk answers like any unknown
+//! command without touching the keymap. Whether Vial is locked follows rmk's
+//! answers to the unlock poll and the lock command.
+//!
+//! rmk echoes VIA's custom value requests without doing anything. The dongle
+//! answers those on [`CUSTOM_CHANNEL`] itself: a set is handed to the module
+//! of the value and the echo of a get gets the value filled in. They aren't
+//! locked, they only change what a key of the keymap can change as well.
+//! Besides the values VIA knows about, they carry what `rmkctl` asks for, the
+//! host tool in `rmkctl/`.
+//!
+//! The USB serial port of the `usb_logging` build goes through it too, its
+//! bulk endpoint is the only one. What the host types there goes to the
+//! console, see `console.rs`.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embassy_usb::driver::{
+    Driver, Endpoint, EndpointAddress, EndpointAllocError, EndpointError, EndpointIn, EndpointInfo,
+    EndpointOut, EndpointType,
+};
+use rmk::types::protocol::vial::{ViaCommand, VialCommand, VialDynamic};
+
+use crate::console;
+use crate::default_layer;
+use crate::keymap::{COL, ROW};
+use crate::remote::{self, Remote};
+use crate::stats;
+use crate::status;
+
+/// Size of a VIA report
+const REPORT_LEN: usize = 32;
+
+static UNLOCKED: AtomicBool = AtomicBool::new(false);
+
+/// An unlock poll was handed to rmk, its answer is the next report
+static POLLING: AtomicBool = AtomicBool::new(false);
+
+/// VIA's channel for the values of the keyboard itself, the others are for
+/// lighting and audio
+pub const CUSTOM_CHANNEL: u8 = 0;
+
+/// Values of the dongle on [`CUSTOM_CHANNEL`], a custom value request is
+/// `[command, channel, value, data..]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CustomValue {
+    /// The default layer, set like a `df!` key does
+    DefaultLayer = 0x01,
+    /// The highest active layer, a set toggles one of the remote layers, see
+    /// `remote.rs`
+    Layer = 0x02,
+    /// The BLE profile of the host link, set like rmk's profile keys do
+    HostProfile = 0x03,
+    /// Whether each half is connected and its battery level, `0xff` while
+    /// unknown. Can't be set.
+    Halves = 0x04,
+    /// The presses of [`KEY_PRESSES_PER_GET`] keys of a row since the dongle
+    /// started, as big-endian `u32`s after the row and column of the first
+    /// key. Can't be set.
+    KeyPresses = 0x05,
+}
+
+/// Keys a get of [`CustomValue::KeyPresses`] answers for, those past the end
+/// of the row count zero
+pub const KEY_PRESSES_PER_GET: usize = 6;
+
+impl CustomValue {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x01 => Some(Self::DefaultLayer),
+            0x02 => Some(Self::Layer),
+            0x03 => Some(Self::HostProfile),
+            0x04 => Some(Self::Halves),
+            0x05 => Some(Self::KeyPresses),
+            _ => None,
+        }
+    }
+
+    /// Whether a get with `data` can be answered
+    fn can_get(self, data: &[u8]) -> bool {
+        match self {
+            Self::KeyPresses => (data[0] as usize) < ROW && (data[1] as usize) < COL,
+            _ => true,
+        }
+    }
+
+    /// Fills the value into `data`, after the arguments of the get
+    fn get(self, data: &mut [u8]) {
+        match self {
+            Self::DefaultLayer => data[0] = default_layer::current(),
+            Self::Layer => data[0] = status::current().layer,
+            Self::HostProfile => data[0] = status::current().host_profile,
+            Self::Halves => {
+                for (half, state) in status::current().halves.iter().enumerate() {
+                    data[2 * half] = state.connected.into();
+                    data[2 * half + 1] = state.battery_percent.unwrap_or(0xff);
+                }
+            }
+            Self::KeyPresses => {
+                let (row, first) = (data[0], data[1]);
+                for (i, count) in data[2..]
+                    .chunks_exact_mut(4)
+                    .take(KEY_PRESSES_PER_GET)
+                    .enumerate()
+                {
+                    let col = first as usize + i;
+                    let presses = if col < COL {
+                        stats::presses((row, col as u8))
+                    } else {
+                        0
+                    };
+                    count.copy_from_slice(&presses.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    /// Hands `value` on, false if it isn't valid or the value can't be set
+    fn set(self, value: u8) -> bool {
+        match self {
+            Self::DefaultLayer => default_layer::request(value),
+            Self::Layer => remote::request(Remote::ToggleLayer(value)),
+            Self::HostProfile => remote::request(Remote::SwitchProfile(value)),
+            Self::Halves | Self::KeyPresses => false,
+        }
+    }
+}
+
+/// No custom get is waiting for its answer
+const NOT_GETTING: u8 = 0;
+
+/// Custom value of a get handed to rmk, its answer is the next report
+static GETTING: AtomicU8 = AtomicU8::new(NOT_GETTING);
+
+/// Whether Vial was unlocked by holding the unlock keys
+pub fn is_unlocked() -> bool {
+    UNLOCKED.load(Ordering::Acquire)
+}
+
+/// Whether the VIA `report` changes the keymap, macros or settings, or
+/// reboots the keyboard
+fn is_edit(report: &[u8]) -> bool {
+    match ViaCommand::from(report[0]) {
+        ViaCommand::DynamicKeymapSetKeyCode
+        | ViaCommand::DynamicKeymapReset
+        | ViaCommand::EepromReset
+        | ViaCommand::BootloaderJump
+        | ViaCommand::DynamicKeymapMacroSetBuffer
+        | ViaCommand::DynamicKeymapMacroReset
+        | ViaCommand::DynamicKeymapSetBuffer
+        | ViaCommand::DynamicKeymapSetEncoder => true,
+        ViaCommand::Vial => match VialCommand::from(report[1]) {
+            VialCommand::SetEncoder
+            | VialCommand::SetBehaviorSetting
+            | VialCommand::QmkSettingsReset => true,
+            VialCommand::DynamicEntryOp => matches!(
+                VialDynamic::from(report[2]),
+                VialDynamic::DynamicVialMorseSet
+                    | VialDynamic::DynamicVialComboSet
+                    | VialDynamic::DynamicVialKeyOverrideSet
+            ),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Handles a custom value request, false if the value is unknown or invalid
+fn custom_value(report: &[u8]) -> bool {
+    if report[1] != CUSTOM_CHANNEL {
+        return false;
+    }
+    let Some(value) = CustomValue::from_id(report[2]) else {
+        return false;
+    };
+    match ViaCommand::from(report[0]) {
+        ViaCommand::CustomSetValue => value.set(report[3]),
+        _ if value.can_get(&report[3..]) => {
+            GETTING.store(value as u8, Ordering::Release);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Follows the lock commands in a request from the host, refuses edits
+/// while locked and handles custom values
+fn filter_request(report: &mut [u8]) {
+    match ViaCommand::from(report[0]) {
+        ViaCommand::Vial => match VialCommand::from(report[1]) {
+            VialCommand::UnlockPoll => POLLING.store(true, Ordering::Release),
+            VialCommand::Lock => UNLOCKED.store(false, Ordering::Release),
+            _ => (),
+        },
+        ViaCommand::CustomSetValue | ViaCommand::CustomGetValue if !custom_value(report) => {
+            report[0] = ViaCommand::Unhandled as u8;
+        }
+        _ => (),
+    }
+    if !is_unlocked() && is_edit(report) {
+        report[0] = ViaCommand::Unhandled as u8;
+    }
+}
+
+/// Picks up rmk's answer to an unlock poll, its first byte is 1 once
+/// unlocked, and fills in the value of a custom get. rmk's echo still has
+/// the arguments of the get.
+fn filter_response(report: &mut [u8]) {
+    if POLLING.swap(false, Ordering::AcqRel) {
+        UNLOCKED.store(report[0] == 1, Ordering::Release);
+    }
+    if let Some(value) = CustomValue::from_id(GETTING.swap(NOT_GETTING, Ordering::AcqRel)) {
+        value.get(&mut report[3..]);
+    }
+}
+
+/// USB driver that passes the VIA reports through [`filter_request`] and
+/// [`filter_response`]
+pub struct ViaDriver<D> {
+    inner: D,
+}
+
+impl<D> ViaDriver<D> {
+    pub fn new(driver: D) -> Self {
+        Self { inner: driver }
+    }
+}
+
+impl<'d, D: Driver<'d>> Driver<'d> for ViaDriver<D> {
+    type EndpointOut = ViaEndpoint<D::EndpointOut>;
+    type EndpointIn = ViaEndpoint<D::EndpointIn>;
+    type ControlPipe = D::ControlPipe;
+    type Bus = D::Bus;
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_type: EndpointType,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        let inner =
+            self.inner
+                .alloc_endpoint_out(ep_type, ep_addr, max_packet_size, interval_ms)?;
+        Ok(ViaEndpoint::new(inner, ep_type))
+    }
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_type: EndpointType,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        let inner = self
+            .inner
+            .alloc_endpoint_in(ep_type, ep_addr, max_packet_size, interval_ms)?;
+        Ok(ViaEndpoint::new(inner, ep_type))
+    }
+
+    fn start(self, control_max_packet_size: u16) -> (Self::Bus, Self::ControlPipe) {
+        self.inner.start(control_max_packet_size)
+    }
+}
+
+/// Endpoint of [`ViaDriver`], only HID endpoints carry VIA reports and only
+/// the serial port's are bulk endpoints
+pub struct ViaEndpoint<E> {
+    inner: E,
+    ep_type: EndpointType,
+}
+
+impl<E> ViaEndpoint<E> {
+    fn new(inner: E, ep_type: EndpointType) -> Self {
+        Self { inner, ep_type }
+    }
+}
+
+impl<E: Endpoint> Endpoint for ViaEndpoint<E> {
+    fn info(&self) -> &EndpointInfo {
+        self.inner.info()
+    }
+
+    async fn wait_enabled(&mut self) {
+        self.inner.wait_enabled().await
+    }
+}
+
+impl<E: EndpointOut> EndpointOut for ViaEndpoint<E> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, EndpointError> {
+        let n = self.inner.read(buf).await?;
+        match self.ep_type {
+            EndpointType::Interrupt if n == REPORT_LEN => filter_request(&mut buf[..n]),
+            EndpointType::Bulk => console::receive(&buf[..n]),
+            _ => (),
+        }
+        Ok(n)
+    }
+}
+
+impl<E: EndpointIn> EndpointIn for ViaEndpoint<E> {
+    async fn write(&mut self, buf: &[u8]) -> Result<(), EndpointError> {
+        if self.ep_type == EndpointType::Interrupt && buf.len() == REPORT_LEN {
+            let mut report = [0; REPORT_LEN];
+            report.copy_from_slice(buf);
+            filter_response(&mut report);
+            return self.inner.write(&report).await;
+        }
+        self.inner.write(buf).await
+    }
+}
@@ -0,0 +1,144 @@
+//! The digits and symbols of the num layer type their shifted form when held
+//! for the auto-shift time. Another key pressed meanwhile taps them, and the
+//! toggle key on the adjust layer turns auto-shift off.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run};
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+use rmk::types::modifier::ModifierCombination;
+use rmk_corne::auto_shift::{
+    self, AUTO_SHIFT_TOGGLE, AutoShiftClass, AutoShiftClasses, auto_shift, shifts,
+};
+use rmk_corne::host_layout::HostLayout;
+use rmk_corne::keymap::{
+    AUTO_SHIFT_CLASSES, AUTO_SHIFT_MS, HOST_LAYOUT, behavior_config, get_default_keymap,
+};
+
+/// `kol!(Space, 1)` and `kol!(Enter, 2)`
+const NUM: (u8, u8) = (3, 5);
+const NAV: (u8, u8) = (3, 6);
+/// 7 and 8 on the num layer
+const SEVEN_KEY: (u8, u8) = (1, 7);
+const EIGHT_KEY: (u8, u8) = (1, 8);
+/// The toggle on the adjust layer
+const TOGGLE: (u8, u8) = (1, 1);
+
+const NUM_LAYER: usize = 1;
+const ADJUST: usize = 5;
+
+const SEVEN: u8 = 0x24;
+const EIGHT: u8 = 0x25;
+const LEFT_SHIFT: u8 = 0x02;
+
+/// Longer than the tapping term of the thumb keys
+const LAYER_MS: u64 = 250;
+const TAP_MS: u64 = 100;
+const HOLD_MS: u64 = AUTO_SHIFT_MS as u64 + 100;
+
+/// Types `steps` on the num layer
+fn on_num(steps: &[Step]) -> Vec<KeyState> {
+    let steps = [[press(NUM, 0)].as_slice(), steps, &[release(NUM, LAYER_MS)]].concat();
+    run(get_default_keymap(), behavior_config(), &steps)
+}
+
+fn typed(keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(0, vec![key]), (0, vec![])])
+        .collect()
+}
+
+#[test]
+fn the_num_layer_auto_shifts() {
+    let keymap = get_default_keymap();
+    let digits = keymap[NUM_LAYER][1][1..11]
+        .iter()
+        .filter(|action| shifts(action, true))
+        .count();
+    assert_eq!(digits, 10);
+    assert_eq!(
+        keymap[NUM_LAYER][SEVEN_KEY.0 as usize][SEVEN_KEY.1 as usize],
+        auto_shift(HOST_LAYOUT, '7')
+    );
+    assert_eq!(
+        keymap[ADJUST][TOGGLE.0 as usize][TOGGLE.1 as usize],
+        AUTO_SHIFT_TOGGLE
+    );
+}
+
+#[test]
+fn the_classes_pick_the_keys_that_shift() {
+    assert_eq!(
+        AutoShiftClass::of(Action::Key(KeyCode::Kc1)),
+        Some(AutoShiftClass::Digit)
+    );
+    assert_eq!(
+        AutoShiftClass::of(Action::Key(KeyCode::LeftBracket)),
+        Some(AutoShiftClass::Symbol)
+    );
+    // `[` on German
+    assert_eq!(
+        AutoShiftClass::of(Action::KeyWithModifier(
+            KeyCode::Kc8,
+            ModifierCombination::RALT
+        )),
+        Some(AutoShiftClass::Symbol)
+    );
+    assert_eq!(AutoShiftClass::of(Action::Key(KeyCode::A)), None);
+
+    let digits = AutoShiftClasses {
+        digits: true,
+        symbols: false,
+    };
+    assert!(digits.contains(AutoShiftClass::Digit));
+    assert!(!digits.contains(AutoShiftClass::Symbol));
+
+    let seven = auto_shift(HostLayout::Us, '7');
+    assert_eq!(auto_shift::class(&seven), Some(AutoShiftClass::Digit));
+    assert_eq!(auto_shift::class(&KeyAction::No), None);
+    assert_eq!(
+        shifts(&seven, true),
+        AUTO_SHIFT_CLASSES.contains(AutoShiftClass::Digit)
+    );
+    assert!(!shifts(&seven, false));
+}
+
+#[test]
+fn a_tap_types_the_key() {
+    let steps = [press(SEVEN_KEY, LAYER_MS), release(SEVEN_KEY, TAP_MS)];
+    assert_eq!(on_num(&steps), typed(&[SEVEN]));
+}
+
+#[test]
+fn a_hold_types_the_shifted_key() {
+    let steps = [press(SEVEN_KEY, LAYER_MS), release(SEVEN_KEY, HOLD_MS)];
+    assert_eq!(on_num(&steps), [(LEFT_SHIFT, vec![SEVEN]), (0, vec![])]);
+}
+
+#[test]
+fn a_roll_keeps_its_order_and_isnt_shifted() {
+    // 7 is held until after 8, for longer than the auto-shift time in all
+    let steps = [
+        press(SEVEN_KEY, LAYER_MS),
+        press(EIGHT_KEY, TAP_MS),
+        release(SEVEN_KEY, HOLD_MS - TAP_MS),
+        release(EIGHT_KEY, 50),
+    ];
+    assert_eq!(on_num(&steps), typed(&[SEVEN, EIGHT]));
+}
+
+#[test]
+fn the_toggle_turns_auto_shift_off_and_on() {
+    let toggle = [
+        press(NAV, 20),
+        press(TOGGLE, LAYER_MS),
+        release(TOGGLE, 20),
+        release(NAV, 20),
+    ];
+    let hold_seven = [press(SEVEN_KEY, LAYER_MS), release(SEVEN_KEY, HOLD_MS)];
+    let steps = [toggle.as_slice(), &hold_seven, &toggle, &hold_seven].concat();
+    let mut expected = typed(&[SEVEN]);
+    expected.extend([(LEFT_SHIFT, vec![SEVEN]), (0, vec![])]);
+    assert_eq!(on_num(&steps), expected);
+}
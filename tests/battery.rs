@@ -0,0 +1,116 @@
+//! The halves turn their ADC readings into battery levels and send them to
+//! the dongle as custom events, the dongle keeps them for the status.
+
+use std::collections::VecDeque;
+
+use embassy_time::{Duration, Timer, with_timeout};
+use rmk::channel::EVENT_CHANNEL;
+use rmk::controller::Controller;
+use rmk::embassy_futures::block_on;
+use rmk::embassy_futures::select::select;
+use rmk::event::{ControllerEvent, Event, KeyboardEvent};
+use rmk::input_device::InputDevice;
+use rmk_corne::battery::{BatteryReporter, percent};
+use rmk_corne::split::{self, HalfMessage};
+use rmk_corne::status::{self, HalfStatus, StatusController};
+
+/// The divider of rmk's nRF52840 examples
+const DIVIDER: (u32, u32) = (2000, 2806);
+
+/// Reads the events it was made with, one after the other
+struct FakeAdc(VecDeque<Event>);
+
+impl InputDevice for FakeAdc {
+    async fn read_event(&mut self) -> Event {
+        self.0.pop_front().expect("no event left")
+    }
+}
+
+#[test]
+fn adc_readings_turn_into_percent() {
+    // 4.2 V and more is full
+    assert_eq!(percent(4755, (1, 1)), 100);
+    assert_eq!(percent(4900, (1, 1)), 100);
+    // 3.6 V and less is empty
+    assert_eq!(percent(4055, (1, 1)), 0);
+    assert_eq!(percent(3000, (1, 1)), 0);
+    assert_eq!(percent(4405, (1, 1)), 50);
+    // Behind the divider the ADC sees less
+    assert_eq!(percent(3140, DIVIDER), 50);
+}
+
+#[test]
+fn messages_of_the_halves_survive_the_custom_event() {
+    let message = HalfMessage::Battery {
+        half: 1,
+        percent: 42,
+    };
+    assert_eq!(HalfMessage::from_event(&message.to_event()), Some(message));
+
+    let mut data = [0; 16];
+    assert_eq!(HalfMessage::from_event(&Event::Custom(data)), None);
+    // No third half, no more than full
+    data[..3].copy_from_slice(&[0x01, 2, 42]);
+    assert_eq!(HalfMessage::from_event(&Event::Custom(data)), None);
+    data[..3].copy_from_slice(&[0x01, 1, 101]);
+    assert_eq!(HalfMessage::from_event(&Event::Custom(data)), None);
+    assert_eq!(HalfMessage::from_event(&Event::Battery(4000)), None);
+}
+
+#[test]
+fn the_reporter_sends_battery_levels_only() {
+    let adc = FakeAdc(VecDeque::from([
+        Event::Key(KeyboardEvent::key(0, 0, true)),
+        Event::Battery(4755),
+        Event::Battery(3140),
+    ]));
+    let mut reporter = BatteryReporter::new(adc, 0, DIVIDER);
+    block_on(async {
+        let full = HalfMessage::from_event(&reporter.read_event().await);
+        assert_eq!(
+            full,
+            Some(HalfMessage::Battery {
+                half: 0,
+                percent: 100
+            })
+        );
+        let half = HalfMessage::from_event(&reporter.read_event().await);
+        assert_eq!(
+            half,
+            Some(HalfMessage::Battery {
+                half: 0,
+                percent: 50
+            })
+        );
+    });
+}
+
+#[test]
+fn the_dongle_keeps_the_battery_levels_until_a_half_reconnects() {
+    let mut controller = StatusController::new();
+    let received = block_on(with_timeout(
+        Duration::from_secs(5),
+        select(split::receive_half_messages(), async {
+            controller
+                .process_event(ControllerEvent::SplitPeripheral(1, true))
+                .await;
+            let message = HalfMessage::Battery {
+                half: 1,
+                percent: 80,
+            };
+            EVENT_CHANNEL.send(message.to_event()).await;
+            while status::current().halves[1].battery_percent.is_none() {
+                Timer::after_millis(1).await;
+            }
+        }),
+    ));
+    assert!(received.is_ok());
+    let expected = HalfStatus {
+        connected: true,
+        battery_percent: Some(80),
+    };
+    assert_eq!(status::current().halves, [HalfStatus::default(), expected]);
+
+    block_on(controller.process_event(ControllerEvent::SplitPeripheral(1, false)));
+    assert_eq!(status::current().halves[1], HalfStatus::default());
+}
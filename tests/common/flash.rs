@@ -0,0 +1,75 @@
+//! Flash in RAM for rmk's storage.
+
+use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind};
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+
+pub const SECTOR: usize = 4096;
+
+pub struct RamFlash {
+    data: Vec<u8>,
+}
+
+impl RamFlash {
+    /// Erased flash of `sectors` sectors
+    pub fn new(sectors: usize) -> Self {
+        Self {
+            data: vec![0xff; sectors * SECTOR],
+        }
+    }
+
+    fn range(&self, offset: u32, len: usize) -> Result<std::ops::Range<usize>, RamFlashError> {
+        let start = offset as usize;
+        let end = start + len;
+        if end > self.data.len() {
+            return Err(RamFlashError(NorFlashErrorKind::OutOfBounds));
+        }
+        Ok(start..end)
+    }
+}
+
+#[derive(Debug)]
+pub struct RamFlashError(NorFlashErrorKind);
+
+impl NorFlashError for RamFlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        self.0
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = RamFlashError;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let range = self.range(offset, bytes.len())?;
+        bytes.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let range = self.range(from, (to - from) as usize)?;
+        self.data[range].fill(0xff);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let range = self.range(offset, bytes.len())?;
+        // Like NOR flash, writing can only clear bits
+        for (cell, byte) in self.data[range].iter_mut().zip(bytes) {
+            *cell &= byte;
+        }
+        Ok(())
+    }
+}
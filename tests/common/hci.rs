@@ -0,0 +1,184 @@
+//! A BLE controller without a radio: the events queued with
+//! [`SoftController::push_event`] are read by the host, the commands it runs
+//! are kept for [`SoftController::commands`].
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::pending;
+
+use bt_hci::ControllerToHostPacket;
+use bt_hci::cmd::le::LeConnUpdate;
+use bt_hci::cmd::{Cmd, Error as CmdError};
+use bt_hci::controller::{Controller, ControllerCmdAsync, ControllerCmdSync};
+use bt_hci::data::{AclPacket, IsoPacket, SyncPacket};
+use bt_hci::event::{EventKind, EventPacket};
+use bt_hci::param::ConnHandle;
+use embedded_io_async::ErrorType;
+use rmk_corne::radio::TxPower;
+
+bt_hci::cmd! {
+    /// Zephyr's vendor command for the TX power, that of the nRF controller
+    WriteTxPower(VENDOR_SPECIFIC, 0x000e) {
+        WriteTxPowerParams {
+            handle_type: u8,
+            handle: u16,
+            tx_power_level: i8,
+        }
+        WriteTxPowerReturn {
+            selected_tx_power: i8,
+        }
+    }
+}
+
+/// `handle_type` of a connection
+const HANDLE_CONN: u8 = 2;
+
+/// What `apply_links` builds the TX power command with
+pub fn write_tx_power(handle: ConnHandle, power: TxPower) -> WriteTxPower {
+    WriteTxPower::new(HANDLE_CONN, handle.raw(), power.dbm())
+}
+
+/// A command the controller got, the durations in HCI's steps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    TxPower {
+        handle: u16,
+        dbm: i8,
+    },
+    ConnUpdate {
+        handle: u16,
+        interval: u16,
+        latency: u16,
+        timeout: u16,
+    },
+}
+
+#[derive(Default)]
+pub struct SoftController {
+    events: RefCell<VecDeque<(EventKind, Vec<u8>)>>,
+    commands: RefCell<Vec<Command>>,
+}
+
+impl SoftController {
+    pub fn push_event(&self, kind: EventKind, data: Vec<u8>) {
+        self.events.borrow_mut().push_back((kind, data));
+    }
+
+    pub fn commands(&self) -> Vec<Command> {
+        self.commands.borrow().clone()
+    }
+}
+
+/// LE Connection Complete of `handle`, `central` for the role of this end
+pub fn connection_complete(
+    handle: u16,
+    central: bool,
+    interval: u16,
+    latency: u16,
+    timeout: u16,
+) -> (EventKind, Vec<u8>) {
+    let mut data = vec![0x01, 0x00];
+    data.extend(handle.to_le_bytes());
+    data.push(if central { 0 } else { 1 });
+    // Peer address kind and address
+    data.extend([0; 7]);
+    data.extend(interval.to_le_bytes());
+    data.extend(latency.to_le_bytes());
+    data.extend(timeout.to_le_bytes());
+    // Clock accuracy
+    data.push(0);
+    (EventKind::Le, data)
+}
+
+/// LE Connection Update Complete of `handle`
+pub fn update_complete(
+    handle: u16,
+    interval: u16,
+    latency: u16,
+    timeout: u16,
+) -> (EventKind, Vec<u8>) {
+    let mut data = vec![0x03, 0x00];
+    data.extend(handle.to_le_bytes());
+    data.extend(interval.to_le_bytes());
+    data.extend(latency.to_le_bytes());
+    data.extend(timeout.to_le_bytes());
+    (EventKind::Le, data)
+}
+
+/// Disconnection Complete of `handle`, the remote end left
+pub fn disconnection_complete(handle: u16) -> (EventKind, Vec<u8>) {
+    let mut data = vec![0x00];
+    data.extend(handle.to_le_bytes());
+    data.push(0x13);
+    (EventKind::DisconnectionComplete, data)
+}
+
+impl ErrorType for SoftController {
+    type Error = Infallible;
+}
+
+impl Controller for SoftController {
+    async fn write_acl_data(&self, _: &AclPacket<'_>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn write_sync_data(&self, _: &SyncPacket<'_>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn write_iso_data(&self, _: &IsoPacket<'_>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Waits forever once every event was read
+    async fn read<'a>(&self, buf: &'a mut [u8]) -> Result<ControllerToHostPacket<'a>, Self::Error> {
+        let next = self.events.borrow_mut().pop_front();
+        let Some((kind, data)) = next else {
+            return pending().await;
+        };
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(ControllerToHostPacket::Event(EventPacket {
+            kind,
+            data: &buf[..data.len()],
+        }))
+    }
+}
+
+impl ControllerCmdSync<WriteTxPower> for SoftController {
+    async fn exec(&self, cmd: &WriteTxPower) -> Result<WriteTxPowerReturn, CmdError<Infallible>> {
+        let WriteTxPowerParams {
+            handle,
+            tx_power_level,
+            ..
+        } = *cmd.params();
+        self.commands.borrow_mut().push(Command::TxPower {
+            handle,
+            dbm: tx_power_level,
+        });
+        Ok(WriteTxPowerReturn {
+            selected_tx_power: tx_power_level,
+        })
+    }
+}
+
+impl ControllerCmdAsync<LeConnUpdate> for SoftController {
+    async fn exec(&self, cmd: &LeConnUpdate) -> Result<(), CmdError<Infallible>> {
+        let params = *cmd.params();
+        let (handle, interval_min, interval_max, latency, timeout) = (
+            params.handle,
+            params.conn_interval_min,
+            params.conn_interval_max,
+            params.max_latency,
+            params.supervision_timeout,
+        );
+        assert_eq!(interval_min, interval_max);
+        self.commands.borrow_mut().push(Command::ConnUpdate {
+            handle: handle.raw(),
+            interval: interval_min.as_u16(),
+            latency,
+            timeout: timeout.as_u16(),
+        });
+        Ok(())
+    }
+}
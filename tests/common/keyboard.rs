@@ -0,0 +1,145 @@
+//! Runs rmk's keyboard on the host: presses keys of a keymap one after
+//! another and collects the keyboard reports it sends, the way rmk's own
+//! tests do.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard};
+
+use embassy_time::{Duration, Timer};
+use rmk::channel::{KEY_EVENT_CHANNEL, KEYBOARD_REPORT_CHANNEL};
+use rmk::config::BehaviorConfig;
+use rmk::embassy_futures::block_on;
+use rmk::embassy_futures::select::select4;
+use rmk::event::KeyboardEvent;
+use rmk::hid::Report;
+use rmk::input_device::Runnable;
+use rmk::keymap::KeyMap;
+use rmk::types::action::{EncoderAction, KeyAction};
+use rmk_corne::keyboard::Keyboard;
+use rmk_corne::keymap::{COL, NUM_LAYER, ROW, positional_config};
+
+/// The channels are global, so only one keyboard runs at a time
+static RUNNING: Mutex<()> = Mutex::new(());
+
+/// How long the keyboard keeps running after the last key, for timeouts
+const SETTLE_MS: u64 = 500;
+
+/// Waits until no other test runs rmk
+pub fn exclusive() -> MutexGuard<'static, ()> {
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A key press or release after waiting `after_ms`
+#[derive(Clone, Copy)]
+pub struct Step {
+    pub pos: (u8, u8),
+    pub pressed: bool,
+    pub after_ms: u64,
+}
+
+pub const fn press((row, col): (u8, u8), after_ms: u64) -> Step {
+    Step {
+        pos: (row, col),
+        pressed: true,
+        after_ms,
+    }
+}
+
+pub const fn release((row, col): (u8, u8), after_ms: u64) -> Step {
+    Step {
+        pos: (row, col),
+        pressed: false,
+        after_ms,
+    }
+}
+
+/// Modifier byte and the pressed keycodes of a keyboard report
+pub type KeyState = (u8, Vec<u8>);
+
+/// rmk's keymap with the keymap's hands, it borrows all of its parts for as
+/// long as it lives
+pub fn keymap(
+    keymap: [[[KeyAction; COL]; ROW]; NUM_LAYER],
+    behavior: BehaviorConfig,
+) -> &'static RefCell<KeyMap<'static, ROW, COL, NUM_LAYER, 0>> {
+    let keymap = Box::leak(Box::new(keymap));
+    let encoders = Box::leak(Box::new([const { [] as [EncoderAction; 0] }; NUM_LAYER]));
+    let behavior = Box::leak(Box::new(behavior));
+    let positions = Box::leak(Box::new(positional_config()));
+    Box::leak(Box::new(RefCell::new(block_on(KeyMap::new(
+        keymap,
+        Some(encoders),
+        behavior,
+        positions,
+    )))))
+}
+
+/// Runs `steps` on a keyboard with `keymap` next to `task`, which is polled
+/// before the first step. Returns every change of the keys the host sees,
+/// and every other report.
+pub fn run_reports(
+    keymap: [[[KeyAction; COL]; ROW]; NUM_LAYER],
+    behavior: BehaviorConfig,
+    steps: &[Step],
+    task: impl Future<Output = ()>,
+) -> (Vec<KeyState>, Vec<Report>) {
+    let _running = exclusive();
+    while KEYBOARD_REPORT_CHANNEL.try_receive().is_ok() {}
+
+    let mut keyboard = Keyboard::new(self::keymap(keymap, behavior));
+    let states = RefCell::new(vec![(0, vec![])]);
+    let reports = RefCell::new(vec![]);
+    block_on(select4(
+        keyboard.run(),
+        task,
+        async {
+            for step in steps {
+                Timer::after(Duration::from_millis(step.after_ms)).await;
+                let (row, col) = step.pos;
+                KEY_EVENT_CHANNEL
+                    .send(KeyboardEvent::key(row, col, step.pressed))
+                    .await;
+            }
+            Timer::after(Duration::from_millis(SETTLE_MS)).await;
+        },
+        async {
+            loop {
+                let report = match KEYBOARD_REPORT_CHANNEL.receive().await {
+                    Report::KeyboardReport(report) => report,
+                    report => {
+                        reports.borrow_mut().push(report);
+                        continue;
+                    }
+                };
+                let keys = report.keycodes.into_iter().filter(|&k| k != 0).collect();
+                let state = (report.modifier, keys);
+                let mut states = states.borrow_mut();
+                if states.last() != Some(&state) {
+                    states.push(state);
+                }
+            }
+        },
+    ));
+    (states.into_inner().split_off(1), reports.into_inner())
+}
+
+/// Runs `steps` on a keyboard with `keymap` next to `task`, which is polled
+/// before the first step. Returns every change of the keys the host sees.
+pub fn run_with(
+    keymap: [[[KeyAction; COL]; ROW]; NUM_LAYER],
+    behavior: BehaviorConfig,
+    steps: &[Step],
+    task: impl Future<Output = ()>,
+) -> Vec<KeyState> {
+    run_reports(keymap, behavior, steps, task).0
+}
+
+/// Runs `steps` on a keyboard with `keymap`
+pub fn run(
+    keymap: [[[KeyAction; COL]; ROW]; NUM_LAYER],
+    behavior: BehaviorConfig,
+    steps: &[Step],
+) -> Vec<KeyState> {
+    run_with(keymap, behavior, steps, std::future::pending())
+}
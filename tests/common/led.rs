@@ -0,0 +1,34 @@
+//! An LED pin that can be looked at after the keyboard ran.
+
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::digital::{ErrorType, OutputPin};
+
+#[derive(Clone, Default)]
+pub struct Led {
+    high: Rc<Cell<bool>>,
+}
+
+impl Led {
+    pub fn is_high(&self) -> bool {
+        self.high.get()
+    }
+}
+
+impl ErrorType for Led {
+    type Error = Infallible;
+}
+
+impl OutputPin for Led {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.high.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.high.set(true);
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+//! Stand-ins for the hardware rmk runs on, shared by the tests.
+
+// Not every test uses all of it
+#![allow(dead_code)]
+
+pub mod flash;
+pub mod hci;
+pub mod keyboard;
+pub mod led;
+pub mod serial;
+pub mod usb;
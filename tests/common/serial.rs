@@ -0,0 +1,57 @@
+//! A serial port in memory: what is sent with [`SoftSerial::send`] is read by
+//! the device, what the device writes is kept for [`SoftSerial::received`].
+
+use std::collections::VecDeque;
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+#[derive(Default)]
+pub struct SoftSerial {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+/// Everything sent was read
+#[derive(Debug, PartialEq, Eq)]
+pub struct EndOfInput;
+
+impl embedded_io_async::Error for EndOfInput {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl SoftSerial {
+    /// Queues `text` as if the host typed it
+    pub fn send(&mut self, text: &str) {
+        self.input.extend(text.bytes());
+    }
+
+    pub fn received(&self) -> &str {
+        std::str::from_utf8(&self.output).unwrap()
+    }
+}
+
+impl ErrorType for SoftSerial {
+    type Error = EndOfInput;
+}
+
+impl Read for SoftSerial {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, EndOfInput> {
+        if self.input.is_empty() {
+            return Err(EndOfInput);
+        }
+        let len = buf.len().min(self.input.len());
+        for (byte, input) in buf.iter_mut().zip(self.input.drain(..len)) {
+            *byte = input;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for SoftSerial {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, EndOfInput> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
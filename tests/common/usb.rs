@@ -0,0 +1,227 @@
+//! USB driver without a bus for `run_rmk`: the host never enumerates it, the
+//! tests talk to rmk's Vial endpoints directly through [`VialHost`], and to
+//! a bulk endpoint like that of the USB serial port through [`SerialHost`].
+
+use std::future::pending;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_usb::driver::{
+    Bus, ControlPipe, Direction, Driver, Endpoint, EndpointAddress, EndpointAllocError,
+    EndpointError, EndpointIn, EndpointInfo, EndpointOut, EndpointType, Event, Unsupported,
+};
+
+/// Size of a VIA report
+pub const REPORT_LEN: usize = 32;
+
+/// rmk allocates the keyboard's LED report endpoint first, then Vial's
+const VIAL_OUT_INDEX: usize = 1;
+
+type Report = [u8; REPORT_LEN];
+
+static TO_DEVICE: Channel<CriticalSectionRawMutex, Report, 1> = Channel::new();
+static FROM_DEVICE: Channel<CriticalSectionRawMutex, Report, 1> = Channel::new();
+static SERIAL_TO_DEVICE: Channel<CriticalSectionRawMutex, Vec<u8>, 1> = Channel::new();
+
+/// The host side of rmk's Vial endpoints
+pub struct VialHost;
+
+impl VialHost {
+    /// Sends a VIA request and waits for rmk's answer
+    pub async fn request(&self, request: &[u8]) -> Report {
+        let mut report = [0; REPORT_LEN];
+        report[..request.len()].copy_from_slice(request);
+        TO_DEVICE.send(report).await;
+        FROM_DEVICE.receive().await
+    }
+}
+
+/// The host side of a bulk OUT endpoint
+pub struct SerialHost;
+
+impl SerialHost {
+    /// Sends `text` in one packet
+    pub async fn send(&self, text: &str) {
+        SERIAL_TO_DEVICE.send(text.as_bytes().to_vec()).await;
+    }
+}
+
+#[derive(Default)]
+pub struct MockDriver {
+    outs: usize,
+    ins: usize,
+}
+
+fn info(index: usize, direction: Direction, ep_type: EndpointType, size: u16) -> EndpointInfo {
+    EndpointInfo {
+        addr: EndpointAddress::from_parts(index + 1, direction),
+        ep_type,
+        max_packet_size: size,
+        interval_ms: 1,
+    }
+}
+
+impl<'d> Driver<'d> for MockDriver {
+    type EndpointOut = MockOut;
+    type EndpointIn = MockIn;
+    type ControlPipe = MockControl;
+    type Bus = MockBus;
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_type: EndpointType,
+        _ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        _interval_ms: u8,
+    ) -> Result<MockOut, EndpointAllocError> {
+        let index = self.outs;
+        self.outs += 1;
+        let source = match ep_type {
+            EndpointType::Bulk => Source::Serial,
+            _ if index == VIAL_OUT_INDEX => Source::Vial,
+            _ => Source::Nothing,
+        };
+        Ok(MockOut {
+            source,
+            info: info(index, Direction::Out, ep_type, max_packet_size),
+        })
+    }
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_type: EndpointType,
+        _ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        _interval_ms: u8,
+    ) -> Result<MockIn, EndpointAllocError> {
+        let index = self.ins;
+        self.ins += 1;
+        Ok(MockIn {
+            info: info(index, Direction::In, ep_type, max_packet_size),
+        })
+    }
+
+    fn start(self, _control_max_packet_size: u16) -> (MockBus, MockControl) {
+        (MockBus, MockControl)
+    }
+}
+
+/// What a [`MockOut`] reads
+enum Source {
+    Vial,
+    Serial,
+    Nothing,
+}
+
+pub struct MockOut {
+    source: Source,
+    info: EndpointInfo,
+}
+
+impl Endpoint for MockOut {
+    fn info(&self) -> &EndpointInfo {
+        &self.info
+    }
+
+    async fn wait_enabled(&mut self) {}
+}
+
+impl EndpointOut for MockOut {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, EndpointError> {
+        match self.source {
+            Source::Vial => {
+                let report = TO_DEVICE.receive().await;
+                buf[..REPORT_LEN].copy_from_slice(&report);
+                Ok(REPORT_LEN)
+            }
+            Source::Serial => {
+                let packet = SERIAL_TO_DEVICE.receive().await;
+                buf[..packet.len()].copy_from_slice(&packet);
+                Ok(packet.len())
+            }
+            Source::Nothing => pending().await,
+        }
+    }
+}
+
+pub struct MockIn {
+    info: EndpointInfo,
+}
+
+impl Endpoint for MockIn {
+    fn info(&self) -> &EndpointInfo {
+        &self.info
+    }
+
+    async fn wait_enabled(&mut self) {}
+}
+
+impl EndpointIn for MockIn {
+    async fn write(&mut self, buf: &[u8]) -> Result<(), EndpointError> {
+        // Keyboard reports are shorter, only Vial's answers are kept
+        if let Ok(report) = buf.try_into() {
+            FROM_DEVICE.send(report).await;
+        }
+        Ok(())
+    }
+}
+
+pub struct MockBus;
+
+impl Bus for MockBus {
+    async fn enable(&mut self) {}
+
+    async fn disable(&mut self) {}
+
+    async fn poll(&mut self) -> Event {
+        pending().await
+    }
+
+    fn endpoint_set_enabled(&mut self, _ep_addr: EndpointAddress, _enabled: bool) {}
+
+    fn endpoint_set_stalled(&mut self, _ep_addr: EndpointAddress, _stalled: bool) {}
+
+    fn endpoint_is_stalled(&mut self, _ep_addr: EndpointAddress) -> bool {
+        false
+    }
+
+    async fn remote_wakeup(&mut self) -> Result<(), Unsupported> {
+        Err(Unsupported)
+    }
+}
+
+pub struct MockControl;
+
+impl ControlPipe for MockControl {
+    fn max_packet_size(&self) -> usize {
+        64
+    }
+
+    async fn setup(&mut self) -> [u8; 8] {
+        pending().await
+    }
+
+    async fn data_out(
+        &mut self,
+        _buf: &mut [u8],
+        _first: bool,
+        _last: bool,
+    ) -> Result<usize, EndpointError> {
+        Err(EndpointError::Disabled)
+    }
+
+    async fn data_in(
+        &mut self,
+        _data: &[u8],
+        _first: bool,
+        _last: bool,
+    ) -> Result<(), EndpointError> {
+        Err(EndpointError::Disabled)
+    }
+
+    async fn accept(&mut self) {}
+
+    async fn reject(&mut self) {}
+
+    async fn accept_set_address(&mut self, _addr: u8) {}
+}
@@ -0,0 +1,282 @@
+//! The console parses lines typed on a serial port, shows the status with
+//! the links and the keymap, sets the tapping terms and auto-shift and hands
+//! forgetting the halves and the bootloader to the dongle. On the dongle it
+//! runs on the USB serial port, and answers as log lines.
+
+mod common;
+
+use std::sync::Mutex;
+
+use bt_hci::controller::Controller as _;
+use common::flash::RamFlash;
+use common::hci::{SoftController, connection_complete};
+use common::serial::{EndOfInput, SoftSerial};
+use common::usb::{MockDriver, SerialHost};
+use embassy_time::{Duration, Timer, with_timeout};
+use embassy_usb::driver::{Driver, EndpointOut, EndpointType};
+use rmk::controller::Controller;
+use rmk::embassy_futures::block_on;
+use rmk::embassy_futures::select::select3;
+use rmk::event::ControllerEvent;
+use rmk_corne::auto_shift;
+use rmk_corne::console::{
+    Command, ConsoleBackend, LineBuffer, MAX_LINE_LEN, Param, ParseError, UsbSerial, parse,
+    run_console,
+};
+use rmk_corne::keymap::{COL, NUM_LAYER, ROW, TAPPING_TERM_MS};
+use rmk_corne::radio::{Board, LinkController};
+use rmk_corne::settings::{SETTINGS_SIZE, Setting, Settings};
+use rmk_corne::status::{self, StatusController};
+use rmk_corne::tapping_term::{self, TermTarget};
+use rmk_corne::via::ViaDriver;
+
+#[derive(Default)]
+struct Backend {
+    forgot: bool,
+    bootloader: bool,
+}
+
+impl ConsoleBackend for Backend {
+    async fn forget_peripherals(&mut self) {
+        self.forgot = true;
+    }
+
+    async fn bootloader(&mut self) {
+        self.bootloader = true;
+    }
+}
+
+fn settings_flash() -> RamFlash {
+    RamFlash::new(SETTINGS_SIZE as usize / common::flash::SECTOR)
+}
+
+/// Types `input` on the console, returns its answer
+fn console(input: &str, backend: &mut Backend, flash: &mut RamFlash) -> String {
+    let mut serial = SoftSerial::default();
+    serial.send(input);
+    let result = block_on(run_console(
+        &mut serial,
+        &mut Settings::new(&mut *flash),
+        backend,
+    ));
+    assert_eq!(result, Err(EndOfInput));
+    serial.received().to_string()
+}
+
+#[test]
+fn the_commands_parse() {
+    assert_eq!(parse("status"), Ok(Command::Status));
+    assert_eq!(parse("  help "), Ok(Command::Help));
+    assert_eq!(parse("keymap"), Ok(Command::Keymap(None)));
+    assert_eq!(parse("keymap 3"), Ok(Command::Keymap(Some(3))));
+    assert_eq!(
+        parse("set term.hrm 220"),
+        Ok(Command::Set(
+            Param::TappingTerm(TermTarget::HomeRowMods),
+            220
+        ))
+    );
+    assert_eq!(
+        parse("set  autoshift\t0"),
+        Ok(Command::Set(Param::AutoShift, 0))
+    );
+    assert_eq!(parse("forget"), Ok(Command::ForgetPeripherals));
+    assert_eq!(parse("bootloader"), Ok(Command::Bootloader));
+}
+
+#[test]
+fn bad_lines_are_refused() {
+    assert_eq!(parse("reboot"), Err(ParseError::UnknownCommand));
+    assert_eq!(parse("set speed 3"), Err(ParseError::UnknownParam));
+    assert_eq!(parse("set term"), Err(ParseError::MissingArgument));
+    assert_eq!(parse("set term fast"), Err(ParseError::BadNumber));
+    assert_eq!(parse("keymap -1"), Err(ParseError::BadNumber));
+    assert_eq!(parse("status now"), Err(ParseError::TooManyArguments));
+}
+
+#[test]
+fn lines_end_on_either_newline_and_take_backspace() {
+    let mut lines = LineBuffer::new();
+    let mut complete = vec![];
+    for &byte in b"stat\x7ftus\r\n\r\nhelp\n" {
+        if let Some(line) = lines.push(byte) {
+            complete.push(line.map(|line| line.to_string()));
+        }
+    }
+    assert_eq!(complete, [Ok("status".to_string()), Ok("help".to_string())]);
+
+    let long = "x".repeat(MAX_LINE_LEN + 1);
+    let mut result = None;
+    for byte in long.bytes().chain(*b"\r") {
+        result = lines.push(byte).or(result);
+    }
+    assert_eq!(result, Some(Err(ParseError::LineTooLong)));
+    // The next line is fine again
+    let answer = console(
+        &format!("{long}\r\nbogus\r\n"),
+        &mut Backend::default(),
+        &mut settings_flash(),
+    );
+    assert_eq!(answer, "line too long\r\nunknown command, try `help`\r\n");
+}
+
+#[test]
+fn the_status_shows_the_halves_layers_host_profile_and_links() {
+    let mut status = StatusController::new();
+    // The left half is up, at the parameters the dongle asks for
+    let controller = SoftController::default();
+    let (kind, data) = connection_complete(1, true, 6, 0, 200);
+    controller.push_event(kind, data);
+    block_on(async {
+        status
+            .process_event(ControllerEvent::SplitPeripheral(0, true))
+            .await;
+        status.process_event(ControllerEvent::Layer(2)).await;
+        let links = LinkController::new(&controller, Board::Dongle);
+        links.read(&mut [0; 64]).await.unwrap();
+    });
+    status::set_host_profile(1);
+    let answer = console("status\r\n", &mut Backend::default(), &mut settings_flash());
+    assert_eq!(
+        answer,
+        "left: connected, battery unknown\r\n\
+         right: disconnected, battery unknown\r\n\
+         layer: 2\r\n\
+         default layer: 0\r\n\
+         host profile: 1\r\n\
+         split link: 0 dBm, interval 7.5ms, latency 0, timeout 2000ms (low latency)\r\n\
+         host link: 0 dBm, interval 7.5ms, latency 0, timeout 2000ms (low latency)\r\n  \
+         split link 1: interval 7.5ms, latency 0, timeout 2000ms (low latency)\r\n"
+    );
+}
+
+#[test]
+fn the_keymap_is_dumped_by_layer() {
+    let answer = console(
+        "keymap 1\r\n",
+        &mut Backend::default(),
+        &mut settings_flash(),
+    );
+    let lines: Vec<&str> = answer.lines().collect();
+    assert_eq!(lines.len(), 1 + ROW * COL);
+    assert_eq!(lines[0], "layer 1");
+    // `ash!(Kc7)`
+    assert!(lines.contains(&"  1,7: Kc7/LShift+Kc7"));
+
+    let answer = console("keymap\r\n", &mut Backend::default(), &mut settings_flash());
+    assert_eq!(answer.lines().count(), NUM_LAYER * (1 + ROW * COL));
+    // `hrm!(F, LSHIFT)`, `kol!(Space, 1)`, `td!(0)` and nothing
+    for key in ["1,4: F/LShift", "3,5: Space/MO(1)", "3,4: TD(0)", "0,0: No"] {
+        assert!(answer.contains(&format!("  {key}\r\n")), "{key}");
+    }
+
+    let answer = console(
+        "keymap 9\r\n",
+        &mut Backend::default(),
+        &mut settings_flash(),
+    );
+    assert_eq!(answer, "no such layer\r\n");
+}
+
+#[test]
+fn set_changes_and_stores_the_tapping_terms_and_auto_shift() {
+    let mut flash = settings_flash();
+    let answer = console(
+        "set term.thumb 232\r\nset term 20\r\n",
+        &mut Backend::default(),
+        &mut flash,
+    );
+    assert_eq!(answer, "ok\r\nout of range\r\n");
+    let terms = tapping_term::current();
+    assert_eq!(terms.get(TermTarget::Thumbs), 230);
+    assert_eq!(terms.global, TAPPING_TERM_MS);
+    // In steps of 5 ms
+    let stored = block_on(Settings::new(&mut flash).get(Setting::ThumbTerm));
+    assert_eq!(stored, Some(46));
+
+    let answer = console("set autoshift 0\r\n", &mut Backend::default(), &mut flash);
+    assert_eq!(answer, "ok\r\n");
+    assert!(!auto_shift::is_enabled());
+    let answer = console(
+        "set autoshift 2\r\nset autoshift 1\r\n",
+        &mut Backend::default(),
+        &mut flash,
+    );
+    assert_eq!(answer, "out of range\r\nok\r\n");
+    assert!(auto_shift::is_enabled());
+}
+
+#[test]
+fn forget_and_bootloader_go_to_the_dongle() {
+    let mut backend = Backend::default();
+    let answer = console(
+        "forget\r\nbootloader\r\n",
+        &mut backend,
+        &mut settings_flash(),
+    );
+    assert_eq!(
+        answer,
+        "halves forgotten, they pair again after a reboot\r\nrebooting to the bootloader\r\n"
+    );
+    assert!(backend.forgot);
+    assert!(backend.bootloader);
+}
+
+/// The log lines written so far
+static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOG.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn the_usb_serial_port_answers_as_log_lines() {
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let mut driver = ViaDriver::new(MockDriver::default());
+    let mut serial_out = driver
+        .alloc_endpoint_out(EndpointType::Bulk, None, 64, 0)
+        .unwrap();
+    let mut flash = settings_flash();
+    let mut settings = Settings::new(&mut flash);
+    let answered = block_on(with_timeout(
+        Duration::from_secs(5),
+        select3(
+            run_console(
+                &mut UsbSerial::new(),
+                &mut settings,
+                &mut Backend::default(),
+            ),
+            // rmk's logger reads the port and throws away what it reads
+            async {
+                let mut buf = [0; 64];
+                loop {
+                    serial_out.read(&mut buf).await.unwrap();
+                }
+            },
+            async {
+                SerialHost.send("keymap 9\r").await;
+                SerialHost.send("\nhelp\r\n").await;
+                while LOG.lock().unwrap().len() < 9 {
+                    Timer::after_millis(10).await;
+                }
+            },
+        ),
+    ));
+    assert!(answered.is_ok());
+    let log = LOG.lock().unwrap();
+    assert_eq!(log[0], "no such layer");
+    assert!(log[1].starts_with("status "));
+    assert!(log[8].starts_with("bootloader "));
+}
@@ -0,0 +1,209 @@
+//! The default layer follows the `df!` keys of the keymap, is stored in the
+//! settings and comes back after a restart, and the host can read and set it
+//! over VIA.
+
+mod common;
+
+use common::flash::RamFlash;
+use common::keyboard::{KeyState, Step, exclusive, press, release, run_with};
+use common::led::Led;
+use common::usb::{MockDriver, VialHost};
+use embassy_time::Timer;
+use rmk::config::{BehaviorConfig, PositionalConfig, RmkConfig, StorageConfig};
+use rmk::controller::EventController;
+use rmk::embassy_futures::block_on;
+use rmk::embassy_futures::select::{Either4, select4};
+use rmk::input_device::Runnable;
+use rmk::types::action::EncoderAction;
+use rmk::{initialize_encoder_keymap_and_storage, run_rmk};
+use rmk_corne::default_layer::{self, DefaultLayerController, is_default_layer};
+use rmk_corne::keyboard::Keyboard;
+use rmk_corne::keymap::{COL, NUM_LAYER, ROW, get_default_keymap};
+use rmk_corne::settings::{SETTINGS_SIZE, Setting, Settings};
+use rmk_corne::via::{CUSTOM_CHANNEL, CustomValue, ViaDriver};
+
+const CUSTOM_SET_VALUE: u8 = 0x07;
+const CUSTOM_GET_VALUE: u8 = 0x08;
+const UNHANDLED: u8 = 0xff;
+
+const TAB: u8 = 0x2b;
+
+/// `df!(3)` on the base layer, `df!(0)` on the gaming layer
+const SWITCH: (u8, u8) = (0, 11);
+/// Nothing on the base layer, Tab on the gaming layer
+const TAB_KEY: (u8, u8) = (0, 0);
+
+const GAMING: u8 = 3;
+
+fn settings_flash() -> RamFlash {
+    RamFlash::new(SETTINGS_SIZE as usize / common::flash::SECTOR)
+}
+
+fn stored(flash: &mut RamFlash) -> Option<u8> {
+    block_on(Settings::new(flash).get(Setting::DefaultLayer))
+}
+
+/// Types `steps` with the default layer controller running on `flash`,
+/// returns the keys the host saw and whether the LED is lit
+fn type_keys(flash: &mut RamFlash, steps: &[Step]) -> (Vec<KeyState>, bool) {
+    let led = Led::default();
+    let states = run_with(
+        get_default_keymap(),
+        BehaviorConfig::default(),
+        steps,
+        async {
+            let mut controller =
+                DefaultLayerController::new(led.clone(), true, Settings::new(&mut *flash)).await;
+            controller.event_loop().await
+        },
+    );
+    (states, !led.is_high())
+}
+
+#[test]
+fn the_default_layers_are_base_and_gaming() {
+    let layers: Vec<u8> = (0..NUM_LAYER as u8 + 1)
+        .filter(|&layer| is_default_layer(layer))
+        .collect();
+    assert_eq!(layers, [0, GAMING]);
+}
+
+#[test]
+fn the_switch_key_stores_the_default_layer() {
+    let mut flash = settings_flash();
+    let tap_tab = [press(TAB_KEY, 0), release(TAB_KEY, 20)];
+
+    let (states, lit) = type_keys(&mut flash, &tap_tab);
+    assert_eq!(states, []);
+    assert!(!lit);
+    assert_eq!(stored(&mut flash), None);
+
+    let steps = [
+        press(SWITCH, 0),
+        release(SWITCH, 20),
+        press(TAB_KEY, 20),
+        release(TAB_KEY, 20),
+    ];
+    let (states, lit) = type_keys(&mut flash, &steps);
+    assert_eq!(states, [(0, vec![TAB]), (0, vec![])]);
+    assert!(lit);
+    assert_eq!(stored(&mut flash), Some(GAMING));
+}
+
+#[test]
+fn the_default_layer_survives_a_restart() {
+    let mut flash = settings_flash();
+    block_on(Settings::new(&mut flash).set(Setting::DefaultLayer, GAMING));
+
+    let tap_tab = [press(TAB_KEY, 20), release(TAB_KEY, 20)];
+    let (states, lit) = type_keys(&mut flash, &tap_tab);
+    assert_eq!(states, [(0, vec![TAB]), (0, vec![])]);
+    assert!(lit);
+
+    // Back to the base layer, also after the next restart
+    let steps = [press(SWITCH, 20), release(SWITCH, 20)];
+    let (_, lit) = type_keys(&mut flash, &steps);
+    assert!(!lit);
+    assert_eq!(stored(&mut flash), Some(0));
+    let (states, lit) = type_keys(&mut flash, &tap_tab);
+    assert_eq!(states, []);
+    assert!(!lit);
+}
+
+async fn get_default_layer(host: &VialHost) -> u8 {
+    let answer = host
+        .request(&[
+            CUSTOM_GET_VALUE,
+            CUSTOM_CHANNEL,
+            CustomValue::DefaultLayer as u8,
+        ])
+        .await;
+    assert_eq!(answer[0], CUSTOM_GET_VALUE);
+    answer[3]
+}
+
+/// Asks for `layer` as the default layer, returns the command rmk answered to
+async fn set_default_layer(host: &VialHost, layer: u8) -> u8 {
+    let answer = host
+        .request(&[
+            CUSTOM_SET_VALUE,
+            CUSTOM_CHANNEL,
+            CustomValue::DefaultLayer as u8,
+            layer,
+        ])
+        .await;
+    // The controller taps the switch key
+    Timer::after_millis(50).await;
+    answer[0]
+}
+
+async fn via_session(host: VialHost) {
+    assert_eq!(get_default_layer(&host).await, 0);
+
+    assert_eq!(set_default_layer(&host, GAMING).await, CUSTOM_SET_VALUE);
+    assert_eq!(get_default_layer(&host).await, GAMING);
+    assert_eq!(default_layer::current(), GAMING);
+
+    // The nav layer can't be the default layer
+    assert_eq!(set_default_layer(&host, 2).await, UNHANDLED);
+    assert_eq!(set_default_layer(&host, NUM_LAYER as u8).await, UNHANDLED);
+    assert_eq!(get_default_layer(&host).await, GAMING);
+
+    // Unknown values are left to rmk, which doesn't know them either
+    let answer = host
+        .request(&[CUSTOM_GET_VALUE, CUSTOM_CHANNEL, 0x7f])
+        .await;
+    assert_eq!(answer[0], UNHANDLED);
+
+    assert_eq!(set_default_layer(&host, 0).await, CUSTOM_SET_VALUE);
+    assert_eq!(get_default_layer(&host).await, 0);
+}
+
+#[test]
+fn the_host_sets_the_default_layer_over_via() {
+    let _running = exclusive();
+    let mut flash = settings_flash();
+    let keymap = Box::leak(Box::new(get_default_keymap()));
+    let encoders = Box::leak(Box::new([const { [] as [EncoderAction; 0] }; NUM_LAYER]));
+    let behavior = Box::leak(Box::new(BehaviorConfig::default()));
+    let positions = Box::leak(Box::new(PositionalConfig::<ROW, COL>::default()));
+    let storage_config = StorageConfig {
+        num_sectors: 4,
+        ..Default::default()
+    };
+    let (keymap, mut storage) = block_on(initialize_encoder_keymap_and_storage(
+        keymap,
+        encoders,
+        RamFlash::new(4),
+        &storage_config,
+        behavior,
+        positions,
+    ));
+    let keymap = Box::leak(Box::new(keymap));
+    let rmk_config = RmkConfig {
+        storage_config,
+        ..Default::default()
+    };
+
+    let mut keyboard = Keyboard::new(keymap);
+    let led = Led::default();
+    let session = block_on(select4(
+        run_rmk(
+            keymap,
+            ViaDriver::new(MockDriver::default()),
+            &mut storage,
+            rmk_config,
+        ),
+        keyboard.run(),
+        async {
+            let mut controller =
+                DefaultLayerController::new(led.clone(), true, Settings::new(&mut flash)).await;
+            controller.event_loop().await
+        },
+        via_session(VialHost),
+    ));
+    assert!(matches!(session, Either4::Fourth(())));
+    // Off on the base layer
+    assert!(led.is_high());
+    assert_eq!(stored(&mut flash), Some(0));
+}
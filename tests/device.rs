@@ -0,0 +1,33 @@
+//! The USB serial number is the device id in hex, and the product name tells
+//! the build variants apart. The BLE address comes from the device id too.
+
+use rmk_corne::device::{PRODUCT_NAME, SerialNumber, ble_addr, product_name};
+
+#[test]
+fn the_serial_number_is_the_device_id_in_hex() {
+    assert_eq!(
+        SerialNumber::new(0x0123_4567_89AB_CDEF).as_str(),
+        "0123456789ABCDEF"
+    );
+    assert_eq!(SerialNumber::new(0x2a).as_str(), "000000000000002A");
+    assert_eq!(SerialNumber::new(u64::MAX).as_str(), "FFFFFFFFFFFFFFFF");
+}
+
+#[test]
+fn the_product_name_has_the_build_variant() {
+    assert_eq!(product_name(false, false), "RMK Keyboard");
+    assert_eq!(product_name(true, false), "RMK Keyboard-reset");
+    assert_eq!(product_name(false, true), "RMK Keyboard-log");
+    assert_eq!(product_name(true, true), "RMK Keyboard-reset-log");
+    // The tests build without either feature
+    assert_eq!(PRODUCT_NAME, "RMK Keyboard");
+}
+
+#[test]
+fn the_ble_address_is_a_static_random_address_of_the_device_id() {
+    assert_eq!(
+        ble_addr(0x0123_4567_89AB_CDEF),
+        [0xEF, 0xCD, 0xAB, 0x89, 0x67, 0xC5]
+    );
+    assert_eq!(ble_addr(0), [0, 0, 0, 0, 0, 0xC0]);
+}
@@ -0,0 +1,201 @@
+//! The keymap types its symbols as characters of the host layout: the key
+//! and modifier come from the layout's table, dead keys are followed by
+//! space and characters the layout doesn't have fail the build.
+
+mod common;
+
+use common::keyboard::{KeyState, press, release, run};
+use rmk::heapless;
+use rmk::keyboard_macros::define_macro_sequences;
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+use rmk::types::modifier::ModifierCombination;
+use rmk_corne::host_layout::{
+    HostLayout, char_action, char_key, check_text, dead_key_macros, text_macro, us_shifted,
+};
+use rmk_corne::keymap::{
+    COL, MACROS, NUM_LAYER, ROW, TAP_DANCES, behavior_config, get_default_keymap, keymap,
+};
+use rmk_corne::validate::validate_keymap;
+
+type Keymap = [[[KeyAction; COL]; ROW]; NUM_LAYER];
+
+/// The keymap of every layout, in the order of `HostLayout::ALL`. It is
+/// evaluated while the tests build, so a character of the keymap that one of
+/// the layouts can't type fails them.
+const KEYMAPS: [Keymap; 3] = [keymap::<0>(), keymap::<1>(), keymap::<2>()];
+
+/// `kol!(Space, 1)`
+const NUM: (u8, u8) = (3, 5);
+/// `ch!('~')` and `ch!('{')` on the num layer
+const TILDE_KEY: (u8, u8) = (0, 6);
+const BRACE_KEY: (u8, u8) = (0, 7);
+const NUM_LAYER_INDEX: usize = 1;
+
+const KC7: u8 = 0x24;
+const LEFT_BRACKET: u8 = 0x2f;
+const RIGHT_BRACKET: u8 = 0x30;
+const SPACE: u8 = 0x2c;
+const LEFT_SHIFT: u8 = 0x02;
+const RIGHT_ALT: u8 = 0x40;
+
+/// Longer than the tapping term of the thumb keys
+const LAYER_MS: u64 = 250;
+
+fn key(layout: HostLayout, c: char) -> (KeyCode, ModifierCombination, bool) {
+    let key = char_key(layout, c).unwrap();
+    (key.keycode, key.modifiers, key.dead)
+}
+
+/// Taps `pos` on the num layer of `layout`'s keymap, with rmk's macros of
+/// that layout
+fn tap_on_num(layout: HostLayout, pos: (u8, u8)) -> Vec<KeyState> {
+    let mut behavior_config = behavior_config();
+    let macros: heapless::Vec<_, 16> = MACROS
+        .iter()
+        .map(|text| text_macro(layout, text))
+        .chain(dead_key_macros(layout))
+        .collect();
+    behavior_config.keyboard_macros.macro_sequences = define_macro_sequences(&macros);
+    let steps = [
+        press(NUM, 0),
+        press(pos, LAYER_MS),
+        release(pos, 50),
+        release(NUM, LAYER_MS),
+    ];
+    run(KEYMAPS[layout as usize], behavior_config, &steps)
+}
+
+#[test]
+fn the_tables_have_the_keys_of_each_layout() {
+    let none = ModifierCombination::new();
+    let shift = ModifierCombination::LSHIFT;
+    let alt_gr = ModifierCombination::RALT;
+    assert_eq!(
+        key(HostLayout::Us, '{'),
+        (KeyCode::LeftBracket, shift, false)
+    );
+    assert_eq!(key(HostLayout::German, '{'), (KeyCode::Kc7, alt_gr, false));
+    assert_eq!(key(HostLayout::Nordic, '{'), (KeyCode::Kc7, alt_gr, false));
+    assert_eq!(key(HostLayout::German, 'z'), (KeyCode::Y, none, false));
+    assert_eq!(key(HostLayout::German, ';'), (KeyCode::Comma, shift, false));
+    assert_eq!(
+        key(HostLayout::Nordic, '\''),
+        (KeyCode::NonusHash, none, false)
+    );
+    assert_eq!(key(HostLayout::German, '^'), (KeyCode::Grave, none, true));
+    assert_eq!(
+        key(HostLayout::Nordic, '~'),
+        (KeyCode::RightBracket, alt_gr, true)
+    );
+    assert_eq!(char_key(HostLayout::Us, 'ä'), None);
+    assert_eq!(char_key(HostLayout::German, 'å'), None);
+}
+
+#[test]
+fn dead_keys_trigger_their_macro() {
+    assert_eq!(
+        char_action(HostLayout::Nordic, '~'),
+        Action::TriggerMacro(MACROS.len() as u8 + 4)
+    );
+    assert_eq!(
+        char_action(HostLayout::German, '^'),
+        Action::TriggerMacro(MACROS.len() as u8)
+    );
+    // `^` isn't dead on US
+    assert_eq!(
+        char_action(HostLayout::Us, '^'),
+        Action::KeyWithModifier(KeyCode::Kc6, ModifierCombination::LSHIFT)
+    );
+}
+
+#[test]
+#[should_panic(expected = "`ä` can't be typed on the US host layout")]
+fn a_character_the_layout_doesnt_have_panics() {
+    char_action(HostLayout::Us, 'ä');
+}
+
+#[test]
+fn auto_shift_holds_the_us_shifted_character() {
+    assert_eq!(us_shifted('1'), '!');
+    assert_eq!(us_shifted('['), '{');
+    assert_eq!(us_shifted('`'), '~');
+    assert_eq!(us_shifted('-'), '_');
+}
+
+#[test]
+fn macros_press_the_modifier_around_the_key() {
+    let typed: heapless::Vec<_, 16> = text_macro(HostLayout::German, "y{");
+    assert_eq!(
+        format!("{typed:?}"),
+        "[Tap(Z), Press(RAlt), Tap(Kc7), Release(RAlt)]"
+    );
+    let dead: Vec<heapless::Vec<_, 16>> = dead_key_macros(HostLayout::Nordic).collect();
+    assert_eq!(dead.len(), HostLayout::Nordic.dead_chars().len());
+    assert_eq!(
+        format!("{:?}", dead[4]),
+        "[Press(RAlt), Tap(RightBracket), Release(RAlt), Tap(Space)]"
+    );
+}
+
+#[test]
+fn every_layout_types_the_macros() {
+    for layout in HostLayout::ALL {
+        for text in MACROS {
+            check_text(layout, text);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "`ü` can't be typed on the Nordic host layout")]
+fn a_macro_the_layout_cant_type_panics() {
+    check_text(HostLayout::Nordic, "grüß");
+}
+
+#[test]
+fn the_keymap_builds_for_every_layout() {
+    assert_eq!(KEYMAPS[0], get_default_keymap());
+    for layout in HostLayout::ALL {
+        let keymap = &KEYMAPS[layout as usize];
+        assert_eq!(
+            validate_keymap(keymap, &TAP_DANCES),
+            Ok(()),
+            "{}",
+            layout.name()
+        );
+        assert_eq!(
+            keymap[NUM_LAYER_INDEX][BRACE_KEY.0 as usize][BRACE_KEY.1 as usize],
+            KeyAction::Single(char_action(layout, '{')),
+            "{}",
+            layout.name()
+        );
+    }
+}
+
+#[test]
+fn symbols_type_on_the_host_layout() {
+    assert_eq!(
+        tap_on_num(HostLayout::Us, BRACE_KEY),
+        [(LEFT_SHIFT, vec![LEFT_BRACKET]), (0, vec![])]
+    );
+    assert_eq!(
+        tap_on_num(HostLayout::German, BRACE_KEY),
+        [(RIGHT_ALT, vec![KC7]), (0, vec![])]
+    );
+}
+
+#[test]
+fn dead_keys_are_followed_by_space() {
+    assert_eq!(
+        tap_on_num(HostLayout::Nordic, TILDE_KEY),
+        [
+            (RIGHT_ALT, vec![]),
+            (RIGHT_ALT, vec![RIGHT_BRACKET]),
+            (RIGHT_ALT, vec![]),
+            (0, vec![]),
+            (0, vec![SPACE]),
+            (0, vec![]),
+        ]
+    );
+}
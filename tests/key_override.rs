@@ -0,0 +1,118 @@
+//! Shift+Backspace types Delete and Shift+Comma a semicolon, without the
+//! shift, whichever key the shift comes from.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run};
+use rmk::types::action::Action;
+use rmk::types::keycode::KeyCode;
+use rmk_corne::keymap::{KEY_OVERRIDES, behavior_config, get_default_keymap};
+
+/// `hrm!(F, LSHIFT)` and `hrm!(J, LSHIFT)`
+const HRM_F: (u8, u8) = (1, 4);
+const HRM_J: (u8, u8) = (1, 7);
+/// The one-shot shift thumb key that was Delete
+const ONE_SHOT_SHIFT: (u8, u8) = (3, 8);
+const BACKSPACE_KEY: (u8, u8) = (3, 3);
+const COMMA_KEY: (u8, u8) = (2, 8);
+const E_KEY: (u8, u8) = (0, 3);
+
+const E: u8 = 0x08;
+const BACKSPACE: u8 = 0x2a;
+const DELETE: u8 = 0x4c;
+const COMMA: u8 = 0x36;
+const SEMICOLON: u8 = 0x33;
+
+const LSHIFT: u8 = 0x02;
+
+/// Longer than the tapping term, and than the idle time after which flow
+/// tap lets a tap-hold key be held
+const HOLD_MS: u64 = 250;
+
+fn type_keys(steps: &[Step]) -> Vec<KeyState> {
+    run(get_default_keymap(), behavior_config(), steps)
+}
+
+fn tap(pos: (u8, u8)) -> [Step; 2] {
+    [press(pos, 50), release(pos, 20)]
+}
+
+/// Taps `key` while the home row shift at `shift` is held
+fn with_hrm_shift(shift: (u8, u8), key: (u8, u8)) -> Vec<Step> {
+    vec![
+        press(shift, HOLD_MS),
+        press(key, HOLD_MS),
+        release(key, 20),
+        release(shift, 20),
+    ]
+}
+
+#[test]
+fn the_keymap_overrides_backspace_and_comma() {
+    let overrides: Vec<_> = KEY_OVERRIDES
+        .iter()
+        .map(|o| (o.trigger, o.output))
+        .collect();
+    assert_eq!(
+        overrides,
+        [
+            (
+                Action::Key(KeyCode::Backspace),
+                Action::Key(KeyCode::Delete)
+            ),
+            (Action::Key(KeyCode::Comma), Action::Key(KeyCode::Semicolon))
+        ]
+    );
+}
+
+#[test]
+fn home_row_shift_and_backspace_is_delete() {
+    // rmk keeps the shift off until it is released
+    let expected = [(LSHIFT, vec![]), (0, vec![DELETE]), (0, vec![])];
+    assert_eq!(type_keys(&with_hrm_shift(HRM_F, BACKSPACE_KEY)), expected);
+    assert_eq!(type_keys(&with_hrm_shift(HRM_J, BACKSPACE_KEY)), expected);
+}
+
+#[test]
+fn home_row_shift_and_comma_is_semicolon() {
+    // rmk keeps the shift off until it is released
+    let expected = [(LSHIFT, vec![]), (0, vec![SEMICOLON]), (0, vec![])];
+    assert_eq!(type_keys(&with_hrm_shift(HRM_F, COMMA_KEY)), expected);
+    assert_eq!(type_keys(&with_hrm_shift(HRM_J, COMMA_KEY)), expected);
+}
+
+#[test]
+fn other_keys_stay_shifted() {
+    let expected = [
+        (LSHIFT, vec![]),
+        (LSHIFT, vec![E]),
+        (LSHIFT, vec![]),
+        (0, vec![]),
+    ];
+    assert_eq!(type_keys(&with_hrm_shift(HRM_F, E_KEY)), expected);
+}
+
+#[test]
+fn without_shift_nothing_is_overridden() {
+    let steps = [tap(BACKSPACE_KEY), tap(COMMA_KEY)].concat();
+    let expected = [
+        (0, vec![BACKSPACE]),
+        (0, vec![]),
+        (0, vec![COMMA]),
+        (0, vec![]),
+    ];
+    assert_eq!(type_keys(&steps), expected);
+}
+
+#[test]
+fn one_shot_shift_and_backspace_is_delete() {
+    let steps = [tap(ONE_SHOT_SHIFT), tap(BACKSPACE_KEY), tap(BACKSPACE_KEY)].concat();
+    let expected = [
+        (LSHIFT, vec![]),
+        (0, vec![DELETE]),
+        (0, vec![]),
+        (0, vec![BACKSPACE]),
+        (0, vec![]),
+    ];
+    assert_eq!(type_keys(&steps), expected);
+}
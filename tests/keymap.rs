@@ -0,0 +1,133 @@
+//! The keymap has the size rmk is built with, every layer a key refers to
+//! exists and every layer can be reached from the base layer.
+
+use rmk::config::Hand;
+use rmk::types::action::{Action, KeyAction};
+use rmk_corne::keymap::{
+    COL, HANDS, LEADER_LAYER, LEADER_LAYER_KEY, NUM_LAYER, ONE_SHOT_KEYS, ROW, TAP_DANCES,
+    TRI_LAYER, UNWIRED_KEYS, VIAL_UNLOCK_KEYS, get_default_keymap,
+};
+use rmk_corne::leader::LEADER;
+use rmk_corne::one_shot::{self, OneShot};
+
+/// What a key does, with the taps and holds of a tap dance
+fn actions(key: &KeyAction) -> Vec<Action> {
+    match *key {
+        KeyAction::Single(action) | KeyAction::Tap(action) => vec![action],
+        KeyAction::TapHold(tap, hold, _) => vec![tap, hold],
+        KeyAction::Morse(i) => {
+            let dance = &TAP_DANCES[i as usize];
+            dance.taps.iter().chain(&dance.holds).copied().collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Layers a key turns on, toggles or switches to. The firmware holds the
+/// layer of a one-shot key and of the leader key.
+fn layer_targets(key: &KeyAction) -> Vec<u8> {
+    let mut targets: Vec<u8> = actions(key)
+        .into_iter()
+        .filter_map(|action| match action {
+            Action::LayerOn(layer)
+            | Action::LayerOnWithModifier(layer, _)
+            | Action::LayerOff(layer)
+            | Action::LayerToggle(layer)
+            | Action::LayerToggleOnly(layer)
+            | Action::DefaultLayer(layer)
+            | Action::OneShotLayer(layer) => Some(layer),
+            _ => None,
+        })
+        .collect();
+    if let Some(i) = one_shot::find(key)
+        && let OneShot::Layer(layer) = ONE_SHOT_KEYS[i].one_shot
+    {
+        targets.push(layer);
+    }
+    if *key == LEADER {
+        targets.push(LEADER_LAYER);
+    }
+    targets
+}
+
+/// The keys of `layer` that have a switch
+fn wired_keys(layer: &[[KeyAction; COL]; ROW]) -> impl Iterator<Item = &KeyAction> {
+    (0..ROW).flat_map(move |row| {
+        (0..COL)
+            .filter(move |&col| !UNWIRED_KEYS.contains(&(row as u8, col as u8)))
+            .map(move |col| &layer[row][col])
+    })
+}
+
+#[test]
+fn keymap_has_every_layer() {
+    let keymap = get_default_keymap();
+    assert_eq!(keymap.len(), NUM_LAYER);
+    for layer in &keymap {
+        assert_eq!(layer.len(), ROW);
+        assert!(layer.iter().all(|row| row.len() == COL));
+    }
+}
+
+#[test]
+fn halves_split_the_columns() {
+    for row in HANDS {
+        assert!(row[..COL / 2].iter().all(|&hand| hand == Hand::Left));
+        assert!(row[COL / 2..].iter().all(|&hand| hand == Hand::Right));
+    }
+}
+
+#[test]
+fn positions_are_in_the_matrix() {
+    let in_matrix = |(row, col): (u8, u8)| (row as usize) < ROW && (col as usize) < COL;
+    assert!(VIAL_UNLOCK_KEYS.into_iter().all(in_matrix));
+    assert!(UNWIRED_KEYS.into_iter().all(in_matrix));
+    assert!(ONE_SHOT_KEYS.iter().all(|key| in_matrix(key.hold)));
+    assert!(in_matrix(LEADER_LAYER_KEY));
+}
+
+#[test]
+fn layer_targets_are_in_range() {
+    for (layer, rows) in get_default_keymap().iter().enumerate() {
+        for (row, keys) in rows.iter().enumerate() {
+            for (col, key) in keys.iter().enumerate() {
+                for target in layer_targets(key) {
+                    assert!(
+                        (target as usize) < NUM_LAYER,
+                        "layer {layer} ({row}, {col}) refers to layer {target}"
+                    );
+                }
+            }
+        }
+    }
+    assert!(TRI_LAYER.iter().all(|&layer| (layer as usize) < NUM_LAYER));
+    assert!((LEADER_LAYER as usize) < NUM_LAYER);
+}
+
+#[test]
+fn every_layer_is_reachable() {
+    let keymap = get_default_keymap();
+    let mut reachable = [false; NUM_LAYER];
+    reachable[0] = true;
+    loop {
+        let mut found = reachable;
+        for layer in (0..NUM_LAYER).filter(|&layer| reachable[layer]) {
+            for target in wired_keys(&keymap[layer]).flat_map(layer_targets) {
+                found[target as usize] = true;
+            }
+        }
+        let [lower, upper, adjust] = TRI_LAYER.map(usize::from);
+        if found[lower] && found[upper] {
+            found[adjust] = true;
+        }
+        if found == reachable {
+            break;
+        }
+        reachable = found;
+    }
+    let unreachable: Vec<usize> = (0..NUM_LAYER).filter(|&layer| !reachable[layer]).collect();
+    assert!(
+        unreachable.is_empty(),
+        "unreachable layers: {unreachable:?}"
+    );
+}
@@ -0,0 +1,168 @@
+//! The layer lock key keeps the held num or nav layer on after its thumb key
+//! is released, until the lock key is pressed again or the thumb key is held
+//! and released again. A tapped thumb key types its key and keeps the lock.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run};
+use rmk::types::action::{Action, KeyAction};
+use rmk_corne::keymap::{LEADER_LAYER, LOCKABLE_LAYERS, behavior_config, get_default_keymap};
+use rmk_corne::layer_lock::{LAYER_LOCK, LayerLock};
+use rmk_corne::leader::layer_toggle_position;
+
+/// `kol!(Space, 1)` and `kol!(Enter, 2)`
+const NUM: (u8, u8) = (3, 5);
+const NAV: (u8, u8) = (3, 6);
+/// The lock key on num and nav
+const LOCK: (u8, u8) = (0, 0);
+/// H on the base layer and Left on the nav layer
+const LEFT_KEY: (u8, u8) = (1, 6);
+/// E on the base layer and [ on the num layer
+const BRACKET_KEY: (u8, u8) = (0, 3);
+
+const H: u8 = 0x0b;
+const E: u8 = 0x08;
+const LEFT: u8 = 0x50;
+const ENTER: u8 = 0x28;
+const LEFT_BRACKET: u8 = 0x2f;
+
+const NAV_LAYER: u8 = 2;
+
+/// Longer than the tapping term of the thumb keys, and than the idle time
+/// after which flow tap lets them be held
+const HOLD_MS: u64 = 250;
+
+fn type_keys(steps: &[Step]) -> Vec<KeyState> {
+    run(get_default_keymap(), behavior_config(), steps)
+}
+
+fn tap(pos: (u8, u8)) -> [Step; 2] {
+    [press(pos, HOLD_MS), release(pos, 20)]
+}
+
+/// Holds the thumb key at `thumb`, locks its layer and releases it
+fn lock(thumb: (u8, u8)) -> Vec<Step> {
+    [
+        vec![press(thumb, HOLD_MS)],
+        tap(LOCK).into(),
+        vec![release(thumb, 20)],
+    ]
+    .concat()
+}
+
+fn typed(keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(0, vec![key]), (0, vec![])])
+        .collect()
+}
+
+#[test]
+fn the_lock_key_is_on_num_and_nav() {
+    let keymap = get_default_keymap();
+    for layer in LOCKABLE_LAYERS {
+        assert_eq!(
+            keymap[layer as usize][LOCK.0 as usize][LOCK.1 as usize],
+            LAYER_LOCK
+        );
+    }
+    let leader_layer = keymap[LEADER_LAYER as usize];
+    for (i, layer) in LOCKABLE_LAYERS.into_iter().enumerate() {
+        let (row, col) = layer_toggle_position(i);
+        assert_eq!(
+            leader_layer[row as usize][col as usize],
+            KeyAction::Single(Action::LayerToggle(layer))
+        );
+    }
+}
+
+#[test]
+fn the_lock_follows_rmks_layers() {
+    let mut lock = LayerLock::new();
+    // Nothing is held on the base layer
+    lock.lock_key(0);
+    assert_eq!(lock.locked(), None);
+
+    lock.lock_key(NAV_LAYER);
+    assert_eq!(lock.locked(), Some(NAV_LAYER));
+    assert_eq!(lock.take_toggle(), None);
+    // rmk turned nav off with the release of its key, it goes back on
+    lock.layer(0);
+    assert_eq!(lock.take_toggle(), Some(NAV_LAYER));
+    lock.layer(NAV_LAYER);
+    // Adjust over nav and back
+    lock.layer(5);
+    lock.layer(NAV_LAYER);
+    assert_eq!(lock.locked(), Some(NAV_LAYER));
+    assert_eq!(lock.take_toggle(), None);
+
+    // The lock key turns it off
+    lock.lock_key(NAV_LAYER);
+    assert_eq!(lock.locked(), None);
+    assert_eq!(lock.take_toggle(), Some(NAV_LAYER));
+
+    // rmk turning it off once more means its key was held and released
+    lock.lock_key(NAV_LAYER);
+    lock.layer(0);
+    lock.take_toggle();
+    lock.layer(0);
+    assert_eq!(lock.locked(), None);
+    assert_eq!(lock.take_toggle(), None);
+
+    // Pressed again while its key is still down, the release turns it off
+    lock.lock_key(NAV_LAYER);
+    lock.lock_key(NAV_LAYER);
+    lock.layer(0);
+    assert_eq!(lock.locked(), None);
+    assert_eq!(lock.take_toggle(), None);
+}
+
+#[test]
+fn the_lock_key_locks_and_unlocks_nav() {
+    let steps = [
+        lock(NAV),
+        tap(LEFT_KEY).into(),
+        tap(LOCK).into(),
+        tap(LEFT_KEY).into(),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[LEFT, H]));
+}
+
+#[test]
+fn the_lock_key_locks_num() {
+    let steps = [lock(NUM), tap(BRACKET_KEY).into(), tap(BRACKET_KEY).into()].concat();
+    assert_eq!(type_keys(&steps), typed(&[LEFT_BRACKET, LEFT_BRACKET]));
+
+    // Pressed twice while the thumb key is held, nothing is locked
+    let steps = [
+        vec![press(NUM, HOLD_MS)],
+        tap(LOCK).into(),
+        tap(LOCK).into(),
+        vec![release(NUM, 20)],
+        tap(BRACKET_KEY).into(),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[E]));
+}
+
+#[test]
+fn holding_the_thumb_key_again_unlocks() {
+    let steps = [
+        lock(NAV),
+        vec![press(NAV, HOLD_MS), release(NAV, HOLD_MS)],
+        tap(LEFT_KEY).into(),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[H]));
+}
+
+#[test]
+fn tapping_the_thumb_key_types_and_keeps_the_lock() {
+    let steps = [
+        lock(NAV),
+        vec![press(NAV, HOLD_MS), release(NAV, 50)],
+        tap(LEFT_KEY).into(),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[ENTER, LEFT]));
+}
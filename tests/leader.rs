@@ -0,0 +1,223 @@
+//! Leader sequences: the trie rejects sequences that start with another one,
+//! the keys of a sequence never reach the host, and a complete sequence runs
+//! its key through rmk: a macro, a layer toggle or a system key.
+
+mod common;
+
+use std::cell::RefCell;
+use std::panic::catch_unwind;
+
+use common::keyboard::{KeyState, Step, press, release, run, run_with};
+use rmk::channel::CONTROLLER_CHANNEL;
+use rmk::event::{ControllerEvent, KeyboardEvent};
+use rmk::types::action::KeyAction;
+use rmk::types::keycode::KeyCode;
+use rmk::{k, tg};
+use rmk_corne::keymap::{
+    LEADER_LAYER, LEADER_SEQUENCES, LEADER_TIMEOUT_MS, behavior_config, get_default_keymap,
+};
+use rmk_corne::leader::{
+    LEADER, LEADER_TRIE, LeaderSequence, LeaderStep, LeaderTrie, sequence_position,
+};
+
+const LEADER_POS: (u8, u8) = (1, 0);
+/// On the base layer, `hrm!` for S, L and D
+const B: (u8, u8) = (2, 5);
+const C: (u8, u8) = (2, 3);
+const D: (u8, u8) = (1, 3);
+const G: (u8, u8) = (1, 5);
+const L: (u8, u8) = (1, 9);
+const M: (u8, u8) = (2, 7);
+const O: (u8, u8) = (0, 9);
+const R: (u8, u8) = (0, 4);
+const S: (u8, u8) = (1, 2);
+const T: (u8, u8) = (0, 5);
+const X: (u8, u8) = (2, 2);
+/// `tg!(6)` on the mouse layer
+const MOUSE_TOGGLE: (u8, u8) = (2, 5);
+/// E on the base layer, nothing on the mouse layer
+const E: (u8, u8) = (0, 3);
+
+const KEY_E: u8 = 0x08;
+const KEY_S: u8 = 0x16;
+const KEY_X: u8 = 0x1b;
+
+fn type_keys(steps: &[Step]) -> Vec<KeyState> {
+    run(get_default_keymap(), behavior_config(), steps)
+}
+
+/// Types `steps`, returns the keys the host saw and the keys rmk ran on
+/// the leader layer
+fn type_keys_and_leader_keys(steps: &[Step]) -> (Vec<KeyState>, Vec<KeyAction>) {
+    let keys = RefCell::new(vec![]);
+    let pressed_keys: Vec<KeyboardEvent> = (0..LEADER_SEQUENCES.len())
+        .map(|i| {
+            let (row, col) = sequence_position(i);
+            KeyboardEvent::key(row, col, true)
+        })
+        .collect();
+    let states = run_with(get_default_keymap(), behavior_config(), steps, async {
+        let mut sub = CONTROLLER_CHANNEL.subscriber().unwrap();
+        let mut layer = 0;
+        loop {
+            match sub.next_message_pure().await {
+                ControllerEvent::Layer(l) => layer = l,
+                ControllerEvent::Key(event, action)
+                    if layer == LEADER_LAYER && pressed_keys.contains(&event) =>
+                {
+                    keys.borrow_mut().push(action)
+                }
+                _ => (),
+            }
+        }
+    });
+    (states, keys.into_inner())
+}
+
+fn tap(pos: (u8, u8)) -> [Step; 2] {
+    [press(pos, 30), release(pos, 20)]
+}
+
+/// The leader key followed by `keys`
+fn sequence(keys: &[(u8, u8)]) -> Vec<Step> {
+    [LEADER_POS]
+        .iter()
+        .chain(keys)
+        .flat_map(|&pos| tap(pos))
+        .collect()
+}
+
+fn typed(keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(0, vec![key]), (0, vec![])])
+        .collect()
+}
+
+/// The keycodes of `text`, lowercase letters, space and newline only
+fn text(text: &str) -> Vec<u8> {
+    text.bytes()
+        .map(|c| match c {
+            b'a'..=b'z' => 0x04 + c - b'a',
+            b' ' => 0x2c,
+            b'\n' => 0x28,
+            _ => panic!("{c}"),
+        })
+        .collect()
+}
+
+#[test]
+fn the_trie_follows_the_sequences() {
+    let step = |keys: &[KeyCode]| {
+        keys.iter()
+            .fold(LeaderStep::Pending(0), |step, &key| match step {
+                LeaderStep::Pending(node) => LEADER_TRIE.step(node, key),
+                step => step,
+            })
+    };
+    for (i, sequence) in LEADER_SEQUENCES.iter().enumerate() {
+        assert_eq!(step(sequence.keys), LeaderStep::Done(i));
+        for len in 1..sequence.keys.len() {
+            assert!(matches!(
+                step(&sequence.keys[..len]),
+                LeaderStep::Pending(_)
+            ));
+        }
+    }
+    assert_eq!(step(&[KeyCode::X]), LeaderStep::NoMatch);
+    assert_eq!(step(&[KeyCode::G, KeyCode::X]), LeaderStep::NoMatch);
+}
+
+#[test]
+fn a_sequence_that_starts_another_one_is_rejected() {
+    const ACTION: KeyAction = k!(A);
+    let g = LeaderSequence::new(&[KeyCode::G], ACTION);
+    let gs = LeaderSequence::new(&[KeyCode::G, KeyCode::S], ACTION);
+    assert!(catch_unwind(|| LeaderTrie::<4>::new(&[g, gs])).is_err());
+    assert!(catch_unwind(|| LeaderTrie::<4>::new(&[gs, g])).is_err());
+    assert!(catch_unwind(|| LeaderTrie::<4>::new(&[g, g])).is_err());
+    let empty = LeaderSequence::new(&[], ACTION);
+    assert!(catch_unwind(|| LeaderTrie::<4>::new(&[empty])).is_err());
+    let gd = LeaderSequence::new(&[KeyCode::G, KeyCode::D], ACTION);
+    assert!(catch_unwind(|| LeaderTrie::<4>::new(&[gs, gd])).is_ok());
+}
+
+#[test]
+fn the_leader_layer_has_the_keys_of_the_sequences() {
+    let keymap = get_default_keymap();
+    assert_eq!(
+        keymap[0][LEADER_POS.0 as usize][LEADER_POS.1 as usize],
+        LEADER
+    );
+    let layer = keymap[LEADER_LAYER as usize];
+    for (i, sequence) in LEADER_SEQUENCES.iter().enumerate() {
+        let (row, col) = sequence_position(i);
+        assert_eq!(layer[row as usize][col as usize], sequence.action);
+    }
+}
+
+#[test]
+fn sequences_type_their_macro() {
+    assert_eq!(type_keys(&sequence(&[G, S])), typed(&text("git status\n")));
+    assert_eq!(type_keys(&sequence(&[G, D])), typed(&text("git diff\n")));
+}
+
+#[test]
+fn m_toggles_the_mouse_layer() {
+    let steps = [sequence(&[M]), tap(E).into()].concat();
+    assert_eq!(type_keys(&steps), []);
+
+    // The mouse layer has no leader key, but its own toggle
+    let steps = [sequence(&[M]), tap(MOUSE_TOGGLE).into(), tap(E).into()].concat();
+    assert_eq!(type_keys(&steps), typed(&[KEY_E]));
+
+    let (_, keys) = type_keys_and_leader_keys(&sequence(&[M]));
+    assert_eq!(keys, [tg!(6)]);
+}
+
+#[test]
+fn system_sequences_run_rmks_keys() {
+    let (states, keys) = type_keys_and_leader_keys(&sequence(&[B, O, O, T]));
+    assert_eq!(states, []);
+    assert_eq!(keys, [k!(Bootloader)]);
+
+    let (states, keys) = type_keys_and_leader_keys(&sequence(&[C, L, R]));
+    assert_eq!(states, []);
+    assert_eq!(keys, [k!(User5)]);
+}
+
+#[test]
+fn a_wrong_key_ends_the_sequence_without_typing() {
+    let steps = [sequence(&[G, X]), tap(X).into()].concat();
+    let (states, keys) = type_keys_and_leader_keys(&steps);
+    assert_eq!(states, typed(&[KEY_X]));
+    assert_eq!(keys, []);
+}
+
+#[test]
+fn a_sequence_times_out() {
+    let steps = [
+        sequence(&[G]),
+        vec![press(S, LEADER_TIMEOUT_MS + 100), release(S, 20)],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[KEY_S]));
+}
+
+#[test]
+fn keys_held_into_a_sequence_are_released() {
+    // E goes down before the leader key and comes up during the sequence
+    let steps = [
+        vec![press(E, 30)],
+        tap(LEADER_POS).into(),
+        vec![release(E, 20)],
+        tap(G).into(),
+        tap(D).into(),
+    ]
+    .concat();
+    let expected = [
+        vec![(0, vec![KEY_E]), (0, vec![])],
+        typed(&text("git diff\n")),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+}
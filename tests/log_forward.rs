@@ -0,0 +1,202 @@
+//! The halves send their defmt log to the dongle in custom events, the dongle
+//! puts the frames back together and writes them to its USB log, tagged with
+//! the half.
+
+use std::sync::Mutex;
+
+use embassy_time::{Duration, Timer, with_timeout};
+use rmk::channel::EVENT_CHANNEL;
+use rmk::embassy_futures::block_on;
+use rmk::embassy_futures::select::select;
+use rmk::input_device::InputDevice;
+use rmk_corne::log_forward::{Joined, LogJoiner, LogReporter, MAX_FRAME_LEN, capture};
+use rmk_corne::split::{self, HalfMessage, LOG_CHUNK_LEN};
+
+/// Sends `bytes` from the half `half` as [`LogReporter`] does, from `seq` on
+fn messages(half: u8, seq: u8, bytes: &[u8]) -> Vec<HalfMessage> {
+    bytes
+        .chunks(LOG_CHUNK_LEN)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut data = [0; LOG_CHUNK_LEN];
+            data[..chunk.len()].copy_from_slice(chunk);
+            HalfMessage::Log {
+                half,
+                seq: seq.wrapping_add(i as u8),
+                len: chunk.len() as u8,
+                data,
+            }
+        })
+        .collect()
+}
+
+/// What the joiner gets out of `messages`, frames as vectors
+fn join(joiner: &mut LogJoiner, messages: &[HalfMessage]) -> Vec<Result<Vec<u8>, Joined<'static>>> {
+    let mut joined = vec![];
+    for message in messages {
+        let HalfMessage::Log { seq, len, data, .. } = *message else {
+            panic!("not a log message");
+        };
+        joiner.receive(seq, &data[..len as usize], |frame| {
+            joined.push(match frame {
+                Joined::Frame(frame) => Ok(frame.to_vec()),
+                Joined::Lost(count) => Err(Joined::Lost(count)),
+                Joined::TooLong => Err(Joined::TooLong),
+            })
+        });
+    }
+    joined
+}
+
+#[test]
+fn the_reporter_sends_what_the_half_logged_in_order() {
+    let log: Vec<u8> = (1..=30).collect();
+    capture(&log);
+    let mut reporter = LogReporter::new(1);
+    let mut sent = vec![];
+    block_on(async {
+        while sent.len() < log.len() {
+            let event = reporter.read_event().await;
+            let Some(HalfMessage::Log {
+                half,
+                seq,
+                len,
+                data,
+            }) = HalfMessage::from_event(&event)
+            else {
+                panic!("not a log message");
+            };
+            assert_eq!(half, 1);
+            assert_eq!(seq as usize, sent.len() / LOG_CHUNK_LEN);
+            sent.extend_from_slice(&data[..len as usize]);
+        }
+    });
+    assert_eq!(sent, log);
+}
+
+#[test]
+fn log_messages_survive_the_custom_event() {
+    let message = messages(0, 7, b"hello")[0];
+    assert_eq!(HalfMessage::from_event(&message.to_event()), Some(message));
+
+    let mut data = [0; 16];
+    data[..4].copy_from_slice(&[0x02, 0, 0, LOG_CHUNK_LEN as u8 + 1]);
+    assert_eq!(
+        HalfMessage::from_event(&rmk::event::Event::Custom(data)),
+        None
+    );
+}
+
+#[test]
+fn frames_are_joined_across_messages() {
+    let mut log = vec![];
+    log.extend_from_slice(&[5; 20]);
+    log.push(0);
+    log.extend_from_slice(&[6, 0]);
+    log.extend_from_slice(&[7; 3]);
+    let mut joiner = LogJoiner::new();
+    let mut frame = vec![5; 20];
+    frame.push(0);
+    assert_eq!(
+        join(&mut joiner, &messages(0, 0, &log)),
+        [Ok(frame), Ok(vec![6, 0])]
+    );
+    // The unfinished frame goes on in the next message
+    assert_eq!(
+        join(&mut joiner, &messages(0, 3, &[7, 0])),
+        [Ok(vec![7, 7, 7, 7, 0])]
+    );
+}
+
+#[test]
+fn lost_messages_skip_to_the_next_frame() {
+    let mut joiner = LogJoiner::new();
+    assert_eq!(join(&mut joiner, &messages(1, 250, &[1, 2])), []);
+    // 251 and 252 went missing, the bytes up to the next zero are the end of
+    // a frame that can't be decoded
+    let after_gap = messages(1, 253, &[3, 4, 0, 8, 0]);
+    assert_eq!(
+        join(&mut joiner, &after_gap),
+        [Err(Joined::Lost(2)), Ok(vec![8, 0])]
+    );
+    // The sequence wraps around
+    assert_eq!(
+        join(&mut joiner, &messages(1, 254, &[9, 0])),
+        [Ok(vec![9, 0])]
+    );
+    assert_eq!(
+        join(
+            &mut joiner,
+            &messages(1, 255, &[0])
+                .into_iter()
+                .chain(messages(1, 0, &[1, 0]))
+                .collect::<Vec<_>>()
+        ),
+        [Ok(vec![0]), Ok(vec![1, 0])]
+    );
+}
+
+#[test]
+fn frames_too_long_are_dropped() {
+    let mut log = vec![1; MAX_FRAME_LEN + 10];
+    log.extend_from_slice(&[0, 2, 0]);
+    let mut joiner = LogJoiner::new();
+    assert_eq!(
+        join(&mut joiner, &messages(0, 0, &log)),
+        [Err(Joined::TooLong), Ok(vec![2, 0])]
+    );
+}
+
+/// The log lines written so far
+static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOG.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn the_dongle_writes_the_frames_to_its_usb_log() {
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let mut left = vec![0xab; 14];
+    left.push(0);
+    let right = [0x01, 0xff, 0x00];
+    let written = block_on(with_timeout(
+        Duration::from_secs(5),
+        select(split::receive_half_messages(), async {
+            for message in messages(0, 0, &left)
+                .into_iter()
+                .chain(messages(1, 0, &right))
+            {
+                EVENT_CHANNEL.send(message.to_event()).await;
+            }
+            // The left half lost a message
+            for message in messages(0, 3, &[0x02, 0x00]) {
+                EVENT_CHANNEL.send(message.to_event()).await;
+            }
+            while LOG.lock().unwrap().len() < 3 {
+                Timer::after_millis(1).await;
+            }
+        }),
+    ));
+    assert!(written.is_ok());
+    assert_eq!(
+        *LOG.lock().unwrap(),
+        [
+            format!("left defmt: {}00", "ab".repeat(14)),
+            "right defmt: 01ff00".to_string(),
+            "left log: lost 1 messages".to_string(),
+        ]
+    );
+}
@@ -0,0 +1,150 @@
+//! The media layer sends consumer and system control reports: the usages of
+//! its keys, the report descriptors the host parses them with, and the bytes
+//! rmk sends for each key.
+
+mod common;
+
+use common::keyboard::{press, release, run_reports};
+use rmk::hid::Report;
+use rmk::types::action::{Action, KeyAction};
+use rmk::types::keycode::KeyCode;
+use rmk_corne::keymap::{behavior_config, get_default_keymap};
+use usbd_hid::descriptor::{MediaKeyboardReport, SerializedDescriptor, SystemControlReport};
+
+/// `kol!(Enter, 2)`, and `mo!(7)` on the nav layer
+const NAV: (u8, u8) = (3, 6);
+const MEDIA: (u8, u8) = (0, 5);
+const MEDIA_LAYER: usize = 7;
+
+/// Keys of the media layer and their usages of the HID usage tables,
+/// consumer page (0x0C)
+const CONSUMER: [((u8, u8), KeyCode, u16); 8] = [
+    ((0, 6), KeyCode::MediaPrevTrack, 0xb6),
+    ((0, 7), KeyCode::AudioVolDown, 0xea),
+    ((0, 8), KeyCode::AudioVolUp, 0xe9),
+    ((0, 9), KeyCode::MediaNextTrack, 0xb5),
+    ((1, 6), KeyCode::MediaPlayPause, 0xcd),
+    ((1, 7), KeyCode::BrightnessDown, 0x70),
+    ((1, 8), KeyCode::BrightnessUp, 0x6f),
+    ((1, 9), KeyCode::AudioMute, 0xe2),
+];
+
+/// Generic desktop page (0x01)
+const SYSTEM: [((u8, u8), KeyCode, u8); 3] = [
+    ((2, 6), KeyCode::SystemSleep, 0x82),
+    ((2, 7), KeyCode::SystemWake, 0x83),
+    ((2, 11), KeyCode::SystemPower, 0x81),
+];
+
+/// Longer than the tapping term of the thumb keys, and than the idle time
+/// after which flow tap lets them be held
+const HOLD_MS: u64 = 250;
+
+fn payload<T: serde::Serialize>(report: &T) -> Vec<u8> {
+    let mut buf = [0; 8];
+    let len = ssmarshal::serialize(&mut buf, report).unwrap();
+    buf[..len].to_vec()
+}
+
+/// Whether `descriptor` has the items in `items` in this order
+fn has_items(descriptor: &[u8], items: &[u8]) -> bool {
+    descriptor
+        .windows(items.len())
+        .any(|window| window == items)
+}
+
+/// Taps `keys` on the media layer, returns the payloads of the consumer and
+/// system control reports
+fn tap_media_keys(keys: &[(u8, u8)]) -> Vec<Vec<u8>> {
+    let mut steps = vec![press(NAV, HOLD_MS), press(MEDIA, HOLD_MS)];
+    for &key in keys {
+        steps.extend([press(key, 50), release(key, 20)]);
+    }
+    steps.extend([release(MEDIA, 20), release(NAV, 20)]);
+    let (typed, reports) = run_reports(
+        get_default_keymap(),
+        behavior_config(),
+        &steps,
+        std::future::pending(),
+    );
+    assert_eq!(typed, []);
+    reports
+        .iter()
+        .map(|report| match report {
+            Report::MediaKeyboardReport(report) => payload(report),
+            Report::SystemControlReport(report) => payload(report),
+            report => panic!("{report:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn the_media_layer_has_every_usage() {
+    let layer = get_default_keymap()[MEDIA_LAYER];
+    let keys: Vec<KeyCode> = layer
+        .iter()
+        .flatten()
+        .filter_map(|key| match *key {
+            KeyAction::Single(Action::Key(keycode))
+                if keycode.is_consumer() || keycode.is_system() =>
+            {
+                Some(keycode)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(keys.len(), CONSUMER.len() + SYSTEM.len());
+    for ((row, col), keycode, _) in CONSUMER {
+        assert_eq!(
+            layer[row as usize][col as usize],
+            KeyAction::Single(Action::Key(keycode))
+        );
+    }
+    for ((row, col), keycode, _) in SYSTEM {
+        assert_eq!(
+            layer[row as usize][col as usize],
+            KeyAction::Single(Action::Key(keycode))
+        );
+    }
+}
+
+#[test]
+fn consumer_report_descriptor() {
+    let descriptor = MediaKeyboardReport::desc();
+    // Usage Page (Consumer), Usage (Consumer Control), Collection (Application)
+    assert!(has_items(descriptor, &[0x05, 0x0c, 0x09, 0x01, 0xa1, 0x01]));
+    // One 16 bit usage per report
+    assert!(has_items(descriptor, &[0x75, 0x10]));
+    assert!(has_items(descriptor, &[0x95, 0x01]));
+}
+
+#[test]
+fn system_report_descriptor() {
+    let descriptor = SystemControlReport::desc();
+    // Usage Page (Generic Desktop), Usage (System Control), Collection
+    // (Application)
+    assert!(has_items(descriptor, &[0x05, 0x01, 0x09, 0x80, 0xa1, 0x01]));
+    // Power down to wake up are in the usage range
+    assert!(has_items(descriptor, &[0x19, 0x81]));
+}
+
+#[test]
+fn consumer_reports() {
+    let keys: Vec<(u8, u8)> = CONSUMER.iter().map(|&(pos, ..)| pos).collect();
+    // The usage while the key is down, no usage once it is released
+    let expected: Vec<Vec<u8>> = CONSUMER
+        .iter()
+        .flat_map(|&(_, _, usage)| [usage.to_le_bytes().to_vec(), vec![0, 0]])
+        .collect();
+    assert_eq!(tap_media_keys(&keys), expected);
+}
+
+#[test]
+fn system_reports() {
+    let keys: Vec<(u8, u8)> = SYSTEM.iter().map(|&(pos, ..)| pos).collect();
+    let expected: Vec<Vec<u8>> = SYSTEM
+        .iter()
+        .flat_map(|&(_, _, usage)| [vec![usage], vec![0]])
+        .collect();
+    assert_eq!(tap_media_keys(&keys), expected);
+}
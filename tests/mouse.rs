@@ -0,0 +1,218 @@
+//! The acceleration curves of the mouse keys on their own, and moving,
+//! scrolling and clicking on the mouse layer of the keymap.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run_reports};
+use rmk::controller::EventController;
+use rmk::hid::Report;
+use rmk::types::keycode::KeyCode;
+use rmk_corne::keymap::{
+    MOUSE_MOVE_CURVE, MOUSE_PRECISION_CURVE, MOUSE_WHEEL_CURVE, behavior_config, get_default_keymap,
+};
+use rmk_corne::mouse::{MouseCurve, MouseKey, MouseKeyController};
+
+/// `kol!(Enter, 2)`, and `tg!(6)` on the nav and the mouse layer
+const NAV: (u8, u8) = (3, 6);
+const TOGGLE: (u8, u8) = (2, 5);
+/// On the mouse layer
+const RIGHT: (u8, u8) = (1, 9);
+const UP: (u8, u8) = (1, 8);
+const WHEEL_DOWN: (u8, u8) = (0, 7);
+const BUTTON1: (u8, u8) = (3, 5);
+const PRECISION: (u8, u8) = (2, 4);
+/// E on the base layer, nothing on the mouse layer
+const KEY: (u8, u8) = (0, 3);
+
+const E: u8 = 0x08;
+
+/// Longer than the tapping term of the thumb keys, and than the idle time
+/// after which flow tap lets them be held
+const HOLD_MS: u64 = 250;
+
+/// Buttons, x, y, wheel and pan of a mouse report
+type MouseState = (u8, i8, i8, i8, i8);
+
+fn mouse_keys(steps: &[Step]) -> (Vec<KeyState>, Vec<MouseState>) {
+    let (keys, reports) = run_reports(get_default_keymap(), behavior_config(), steps, async {
+        MouseKeyController::new().event_loop().await
+    });
+    let mouse = reports
+        .into_iter()
+        .filter_map(|report| match report {
+            Report::MouseReport(r) => Some((r.buttons, r.x, r.y, r.wheel, r.pan)),
+            _ => None,
+        })
+        .collect();
+    (keys, mouse)
+}
+
+/// Toggles the mouse layer from the nav layer
+fn toggle() -> Vec<Step> {
+    vec![
+        press(NAV, HOLD_MS),
+        press(TOGGLE, HOLD_MS),
+        release(TOGGLE, 20),
+        release(NAV, 20),
+    ]
+}
+
+fn tap(pos: (u8, u8)) -> [Step; 2] {
+    [press(pos, 50), release(pos, 20)]
+}
+
+#[test]
+fn constant_curve_never_changes() {
+    let curve = MouseCurve::Constant { speed: 3 };
+    assert_eq!(curve.step(0), 3);
+    assert_eq!(curve.step(10_000), 3);
+}
+
+#[test]
+fn accelerated_curve_ramps_up_to_max() {
+    let curve = MouseCurve::Accelerated {
+        start: 2,
+        max: 22,
+        ramp_ms: 1000,
+        exponent: 1,
+    };
+    assert_eq!(curve.step(0), 2);
+    assert_eq!(curve.step(500), 12);
+    assert_eq!(curve.step(1000), 22);
+    assert_eq!(curve.step(u64::MAX), 22);
+}
+
+#[test]
+fn higher_exponent_stays_slow_for_longer() {
+    let linear = MouseCurve::Accelerated {
+        start: 2,
+        max: 22,
+        ramp_ms: 1000,
+        exponent: 1,
+    };
+    let squared = MouseCurve::Accelerated {
+        start: 2,
+        max: 22,
+        ramp_ms: 1000,
+        exponent: 2,
+    };
+    assert_eq!(squared.step(500), 7);
+    assert!(squared.step(500) < linear.step(500));
+    assert_eq!(squared.step(1000), linear.step(1000));
+}
+
+#[test]
+fn long_ramps_and_large_exponents_dont_overflow() {
+    let curve = MouseCurve::Accelerated {
+        start: 1,
+        max: 127,
+        ramp_ms: u32::MAX,
+        exponent: u8::MAX,
+    };
+    assert_eq!(curve.step(0), 1);
+    assert_eq!(curve.step(u32::MAX as u64 - 1), 126);
+    assert_eq!(curve.step(u32::MAX as u64), 127);
+}
+
+#[test]
+fn curves_that_stall_or_overflow_a_report_are_invalid() {
+    assert!(!MouseCurve::Constant { speed: 0 }.is_valid());
+    assert!(!MouseCurve::Constant { speed: 128 }.is_valid());
+    let ramp = |start, max| MouseCurve::Accelerated {
+        start,
+        max,
+        ramp_ms: 1000,
+        exponent: 2,
+    };
+    assert!(ramp(1, 127).is_valid());
+    assert!(!ramp(0, 10).is_valid());
+    assert!(!ramp(10, 2).is_valid());
+    assert!(!ramp(1, 200).is_valid());
+}
+
+#[test]
+fn configured_curves_are_valid_and_speed_up() {
+    for curve in [MOUSE_MOVE_CURVE, MOUSE_PRECISION_CURVE, MOUSE_WHEEL_CURVE] {
+        assert!(curve.is_valid(), "{curve:?}");
+        for held_ms in (16..5000).step_by(16) {
+            assert!(curve.step(held_ms) >= curve.step(held_ms - 16), "{curve:?}");
+        }
+    }
+}
+
+#[test]
+fn mouse_keys_round_trip_through_keycodes() {
+    for key in MouseKey::ALL {
+        assert_eq!(MouseKey::from_keycode(key.keycode()), Some(key));
+    }
+    assert_eq!(MouseKey::from_keycode(KeyCode::A), None);
+    assert_eq!(MouseKey::from_keycode(KeyCode::User0), None);
+}
+
+#[test]
+fn buttons_click() {
+    let steps = [toggle(), tap(BUTTON1).into()].concat();
+    let (keys, mouse) = mouse_keys(&steps);
+    assert_eq!(keys, []);
+    assert_eq!(mouse, [(1, 0, 0, 0, 0), (0, 0, 0, 0, 0)]);
+}
+
+#[test]
+fn held_keys_move_faster_and_faster() {
+    let steps = [toggle(), vec![press(RIGHT, 50), release(RIGHT, 500)]].concat();
+    let (_, mouse) = mouse_keys(&steps);
+    assert!(mouse.len() > 20, "{mouse:?}");
+    assert_eq!(mouse[0], (0, MOUSE_MOVE_CURVE.step(0) as i8, 0, 0, 0));
+    for pair in mouse.windows(2) {
+        let ((_, a, ..), (_, b, ..)) = (pair[0], pair[1]);
+        assert!(b >= a, "{mouse:?}");
+    }
+    assert!(mouse.last().unwrap().1 > mouse[0].1);
+
+    // Diagonally with two keys
+    let steps = [
+        toggle(),
+        vec![press(RIGHT, 50), press(UP, 0)],
+        vec![release(RIGHT, 200), release(UP, 0)],
+    ]
+    .concat();
+    let (_, mouse) = mouse_keys(&steps);
+    assert!(mouse.iter().any(|&(_, x, y, ..)| x > 0 && y == -x));
+}
+
+#[test]
+fn precision_moves_at_a_constant_speed() {
+    let steps = [
+        toggle(),
+        tap(PRECISION).into(),
+        vec![press(RIGHT, 50), release(RIGHT, 500)],
+    ]
+    .concat();
+    let (_, mouse) = mouse_keys(&steps);
+    assert!(mouse.len() > 20);
+    let speed = MOUSE_PRECISION_CURVE.step(0) as i8;
+    assert!(mouse.iter().all(|&report| report == (0, speed, 0, 0, 0)));
+}
+
+#[test]
+fn the_wheel_scrolls_every_interval() {
+    let steps = [
+        toggle(),
+        vec![press(WHEEL_DOWN, 50), release(WHEEL_DOWN, 200)],
+    ]
+    .concat();
+    let (_, mouse) = mouse_keys(&steps);
+    // At 0, 80 and 160 ms
+    assert_eq!(mouse.len(), 3, "{mouse:?}");
+    assert!(mouse.iter().all(|&(_, x, y, wheel, pan)| {
+        x == 0 && y == 0 && pan == 0 && wheel == -(MOUSE_WHEEL_CURVE.step(0) as i8)
+    }));
+}
+
+#[test]
+fn toggled_off_the_keys_type_again() {
+    let steps = [toggle(), tap(KEY).into(), toggle(), tap(KEY).into()].concat();
+    let (keys, mouse) = mouse_keys(&steps);
+    assert_eq!(keys, [(0, vec![E]), (0, vec![])]);
+    assert_eq!(mouse, []);
+}
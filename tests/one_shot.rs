@@ -0,0 +1,299 @@
+//! One-shot modifiers and layers: the state of the keys on its own, and
+//! typing with them on the keymap, stacked, locked and next to the `hrm!`
+//! tap-holds.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run};
+use rmk_corne::keymap::{ONE_SHOT, behavior_config, get_default_keymap};
+use rmk_corne::one_shot::{DoubleTap, OneShotConfig, OneShots};
+
+/// `kol!(Enter, 2)`, the one-shot modifiers are on the nav layer
+const NAV: (u8, u8) = (3, 6);
+/// On the nav layer
+const SHIFT: (u8, u8) = (1, 4);
+const CTRL: (u8, u8) = (1, 3);
+/// One-shot num layer on the base layer
+const NUM: (u8, u8) = (2, 0);
+/// E, [ on num
+const KEY: (u8, u8) = (0, 3);
+/// `hrm!(A, LALT)` and `hrm!(D, LCTRL)`
+const HRM_A: (u8, u8) = (1, 1);
+const HRM_D: (u8, u8) = (1, 3);
+
+const E: u8 = 0x08;
+const A: u8 = 0x04;
+const LEFT_BRACKET: u8 = 0x2f;
+
+const LCTRL: u8 = 0x01;
+const LSHIFT: u8 = 0x02;
+
+/// Longer than the tapping term, and than the idle time after which flow
+/// tap lets a tap-hold key be held
+const HOLD_MS: u64 = 250;
+
+const CONFIG: OneShotConfig = OneShotConfig {
+    timeout_ms: 1000,
+    double_tap: DoubleTap::Lock,
+};
+
+fn type_keys(steps: &[Step]) -> Vec<KeyState> {
+    run(get_default_keymap(), behavior_config(), steps)
+}
+
+fn tap(pos: (u8, u8)) -> [Step; 2] {
+    [press(pos, 50), release(pos, 20)]
+}
+
+/// Taps the one-shot keys at `keys` on the nav layer
+fn on_nav(keys: &[(u8, u8)]) -> Vec<Step> {
+    let mut steps = vec![press(NAV, HOLD_MS)];
+    for (i, &key) in keys.iter().enumerate() {
+        let after_ms = if i == 0 { HOLD_MS } else { 50 };
+        steps.extend([press(key, after_ms), release(key, 20)]);
+    }
+    steps.push(release(NAV, 20));
+    steps
+}
+
+fn typed(modifiers: u8, keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(modifiers, vec![key]), (modifiers, vec![])])
+        .collect()
+}
+
+#[test]
+fn a_tap_is_on_for_the_next_key() {
+    let mut keys = OneShots::<2>::new(CONFIG);
+    keys.press(0);
+    assert!(keys.is_on(0));
+    keys.release(0, 0);
+    assert!(keys.is_on(0));
+    assert_eq!(keys.deadline_ms(), Some(1000));
+    keys.use_up();
+    assert!(!keys.is_on(0));
+    assert_eq!(keys.deadline_ms(), None);
+}
+
+#[test]
+fn a_tap_times_out() {
+    let mut keys = OneShots::<2>::new(CONFIG);
+    keys.press(0);
+    keys.release(0, 100);
+    keys.time_out(1099);
+    assert!(keys.is_on(0));
+    keys.time_out(1100);
+    assert!(!keys.is_on(0));
+}
+
+#[test]
+fn a_held_key_stays_on_for_every_key() {
+    let mut keys = OneShots::<2>::new(CONFIG);
+    keys.press(0);
+    keys.use_up();
+    keys.use_up();
+    assert!(keys.is_on(0));
+    keys.release(0, 0);
+    assert!(!keys.is_on(0));
+}
+
+#[test]
+fn taps_stack() {
+    let mut keys = OneShots::<2>::new(CONFIG);
+    for i in 0..2 {
+        keys.press(i);
+        keys.release(i, 0);
+    }
+    assert!(keys.is_on(0) && keys.is_on(1));
+    keys.use_up();
+    assert!(!keys.is_on(0) && !keys.is_on(1));
+}
+
+#[test]
+fn a_double_tap_locks_until_the_next_tap() {
+    let mut keys = OneShots::<2>::new(CONFIG);
+    keys.press(0);
+    keys.release(0, 0);
+    keys.press(0);
+    keys.release(0, 100);
+    assert!(keys.is_locked(0));
+    keys.use_up();
+    keys.time_out(5000);
+    assert!(keys.is_on(0));
+
+    // The unlocking tap doesn't wait for a next key
+    keys.press(0);
+    assert!(keys.is_on(0));
+    keys.release(0, 5000);
+    assert!(!keys.is_on(0));
+    assert_eq!(keys.deadline_ms(), None);
+}
+
+#[test]
+fn a_double_tap_can_cancel_or_do_nothing() {
+    let mut keys = OneShots::<1>::new(OneShotConfig {
+        double_tap: DoubleTap::Cancel,
+        ..CONFIG
+    });
+    keys.press(0);
+    keys.release(0, 0);
+    keys.press(0);
+    keys.release(0, 100);
+    assert!(!keys.is_on(0));
+
+    let mut keys = OneShots::<1>::new(OneShotConfig {
+        double_tap: DoubleTap::Ignore,
+        ..CONFIG
+    });
+    keys.press(0);
+    keys.release(0, 0);
+    keys.press(0);
+    keys.release(0, 100);
+    assert!(keys.is_on(0) && !keys.is_locked(0));
+    assert_eq!(keys.deadline_ms(), Some(1100));
+}
+
+#[test]
+fn the_keymap_locks_on_a_double_tap() {
+    assert_eq!(ONE_SHOT.double_tap, DoubleTap::Lock);
+}
+
+#[test]
+fn one_shot_shift() {
+    let steps = [on_nav(&[SHIFT]), tap(KEY).into(), tap(KEY).into()].concat();
+    let expected = [
+        vec![(LSHIFT, vec![])],
+        typed(LSHIFT, &[E]),
+        vec![(0, vec![])],
+        typed(0, &[E]),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+
+    // Nothing typed in time
+    let steps = [on_nav(&[SHIFT]), vec![press(KEY, 1200), release(KEY, 20)]].concat();
+    let expected = [vec![(LSHIFT, vec![]), (0, vec![])], typed(0, &[E])].concat();
+    assert_eq!(type_keys(&steps), expected);
+}
+
+#[test]
+fn held_one_shot_shift() {
+    let steps = [
+        vec![press(NAV, HOLD_MS), press(SHIFT, HOLD_MS), release(NAV, 20)],
+        tap(KEY).into(),
+        tap(KEY).into(),
+        vec![release(SHIFT, 20)],
+        tap(KEY).into(),
+    ]
+    .concat();
+    let expected = [
+        vec![(LSHIFT, vec![])],
+        typed(LSHIFT, &[E, E]),
+        vec![(0, vec![])],
+        typed(0, &[E]),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+}
+
+#[test]
+fn stacked_one_shot_mods() {
+    let steps = [on_nav(&[SHIFT, CTRL]), tap(KEY).into()].concat();
+    let expected = [
+        vec![(LSHIFT, vec![]), (LSHIFT | LCTRL, vec![])],
+        typed(LSHIFT | LCTRL, &[E]),
+        vec![(LCTRL, vec![]), (0, vec![])],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+}
+
+#[test]
+fn lock_type_unlock_type() {
+    let steps = [
+        on_nav(&[SHIFT, SHIFT]),
+        tap(KEY).into(),
+        tap(KEY).into(),
+        on_nav(&[SHIFT]),
+        tap(KEY).into(),
+        tap(KEY).into(),
+    ]
+    .concat();
+    let expected = [
+        vec![(LSHIFT, vec![])],
+        typed(LSHIFT, &[E, E]),
+        vec![(0, vec![])],
+        typed(0, &[E, E]),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+}
+
+#[test]
+fn one_shot_num_layer() {
+    let steps = [tap(NUM), tap(KEY), tap(KEY)].concat();
+    assert_eq!(type_keys(&steps), typed(0, &[LEFT_BRACKET, E]));
+
+    // Locked, rmk's own layer keys don't turn it off
+    let steps = [
+        tap(NUM).as_slice(),
+        &tap(NUM),
+        &tap(KEY),
+        &[press((3, 5), HOLD_MS), press(KEY, HOLD_MS)],
+        &[release(KEY, 20), release((3, 5), 20)],
+        &tap(KEY),
+        &tap(NUM),
+        &tap(KEY),
+    ]
+    .concat();
+    assert_eq!(
+        type_keys(&steps),
+        typed(0, &[LEFT_BRACKET, LEFT_BRACKET, LEFT_BRACKET, E])
+    );
+}
+
+#[test]
+fn one_shot_shift_and_home_row_mods() {
+    // A tapped home row key is the next key
+    let steps = [on_nav(&[SHIFT]), tap(HRM_A).into(), tap(KEY).into()].concat();
+    let expected = [
+        vec![(LSHIFT, vec![])],
+        typed(LSHIFT, &[A]),
+        vec![(0, vec![])],
+        typed(0, &[E]),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+
+    // A held one adds its modifier, the key typed while it's held is next
+    let steps = [
+        on_nav(&[SHIFT]),
+        vec![press(HRM_D, HOLD_MS), press(KEY, HOLD_MS), release(KEY, 20)],
+        vec![release(HRM_D, 20)],
+        tap(KEY).into(),
+    ]
+    .concat();
+    let expected = [
+        vec![(LSHIFT, vec![]), (LSHIFT | LCTRL, vec![])],
+        typed(LSHIFT | LCTRL, &[E]),
+        vec![(LCTRL, vec![]), (0, vec![])],
+        typed(0, &[E]),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+
+    // Held and released on its own it isn't the next key
+    let steps = [
+        on_nav(&[SHIFT]),
+        vec![press(HRM_D, HOLD_MS), release(HRM_D, HOLD_MS)],
+        tap(KEY).into(),
+    ]
+    .concat();
+    let expected = [
+        vec![(LSHIFT, vec![]), (LSHIFT | LCTRL, vec![]), (LSHIFT, vec![])],
+        typed(LSHIFT, &[E]),
+        vec![(0, vec![])],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), expected);
+}
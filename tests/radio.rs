@@ -0,0 +1,249 @@
+//! The TX power and connection parameters keymap.rs picks for the links: what
+//! BLE allows, and how the controller wrapper applies them to every new
+//! connection and keeps what both ends agreed on.
+
+mod common;
+
+use bt_hci::controller::Controller;
+use bt_hci::event::EventPacket;
+use bt_hci::param::{ConnHandle, LeConnRole};
+use common::hci::{
+    Command, SoftController, connection_complete, disconnection_complete, update_complete,
+    write_tx_power,
+};
+use embassy_time::{Duration, with_timeout};
+use rmk::embassy_futures::block_on;
+use rmk_corne::keymap::{HOST_LINK, SPLIT_LINK};
+use rmk_corne::radio::{
+    Board, ConnParams, Connection, Link, LinkController, LinkEvent, LinkKind, TxPower, apply_links,
+    assert_valid, connections, link_event,
+};
+
+/// 30 ms, latency 4 and 5 s, what a host might pick
+const HOST_PICKED: ConnParams = ConnParams {
+    interval_us: 30_000,
+    latency: 4,
+    timeout_ms: 5_000,
+};
+
+#[test]
+fn presets_are_valid() {
+    assert_eq!(ConnParams::LOW_LATENCY.check(), Ok(()));
+    assert_eq!(ConnParams::BATTERY.check(), Ok(()));
+    assert_eq!(SPLIT_LINK.params.check(), Ok(()));
+    assert_eq!(HOST_LINK.params.check(), Ok(()));
+}
+
+#[test]
+fn rejects_what_ble_does_not_allow() {
+    let valid = ConnParams::LOW_LATENCY;
+    let too_short = ConnParams {
+        interval_us: 6_250,
+        ..valid
+    };
+    assert!(too_short.check().unwrap_err().contains("7.5 ms"));
+    let off_step = ConnParams {
+        interval_us: 8_000,
+        ..valid
+    };
+    assert!(off_step.check().unwrap_err().contains("1.25 ms"));
+    let too_lazy = ConnParams {
+        latency: 500,
+        ..valid
+    };
+    assert!(too_lazy.check().unwrap_err().contains("499"));
+    let odd_timeout = ConnParams {
+        timeout_ms: 2_005,
+        ..valid
+    };
+    assert!(odd_timeout.check().unwrap_err().contains("10 ms"));
+}
+
+/// 30 events of 15 ms slept through twice is 930 ms
+#[test]
+fn timeout_has_to_outlast_the_latency() {
+    let battery = ConnParams::BATTERY;
+    let short = ConnParams {
+        timeout_ms: 930,
+        ..battery
+    };
+    assert!(short.check().is_err());
+    let long_enough = ConnParams {
+        timeout_ms: 940,
+        ..battery
+    };
+    assert_eq!(long_enough.check(), Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "SPLIT_LINK: the interval has to be a multiple of 1.25 ms")]
+fn invalid_params_fail_the_build() {
+    let params = ConnParams {
+        interval_us: 8_000,
+        ..ConnParams::LOW_LATENCY
+    };
+    assert_valid("SPLIT_LINK", &params);
+}
+
+#[test]
+fn shows_the_values() {
+    assert_eq!(
+        ConnParams::BATTERY.to_string(),
+        "interval 15ms, latency 30, timeout 6000ms (battery)"
+    );
+    let custom = ConnParams {
+        interval_us: 8_750,
+        latency: 2,
+        timeout_ms: 3_000,
+    };
+    assert_eq!(
+        custom.to_string(),
+        "interval 8.75ms, latency 2, timeout 3000ms"
+    );
+    let link = Link {
+        tx_power: TxPower::Plus4,
+        params: ConnParams::LOW_LATENCY,
+    };
+    assert_eq!(
+        link.to_string(),
+        "4 dBm, interval 7.5ms, latency 0, timeout 2000ms (low latency)"
+    );
+    assert_eq!(TxPower::Minus8.to_string(), "-8 dBm");
+    assert_eq!(TxPower::default().dbm(), 0);
+}
+
+#[test]
+fn converts_to_hci_steps() {
+    assert_eq!(ConnParams::LOW_LATENCY.hci_interval().as_u16(), 6);
+    assert_eq!(ConnParams::LOW_LATENCY.hci_timeout().as_u16(), 200);
+    assert_eq!(ConnParams::BATTERY.hci_interval().as_u16(), 12);
+}
+
+#[test]
+fn the_role_tells_the_link() {
+    assert_eq!(
+        Board::Dongle.link_kind(LeConnRole::Central),
+        LinkKind::Split
+    );
+    assert_eq!(
+        Board::Dongle.link_kind(LeConnRole::Peripheral),
+        LinkKind::Host
+    );
+    assert_eq!(
+        Board::Half.link_kind(LeConnRole::Peripheral),
+        LinkKind::Split
+    );
+    assert_eq!(LinkKind::Host.link(), HOST_LINK);
+}
+
+fn event_of((kind, data): &(bt_hci::event::EventKind, Vec<u8>)) -> Option<LinkEvent> {
+    link_event(&EventPacket { kind: *kind, data })
+}
+
+#[test]
+fn reads_the_link_events() {
+    let handle = ConnHandle::new(5);
+    assert_eq!(
+        event_of(&connection_complete(5, false, 24, 4, 500)),
+        Some(LinkEvent::Connected(
+            handle,
+            LeConnRole::Peripheral,
+            HOST_PICKED
+        ))
+    );
+    assert_eq!(
+        event_of(&update_complete(5, 6, 0, 200)),
+        Some(LinkEvent::Updated(handle, ConnParams::LOW_LATENCY))
+    );
+    assert_eq!(
+        event_of(&disconnection_complete(5)),
+        Some(LinkEvent::Disconnected(handle))
+    );
+    // Failed, cut short
+    let (kind, mut failed) = connection_complete(5, true, 6, 0, 200);
+    failed[1] = 0x3e;
+    assert_eq!(event_of(&(kind, failed)), None);
+    let (kind, data) = connection_complete(5, true, 6, 0, 200);
+    assert_eq!(event_of(&(kind, data[..8].to_vec())), None);
+}
+
+/// Reads every queued event through `links`
+fn read_events(links: &LinkController<SoftController>, count: usize) {
+    block_on(async {
+        let mut buf = [0; 64];
+        for _ in 0..count {
+            links.read(&mut buf).await.unwrap();
+        }
+    });
+}
+
+#[test]
+fn new_links_get_their_settings_and_report_what_they_got() {
+    let controller = SoftController::default();
+    let links = LinkController::new(&controller, Board::Dongle);
+    // A half at 7.5 ms and the host at its own choice
+    let (kind, data) = connection_complete(1, true, 6, 0, 200);
+    controller.push_event(kind, data);
+    let (kind, data) = connection_complete(2, false, 24, 4, 500);
+    controller.push_event(kind, data);
+    read_events(&links, 2);
+
+    let split = Connection {
+        handle: ConnHandle::new(1),
+        kind: LinkKind::Split,
+        params: ConnParams::LOW_LATENCY,
+    };
+    let host = Connection {
+        handle: ConnHandle::new(2),
+        kind: LinkKind::Host,
+        params: HOST_PICKED,
+    };
+    assert_eq!(connections(), [Some(split), Some(host), None]);
+
+    let _ = block_on(with_timeout(
+        Duration::from_millis(20),
+        apply_links(&controller, write_tx_power),
+    ));
+    let (split_params, host_params) = (SPLIT_LINK.params, HOST_LINK.params);
+    assert_eq!(
+        controller.commands(),
+        [
+            Command::TxPower {
+                handle: 1,
+                dbm: SPLIT_LINK.tx_power.dbm(),
+            },
+            Command::ConnUpdate {
+                handle: 1,
+                interval: split_params.hci_interval().as_u16(),
+                latency: split_params.latency,
+                timeout: split_params.hci_timeout().as_u16(),
+            },
+            Command::TxPower {
+                handle: 2,
+                dbm: HOST_LINK.tx_power.dbm(),
+            },
+            Command::ConnUpdate {
+                handle: 2,
+                interval: host_params.hci_interval().as_u16(),
+                latency: host_params.latency,
+                timeout: host_params.hci_timeout().as_u16(),
+            },
+        ]
+    );
+
+    // The host agrees to 15 ms, then the half goes away
+    let (kind, data) = update_complete(2, 12, 0, 200);
+    controller.push_event(kind, data);
+    let (kind, data) = disconnection_complete(1);
+    controller.push_event(kind, data);
+    read_events(&links, 2);
+    let host = Connection {
+        params: ConnParams {
+            interval_us: 15_000,
+            latency: 0,
+            timeout_ms: 2_000,
+        },
+        ..host
+    };
+    assert_eq!(connections(), [None, Some(host), None]);
+}
@@ -0,0 +1,188 @@
+//! The host switches layers and BLE profiles over raw HID and reads the
+//! status of the halves and the key presses, the way `rmkctl` does.
+
+mod common;
+
+use common::flash::RamFlash;
+use common::keyboard::exclusive;
+use common::usb::{MockDriver, VialHost};
+use embassy_time::Timer;
+use rmk::channel::{CONTROLLER_CHANNEL, EVENT_CHANNEL, KEY_EVENT_CHANNEL};
+use rmk::config::{BehaviorConfig, PositionalConfig, RmkConfig, StorageConfig};
+use rmk::controller::{Controller, EventController};
+use rmk::embassy_futures::block_on;
+use rmk::embassy_futures::select::{Either4, select, select4};
+use rmk::event::{ControllerEvent, KeyboardEvent};
+use rmk::input_device::Runnable;
+use rmk::types::action::{Action, EncoderAction, KeyAction};
+use rmk::{initialize_encoder_keymap_and_storage, run_rmk};
+use rmk_corne::keyboard::Keyboard;
+use rmk_corne::keymap::{COL, NUM_LAYER, PROFILE_KEYS, ROW, get_default_keymap};
+use rmk_corne::remote::{self, Remote};
+use rmk_corne::split::{self, HalfMessage};
+use rmk_corne::status::{self, StatusController};
+use rmk_corne::via::{CUSTOM_CHANNEL, CustomValue, KEY_PRESSES_PER_GET, ViaDriver};
+
+const CUSTOM_SET_VALUE: u8 = 0x07;
+const CUSTOM_GET_VALUE: u8 = 0x08;
+const UNHANDLED: u8 = 0xff;
+
+const MOUSE: u8 = 6;
+
+/// Q on the base layer
+const Q: (u8, u8) = (0, 1);
+
+/// Asks for `value` with `args`, returns the command rmk answered to and the
+/// data of the answer
+async fn get(host: &VialHost, value: CustomValue, args: &[u8]) -> (u8, Vec<u8>) {
+    let mut request = vec![CUSTOM_GET_VALUE, CUSTOM_CHANNEL, value as u8];
+    request.extend_from_slice(args);
+    let answer = host.request(&request).await;
+    (answer[0], answer[3..].to_vec())
+}
+
+/// Sets `value`, returns the command rmk answered to
+async fn set(host: &VialHost, value: CustomValue, data: u8) -> u8 {
+    let answer = host
+        .request(&[CUSTOM_SET_VALUE, CUSTOM_CHANNEL, value as u8, data])
+        .await;
+    // The keyboard taps the key on the leader layer
+    Timer::after_millis(50).await;
+    answer[0]
+}
+
+async fn tap(pos: (u8, u8)) {
+    for pressed in [true, false] {
+        KEY_EVENT_CHANNEL
+            .send(KeyboardEvent::key(pos.0, pos.1, pressed))
+            .await;
+        Timer::after_millis(20).await;
+    }
+}
+
+async fn host_session(host: VialHost) {
+    let mut controller = CONTROLLER_CHANNEL.subscriber().unwrap();
+
+    // The host turns the mouse layer on and off
+    assert_eq!(get(&host, CustomValue::Layer, &[]).await.1[0], 0);
+    assert_eq!(
+        set(&host, CustomValue::Layer, MOUSE).await,
+        CUSTOM_SET_VALUE
+    );
+    assert_eq!(get(&host, CustomValue::Layer, &[]).await.1[0], MOUSE);
+    assert_eq!(
+        set(&host, CustomValue::Layer, MOUSE).await,
+        CUSTOM_SET_VALUE
+    );
+    assert_eq!(get(&host, CustomValue::Layer, &[]).await.1[0], 0);
+    // The adjust layer only comes with num and nav
+    assert_eq!(set(&host, CustomValue::Layer, 5).await, UNHANDLED);
+
+    // The profile keys of rmk switch the profile
+    while controller.try_next_message_pure().is_some() {}
+    assert_eq!(
+        set(&host, CustomValue::HostProfile, 1).await,
+        CUSTOM_SET_VALUE
+    );
+    let profile_key = KeyAction::Single(Action::Key(PROFILE_KEYS[1]));
+    let mut tapped = false;
+    while let Some(event) = controller.try_next_message_pure() {
+        tapped |= matches!(event, ControllerEvent::Key(_, action) if action == profile_key);
+    }
+    assert!(tapped);
+    assert_eq!(
+        set(&host, CustomValue::HostProfile, PROFILE_KEYS.len() as u8).await,
+        UNHANDLED
+    );
+    status::set_host_profile(1);
+    assert_eq!(get(&host, CustomValue::HostProfile, &[]).await.1[0], 1);
+
+    // The right half is connected and sent its battery level
+    EVENT_CHANNEL
+        .send(
+            HalfMessage::Battery {
+                half: 1,
+                percent: 80,
+            }
+            .to_event(),
+        )
+        .await;
+    Timer::after_millis(20).await;
+    assert_eq!(
+        get(&host, CustomValue::Halves, &[]).await.1[..4],
+        [0, 0xff, 1, 80]
+    );
+
+    tap(Q).await;
+    tap(Q).await;
+    let (command, data) = get(&host, CustomValue::KeyPresses, &[Q.0, 0]).await;
+    assert_eq!(command, CUSTOM_GET_VALUE);
+    let counts: Vec<u32> = data[2..]
+        .chunks_exact(4)
+        .take(KEY_PRESSES_PER_GET)
+        .map(|count| u32::from_be_bytes(count.try_into().unwrap()))
+        .collect();
+    assert_eq!(counts, [0, 2, 0, 0, 0, 0]);
+    // Past the end of the row nothing was pressed
+    let (_, data) = get(&host, CustomValue::KeyPresses, &[Q.0, COL as u8 - 1]).await;
+    assert_eq!(data[6..26], [0; 20]);
+
+    // The counts are read only, and only for keys of the keyboard
+    assert_eq!(set(&host, CustomValue::KeyPresses, 0).await, UNHANDLED);
+    assert_eq!(set(&host, CustomValue::Halves, 0).await, UNHANDLED);
+    let (command, _) = get(&host, CustomValue::KeyPresses, &[ROW as u8, 0]).await;
+    assert_eq!(command, UNHANDLED);
+    let (command, _) = get(&host, CustomValue::KeyPresses, &[0, COL as u8]).await;
+    assert_eq!(command, UNHANDLED);
+}
+
+#[test]
+fn the_host_switches_layers_and_reads_the_status() {
+    let _running = exclusive();
+    let keymap = Box::leak(Box::new(get_default_keymap()));
+    let encoders = Box::leak(Box::new([const { [] as [EncoderAction; 0] }; NUM_LAYER]));
+    let behavior = Box::leak(Box::new(BehaviorConfig::default()));
+    let positions = Box::leak(Box::new(PositionalConfig::<ROW, COL>::default()));
+    let storage_config = StorageConfig {
+        num_sectors: 4,
+        ..Default::default()
+    };
+    let (keymap, mut storage) = block_on(initialize_encoder_keymap_and_storage(
+        keymap,
+        encoders,
+        RamFlash::new(4),
+        &storage_config,
+        behavior,
+        positions,
+    ));
+    let keymap = Box::leak(Box::new(keymap));
+    let rmk_config = RmkConfig {
+        storage_config,
+        ..Default::default()
+    };
+
+    let mut keyboard = Keyboard::new(keymap);
+    let mut status = StatusController::new();
+    block_on(status.process_event(ControllerEvent::SplitPeripheral(1, true)));
+    let session = block_on(select4(
+        run_rmk(
+            keymap,
+            ViaDriver::new(MockDriver::default()),
+            &mut storage,
+            rmk_config,
+        ),
+        keyboard.run(),
+        select(status.event_loop(), split::receive_half_messages()),
+        host_session(VialHost),
+    ));
+    assert!(matches!(session, Either4::Fourth(())));
+}
+
+#[test]
+fn only_remote_layers_and_known_profiles_are_requested() {
+    assert!(!remote::request(Remote::ToggleLayer(0)));
+    assert!(!remote::request(Remote::ToggleLayer(NUM_LAYER as u8)));
+    assert!(!remote::request(Remote::SwitchProfile(
+        PROFILE_KEYS.len() as u8
+    )));
+}
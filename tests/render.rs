@@ -0,0 +1,145 @@
+use rmk::types::action::{Action, KeyAction, MorseProfile};
+use rmk::types::keycode::KeyCode;
+use rmk::types::modifier::ModifierCombination;
+use rmk_corne::keymap::{
+    COL, LAYER_NAMES, NUM_LAYER, PROFILE_KEYS, ROW, UNWIRED_KEYS, get_default_keymap,
+};
+use rmk_corne::leader::LEADER;
+use rmk_corne::one_shot::{OneShot, one_shot_key};
+use rmk_corne::render::{Legend, legend, write_ascii, write_svg};
+
+fn labels(key: KeyAction) -> (String, Option<String>) {
+    match legend(key) {
+        Legend::Key { tap, hold } => (tap.to_string(), hold.map(|hold| hold.to_string())),
+        other => panic!("not a key: {other:?}"),
+    }
+}
+
+#[test]
+fn tap_holds_have_both_legends() {
+    let profile = MorseProfile::new(None, None, None, None);
+    let home_row_mod = KeyAction::TapHold(
+        Action::Key(KeyCode::A),
+        Action::Modifier(ModifierCombination::LALT),
+        profile,
+    );
+    assert_eq!(labels(home_row_mod), ("A".into(), Some("Alt".into())));
+
+    let thumb = KeyAction::TapHold(Action::Key(KeyCode::Space), Action::LayerOn(1), profile);
+    assert_eq!(labels(thumb), ("Space".into(), Some("L1".into())));
+
+    let auto_shift = KeyAction::TapHold(
+        Action::Key(KeyCode::Kc1),
+        Action::KeyWithModifier(KeyCode::Kc1, ModifierCombination::LSHIFT),
+        profile,
+    );
+    assert_eq!(labels(auto_shift), ("1".into(), Some("S-1".into())));
+}
+
+#[test]
+fn long_names_are_cut_off() {
+    let key = KeyAction::Single(Action::Key(KeyCode::MediaPrevTrack));
+    let (tap, _) = labels(key);
+    assert_eq!(tap.chars().count(), 8);
+}
+
+#[test]
+fn transparent_keys_look_different() {
+    assert_eq!(legend(KeyAction::Transparent), Legend::Transparent);
+
+    // The gaming upper layer is mostly transparent
+    let keymap = get_default_keymap();
+    let mut ascii = String::new();
+    write_ascii(&mut ascii, 4, &keymap[4]).unwrap();
+    assert!(ascii.starts_with("Layer 4: gaming upper\n"));
+    assert!(ascii.contains("| ________ |"));
+
+    let mut svg = String::new();
+    write_svg(&mut svg, 4, &keymap[4]).unwrap();
+    let transparent = keymap[4]
+        .iter()
+        .enumerate()
+        .flat_map(|(row, keys)| {
+            keys.iter()
+                .enumerate()
+                .map(move |(col, key)| (row, col, key))
+        })
+        .filter(|&(row, col, key)| {
+            *key == KeyAction::Transparent && !UNWIRED_KEYS.contains(&(row as u8, col as u8))
+        })
+        .count();
+    assert_eq!(
+        svg.matches(r#"<rect class="transparent""#).count(),
+        transparent
+    );
+}
+
+#[test]
+fn unwired_keys_are_left_out() {
+    let keymap = get_default_keymap();
+    let mut svg = String::new();
+    write_svg(&mut svg, 0, &keymap[0]).unwrap();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert_eq!(
+        svg.matches("<rect ").count(),
+        ROW * COL - UNWIRED_KEYS.len()
+    );
+}
+
+#[test]
+fn ascii_tables_line_up() {
+    let keymap = get_default_keymap();
+    let mut ascii = String::new();
+    write_ascii(&mut ascii, 0, &keymap[0]).unwrap();
+    let lines: Vec<&str> = ascii.lines().collect();
+    // Title, then a border and two label lines per row and a last border
+    assert_eq!(lines.len(), 1 + ROW * 3 + 1);
+    // The full rows are equally long, the thumb row is narrower
+    assert!(lines[1..10].iter().all(|line| line.len() == lines[1].len()));
+    assert!(lines[11].len() < lines[1].len());
+    assert!(lines[2].contains("| Q"));
+    assert!(lines[6].contains("| Alt"));
+}
+
+#[test]
+fn our_keys_have_names() {
+    assert_eq!(labels(LEADER), ("Leader".into(), None));
+    assert_eq!(
+        labels(one_shot_key(OneShot::Modifier(KeyCode::LShift))),
+        ("OS Shift".into(), None)
+    );
+    assert_eq!(
+        labels(one_shot_key(OneShot::Layer(1))),
+        ("OSL1".into(), None)
+    );
+    assert_eq!(
+        labels(KeyAction::Single(Action::TriggerMacro(1))),
+        ("Macro1".into(), None)
+    );
+    let profile = KeyAction::Single(Action::Key(PROFILE_KEYS[2]));
+    assert_eq!(labels(profile), ("BT2".into(), None));
+}
+
+#[test]
+fn tap_dances_show_their_taps_and_holds() {
+    // Escape, the gaming layer for two taps, nav held
+    assert_eq!(
+        labels(KeyAction::Morse(0)),
+        ("Esc/DF3".into(), Some("L2".into()))
+    );
+}
+
+#[test]
+fn every_layer_is_drawn() {
+    let keymap = get_default_keymap();
+    for (layer, keys) in keymap.iter().enumerate() {
+        let mut ascii = String::new();
+        write_ascii(&mut ascii, layer, keys).unwrap();
+        assert!(ascii.starts_with(&format!("Layer {layer}: {}\n", LAYER_NAMES[layer])));
+        let mut svg = String::new();
+        write_svg(&mut svg, layer, keys).unwrap();
+        assert!(svg.contains(LAYER_NAMES[layer]));
+    }
+    assert_eq!(keymap.len(), NUM_LAYER);
+}
@@ -0,0 +1,144 @@
+//! The Escape tap dance: tapped it is Escape, also when another key follows
+//! before it could be tapped again, tapped twice it makes gaming the default layer, which the
+//! default layer controller stores and shows, and held it is the nav layer.
+
+mod common;
+
+use common::flash::RamFlash;
+use common::keyboard::{KeyState, Step, press, release, run, run_with};
+use common::led::Led;
+use rmk::controller::EventController;
+use rmk::embassy_futures::block_on;
+use rmk::morse::MorsePattern;
+use rmk::types::action::{Action, KeyAction};
+use rmk_corne::default_layer::DefaultLayerController;
+use rmk_corne::keymap::{
+    TAP_DANCE_PROFILE, TAP_DANCES, TAPPING_TERM_MS, behavior_config, get_default_keymap,
+};
+use rmk_corne::settings::{SETTINGS_SIZE, Setting, Settings};
+
+/// `td!(0)`
+const ESCAPE_KEY: (u8, u8) = (3, 4);
+/// E on the base layer, nothing on the nav layer
+const E_KEY: (u8, u8) = (0, 3);
+/// H on the base layer and Left on the nav layer
+const LEFT_KEY: (u8, u8) = (1, 6);
+/// Nothing on the base layer, Tab on the gaming layer
+const TAB_KEY: (u8, u8) = (0, 0);
+
+const ESCAPE: u8 = 0x29;
+const E: u8 = 0x08;
+const LEFT: u8 = 0x50;
+const TAB: u8 = 0x2b;
+
+const GAMING: u8 = 3;
+
+/// Longer than the tapping term, and than the idle time after which flow
+/// tap lets a key be held
+const HOLD_MS: u64 = TAPPING_TERM_MS as u64 + 75;
+
+fn type_keys(steps: &[Step]) -> Vec<KeyState> {
+    run(get_default_keymap(), behavior_config(), steps)
+}
+
+/// Types `steps` with the default layer controller running, returns the keys
+/// the host saw, the stored default layer and whether the LED is lit
+fn type_keys_with_controller(steps: &[Step]) -> (Vec<KeyState>, Option<u8>, bool) {
+    let mut flash = RamFlash::new(SETTINGS_SIZE as usize / common::flash::SECTOR);
+    let led = Led::default();
+    let states = run_with(get_default_keymap(), behavior_config(), steps, async {
+        let mut controller =
+            DefaultLayerController::new(led.clone(), false, Settings::new(&mut flash)).await;
+        controller.event_loop().await
+    });
+    let stored = block_on(Settings::new(&mut flash).get(Setting::DefaultLayer));
+    (states, stored, led.is_high())
+}
+
+fn tap(pos: (u8, u8), after_ms: u64) -> [Step; 2] {
+    [press(pos, after_ms), release(pos, 20)]
+}
+
+fn typed(keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(0, vec![key]), (0, vec![])])
+        .collect()
+}
+
+#[test]
+fn the_escape_tap_dance_is_rmks_morse_key() {
+    assert_eq!(
+        get_default_keymap()[0][ESCAPE_KEY.0 as usize][ESCAPE_KEY.1 as usize],
+        KeyAction::Morse(0)
+    );
+    let morse = TAP_DANCES[0].morse(TAP_DANCE_PROFILE);
+    let action = |pattern| morse.actions.get(&MorsePattern::from_u16(pattern)).copied();
+    // A one for the start, then 0 for a tap and 1 for a hold
+    assert_eq!(
+        action(0b10),
+        Some(Action::Key(rmk::types::keycode::KeyCode::Escape))
+    );
+    assert_eq!(action(0b11), Some(Action::LayerOn(2)));
+    // The default layer controller switches to the layer
+    assert_eq!(action(0b100), Some(Action::LayerOn(GAMING)));
+    assert_eq!(morse.actions.len(), 3);
+}
+
+#[test]
+fn tap_is_escape() {
+    assert_eq!(type_keys(&tap(ESCAPE_KEY, HOLD_MS)), typed(&[ESCAPE]));
+}
+
+#[test]
+fn another_key_after_a_tap_is_typed_after_escape() {
+    let steps = [tap(ESCAPE_KEY, HOLD_MS), tap(E_KEY, 20)].concat();
+    assert_eq!(type_keys(&steps), typed(&[ESCAPE, E]));
+
+    // Between two taps it keeps them from being a double tap
+    let steps = [
+        tap(ESCAPE_KEY, HOLD_MS),
+        tap(E_KEY, 20),
+        tap(ESCAPE_KEY, 20),
+    ]
+    .concat();
+    let (states, stored, lit) = type_keys_with_controller(&steps);
+    assert_eq!(states, typed(&[ESCAPE, E, ESCAPE]));
+    assert_eq!(stored, None);
+    assert!(!lit);
+}
+
+#[test]
+fn hold_is_the_nav_layer() {
+    let steps = [
+        vec![press(ESCAPE_KEY, HOLD_MS)],
+        tap(LEFT_KEY, HOLD_MS).into(),
+        vec![release(ESCAPE_KEY, 20)],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[LEFT]));
+}
+
+#[test]
+fn another_key_while_held_is_on_the_nav_layer() {
+    let steps = [
+        vec![press(ESCAPE_KEY, HOLD_MS)],
+        tap(LEFT_KEY, 30).into(),
+        vec![release(ESCAPE_KEY, 20)],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[LEFT]));
+}
+
+#[test]
+fn double_tap_makes_gaming_the_default_layer() {
+    let steps = [
+        tap(ESCAPE_KEY, HOLD_MS),
+        tap(ESCAPE_KEY, 30),
+        tap(TAB_KEY, HOLD_MS),
+    ]
+    .concat();
+    let (states, stored, lit) = type_keys_with_controller(&steps);
+    assert_eq!(states, typed(&[TAB]));
+    assert_eq!(stored, Some(GAMING));
+    assert!(lit);
+}
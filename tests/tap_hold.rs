@@ -0,0 +1,165 @@
+//! The tap-hold rules: a thumb layer key is held for a key of the other
+//! hand, tapped for a thumb key of its own and permissive for the rest of its
+//! hand, a home row mod is tapped for a key of its own row and hand, and only
+//! the home row mods have flow tap.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run};
+use rmk::config::Hand;
+use rmk_corne::keymap::{HANDS, behavior_config, get_default_keymap};
+use rmk_corne::tap_hold::{self, Resolve};
+
+/// `kol!(Space, 1)` and `kol!(Enter, 2)`
+const NUM: (u8, u8) = (3, 5);
+const NAV: (u8, u8) = (3, 6);
+/// Backspace, next to the num key
+const BACKSPACE_KEY: (u8, u8) = (3, 3);
+/// `hrm!(J, LSHIFT)` on the base layer, 7 on the num layer
+const SEVEN_KEY: (u8, u8) = (1, 7);
+/// H on the base layer and Left on the nav layer
+const LEFT_KEY: (u8, u8) = (1, 6);
+/// `hrm!(D, LCTRL)` and `hrm!(F, LSHIFT)`
+const D_KEY: (u8, u8) = (1, 3);
+const F_KEY: (u8, u8) = (1, 4);
+const E_KEY: (u8, u8) = (0, 3);
+const W_KEY: (u8, u8) = (0, 2);
+
+const D: u8 = 0x07;
+const E: u8 = 0x08;
+const F: u8 = 0x09;
+const H: u8 = 0x0b;
+const W: u8 = 0x1a;
+const SEVEN: u8 = 0x24;
+const LEFT: u8 = 0x50;
+const ENTER: u8 = 0x28;
+const BACKSPACE: u8 = 0x2a;
+const SPACE: u8 = 0x2c;
+const LEFT_CTRL: u8 = 0x01;
+
+/// Longer than the tapping term, and than the idle time of flow tap
+const HOLD_MS: u64 = 250;
+
+fn type_keys(steps: &[Step]) -> Vec<KeyState> {
+    run(get_default_keymap(), behavior_config(), steps)
+}
+
+fn typed(keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(0, vec![key]), (0, vec![])])
+        .collect()
+}
+
+/// `first` pressed, `second` tapped 30 ms later and `first` released, all
+/// within the tapping term
+fn roll(first: (u8, u8), second: (u8, u8)) -> [Step; 4] {
+    [
+        press(first, HOLD_MS),
+        press(second, 30),
+        release(second, 20),
+        release(first, 20),
+    ]
+}
+
+#[test]
+fn the_halves_are_the_hands() {
+    for row in HANDS {
+        assert_eq!(row[..6], [Hand::Left; 6]);
+        assert_eq!(row[6..], [Hand::Right; 6]);
+    }
+}
+
+#[test]
+fn the_rules_use_hand_and_row() {
+    let num = tap_hold::find(NUM).unwrap();
+    assert_eq!(num.resolve(SEVEN_KEY), Some(Resolve::Hold));
+    assert_eq!(num.resolve(NAV), Some(Resolve::Hold));
+    assert_eq!(num.resolve(BACKSPACE_KEY), Some(Resolve::Tap));
+    assert_eq!(num.resolve(E_KEY), None);
+    assert!(!num.flow_tap);
+
+    let f = tap_hold::find(F_KEY).unwrap();
+    assert_eq!(f.resolve(D_KEY), Some(Resolve::Tap));
+    assert_eq!(f.resolve(W_KEY), None);
+    assert_eq!(f.resolve(SEVEN_KEY), None);
+    assert!(f.flow_tap);
+
+    assert_eq!(tap_hold::find(E_KEY), None);
+}
+
+#[test]
+fn a_thumb_key_is_held_for_the_other_hand() {
+    assert_eq!(type_keys(&roll(NUM, SEVEN_KEY)), typed(&[SEVEN]));
+}
+
+#[test]
+fn a_thumb_key_is_permissive_for_its_own_hand() {
+    // A key tapped while it is held is on its layer, a key released after it
+    // is typed after its tap
+    assert_eq!(type_keys(&roll(NAV, LEFT_KEY)), typed(&[LEFT]));
+    let steps = [
+        press(NAV, HOLD_MS),
+        press(LEFT_KEY, 30),
+        release(NAV, 20),
+        release(LEFT_KEY, 20),
+    ];
+    assert_eq!(
+        type_keys(&steps),
+        [
+            (0, vec![ENTER]),
+            (0, vec![ENTER, H]),
+            (0, vec![H]),
+            (0, vec![])
+        ]
+    );
+}
+
+#[test]
+fn a_thumb_key_is_tapped_for_a_thumb_key_of_its_hand() {
+    assert_eq!(
+        type_keys(&roll(NUM, BACKSPACE_KEY)),
+        typed(&[SPACE, BACKSPACE])
+    );
+}
+
+#[test]
+fn a_thumb_key_after_typing_is_still_held() {
+    let steps = [
+        [press(E_KEY, HOLD_MS), release(E_KEY, 20)].as_slice(),
+        &[press(NUM, 20), press(SEVEN_KEY, 30)],
+        &[release(SEVEN_KEY, 20), release(NUM, 20)],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[E, SEVEN]));
+}
+
+#[test]
+fn a_home_row_mod_after_typing_is_tapped() {
+    // Held past the tapping term, it was pressed right after E
+    let steps = [
+        press(E_KEY, HOLD_MS),
+        release(E_KEY, 20),
+        press(F_KEY, 20),
+        release(F_KEY, HOLD_MS),
+    ];
+    assert_eq!(type_keys(&steps), typed(&[E, F]));
+}
+
+#[test]
+fn home_row_mods_roll_on_their_row() {
+    assert_eq!(type_keys(&roll(F_KEY, D_KEY)), typed(&[F, D]));
+}
+
+#[test]
+fn a_home_row_mod_holds_for_its_hand_on_another_row() {
+    let states = type_keys(&roll(D_KEY, W_KEY));
+    assert_eq!(
+        states,
+        [
+            (LEFT_CTRL, vec![]),
+            (LEFT_CTRL, vec![W]),
+            (LEFT_CTRL, vec![]),
+            (0, vec![]),
+        ]
+    );
+}
@@ -0,0 +1,208 @@
+//! The keys of the adjust layer change the global tapping term and those of
+//! the home row mods and thumb keys, type them out and reset them. The terms
+//! are stored in the settings, come back after a restart and time the
+//! tap-hold keys.
+
+mod common;
+
+use common::flash::RamFlash;
+use common::keyboard::{KeyState, Step, press, release, run_with};
+use rmk::controller::EventController;
+use rmk::embassy_futures::block_on;
+use rmk_corne::keymap::{
+    TAPPING_TERM_MAX_MS, TAPPING_TERM_MIN_MS, TAPPING_TERM_MS, behavior_config, get_default_keymap,
+};
+use rmk_corne::settings::{SETTINGS_SIZE, Setting, Settings};
+use rmk_corne::tapping_term::{
+    TERM_DOWN, TERM_PRINT, TERM_RESET, TERM_SELECT, TERM_UP, TappingTermController, TappingTerms,
+    TermTarget,
+};
+
+/// `kol!(Space, 1)` and `kol!(Enter, 2)`, both held for the adjust layer
+const NUM: (u8, u8) = (3, 5);
+const NAV: (u8, u8) = (3, 6);
+/// `hrm!(F, LSHIFT)`
+const F_KEY: (u8, u8) = (1, 4);
+/// `td!(0)`
+const ESCAPE_KEY: (u8, u8) = (3, 4);
+const E_KEY: (u8, u8) = (0, 3);
+
+/// The tapping term keys on the adjust layer
+const SELECT: (u8, u8) = (2, 1);
+const DOWN: (u8, u8) = (2, 2);
+const UP: (u8, u8) = (2, 3);
+const PRINT: (u8, u8) = (2, 4);
+const RESET: (u8, u8) = (2, 5);
+
+const ADJUST: usize = 5;
+
+const F: u8 = 0x09;
+const SPACE: u8 = 0x2c;
+const LEFT_SHIFT: u8 = 0x02;
+
+fn settings_flash() -> RamFlash {
+    RamFlash::new(SETTINGS_SIZE as usize / common::flash::SECTOR)
+}
+
+fn stored(flash: &mut RamFlash) -> TappingTerms {
+    block_on(TappingTerms::load(&mut Settings::new(flash)))
+}
+
+/// Types `steps` with the tapping term controller running on `flash`
+fn type_keys(flash: &mut RamFlash, steps: &[Step]) -> Vec<KeyState> {
+    run_with(get_default_keymap(), behavior_config(), steps, async {
+        let mut controller = TappingTermController::new(Settings::new(&mut *flash)).await;
+        controller.event_loop().await
+    })
+}
+
+/// Taps the keys at `keys` on the adjust layer
+fn on_adjust(keys: &[(u8, u8)]) -> Vec<Step> {
+    let mut steps = vec![press(NUM, 0), press(NAV, 20)];
+    for &key in keys {
+        steps.extend([press(key, 250), release(key, 20)]);
+    }
+    steps.extend([release(NAV, 20), release(NUM, 20)]);
+    steps
+}
+
+fn typed(text: &str) -> Vec<KeyState> {
+    let keycode = |c: char| match c {
+        'a'..='z' => 0x04 + (c as u8 - b'a'),
+        '1'..='9' => 0x1e + (c as u8 - b'1'),
+        '0' => 0x27,
+        ' ' => SPACE,
+        _ => unreachable!(),
+    };
+    text.chars()
+        .flat_map(|c| [(0, vec![keycode(c)]), (0, vec![])])
+        .collect()
+}
+
+#[test]
+fn the_keys_are_on_the_adjust_layer() {
+    let keymap = get_default_keymap();
+    let keys = [
+        (SELECT, TERM_SELECT),
+        (DOWN, TERM_DOWN),
+        (UP, TERM_UP),
+        (PRINT, TERM_PRINT),
+        (RESET, TERM_RESET),
+    ];
+    for ((row, col), action) in keys {
+        assert_eq!(keymap[ADJUST][row as usize][col as usize], action);
+    }
+}
+
+#[test]
+fn the_keys_have_the_term_of_their_kind() {
+    assert_eq!(TermTarget::of(F_KEY), TermTarget::HomeRowMods);
+    assert_eq!(TermTarget::of(NUM), TermTarget::Thumbs);
+    assert_eq!(TermTarget::of(ESCAPE_KEY), TermTarget::Global);
+    assert_eq!(TermTarget::of(E_KEY), TermTarget::Global);
+}
+
+#[test]
+fn the_terms_follow_the_global_term_until_changed() {
+    let mut terms = TappingTerms::DEFAULT;
+    assert_eq!(terms.of(F_KEY), TAPPING_TERM_MS);
+
+    terms.adjust(TermTarget::Global, 1);
+    assert_eq!(terms.global, TAPPING_TERM_MS + 25);
+    assert_eq!(terms.of(F_KEY), TAPPING_TERM_MS + 25);
+    assert_eq!(terms.of(NUM), TAPPING_TERM_MS + 25);
+
+    terms.adjust(TermTarget::HomeRowMods, 2);
+    terms.adjust(TermTarget::Thumbs, -1);
+    assert_eq!(terms.of(F_KEY), TAPPING_TERM_MS + 75);
+    assert_eq!(terms.of(NUM), TAPPING_TERM_MS);
+
+    terms.reset(TermTarget::Global);
+    assert_eq!(terms.of(ESCAPE_KEY), TAPPING_TERM_MS);
+    assert_eq!(terms.of(F_KEY), TAPPING_TERM_MS + 75);
+    terms.reset(TermTarget::HomeRowMods);
+    assert_eq!(terms.home_row_mods, None);
+    assert_eq!(terms.of(F_KEY), TAPPING_TERM_MS);
+}
+
+#[test]
+fn the_terms_stay_within_their_limits() {
+    let mut terms = TappingTerms::DEFAULT;
+    terms.adjust(TermTarget::Global, i16::MIN);
+    assert_eq!(terms.global, TAPPING_TERM_MIN_MS);
+    terms.adjust(TermTarget::Thumbs, i16::MAX);
+    assert_eq!(terms.thumbs, Some(TAPPING_TERM_MAX_MS));
+}
+
+#[test]
+fn the_global_term_goes_to_rmk() {
+    let mut behavior = behavior_config();
+    let profile = behavior.morse.default_profile;
+    assert_eq!(profile.hold_timeout_ms(), Some(TAPPING_TERM_MS));
+    assert_eq!(profile.gap_timeout_ms(), Some(TAPPING_TERM_MS));
+
+    let mut terms = TappingTerms::DEFAULT;
+    terms.adjust(TermTarget::Global, 2);
+    terms.apply(&mut behavior);
+    let changed = behavior.morse.default_profile;
+    assert_eq!(changed.hold_timeout_ms(), Some(TAPPING_TERM_MS + 50));
+    assert_eq!(changed.gap_timeout_ms(), Some(TAPPING_TERM_MS + 50));
+    assert_eq!(changed.mode(), profile.mode());
+}
+
+#[test]
+fn the_keys_change_and_store_the_terms() {
+    let mut flash = settings_flash();
+    assert_eq!(stored(&mut flash), TappingTerms::DEFAULT);
+
+    // Global up twice, the home row mods down once, the thumbs up and reset
+    let keys = [UP, UP, SELECT, DOWN, SELECT, UP, RESET];
+    assert_eq!(type_keys(&mut flash, &on_adjust(&keys)), []);
+    let expected = TappingTerms {
+        global: TAPPING_TERM_MS + 50,
+        home_row_mods: Some(TAPPING_TERM_MS + 25),
+        thumbs: None,
+    };
+    assert_eq!(stored(&mut flash), expected);
+
+    // The global term back to its default
+    type_keys(&mut flash, &on_adjust(&[RESET]));
+    assert_eq!(stored(&mut flash).global, TAPPING_TERM_MS);
+}
+
+#[test]
+fn the_print_key_types_the_term() {
+    let mut flash = settings_flash();
+    let states = type_keys(&mut flash, &on_adjust(&[PRINT]));
+    assert_eq!(states, typed("global 175ms"));
+
+    let states = type_keys(&mut flash, &on_adjust(&[SELECT, DOWN, DOWN, PRINT]));
+    assert_eq!(states, typed("home row mods 125ms"));
+}
+
+#[test]
+fn a_longer_term_taps_a_home_row_mod_held_longer() {
+    let steps = [press(F_KEY, 0), release(F_KEY, TAPPING_TERM_MS as u64 + 50)];
+    let mut flash = settings_flash();
+    assert_eq!(
+        type_keys(&mut flash, &steps),
+        [(LEFT_SHIFT, vec![]), (0, vec![])]
+    );
+
+    block_on(Settings::new(&mut flash).set(Setting::HomeRowModTerm, 60)); // 300 ms
+    assert_eq!(stored(&mut flash).of(F_KEY), 300);
+    assert_eq!(type_keys(&mut flash, &steps), [(0, vec![F]), (0, vec![])]);
+}
+
+#[test]
+fn a_shorter_term_holds_a_thumb_key_sooner() {
+    let steps = [press(NUM, 0), release(NUM, 125)];
+    let mut flash = settings_flash();
+    assert_eq!(
+        type_keys(&mut flash, &steps),
+        [(0, vec![SPACE]), (0, vec![])]
+    );
+
+    block_on(Settings::new(&mut flash).set(Setting::ThumbTerm, 20)); // 100 ms
+    assert_eq!(type_keys(&mut flash, &steps), []);
+}
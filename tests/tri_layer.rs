@@ -0,0 +1,144 @@
+//! Holding the num and nav thumb keys together turns on the adjust layer,
+//! whichever is pressed or released first, also with nav locked, and a
+//! tapped thumb key doesn't.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run};
+use rmk_corne::keymap::{TRI_LAYER, behavior_config, get_default_keymap};
+
+/// `kol!(Space, 1)` and `kol!(Enter, 2)`
+const NUM: (u8, u8) = (3, 5);
+const NAV: (u8, u8) = (3, 6);
+/// E, [ on num, nothing on nav and F3 on adjust
+const KEY: (u8, u8) = (0, 3);
+/// The layer lock key on num and nav
+const LOCK: (u8, u8) = (0, 0);
+
+const E: u8 = 0x08;
+const LEFT_BRACKET: u8 = 0x2f;
+const F3: u8 = 0x3c;
+const SPACE: u8 = 0x2c;
+const ENTER: u8 = 0x28;
+
+/// Longer than the tapping term of the thumb keys, and than the idle time
+/// after which flow tap lets them be held
+const HOLD_MS: u64 = 250;
+
+fn type_keys(steps: &[Step]) -> Vec<KeyState> {
+    run(get_default_keymap(), behavior_config(), steps)
+}
+
+/// Taps `pos` once the thumb keys pressed before are held
+fn tap(pos: (u8, u8)) -> [Step; 2] {
+    [press(pos, HOLD_MS), release(pos, 20)]
+}
+
+fn typed(keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(0, vec![key]), (0, vec![])])
+        .collect()
+}
+
+#[test]
+fn the_keymap_uses_num_nav_and_adjust() {
+    assert_eq!(TRI_LAYER, [1, 2, 5]);
+}
+
+#[test]
+fn num_then_nav() {
+    // Released in the order they were pressed, nav alone has nothing on the key
+    let steps = [
+        [press(NUM, HOLD_MS), press(NAV, HOLD_MS)],
+        tap(KEY),
+        [release(NUM, HOLD_MS), press(KEY, 20)],
+        [release(KEY, 20), release(NAV, 20)],
+        tap(KEY),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[F3, E]));
+
+    // Released the other way around, back to num
+    let steps = [
+        [press(NUM, HOLD_MS), press(NAV, HOLD_MS)],
+        tap(KEY),
+        [release(NAV, HOLD_MS), press(KEY, 20)],
+        [release(KEY, 20), release(NUM, 20)],
+        tap(KEY),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[F3, LEFT_BRACKET, E]));
+}
+
+#[test]
+fn nav_then_num() {
+    let steps = [
+        [press(NAV, HOLD_MS), press(NUM, HOLD_MS)],
+        tap(KEY),
+        [release(NAV, HOLD_MS), press(KEY, 20)],
+        [release(KEY, 20), release(NUM, 20)],
+        tap(KEY),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[F3, LEFT_BRACKET, E]));
+
+    let steps = [
+        [press(NAV, HOLD_MS), press(NUM, HOLD_MS)],
+        tap(KEY),
+        [release(NUM, HOLD_MS), press(KEY, 20)],
+        [release(KEY, 20), release(NAV, 20)],
+        tap(KEY),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[F3, E]));
+}
+
+#[test]
+fn a_tapped_thumb_key_is_no_layer() {
+    // Nav tapped while num is held types Enter, the key stays on num
+    let steps = [
+        [press(NUM, HOLD_MS), press(NAV, HOLD_MS)],
+        [release(NAV, 50), press(KEY, HOLD_MS)],
+        [release(KEY, 20), release(NUM, 20)],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[ENTER, LEFT_BRACKET]));
+
+    // Num tapped before nav is held
+    let steps = [
+        [press(NUM, HOLD_MS), release(NUM, 50)],
+        [press(NAV, HOLD_MS), press(KEY, HOLD_MS)],
+        [release(KEY, 20), release(NAV, 20)],
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[SPACE]));
+}
+
+#[test]
+fn a_tapped_num_key_is_no_layer_with_nav_locked() {
+    let lock_nav = [
+        press(NAV, HOLD_MS),
+        press(LOCK, HOLD_MS),
+        release(LOCK, 20),
+        release(NAV, 20),
+    ];
+    // Tapped it types Space, the key stays on nav
+    let steps = [
+        lock_nav.as_slice(),
+        &[press(NUM, HOLD_MS), release(NUM, 50)],
+        &tap(KEY),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[SPACE]));
+
+    // Held it is the adjust layer, and nav is still locked after it
+    let steps = [
+        lock_nav.as_slice(),
+        &[press(NUM, HOLD_MS)],
+        &tap(KEY),
+        &[release(NUM, 20)],
+        &tap(KEY),
+    ]
+    .concat();
+    assert_eq!(type_keys(&steps), typed(&[F3]));
+}
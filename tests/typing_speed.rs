@@ -0,0 +1,119 @@
+//! Typing fast makes the tapping terms longer: a home row mod held a little
+//! past its term in a burst is still a tap. A pause or slow typing ends the
+//! burst.
+
+mod common;
+
+use common::keyboard::{KeyState, Step, press, release, run};
+use rmk_corne::keymap::{
+    ADAPTIVE_TERM, FLOW_TAP_MS, TAPPING_TERM_MS, behavior_config, get_default_keymap,
+};
+use rmk_corne::typing_speed::{AdaptiveTermConfig, TypingSpeed};
+
+const CONFIG: AdaptiveTermConfig = AdaptiveTermConfig {
+    fast_interval_ms: 150,
+    min_burst: 3,
+    idle_ms: 500,
+    extra_term_ms: 75,
+    tap_in_burst: false,
+};
+
+/// Q W E R on the base layer
+const BURST_KEYS: [(u8, u8); 4] = [(0, 1), (0, 2), (0, 3), (0, 4)];
+/// `hrm!(F, LSHIFT)`
+const F_KEY: (u8, u8) = (1, 4);
+
+const Q: u8 = 0x14;
+const W: u8 = 0x1a;
+const E: u8 = 0x08;
+const R: u8 = 0x15;
+const F: u8 = 0x09;
+const LEFT_SHIFT: u8 = 0x02;
+
+/// Between the presses of a fast burst, too long apart for flow tap
+const FAST_MS: u64 = 130;
+const SLOW_MS: u64 = 300;
+/// Longer than the tapping term, shorter than it is while typing fast
+const HOLD_MS: u64 = TAPPING_TERM_MS as u64 + 40;
+
+fn pressed_at(speed: &mut TypingSpeed, times: &[u64]) {
+    for &time in times {
+        speed.key_pressed(time);
+    }
+}
+
+/// Presses the burst keys `every_ms` apart, then holds F for [`HOLD_MS`]
+fn burst_and_f(every_ms: u64) -> Vec<KeyState> {
+    let mut steps: Vec<Step> = vec![];
+    for key in BURST_KEYS {
+        steps.extend([press(key, every_ms - 30), release(key, 30)]);
+    }
+    steps.extend([press(F_KEY, every_ms - 30), release(F_KEY, HOLD_MS)]);
+    run(get_default_keymap(), behavior_config(), &steps)
+}
+
+fn typed(keys: &[u8]) -> Vec<KeyState> {
+    keys.iter()
+        .flat_map(|&key| [(0, vec![key]), (0, vec![])])
+        .collect()
+}
+
+#[test]
+fn the_keymap_adapts_the_terms() {
+    let config = ADAPTIVE_TERM.unwrap();
+    assert!(config.fast_interval_ms as u64 > FLOW_TAP_MS);
+    assert!(config.extra_term_ms > 0);
+}
+
+#[test]
+fn a_fast_burst_makes_the_terms_longer() {
+    let mut speed = TypingSpeed::new(CONFIG);
+    pressed_at(&mut speed, &[0, 100, 200, 300]);
+    assert!(speed.is_fast(350));
+    assert_eq!(speed.extra_term_ms(350), 75);
+    assert!(!speed.taps(350));
+
+    let mut speed = TypingSpeed::new(AdaptiveTermConfig {
+        tap_in_burst: true,
+        ..CONFIG
+    });
+    pressed_at(&mut speed, &[0, 100, 200, 300]);
+    assert!(speed.taps(350));
+}
+
+#[test]
+fn short_bursts_and_slow_typing_are_not_fast() {
+    let mut speed = TypingSpeed::new(CONFIG);
+    pressed_at(&mut speed, &[0, 100, 200]);
+    assert!(!speed.is_fast(250));
+
+    let mut speed = TypingSpeed::new(CONFIG);
+    pressed_at(&mut speed, &[0, 300, 600, 900, 1200]);
+    assert_eq!(speed.extra_term_ms(1250), 0);
+
+    // A slow key among fast ones brings the average up
+    let mut speed = TypingSpeed::new(CONFIG);
+    pressed_at(&mut speed, &[0, 100, 200, 300, 700]);
+    assert!(!speed.is_fast(750));
+}
+
+#[test]
+fn a_pause_ends_the_burst() {
+    let mut speed = TypingSpeed::new(CONFIG);
+    pressed_at(&mut speed, &[0, 100, 200, 300]);
+    assert!(!speed.is_fast(800));
+    speed.key_pressed(900);
+    assert!(!speed.is_fast(950));
+}
+
+#[test]
+fn a_home_row_mod_in_a_fast_burst_is_a_tap() {
+    assert_eq!(burst_and_f(FAST_MS), typed(&[Q, W, E, R, F]));
+}
+
+#[test]
+fn a_home_row_mod_after_slow_typing_is_a_hold() {
+    let mut expected = typed(&[Q, W, E, R]);
+    expected.extend([(LEFT_SHIFT, vec![]), (0, vec![])]);
+    assert_eq!(burst_and_f(SLOW_MS), expected);
+}
@@ -0,0 +1,257 @@
+use rmk::types::action::{Action, KeyAction, MorseProfile};
+use rmk::types::keycode::KeyCode;
+use rmk::types::modifier::ModifierCombination;
+use rmk_corne::keymap::{TAP_DANCES, get_default_keymap};
+use rmk_corne::tap_dance::TapDance;
+use rmk_corne::validate::{KeymapError, validate_keymap};
+
+const NO: KeyAction = KeyAction::Single(Action::Key(KeyCode::No));
+
+fn key(keycode: KeyCode) -> KeyAction {
+    KeyAction::Single(Action::Key(keycode))
+}
+
+fn single(action: Action) -> KeyAction {
+    KeyAction::Single(action)
+}
+
+fn tap_hold(keycode: KeyCode, modifier: ModifierCombination) -> KeyAction {
+    KeyAction::TapHold(
+        Action::Key(keycode),
+        Action::Modifier(modifier),
+        MorseProfile::new(None, None, None, None),
+    )
+}
+
+/// A tap dance that does `tap` for one tap and `hold` when held
+fn dance(tap: Action, hold: Action) -> TapDance {
+    TapDance::new(
+        [tap, Action::No, Action::No],
+        [hold, Action::No, Action::No],
+    )
+}
+
+#[test]
+fn accepts_the_keymap() {
+    assert_eq!(validate_keymap(&get_default_keymap(), &TAP_DANCES), Ok(()));
+}
+
+#[test]
+fn finds_layers_out_of_range() {
+    let keymap = [[[key(KeyCode::A), single(Action::LayerOn(2))]], [[NO, NO]]];
+    assert_eq!(
+        validate_keymap(&keymap, &[]),
+        Err(KeymapError::LayerOutOfRange {
+            key: (0, 0, 1),
+            layer: 2
+        })
+    );
+}
+
+#[test]
+fn finds_toggles_without_a_way_back() {
+    let keymap = [[[single(Action::LayerToggle(1)), NO]], [[NO, NO]]];
+    assert_eq!(
+        validate_keymap(&keymap, &[]),
+        Err(KeymapError::NoToggleBack {
+            key: (0, 0, 0),
+            layer: 1
+        })
+    );
+
+    let keymap = [
+        [[single(Action::LayerToggle(1)), NO]],
+        [[single(Action::LayerToggle(1)), NO]],
+    ];
+    assert_eq!(validate_keymap(&keymap, &[]), Ok(()));
+
+    // Toggled off from a layer it turns on, like the leader layer does
+    let keymap = [
+        [[single(Action::LayerToggle(1)), NO]],
+        [[single(Action::LayerOn(2)), NO]],
+        [[single(Action::LayerToggle(1)), NO]],
+    ];
+    assert_eq!(validate_keymap(&keymap, &[]), Ok(()));
+}
+
+#[test]
+fn finds_default_layers_without_a_way_back() {
+    let keymap = [
+        [[single(Action::DefaultLayer(1)), NO]],
+        [[key(KeyCode::A), single(Action::LayerOn(2))]],
+        [[NO, NO]],
+    ];
+    assert_eq!(
+        validate_keymap(&keymap, &[]),
+        Err(KeymapError::NoWayToBase {
+            key: (0, 0, 0),
+            layer: 1
+        })
+    );
+
+    // Back through a layer turned on from the default layer
+    let keymap = [
+        [[single(Action::DefaultLayer(1)), NO]],
+        [[key(KeyCode::A), single(Action::LayerOn(2))]],
+        [[single(Action::DefaultLayer(0)), KeyAction::Transparent]],
+    ];
+    assert_eq!(validate_keymap(&keymap, &[]), Ok(()));
+}
+
+#[test]
+fn finds_transparent_keys_on_default_layers() {
+    let keymap = [[[KeyAction::Transparent, NO]]];
+    assert_eq!(
+        validate_keymap(&keymap, &[]),
+        Err(KeymapError::TransparentOnBase { key: (0, 0, 0) })
+    );
+
+    let keymap = [
+        [[single(Action::DefaultLayer(1)), NO]],
+        [[single(Action::DefaultLayer(0)), KeyAction::Transparent]],
+    ];
+    assert_eq!(
+        validate_keymap(&keymap, &[]),
+        Err(KeymapError::TransparentOnBase { key: (1, 0, 1) })
+    );
+}
+
+#[test]
+fn finds_keys_without_an_action_on_default_layers() {
+    let keymap = [[[NO, KeyAction::No]]];
+    assert_eq!(
+        validate_keymap(&keymap, &[]),
+        Err(KeymapError::NoActionOnBase { key: (0, 0, 1) })
+    );
+
+    // Fine on a layer that is only turned on
+    let keymap = [[[single(Action::LayerOn(1)), NO]], [[NO, KeyAction::No]]];
+    assert_eq!(validate_keymap(&keymap, &[]), Ok(()));
+}
+
+#[test]
+fn finds_duplicate_tap_holds() {
+    let keymap = [[
+        [tap_hold(KeyCode::A, ModifierCombination::LALT), NO],
+        [
+            key(KeyCode::A),
+            tap_hold(KeyCode::A, ModifierCombination::LGUI),
+        ],
+    ]];
+    assert_eq!(
+        validate_keymap(&keymap, &[]),
+        Err(KeymapError::DuplicateTapHold {
+            first: (0, 0, 0),
+            second: (0, 1, 1)
+        })
+    );
+}
+
+#[test]
+fn finds_td_keys_without_a_tap_dance() {
+    let keymap = [[[KeyAction::Morse(1), NO]]];
+    assert_eq!(
+        validate_keymap(&keymap, &[dance(Action::Key(KeyCode::A), Action::No)]),
+        Err(KeymapError::NoTapDance {
+            key: (0, 0, 0),
+            index: 1
+        })
+    );
+}
+
+#[test]
+fn checks_the_layers_of_tap_dances() {
+    let keymap = [[[KeyAction::Morse(0), NO]], [[NO, NO]]];
+    assert_eq!(
+        validate_keymap(
+            &keymap,
+            &[dance(Action::Key(KeyCode::A), Action::LayerOn(2))]
+        ),
+        Err(KeymapError::LayerOutOfRange {
+            key: (0, 0, 0),
+            layer: 2
+        })
+    );
+    assert_eq!(
+        validate_keymap(&keymap, &[dance(Action::LayerToggle(1), Action::No)]),
+        Err(KeymapError::NoToggleBack {
+            key: (0, 0, 0),
+            layer: 1
+        })
+    );
+    assert_eq!(
+        validate_keymap(
+            &keymap,
+            &[dance(Action::Key(KeyCode::A), Action::LayerOn(1))]
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn checks_default_layers_of_tap_dances() {
+    let tap_dances = [dance(Action::DefaultLayer(1), Action::No)];
+    // Switching back needs a df! key, which also makes layer 1 one the
+    // dongle can switch to
+    let keymap = [[[KeyAction::Morse(0), NO]], [[NO, NO]]];
+    assert_eq!(
+        validate_keymap(&keymap, &tap_dances),
+        Err(KeymapError::NoWayToBase {
+            key: (0, 0, 0),
+            layer: 1
+        })
+    );
+    let keymap = [
+        [[KeyAction::Morse(0), NO]],
+        [[single(Action::DefaultLayer(0)), NO]],
+    ];
+    assert_eq!(
+        validate_keymap(&keymap, &tap_dances),
+        Err(KeymapError::NoDefaultLayerKey {
+            key: (0, 0, 0),
+            layer: 1
+        })
+    );
+    let keymap = [
+        [[KeyAction::Morse(0), single(Action::DefaultLayer(1))]],
+        [[single(Action::DefaultLayer(0)), KeyAction::Transparent]],
+    ];
+    assert_eq!(
+        validate_keymap(&keymap, &tap_dances),
+        Err(KeymapError::TransparentOnBase { key: (1, 0, 1) })
+    );
+    let keymap = [
+        [[KeyAction::Morse(0), single(Action::DefaultLayer(1))]],
+        [[single(Action::DefaultLayer(0)), NO]],
+    ];
+    assert_eq!(validate_keymap(&keymap, &tap_dances), Ok(()));
+
+    // Holds only turn layers on
+    assert_eq!(
+        validate_keymap(
+            &keymap,
+            &[dance(Action::Key(KeyCode::A), Action::DefaultLayer(1))]
+        ),
+        Err(KeymapError::DefaultLayerOnHold { key: (0, 0, 0) })
+    );
+}
+
+#[test]
+fn describes_the_key() {
+    let error = KeymapError::DuplicateTapHold {
+        first: (0, 1, 4),
+        second: (0, 1, 7),
+    };
+    assert_eq!(
+        error.message().as_str(),
+        "keymap: layer 0 row 1 col 4 and layer 0 row 1 col 7 are tap-holds of the same key"
+    );
+    let error = KeymapError::LayerOutOfRange {
+        key: (3, 3, 4),
+        layer: 120,
+    };
+    assert_eq!(
+        error.message().as_str(),
+        "keymap: layer 3 row 3 col 4 refers to layer 120, which doesn't exist"
+    );
+}
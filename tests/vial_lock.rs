@@ -0,0 +1,130 @@
+//! Runs rmk with the keymap and the USB driver of the dongle and edits the
+//! keymap over Vial before and after holding the unlock keys.
+
+mod common;
+
+use common::flash::RamFlash;
+use common::usb::{MockDriver, VialHost};
+use embassy_time::Timer;
+use rmk::channel::KEY_EVENT_CHANNEL;
+use rmk::config::{BehaviorConfig, PositionalConfig, RmkConfig, StorageConfig, VialConfig};
+use rmk::embassy_futures::block_on;
+use rmk::embassy_futures::select::{Either3, select3};
+use rmk::event::KeyboardEvent;
+use rmk::input_device::Runnable;
+use rmk::types::action::EncoderAction;
+use rmk::{initialize_encoder_keymap_and_storage, run_rmk};
+use rmk_corne::keyboard::Keyboard;
+use rmk_corne::keymap::{COL, NUM_LAYER, ROW, VIAL_UNLOCK_KEYS, get_default_keymap};
+use rmk_corne::via::{ViaDriver, is_unlocked};
+
+const GET_KEYCODE: u8 = 0x04;
+const SET_KEYCODE: u8 = 0x05;
+const UNHANDLED: u8 = 0xff;
+const VIAL: u8 = 0xfe;
+const GET_UNLOCK_STATUS: u8 = 0x05;
+const UNLOCK_START: u8 = 0x06;
+const UNLOCK_POLL: u8 = 0x07;
+const LOCK: u8 = 0x08;
+
+const KC_A: u16 = 0x04;
+const KC_W: u16 = 0x1a;
+
+/// W on the base layer
+const KEY: (u8, u8) = (0, 2);
+
+async fn get_keycode(host: &VialHost) -> u16 {
+    let answer = host.request(&[GET_KEYCODE, 0, KEY.0, KEY.1]).await;
+    u16::from_be_bytes([answer[4], answer[5]])
+}
+
+/// Asks to change the key to `keycode`, returns the command rmk answered to
+async fn set_keycode(host: &VialHost, keycode: u16) -> u8 {
+    let [high, low] = keycode.to_be_bytes();
+    let answer = host
+        .request(&[SET_KEYCODE, 0, KEY.0, KEY.1, high, low])
+        .await;
+    answer[0]
+}
+
+async fn hold_unlock_keys(pressed: bool) {
+    for (row, col) in VIAL_UNLOCK_KEYS {
+        KEY_EVENT_CHANNEL
+            .send(KeyboardEvent::key(row, col, pressed))
+            .await;
+    }
+    Timer::after_millis(50).await;
+}
+
+async fn vial_session(host: VialHost) {
+    let status = host.request(&[VIAL, GET_UNLOCK_STATUS]).await;
+    assert_eq!(status[0], 0, "Vial starts locked");
+    let keys: Vec<(u8, u8)> = status[2..]
+        .chunks(2)
+        .map(|key| (key[0], key[1]))
+        .take_while(|&key| key != (0xff, 0xff))
+        .collect();
+    assert_eq!(keys, VIAL_UNLOCK_KEYS);
+
+    assert_eq!(set_keycode(&host, KC_A).await, UNHANDLED);
+    assert_eq!(get_keycode(&host).await, KC_W);
+
+    // Polling without the keys held doesn't unlock
+    host.request(&[VIAL, UNLOCK_START]).await;
+    let poll = host.request(&[VIAL, UNLOCK_POLL]).await;
+    assert_eq!((poll[0], poll[2]), (0, VIAL_UNLOCK_KEYS.len() as u8));
+    assert!(!is_unlocked());
+    assert_eq!(set_keycode(&host, KC_A).await, UNHANDLED);
+
+    // rmk reports the unlock on the poll after the one that saw the keys
+    hold_unlock_keys(true).await;
+    let poll = host.request(&[VIAL, UNLOCK_POLL]).await;
+    assert_eq!((poll[0], poll[2]), (0, 0));
+    let poll = host.request(&[VIAL, UNLOCK_POLL]).await;
+    assert_eq!(poll[0], 1);
+    hold_unlock_keys(false).await;
+    assert!(is_unlocked());
+
+    assert_eq!(set_keycode(&host, KC_A).await, SET_KEYCODE);
+    assert_eq!(get_keycode(&host).await, KC_A);
+
+    host.request(&[VIAL, LOCK]).await;
+    assert!(!is_unlocked());
+    assert_eq!(set_keycode(&host, KC_W).await, UNHANDLED);
+    assert_eq!(get_keycode(&host).await, KC_A);
+}
+
+#[test]
+fn vial_edits_need_the_unlock_keys() {
+    let keymap = Box::leak(Box::new(get_default_keymap()));
+    let encoders = Box::leak(Box::new([const { [] as [EncoderAction; 0] }; NUM_LAYER]));
+    let behavior = Box::leak(Box::new(BehaviorConfig::default()));
+    let positions = Box::leak(Box::new(PositionalConfig::<ROW, COL>::default()));
+    let storage_config = StorageConfig {
+        num_sectors: 4,
+        ..Default::default()
+    };
+    let (keymap, mut storage) = block_on(initialize_encoder_keymap_and_storage(
+        keymap,
+        encoders,
+        RamFlash::new(4),
+        &storage_config,
+        behavior,
+        positions,
+    ));
+    let keymap = Box::leak(Box::new(keymap));
+    let rmk_config = RmkConfig {
+        vial_config: VialConfig::new(&[], &[], &VIAL_UNLOCK_KEYS),
+        storage_config,
+        ..Default::default()
+    };
+
+    let mut keyboard = Keyboard::new(keymap);
+    let driver = ViaDriver::new(MockDriver::default());
+    let session = block_on(select3(
+        run_rmk(keymap, driver, &mut storage, rmk_config),
+        keyboard.run(),
+        vial_session(VialHost),
+    ));
+    assert!(matches!(session, Either3::Third(())));
+}